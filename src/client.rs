@@ -0,0 +1,556 @@
+use crate::rtcp::jitter::JitterEstimator;
+use crate::rtcp::liveness::SR_INTERVAL;
+use crate::rtcp::rr::{ReceiverReport, ReceptionStats};
+use crate::rtcp::split_compound;
+use crate::rtp::depacketize::H264Depacketizer;
+use crate::rtp::h264::CLOCK_RATE;
+use crate::rtp::packet::RtpPacket;
+use crate::rtp::random_u64;
+use crate::rtsp::framing::{Frame, RtspFramer};
+use crate::rtsp::sdp::parse_media;
+use crate::rtsp::uri;
+use crate::source::encoder::EncoderConfig;
+use crate::source::rtsp_pull::RtspPullSource;
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+/// Thời gian nhận mặc định của `--client` (`--client-duration` giây)
+pub const DEFAULT_DURATION: Duration = Duration::from_secs(5);
+/// Timeout cho connect và cho mỗi RTSP response
+const RESPONSE_TIMEOUT: Duration = Duration::from_secs(5);
+/// Anomalies in ra tối đa; phần còn lại chỉ được đếm
+const MAX_LISTED_ANOMALIES: usize = 20;
+
+/// `--client <url>`: built-in RTSP client cho smoke test / interop diagnostic
+///
+/// Connect tới URL như `RtspPullSource` (cùng URL handling), negotiate TCP
+/// interleaved, nhận RTP trong `duration` rồi TEARDOWN. Mỗi packet qua
+/// `RtpPacket::parse` và `H264Depacketizer`; report đếm packets/frames/NALUs
+/// và liệt kê anomalies (sequence gaps, IDR trước SPS/PPS, depacketize lỗi).
+/// Returns the process exit code (0 = nhận được video và không có anomaly).
+pub async fn run(url: &str, duration: Duration) -> i32 {
+    let source = RtspPullSource::new(url.to_string(), EncoderConfig::default());
+    println!("📡 Client: pulling {} for {:?}", source.url, duration);
+
+    match receive(&source, duration).await {
+        Ok(report) => {
+            report.print();
+            i32::from(!report.is_clean())
+        }
+        Err(e) => {
+            eprintln!("❌ Client: {}", e);
+            1
+        }
+    }
+}
+
+async fn receive(source: &RtspPullSource, duration: Duration) -> Result<StreamReport, String> {
+    let addr = source.server_addr()?;
+    let url = source.url.as_str();
+    let stream = match tokio::time::timeout(RESPONSE_TIMEOUT, TcpStream::connect(&addr)).await {
+        Ok(stream) => stream.map_err(|e| format!("cannot connect to {}: {}", addr, e))?,
+        Err(_) => return Err(format!("timed out connecting to {}", addr)),
+    };
+    let mut connection = Connection::new(stream);
+
+    connection.request("OPTIONS", url, &[]).await?;
+    let describe = connection.request("DESCRIBE", url, &["Accept: application/sdp".to_string()]).await?;
+    let base = describe.header("Content-Base").unwrap_or(url).to_string();
+    let video = parse_media(&describe.body)
+        .into_iter()
+        .find(|format| format.media == "video" && format.encoding.eq_ignore_ascii_case("H264"))
+        .ok_or("no H264 video track in the SDP")?;
+    let control = match video.control.as_deref() {
+        Some(control) if control.contains("://") => control.to_string(),
+        Some(control) if control != "*" => uri::control_url(&base, control),
+        _ => base.clone(),
+    };
+    println!("🎬 Video: PT {} {}/{}, control {}", video.payload_type, video.encoding, video.clock_rate, control);
+
+    let setup = connection
+        .request("SETUP", &control, &["Transport: RTP/AVP/TCP;unicast;interleaved=0-1".to_string()])
+        .await?;
+    let session = setup.header("Session").ok_or("SETUP response has no Session header")?;
+    connection.session = Some(session.split(';').next().unwrap_or(session).trim().to_string());
+    let (rtp_channel, rtcp_channel) = setup.header("Transport").and_then(interleaved_channels).unwrap_or((0, 1));
+
+    connection.request("PLAY", url, &["Range: npt=0.000-".to_string()]).await?;
+
+    let clock_rate = if video.clock_rate > 0 { video.clock_rate } else { CLOCK_RATE };
+    let mut report = StreamReport::new(video.payload_type, clock_rate);
+    // sprop-parameter-sets trong SDP cũng đủ cho decoder, không chỉ in-band SPS/PPS
+    for set in &video.parameter_sets {
+        report.on_nalu(set, None);
+    }
+
+    let ssrc = random_u64() as u32;
+    let started = Instant::now();
+    let deadline = started + duration;
+    let mut next_rr = started + SR_INTERVAL;
+    loop {
+        let now = Instant::now();
+        if now >= deadline {
+            break;
+        }
+        if now >= next_rr {
+            next_rr += SR_INTERVAL;
+            if let Some(rr) = report.receiver_report(ssrc) {
+                connection.send_interleaved(rtcp_channel, &rr.to_bytes()).await?;
+            }
+        }
+
+        match connection.next_frame(deadline.min(next_rr)).await? {
+            Some(Frame::Interleaved { channel, payload }) if channel == rtp_channel => report.on_rtp(&payload, Instant::now()),
+            Some(Frame::Interleaved { channel, payload }) if channel == rtcp_channel => report.on_rtcp(&payload),
+            _ => {}
+        }
+    }
+    report.elapsed = started.elapsed();
+
+    if let Err(e) = connection.request("TEARDOWN", url, &[]).await {
+        println!("⚠️  TEARDOWN: {}", e);
+    }
+    Ok(report)
+}
+
+/// `interleaved=a-b` của một Transport header
+fn interleaved_channels(transport: &str) -> Option<(u8, u8)> {
+    let range = transport.split(';').find_map(|param| param.trim().strip_prefix("interleaved="))?;
+    let (rtp, rtcp) = range.split_once('-')?;
+    Some((rtp.trim().parse().ok()?, rtcp.trim().parse().ok()?))
+}
+
+/// RTSP response: status code, header block và body
+struct Response {
+    status: u16,
+    status_line: String,
+    headers: Vec<(String, String)>,
+    body: String,
+}
+
+impl Response {
+    fn parse(text: &str) -> Option<Self> {
+        let (head, body) = text.split_once("\r\n\r\n").unwrap_or((text, ""));
+        let mut lines = head.lines();
+        let status_line = lines.next()?.trim().to_string();
+        let status = status_line.strip_prefix("RTSP/1.0 ")?.split_whitespace().next()?.parse().ok()?;
+        let headers = lines
+            .filter_map(|line| line.split_once(':'))
+            .map(|(name, value)| (name.trim().to_string(), value.trim().to_string()))
+            .collect();
+        Some(Self { status, status_line, headers, body: body.to_string() })
+    }
+
+    fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(header, _)| header.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+/// Control connection: requests tuần tự, responses và interleaved data cùng
+/// đi qua `RtspFramer` như phía server
+struct Connection {
+    stream: TcpStream,
+    framer: RtspFramer,
+    buffer: Vec<u8>,
+    cseq: u32,
+    session: Option<String>,
+}
+
+impl Connection {
+    fn new(stream: TcpStream) -> Self {
+        Self {
+            stream,
+            framer: RtspFramer::new(),
+            buffer: vec![0u8; 8192],
+            cseq: 0,
+            session: None,
+        }
+    }
+
+    /// Gửi request và chờ response 200; interleaved data tới trước response
+    /// (RTP trước khi TEARDOWN được xử lý) bị bỏ
+    async fn request(&mut self, method: &str, url: &str, headers: &[String]) -> Result<Response, String> {
+        self.cseq += 1;
+        let mut request = format!("{} {} RTSP/1.0\r\nCSeq: {}\r\nUser-Agent: simulation-media-server/client\r\n", method, url, self.cseq);
+        if let Some(session) = &self.session {
+            request.push_str(&format!("Session: {}\r\n", session));
+        }
+        for header in headers {
+            request.push_str(header);
+            request.push_str("\r\n");
+        }
+        request.push_str("\r\n");
+        self.stream
+            .write_all(request.as_bytes())
+            .await
+            .map_err(|e| format!("{} failed: {}", method, e))?;
+
+        let deadline = Instant::now() + RESPONSE_TIMEOUT;
+        loop {
+            match self.next_frame(deadline).await? {
+                Some(Frame::Request(text)) => {
+                    let response = Response::parse(&text).ok_or_else(|| format!("malformed response to {}", method))?;
+                    println!("📥 {} → {}", method, response.status_line);
+                    if response.status != 200 {
+                        return Err(format!("{} rejected: {}", method, response.status_line));
+                    }
+                    return Ok(response);
+                }
+                Some(Frame::Interleaved { .. }) => {}
+                None => return Err(format!("no response to {} within {:?}", method, RESPONSE_TIMEOUT)),
+            }
+        }
+    }
+
+    /// Frame kế tiếp, None nếu `deadline` qua trước khi có frame hoàn chỉnh
+    async fn next_frame(&mut self, deadline: Instant) -> Result<Option<Frame>, String> {
+        loop {
+            if let Some(frame) = self.framer.next_frame() {
+                return Ok(Some(frame));
+            }
+            if self.framer.overflowed() {
+                return Err("server sent an oversized message".to_string());
+            }
+            match tokio::time::timeout_at(deadline.into(), self.stream.read(&mut self.buffer)).await {
+                Err(_) => return Ok(None),
+                Ok(Ok(0)) => return Err("server closed the connection".to_string()),
+                Ok(Ok(n)) => self.framer.push(&self.buffer[..n]),
+                Ok(Err(e)) => return Err(format!("read failed: {}", e)),
+            }
+        }
+    }
+
+    async fn send_interleaved(&mut self, channel: u8, payload: &[u8]) -> Result<(), String> {
+        let mut frame = vec![b'$', channel];
+        frame.extend_from_slice(&(payload.len() as u16).to_be_bytes());
+        frame.extend_from_slice(payload);
+        self.stream.write_all(&frame).await.map_err(|e| format!("RTCP write failed: {}", e))
+    }
+}
+
+/// Thống kê và anomalies của video stream nhận được
+struct StreamReport {
+    payload_type: u8,
+    ssrc: Option<u32>,
+    packets: u64,
+    payload_bytes: u64,
+    /// Access units (RTP timestamps khác nhau liên tiếp)
+    frames: u64,
+    last_sequence: Option<u16>,
+    last_timestamp: Option<u32>,
+    gaps: u64,
+    missing: u64,
+    /// Duplicate hoặc đến sau packet có sequence lớn hơn
+    reordered: u64,
+    nal_types: [u64; 32],
+    have_sps: bool,
+    have_pps: bool,
+    idr_without_sps: u64,
+    idr_without_pps: u64,
+    sender_reports: u64,
+    /// Middle 32 bits NTP của SR gần nhất và lúc nhận, cho LSR/DLSR
+    last_sr: Option<(u32, Instant)>,
+    anomalies: Vec<String>,
+    anomaly_count: u64,
+    depacketizer: H264Depacketizer,
+    reception: ReceptionStats,
+    /// Interarrival jitter (RFC 3550 A.8) cho RR và report
+    jitter: JitterEstimator,
+    elapsed: Duration,
+}
+
+impl StreamReport {
+    fn new(payload_type: u8, clock_rate: u32) -> Self {
+        Self {
+            payload_type,
+            ssrc: None,
+            packets: 0,
+            payload_bytes: 0,
+            frames: 0,
+            last_sequence: None,
+            last_timestamp: None,
+            gaps: 0,
+            missing: 0,
+            reordered: 0,
+            nal_types: [0; 32],
+            have_sps: false,
+            have_pps: false,
+            idr_without_sps: 0,
+            idr_without_pps: 0,
+            sender_reports: 0,
+            last_sr: None,
+            anomalies: Vec::new(),
+            anomaly_count: 0,
+            depacketizer: H264Depacketizer::new(),
+            reception: ReceptionStats::new(),
+            jitter: JitterEstimator::new(clock_rate),
+            elapsed: Duration::ZERO,
+        }
+    }
+
+    fn anomaly(&mut self, description: String) {
+        self.anomaly_count += 1;
+        if self.anomalies.len() < MAX_LISTED_ANOMALIES {
+            self.anomalies.push(description);
+        }
+    }
+
+    fn on_rtp(&mut self, data: &[u8], arrival: Instant) {
+        let packet = match RtpPacket::parse(data) {
+            Ok(packet) => packet,
+            Err(e) => return self.anomaly(format!("unparseable RTP packet: {:?}", e)),
+        };
+        let header = &packet.header;
+        self.packets += 1;
+        self.payload_bytes += packet.payload.len() as u64;
+        self.reception.on_packet(header.sequence);
+        self.jitter.on_packet(header.ssrc, header.timestamp, arrival);
+
+        if header.payload_type != self.payload_type {
+            self.anomaly(format!("seq {}: payload type {} (SDP says {})", header.sequence, header.payload_type, self.payload_type));
+        }
+        match self.ssrc {
+            Some(ssrc) if ssrc != header.ssrc => {
+                self.anomaly(format!("seq {}: SSRC changed {:08X} → {:08X}", header.sequence, ssrc, header.ssrc));
+                self.ssrc = Some(header.ssrc);
+            }
+            Some(_) => {}
+            None => self.ssrc = Some(header.ssrc),
+        }
+
+        match self.last_sequence.map(|last| (last, header.sequence.wrapping_sub(last))) {
+            None | Some((_, 1)) => self.last_sequence = Some(header.sequence),
+            Some((last, delta)) if delta < 0x8000 => {
+                self.gaps += 1;
+                self.missing += u64::from(delta - 1);
+                self.anomaly(format!("sequence gap {} → {} ({} missing)", last, header.sequence, delta - 1));
+                self.last_sequence = Some(header.sequence);
+            }
+            Some((last, _)) => {
+                self.reordered += 1;
+                self.anomaly(format!("seq {} arrived after {} (duplicate/reordered)", header.sequence, last));
+            }
+        }
+        if self.last_timestamp != Some(header.timestamp) {
+            self.frames += 1;
+            self.last_timestamp = Some(header.timestamp);
+        }
+
+        self.depacketizer.expire(arrival);
+        match self.depacketizer.push(&packet) {
+            Ok(nalus) => {
+                for nalu in nalus {
+                    self.on_nalu(&nalu, Some(packet.header.sequence));
+                }
+            }
+            Err(e) => self.anomaly(format!("seq {}: depacketize failed: {:?}", packet.header.sequence, e)),
+        }
+    }
+
+    /// Đếm NALU theo type; `sequence` None cho parameter sets từ SDP
+    fn on_nalu(&mut self, nalu: &[u8], sequence: Option<u16>) {
+        let Some(&header) = nalu.first() else {
+            return;
+        };
+        let nal_type = header & 0x1F;
+        if sequence.is_some() {
+            self.nal_types[nal_type as usize] += 1;
+        }
+        match nal_type {
+            7 => self.have_sps = true,
+            8 => self.have_pps = true,
+            5 => {
+                self.idr_without_sps += u64::from(!self.have_sps);
+                self.idr_without_pps += u64::from(!self.have_pps);
+            }
+            _ => {}
+        }
+    }
+
+    fn on_rtcp(&mut self, data: &[u8]) {
+        for packet in split_compound(data) {
+            match packet[1] {
+                200 if packet.len() >= 28 => {
+                    self.sender_reports += 1;
+                    let lsr = u32::from_be_bytes([packet[10], packet[11], packet[12], packet[13]]);
+                    self.last_sr = Some((lsr, Instant::now()));
+                }
+                203 => println!("👋 Server sent RTCP BYE"),
+                _ => {}
+            }
+        }
+    }
+
+    /// RR về stream đang nhận (None trước packet đầu tiên)
+    fn receiver_report(&mut self, sender_ssrc: u32) -> Option<ReceiverReport> {
+        let mut block = self.reception.report(self.ssrc?)?;
+        self.jitter.apply(&mut block);
+        if let Some((lsr, received)) = self.last_sr {
+            block.lsr = lsr;
+            block.dlsr = (received.elapsed().as_secs_f64() * 65536.0) as u32;
+        }
+        Some(ReceiverReport { sender_ssrc, reports: vec![block] })
+    }
+
+    /// Anomalies tổng hợp ở cuối (thiếu video, IDR không decode được)
+    fn summary_anomalies(&self) -> Vec<String> {
+        let mut anomalies = Vec::new();
+        if self.packets == 0 {
+            anomalies.push("no RTP packets received".to_string());
+        }
+        if self.idr_without_sps > 0 {
+            anomalies.push(format!("{} IDR(s) before any SPS (in-band or sprop-parameter-sets)", self.idr_without_sps));
+        }
+        if self.idr_without_pps > 0 {
+            anomalies.push(format!("{} IDR(s) before any PPS", self.idr_without_pps));
+        }
+        if self.depacketizer.lost_nalus() > 0 {
+            anomalies.push(format!("{} FU-A NALU(s) dropped incomplete", self.depacketizer.lost_nalus()));
+        }
+        anomalies
+    }
+
+    fn is_clean(&self) -> bool {
+        self.anomaly_count == 0 && self.summary_anomalies().is_empty()
+    }
+
+    fn print(&self) {
+        let secs = self.elapsed.as_secs_f64().max(f64::EPSILON);
+        let count = |nal_type: usize| self.nal_types[nal_type];
+        let other: u64 = self.nal_types.iter().sum::<u64>() - count(1) - count(5) - count(7) - count(8);
+
+        println!("📊 Client report ({:.1}s):", secs);
+        println!("   Packets: {} ({} payload bytes, {:.0} kbps)", self.packets, self.payload_bytes, self.payload_bytes as f64 * 8.0 / secs / 1000.0);
+        println!("   Frames: {} ({:.1} fps)", self.frames, self.frames as f64 / secs);
+        println!("   NALUs: {} IDR, {} non-IDR, {} SPS, {} PPS, {} other", count(5), count(1), count(7), count(8), other);
+        println!("   Sequence: {} gap(s), {} missing, {} duplicate/reordered", self.gaps, self.missing, self.reordered);
+        let jitter = self.ssrc.and_then(|ssrc| self.jitter.jitter(ssrc)).unwrap_or(0);
+        println!("   Jitter: {} ticks ({:.1} ms)", jitter, jitter as f64 * 1000.0 / self.jitter.clock_rate() as f64);
+        println!("   Sender reports: {}", self.sender_reports);
+
+        let summary = self.summary_anomalies();
+        if self.is_clean() {
+            println!("✅ No anomalies");
+            return;
+        }
+        println!("⚠️  Anomalies ({}):", self.anomaly_count + summary.len() as u64);
+        for anomaly in self.anomalies.iter().chain(&summary) {
+            println!("   - {}", anomaly);
+        }
+        if self.anomaly_count > self.anomalies.len() as u64 {
+            println!("   ... {} more", self.anomaly_count - self.anomalies.len() as u64);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rtp::h264::H264Packetizer;
+
+    const SPS: [u8; 4] = [0x67, 0x42, 0x00, 0x1F];
+    const PPS: [u8; 3] = [0x68, 0xCE, 0x3C];
+
+    /// Một access unit (NALUs) → RTP packets bytes, như trên wire
+    fn access_unit(packetizer: &mut H264Packetizer, nalus: &[&[u8]]) -> Vec<Vec<u8>> {
+        let mut packets = Vec::new();
+        for (i, nalu) in nalus.iter().enumerate() {
+            packets.extend(packetizer.packetize(nalu, i + 1 == nalus.len()).iter().map(|p| p.to_bytes()));
+        }
+        packetizer.end_access_unit();
+        packets
+    }
+
+    #[test]
+    fn clean_stream_counts_frames_and_nalus() {
+        let mut packetizer = H264Packetizer::with_ssrc(1);
+        packetizer.set_blocksize(Some(112));
+        let mut report = StreamReport::new(96, CLOCK_RATE);
+        let idr = [&[0x65u8][..], &[0xAB; 300]].concat();
+
+        for packet in access_unit(&mut packetizer, &[&SPS, &PPS, &idr]) {
+            report.on_rtp(&packet, Instant::now());
+        }
+        for _ in 0..4 {
+            for packet in access_unit(&mut packetizer, &[&[0x41, 1, 2, 3]]) {
+                report.on_rtp(&packet, Instant::now());
+            }
+        }
+
+        assert!(report.is_clean(), "{:?}", report.anomalies);
+        assert_eq!(report.frames, 5);
+        assert_eq!((report.nal_types[7], report.nal_types[8], report.nal_types[5], report.nal_types[1]), (1, 1, 1, 4));
+        assert!(report.packets > 6, "IDR should be FU-A fragmented");
+    }
+
+    #[test]
+    fn reports_sequence_gaps_and_missing_sps() {
+        let mut packetizer = H264Packetizer::with_ssrc(1);
+        let mut report = StreamReport::new(96, CLOCK_RATE);
+
+        let mut packets = access_unit(&mut packetizer, &[&PPS, &[0x65, 9, 9]]);
+        packets.extend(access_unit(&mut packetizer, &[&[0x41, 1]]));
+        packets.extend(access_unit(&mut packetizer, &[&[0x41, 2]]));
+        packets.remove(2);
+        for packet in &packets {
+            report.on_rtp(packet, Instant::now());
+        }
+
+        assert_eq!((report.gaps, report.missing), (1, 1));
+        assert_eq!(report.idr_without_sps, 1);
+        assert!(!report.is_clean());
+    }
+
+    #[test]
+    fn sdp_parameter_sets_satisfy_idr() {
+        let mut packetizer = H264Packetizer::with_ssrc(1);
+        let mut report = StreamReport::new(96, CLOCK_RATE);
+        report.on_nalu(&SPS, None);
+        report.on_nalu(&PPS, None);
+        for packet in access_unit(&mut packetizer, &[&[0x65, 9, 9]]) {
+            report.on_rtp(&packet, Instant::now());
+        }
+        assert!(report.is_clean(), "{:?}", report.summary_anomalies());
+        assert_eq!(report.nal_types[7], 0, "SDP sets are not counted as received NALUs");
+    }
+
+    #[test]
+    fn parses_interleaved_channels_and_responses() {
+        assert_eq!(interleaved_channels("RTP/AVP/TCP;unicast;interleaved=2-3"), Some((2, 3)));
+        assert_eq!(interleaved_channels("RTP/AVP;unicast;client_port=5000-5001"), None);
+
+        let response = Response::parse("RTSP/1.0 200 OK\r\nCSeq: 2\r\nSession: ab12;timeout=60\r\n\r\nv=0\r\n").unwrap();
+        assert_eq!(response.status, 200);
+        assert_eq!(response.header("session"), Some("ab12;timeout=60"));
+        assert_eq!(response.body, "v=0\r\n");
+        assert!(Response::parse("HTTP/1.1 200 OK\r\n\r\n").is_none());
+    }
+
+    #[test]
+    fn receiver_report_carries_loss_and_jitter() {
+        let mut packetizer = H264Packetizer::with_ssrc(0xABCD);
+        let mut report = StreamReport::new(96, CLOCK_RATE);
+        assert!(report.receiver_report(1).is_none());
+
+        // AU mỗi 3000 ticks (33.3ms); AU thứ hai đến trễ 10ms, AU thứ ba bị mất
+        let start = Instant::now();
+        let arrivals = [0u64, 43_333, 0, 100_000];
+        for (i, arrival) in arrivals.iter().enumerate() {
+            let packets = access_unit(&mut packetizer, &[&[0x41, i as u8]]);
+            if i != 2 {
+                report.on_rtp(&packets[0], start + Duration::from_micros(*arrival));
+            }
+        }
+
+        let rr = report.receiver_report(1).unwrap();
+        let block = &rr.reports[0];
+        assert_eq!(block.ssrc, 0xABCD);
+        assert_eq!(block.cumulative_lost, 1);
+        // |D| = 900 ticks (10ms) rồi 900 lần nữa: J = 900/16 + (900 - 56)/16
+        assert!((100..=110).contains(&block.jitter), "jitter {}", block.jitter);
+    }
+}
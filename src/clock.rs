@@ -0,0 +1,142 @@
+use std::fmt::Debug;
+#[cfg(test)]
+use std::sync::Mutex;
+#[cfg(test)]
+use std::time::Duration;
+use std::time::{Instant, SystemTime};
+
+/// Nguồn thời gian cho RTCP SR (wall clock → NTP) và pacing loops
+///
+/// Production dùng `SystemClock`; `ManualClock` cho phép tua nhanh để kiểm
+/// tra timestamp/sequence wrap của session chạy nhiều giờ mà không phải chờ.
+pub trait Clock: Debug + Send + Sync {
+    /// Monotonic time (pacing, timeouts)
+    fn now(&self) -> Instant;
+    /// Wall clock (NTP timestamp của SR)
+    fn wall(&self) -> SystemTime;
+}
+
+/// `Instant::now()` / `SystemTime::now()`
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    fn wall(&self) -> SystemTime {
+        SystemTime::now()
+    }
+}
+
+/// Clock đứng yên trừ khi `advance`; cả monotonic lẫn wall đi cùng nhau
+#[cfg(test)]
+#[derive(Debug)]
+pub struct ManualClock {
+    instant: Instant,
+    wall: SystemTime,
+    elapsed: Mutex<Duration>,
+}
+
+#[cfg(test)]
+impl ManualClock {
+    /// Start at `wall` (vd. ngay trước NTP/RTP wrap)
+    pub fn new(wall: SystemTime) -> Self {
+        Self {
+            instant: Instant::now(),
+            wall,
+            elapsed: Mutex::new(Duration::ZERO),
+        }
+    }
+
+    pub fn advance(&self, by: Duration) {
+        *self.elapsed.lock().unwrap() += by;
+    }
+}
+
+#[cfg(test)]
+impl Clock for ManualClock {
+    fn now(&self) -> Instant {
+        self.instant + *self.elapsed.lock().unwrap()
+    }
+
+    fn wall(&self) -> SystemTime {
+        self.wall + *self.elapsed.lock().unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rtcp::liveness::SR_INTERVAL;
+    use crate::rtcp::sr::SenderReport;
+    use crate::rtp::h264::{H264Packetizer, CLOCK_RATE};
+    use std::time::UNIX_EPOCH;
+
+    fn word(bytes: &[u8], at: usize) -> u32 {
+        u32::from_be_bytes([bytes[at], bytes[at + 1], bytes[at + 2], bytes[at + 3]])
+    }
+
+    /// NTP timestamp (seconds, fraction) của SR dưới dạng giây
+    fn ntp_secs(report: &[u8]) -> f64 {
+        word(report, 8) as f64 + word(report, 12) as f64 / 2f64.powi(32)
+    }
+
+    #[test]
+    fn manual_clock_advances_monotonic_and_wall_together() {
+        let clock = ManualClock::new(UNIX_EPOCH + Duration::from_secs(1_000));
+        let (start, wall) = (clock.now(), clock.wall());
+        clock.advance(Duration::from_millis(1500));
+        assert_eq!(clock.now() - start, Duration::from_millis(1500));
+        assert_eq!(clock.wall().duration_since(wall).unwrap(), Duration::from_millis(1500));
+    }
+
+    /// Phiên chạy nhiều giờ, tua nhanh: SR loop của TCP session (một SR mỗi
+    /// `SR_INTERVAL` của clock) qua cả RTP timestamp wrap lẫn sequence wrap
+    #[test]
+    fn sender_reports_stay_consistent_across_rtp_wraps() {
+        let clock = ManualClock::new(UNIX_EPOCH + Duration::from_secs(1_700_000_000));
+        let mut packetizer = H264Packetizer::with_ssrc(0x5151_0000);
+        let frame_ticks = CLOCK_RATE / 30;
+        // ~0.5s trước timestamp wrap, 16 packets trước sequence wrap
+        packetizer.set_timestamp(u32::MAX - 15 * frame_ticks);
+        packetizer.set_sequence(0xFFF0);
+        let report = SenderReport::new(packetizer.ssrc());
+
+        let mut last_sr = clock.now();
+        let mut reports = Vec::new();
+        let mut frames = 0u32;
+        while reports.len() < 2 {
+            for packet in packetizer.packetize(&[0x65, 0x88, 0x84, 0x00], true) {
+                report.add_packet(packet.payload.len());
+            }
+            packetizer.end_access_unit();
+            frames += 1;
+            clock.advance(packetizer.frame_duration());
+
+            if clock.now() - last_sr >= SR_INTERVAL {
+                last_sr = clock.now();
+                report.set_rtp_timestamp(packetizer.timestamp());
+                reports.push((frames, report.to_bytes(clock.wall())));
+            }
+        }
+
+        assert_eq!(packetizer.cycles(), 1);
+        let ((first_frames, first), (second_frames, second)) = (&reports[0], &reports[1]);
+        // RTP timestamp đã wrap trước SR đầu tiên
+        assert!(word(first, 16) < u32::MAX / 2);
+
+        // Wall clock giữa hai SRs khớp RTP delta (modulo 2^32) ở 90 kHz
+        let rtp_delta = word(second, 16).wrapping_sub(word(first, 16));
+        assert_eq!(rtp_delta, (second_frames - first_frames) * frame_ticks);
+        let wall_delta = ntp_secs(second) - ntp_secs(first);
+        assert!((wall_delta - rtp_delta as f64 / CLOCK_RATE as f64).abs() < 0.001, "{}", wall_delta);
+        assert!(wall_delta >= SR_INTERVAL.as_secs_f64() - 0.001);
+
+        // Packet/octet counts không bị ảnh hưởng bởi sequence wrap
+        assert_eq!((word(first, 20), word(first, 24)), (*first_frames, first_frames * 4));
+        assert_eq!((word(second, 20), word(second, 24)), (*second_frames, second_frames * 4));
+        assert_eq!(word(second, 4), 0x5151_0000);
+    }
+}
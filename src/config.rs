@@ -0,0 +1,373 @@
+use crate::clock::{Clock, SystemClock};
+use crate::rtp::h264::FragmentLimit;
+use crate::rtp::impair::ImpairmentConfig;
+use crate::rtp::rtx::DEFAULT_HISTORY_PACKETS;
+use crate::rtsp::acl::AccessList;
+use crate::source::bitrate::BitrateBounds;
+use crate::source::encoder::EncoderConfig;
+use crate::source::file::FileSource;
+use crate::source::params::ParameterSets;
+use crate::source::placeholder::Placeholder;
+use crate::source::rtsp_pull::RtspPullSource;
+use crate::source::Source;
+use std::collections::HashMap;
+use std::net::{Ipv4Addr, SocketAddr};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+/// What the UDP streaming loop does while no client is playing
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum IdlePolicy {
+    /// Keep reading and parsing FFmpeg output, discarding it (default)
+    #[default]
+    Drain,
+    /// Keep FFmpeg alive but stop reading; the pipe fills up and `-re` throttles
+    /// FFmpeg. On resume, stale data is skipped up to the next SPS/IDR
+    PauseReads,
+}
+
+/// What the UDP broadcast does when packetization falls behind real time
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum FrameDropPolicy {
+    /// Always send every access unit, even if latency grows (default)
+    #[default]
+    Never,
+    /// Drop non-keyframe access units until the next keyframe once the
+    /// stream keeps lagging the pacing clock
+    DropPFrames,
+}
+
+impl FrameDropPolicy {
+    /// Parse `never` / `drop-p-frames`
+    pub fn parse(value: &str) -> Result<Self, String> {
+        match value {
+            "never" => Ok(Self::Never),
+            "drop-p-frames" => Ok(Self::DropPFrames),
+            _ => Err(format!("expected never or drop-p-frames, got '{}'", value)),
+        }
+    }
+}
+
+/// Parse `--mounts`: comma-separated `name=path` entries (`cam1=a.mp4,cam2=b.mp4`)
+pub fn parse_mounts(value: &str) -> Result<Vec<(String, String)>, String> {
+    value
+        .split(',')
+        .filter(|entry| !entry.trim().is_empty())
+        .map(|entry| {
+            let (name, path) = entry
+                .split_once('=')
+                .ok_or_else(|| format!("expected name=path, got '{}'", entry.trim()))?;
+            let name = name.trim().trim_matches('/');
+            if name.is_empty() || path.trim().is_empty() {
+                return Err(format!("expected name=path, got '{}'", entry.trim()));
+            }
+            Ok((name.to_string(), path.trim().to_string()))
+        })
+        .collect()
+}
+
+/// Server-side RTP/RTCP source ports. RTP luôn chẵn và RTCP = RTP + 1
+/// (RFC 3550 §11), vì một số clients kiểm tra packets đến đúng từ
+/// `server_port` đã advertise trong SETUP
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PortPair {
+    pub rtp: u16,
+    pub rtcp: u16,
+}
+
+impl Default for PortPair {
+    fn default() -> Self {
+        Self { rtp: 6000, rtcp: 6001 }
+    }
+}
+
+impl PortPair {
+    /// Even RTP port, with RTCP on the next (odd) port
+    pub fn new(rtp: u16) -> Result<Self, String> {
+        if rtp == 0 || !rtp.is_multiple_of(2) {
+            return Err(format!("RTP port must be even and non-zero, got {}", rtp));
+        }
+        Ok(Self { rtp, rtcp: rtp + 1 })
+    }
+
+    /// Parse the RTP port (`--rtp-port`)
+    pub fn parse(value: &str) -> Result<Self, String> {
+        let rtp = value.parse().map_err(|_| format!("expected a port number, got '{}'", value))?;
+        Self::new(rtp)
+    }
+
+    /// Override the RTCP port (`--rtcp-port`) khi RTP + 1 đã bị chiếm
+    pub fn with_rtcp(self, rtcp: u16) -> Result<Self, String> {
+        if rtcp == 0 || rtcp == self.rtp {
+            return Err(format!("RTCP port must be non-zero and differ from RTP port {}, got {}", self.rtp, rtcp));
+        }
+        Ok(Self { rtcp, ..self })
+    }
+
+    /// Cặp chẵn/lẻ kế tiếp sau cả RTP lẫn RTCP port (ports mặc định của audio track)
+    pub fn next_pair(self) -> Self {
+        let rtp = self.rtp.max(self.rtcp).saturating_add(2) & !1;
+        Self { rtp, rtcp: rtp.saturating_add(1) }
+    }
+
+    /// Hai cặp dùng chung ít nhất một port
+    pub fn overlaps(self, other: Self) -> bool {
+        [self.rtp, self.rtcp].iter().any(|port| *port == other.rtp || *port == other.rtcp)
+    }
+
+    /// `server_port` transport parameter; with rtcp-mux both share the RTP port
+    pub fn transport_param(self, rtcp_mux: bool) -> String {
+        if rtcp_mux {
+            format!("server_port={}", self.rtp)
+        } else {
+            format!("server_port={}-{}", self.rtp, self.rtcp)
+        }
+    }
+}
+
+/// Group mà mọi multicast SETUP dùng chung (`--multicast-group`,
+/// `--multicast-ttl`); RTCP ở `port + 1`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MulticastConfig {
+    pub group: Ipv4Addr,
+    pub port: u16,
+    pub ttl: u8,
+}
+
+impl Default for MulticastConfig {
+    fn default() -> Self {
+        // Administratively scoped (RFC 2365), ttl đủ cho vài router trong site
+        Self { group: Ipv4Addr::new(239, 255, 42, 42), port: 5000, ttl: 16 }
+    }
+}
+
+impl MulticastConfig {
+    /// Parse `<group>:<even port>`, e.g. `239.255.0.1:5000`
+    pub fn parse_group(value: &str) -> Result<(Ipv4Addr, u16), String> {
+        let addr: SocketAddr = value.parse().map_err(|_| format!("expected <group>:<port>, got '{}'", value))?;
+        let SocketAddr::V4(addr) = addr else {
+            return Err(format!("expected an IPv4 group, got '{}'", value));
+        };
+        if !addr.ip().is_multicast() {
+            return Err(format!("{} is not a multicast address", addr.ip()));
+        }
+        if addr.port() == 0 || !addr.port().is_multiple_of(2) || addr.port() == u16::MAX {
+            return Err(format!("RTP port must be even and non-zero, got {}", addr.port()));
+        }
+        Ok((*addr.ip(), addr.port()))
+    }
+
+    /// `destination=..;port=..;ttl=..` của SETUP response
+    pub fn transport_params(self) -> String {
+        format!("destination={};port={}-{};ttl={}", self.group, self.port, self.port + 1, self.ttl)
+    }
+}
+
+/// Codec của audio track (`--audio-codec`)
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum AudioCodec {
+    /// AAC-LC, RFC 3640 (default)
+    #[default]
+    Aac,
+    /// Opus, RFC 7587 (`--features opus`)
+    #[cfg(feature = "opus")]
+    Opus,
+}
+
+impl AudioCodec {
+    /// Parse `aac` / `opus`
+    pub fn parse(value: &str) -> Result<Self, String> {
+        match value {
+            "aac" => Ok(Self::Aac),
+            #[cfg(feature = "opus")]
+            "opus" => Ok(Self::Opus),
+            #[cfg(not(feature = "opus"))]
+            "opus" => Err("opus needs a build with --features opus".to_string()),
+            _ => Err(format!("expected aac or opus, got '{}'", value)),
+        }
+    }
+}
+
+/// Format of the per-session access log line emitted when a session ends
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum AccessLogFormat {
+    /// Human-readable `key=value` line (default)
+    #[default]
+    Plain,
+    /// One JSON object per line
+    Json,
+}
+
+impl AccessLogFormat {
+    /// Parse `plain` / `json`
+    pub fn parse(value: &str) -> Result<Self, String> {
+        match value {
+            "plain" => Ok(Self::Plain),
+            "json" => Ok(Self::Json),
+            _ => Err(format!("expected plain or json, got '{}'", value)),
+        }
+    }
+}
+
+/// Server configuration, shared (read-only) giữa RTSP sessions và streaming task
+#[derive(Clone, Debug)]
+pub struct ServerConfig {
+    /// Video file FFmpeg đọc (`--source`, `SMS_SOURCE`), hoặc upstream
+    /// `rtsp://` URL để re-publish
+    pub source: String,
+    /// RTSP listen address (`--rtsp-addr`, `SMS_RTSP_ADDR`)
+    pub rtsp_addr: String,
+    /// Mount served for URLs without a path (`rtsp://host:8554/`)
+    pub default_mount: String,
+    /// Mounts khác ngoài `default_mount` (`--mounts cam1=a.mp4,cam2=rtsp://camera/stream`),
+    /// mỗi mount một file hoặc upstream URL riêng
+    pub mounts: Vec<(String, String)>,
+    /// Advertise `a=rtcp-mux` and, for clients that request it in SETUP,
+    /// send/receive RTCP on the RTP port instead of a separate socket
+    pub rtcp_mux: bool,
+    /// Advertise NACK feedback + an RFC 4588 RTX stream, and retransmit
+    /// packets UDP clients report as lost
+    pub rtx: bool,
+    /// Packets giữ lại cho retransmission (`--rtx-history`); NACK cho packet
+    /// cũ hơn bị bỏ qua
+    pub rtx_history: usize,
+    /// Behavior of the UDP streaming loop during zero-client periods
+    pub idle_policy: IdlePolicy,
+    /// Use the tolerant NALU parser, which resyncs on the first valid start
+    /// code instead of assuming strict Annex-B input
+    pub nalu_resync: bool,
+    /// DEBUG ONLY: reorder/duplicate/delay outgoing RTP per session
+    pub impairment: Option<ImpairmentConfig>,
+    /// Client IP allowlist/denylist áp dụng ngay sau accept()
+    pub access_list: AccessList,
+    /// Bind address of the read-only JSON status endpoint (disabled if None)
+    pub status_addr: Option<String>,
+    /// Known-good SPS/PPS that replace the source's, both in the SDP and in-band
+    pub parameter_sets: Option<ParameterSets>,
+    /// Restart the source pipeline if no packets are produced for this long
+    /// while clients are playing (None disables the watchdog)
+    pub stall_timeout: Option<Duration>,
+    /// Frame-drop policy of the UDP broadcast under CPU pressure
+    pub frame_drop: FrameDropPolicy,
+    /// A session with neither RTSP requests nor RTCP RRs for this long is dead
+    pub session_timeout: Duration,
+    /// H.264 encoder FFmpeg dùng (software hoặc hardware)
+    pub encoder: EncoderConfig,
+    /// Opt-in: spread each UDP client's first keyframe access unit over this
+    /// window instead of sending its fragments back-to-back
+    pub initial_burst: Option<Duration>,
+    /// Wall-clock time tương ứng npt=0 của file source, để map `Range: clock=`
+    /// về media offset. Default là lúc server khởi động (file bắt đầu phát
+    /// từ đầu khi pipeline start); recordings nên set đúng capture time
+    pub media_epoch: SystemTime,
+    /// Format of the access log summary written when each session ends
+    pub access_log: AccessLogFormat,
+    /// Playing sessions are torn down and disconnected after this long,
+    /// regardless of activity (kiosk/demo deployments; None = unlimited)
+    pub max_session_duration: Option<Duration>,
+    /// TCP_NODELAY trên accepted RTSP sockets (interleaved frames nhỏ đi ngay,
+    /// không chờ Nagle)
+    pub tcp_nodelay: bool,
+    /// SO_SNDBUF (bytes) cho RTSP sockets; None giữ OS default
+    pub tcp_send_buffer: Option<u32>,
+    /// Báo lỗi nếu source chưa gửi SPS/PPS sau khoảng này kể từ lúc stream
+    /// bắt đầu; với `--sprop` thì dùng override thay thế (None tắt check)
+    pub parameter_set_timeout: Option<Duration>,
+    /// Refuse DESCRIBE with 503 until the stream has carried SPS and PPS
+    /// (ignored when `parameter_sets` overrides them)
+    pub require_parameter_sets: bool,
+    /// Stream phát khi source file thiếu hoặc FFmpeg lỗi; UDP path tự chuyển
+    /// về source khi file có lại (None: không stream gì)
+    pub placeholder: Option<Placeholder>,
+    /// Gửi filler NAL cho UDP clients khi stream im lặng lâu hơn khoảng này,
+    /// ngắn hơn NAT UDP timeout thông thường (None tắt)
+    pub keepalive_interval: Option<Duration>,
+    /// Bỏ UDP session sau chừng này RTCP RR liên tiếp báo mất toàn bộ packets
+    /// (fraction lost 255/256): client không còn nhận được gì (None tắt)
+    pub loss_evict_reports: Option<u32>,
+    /// Bitrate adaptation của shared UDP stream theo RTCP loss
+    /// (`--bitrate-adapt min:max:step`, kbps, opt-in); None: encoder tự chọn
+    /// bitrate. TCP-interleaved viewers không bị ảnh hưởng (xem
+    /// `BitrateController`)
+    pub bitrate_adapt: Option<BitrateBounds>,
+    /// Source ports của shared UDP stream (`--rtp-port`, RTCP = RTP + 1 trừ
+    /// khi có `--rtcp-port`)
+    pub server_ports: PortPair,
+    /// Range các port pairs lease cho UDP unicast sessions, mỗi session gửi
+    /// từ sockets riêng trên pair của nó (`--session-ports first-last`);
+    /// None: mọi session dùng `server_ports`, không giới hạn
+    pub session_ports: Option<(u16, u16)>,
+    /// Multicast group cho SETUP `multicast` (chỉ default mount)
+    pub multicast: MulticastConfig,
+    /// Audio track (`track2`) của default mount qua UDP, với source ports
+    /// riêng (`--audio`, `--audio-port`); None tắt audio (default)
+    pub audio: Option<PortPair>,
+    /// Codec của audio track (`--audio-codec aac|opus`)
+    pub audio_codec: AudioCodec,
+    /// Max FU-A fragments per NALU và cách xử lý NALU vượt quá (None: không giới hạn)
+    pub fragment_limit: Option<FragmentLimit>,
+    /// Time source cho RTCP SR và pacing (tests tua nhanh bằng `ManualClock`)
+    pub clock: Arc<dyn Clock>,
+}
+
+impl ServerConfig {
+    /// Mount → file registry: `default_mount` phát `source`, cộng `mounts`
+    pub fn mount_table(&self) -> HashMap<String, String> {
+        let mut table: HashMap<String, String> = self.mounts.iter().cloned().collect();
+        table.insert(self.default_mount.clone(), self.source.clone());
+        table
+    }
+
+    /// Source cho một location của mount table: `rtsp://` / `rtsps://` pull
+    /// upstream feed, còn lại là file (bắt đầu tại `seek`)
+    pub fn source_for(&self, location: &str, seek: Option<f64>) -> Box<dyn Source> {
+        if RtspPullSource::is_url(location) {
+            Box::new(RtspPullSource::new(location.to_string(), self.encoder.clone()))
+        } else {
+            Box::new(FileSource::new(location.to_string(), self.encoder.clone()).with_seek(seek))
+        }
+    }
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            source: "./videos/example.mp4".to_string(),
+            rtsp_addr: "0.0.0.0:8554".to_string(),
+            default_mount: "cam".to_string(),
+            mounts: Vec::new(),
+            rtcp_mux: false,
+            rtx: false,
+            rtx_history: DEFAULT_HISTORY_PACKETS,
+            idle_policy: IdlePolicy::default(),
+            nalu_resync: false,
+            impairment: None,
+            access_list: AccessList::default(),
+            status_addr: None,
+            parameter_sets: None,
+            stall_timeout: Some(Duration::from_secs(10)),
+            frame_drop: FrameDropPolicy::default(),
+            session_timeout: Duration::from_secs(60),
+            encoder: EncoderConfig::default(),
+            initial_burst: None,
+            media_epoch: SystemTime::now(),
+            access_log: AccessLogFormat::default(),
+            max_session_duration: None,
+            tcp_nodelay: true,
+            tcp_send_buffer: None,
+            parameter_set_timeout: Some(Duration::from_secs(5)),
+            require_parameter_sets: false,
+            placeholder: Some(Placeholder::Text("No Signal".to_string())),
+            keepalive_interval: Some(Duration::from_secs(15)),
+            loss_evict_reports: Some(3),
+            bitrate_adapt: None,
+            server_ports: PortPair::default(),
+            session_ports: None,
+            multicast: MulticastConfig::default(),
+            audio: None,
+            audio_codec: AudioCodec::Aac,
+            fragment_limit: None,
+            clock: Arc::new(SystemClock),
+        }
+    }
+}
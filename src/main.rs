@@ -1,30 +1,124 @@
+mod client;
+mod clock;
+mod config;
 mod source;
 mod rtsp;
 mod rtp;
 mod rtcp;
+mod selftest;
+mod status;
 
 use std::env;
+use config::{AccessLogFormat, AudioCodec, FrameDropPolicy, IdlePolicy, MulticastConfig, PortPair, ServerConfig};
+use rtsp::acl::AccessList;
+use rtsp::range::parse_clock_time;
+use rtsp::sdp::{AUDIO_TRACK, VIDEO_TRACK};
 use rtsp::server::RtspServer;
-use rtsp::state::{SharedState, create_shared_state};
-use rtp::h264::H264Packetizer;
+use rtsp::state::{EndReason, SharedState, create_shared_state};
+use rtp::aac::AacPacketizer;
+#[cfg(feature = "opus")]
+use rtp::opus::OpusPacketizer;
+use rtp::packet::RtpPacket;
+use rtp::h264::{FragmentLimit, H264Packetizer, OversizePolicy};
+use rtp::ports::PortAllocator;
+use rtcp::bye::Goodbye;
+use rtcp::liveness::{SR_INTERVAL, SR_MIN_INTERVAL};
+use rtcp::nack::GenericNack;
+use rtcp::rr::ReceiverReport;
 use rtcp::sr::SenderReport;
-use source::file::{FileSource, NaluParser};
+use rtp::framedrop::FrameDropper;
+use rtp::impair::ImpairmentConfig;
+use rtp::udp::UdpSender;
+use status::StatusServer;
+use source::adts::AdtsParser;
+#[cfg(feature = "opus")]
+use source::ogg::{is_opus_header, OggPacketReader};
+use source::encoder::{Encoder, EncoderConfig};
+use source::file::{AccessUnitSplitter, FileSource, NaluParser};
+use source::rtsp_pull::RtspPullSource;
+use source::Source;
+use source::params::{ParameterSetMonitor, ParameterSets};
+use source::placeholder::{Placeholder, PRIMARY_RETRY_INTERVAL};
+use source::bitrate::{BitrateBounds, BitrateController, BitrateTarget, ADAPT_INTERVAL};
+use source::restart::RestartBackoff;
+use source::watchdog::Watchdog;
 use tokio::net::UdpSocket;
+use std::collections::HashMap;
+use std::net::SocketAddr;
 use std::sync::Arc;
 use tokio::sync::Mutex;
-use std::time::Duration;
+use std::time::{Duration, SystemTime};
 use std::io::Read;
+use std::process::{Child, ChildStdout};
 
 #[tokio::main]
 async fn main() {
     println!("🚀 Simulation Media Server Starting...");
     println!("=====================================");
     
-    // Create shared state
-    let state = create_shared_state();
+    let settings = match Settings::load() {
+        Ok(settings) => settings,
+        Err(e) => {
+            eprintln!("❌ {}", e);
+            std::process::exit(1);
+        }
+    };
+
+    // --self-test <file.h264>: round-trip packetizer/depacketizer rồi thoát
+    if let Some(path) = settings.value("self-test") {
+        std::process::exit(selftest::run(&path));
+    }
+
+    // --client <url> [--client-duration <secs>]: pull một RTSP URL qua TCP interleaved, report rồi thoát
+    if let Some(url) = settings.value("client") {
+        let secs = |value: &str| value.parse::<u64>().map(Duration::from_secs);
+        let duration = match settings.parse("client-duration", secs) {
+            Ok(duration) => duration.unwrap_or(client::DEFAULT_DURATION),
+            Err(e) => {
+                eprintln!("❌ {}", e);
+                std::process::exit(1);
+            }
+        };
+        std::process::exit(client::run(&url, duration).await);
+    }
+
+    let config = match resolve_config(&settings) {
+        Ok(config) => Arc::new(config),
+        Err(e) => {
+            eprintln!("❌ {}", e);
+            std::process::exit(1);
+        }
+    };
+
+    // Create shared state, seeded with the mount registry
+    let state = create_shared_state(config.mount_table());
+    for (mount, path) in &config.mounts {
+        println!("🗂️  Mount /{} → {}", mount, path);
+    }
+    println!("⚙️  Effective configuration: {:#?}", config);
+    if config.rtcp_mux {
+        println!("🔀 rtcp-mux enabled");
+    }
+    println!("💤 Idle policy: {:?}", config.idle_policy);
+    println!("🐢 Frame-drop policy: {:?}", config.frame_drop);
+    println!("🎞️  Encoder: {} @ {}fps", config.encoder.encoder.name(), config.encoder.fps);
+    if let Some(window) = config.initial_burst {
+        println!("🐇 Initial keyframe burst window: {:?}", window);
+    }
+    if let Some(sets) = &config.parameter_sets {
+        println!("🧬 SPS/PPS override: {:?}", source::params::sps_resolution(&sets.sps));
+    }
+    if let Some(impairment) = &config.impairment {
+        println!("🧪🧪🧪 =====================================");
+        println!("🧪 WARNING: RTP IMPAIRMENT SIMULATION ENABLED");
+        println!("🧪 Outgoing packets will be reordered/duplicated/delayed:");
+        println!("🧪 {:?}", impairment);
+        println!("🧪 Never run this in production!");
+        println!("🧪🧪🧪 =====================================");
+    }
 
     // Start RTSP server
-    let rtsp_server = RtspServer::new("0.0.0.0:8554".to_string(), state.clone());
+    let rtsp_server = RtspServer::new(config.rtsp_addr.clone(), state.clone(), config.clone());
 
     let rtsp_handle = tokio::spawn(async move {
         if let Err(e) = rtsp_server.run().await {
@@ -34,8 +128,42 @@ async fn main() {
 
     println!("Application run on: {} ", env::current_dir().unwrap().display());
 
+    // Start status endpoint (optional)
+    if let Some(status_addr) = config.status_addr.clone() {
+        let status_server = StatusServer::new(status_addr, state.clone(), config.clone());
+        tokio::spawn(async move {
+            if let Err(e) = status_server.run().await {
+                eprintln!("❌ Status endpoint error: {}", e);
+            }
+        });
+    }
+
+    // Max session duration sweep (UDP và TCP sessions), độc lập với streaming task
+    if let Some(max) = config.max_session_duration {
+        println!("⌛ Max session duration: {:?}", max);
+        let state = state.clone();
+        tokio::spawn(async move {
+            loop {
+                tokio::time::sleep(Duration::from_secs(1)).await;
+                state.write().await.expire_sessions(std::time::Instant::now(), max);
+            }
+        });
+    }
+
+    // AAC audio track (track2) của default mount, pipeline riêng với video
+    if let Some(ports) = config.audio {
+        let state = state.clone();
+        let config = config.clone();
+        tokio::spawn(async move {
+            if let Err(e) = start_audio_streaming(state, config, ports).await {
+                eprintln!("❌ Audio streaming error: {}", e);
+            }
+        });
+    }
+
     // Start RTP/RTCP streaming task
     let streaming_state = state.clone();
+    let streaming_config = config.clone();
     let streaming_handle = tokio::spawn(async move {
         // Đợi một chút để RTSP server khởi động
         tokio::time::sleep(Duration::from_secs(2)).await;
@@ -44,7 +172,7 @@ async fn main() {
         println!("=====================================");
         
         // Khởi động video source
-        if let Err(e) = start_video_streaming(streaming_state).await {
+        if let Err(e) = start_video_streaming(streaming_state, streaming_config).await {
             eprintln!("❌ Video streaming error: {}", e);
         }
     });
@@ -53,14 +181,233 @@ async fn main() {
     let _ = tokio::join!(rtsp_handle, streaming_handle);
 }
 
-/// Start video streaming từ MP4 file
-async fn start_video_streaming(state: SharedState) -> std::io::Result<()> {
-    let video_path = "./videos/example.mp4";
+/// Layered settings lookup: defaults < env (`SMS_<KEY>`) < config file
+/// (`--config <path>`, `key = value` lines) < CLI (`--<key> [value]`)
+struct Settings {
+    args: Vec<String>,
+    file: HashMap<String, String>,
+    /// Environment variables (`SMS_*` keys được dùng)
+    env: HashMap<String, String>,
+}
+
+impl Settings {
+    /// Settings của process: `env::args`, `env::vars` và config file của `--config`
+    fn load() -> Result<Self, String> {
+        Self::from_sources(env::args().collect(), env::vars().collect(), |path| std::fs::read_to_string(path))
+    }
+
+    /// Settings từ inputs tường minh; `read_file` đọc file của `--config`
+    fn from_sources(
+        args: Vec<String>,
+        env: HashMap<String, String>,
+        read_file: impl Fn(&str) -> std::io::Result<String>,
+    ) -> Result<Self, String> {
+        let mut settings = Self { args, file: HashMap::new(), env };
+
+        if let Some(path) = settings.value("config") {
+            let text = read_file(&path).map_err(|e| format!("Cannot read config file {}: {}", path, e))?;
+            for (n, line) in text.lines().enumerate() {
+                let line = line.trim();
+                if line.is_empty() || line.starts_with('#') {
+                    continue;
+                }
+                let (key, value) = line
+                    .split_once('=')
+                    .ok_or_else(|| format!("{}:{}: expected key = value", path, n + 1))?;
+                settings.file.insert(key.trim().to_string(), value.trim().to_string());
+            }
+            println!("📄 Loaded config file {}", path);
+        }
+        Ok(settings)
+    }
+
+    /// Value of `--key v` / `key = v` / `SMS_KEY=v`, highest precedence first
+    fn value(&self, key: &str) -> Option<String> {
+        let flag = format!("--{}", key);
+        let mut args = self.args.iter().skip_while(|arg| **arg != flag);
+        if args.next().is_some() {
+            return args.next().cloned();
+        }
+        if let Some(value) = self.file.get(key) {
+            return Some(value.clone());
+        }
+        self.env.get(&format!("SMS_{}", key.to_uppercase().replace('-', "_"))).cloned()
+    }
+
+    /// Boolean switch: `--key` on the CLI, or `true`/`1` in the file or env
+    fn flag(&self, key: &str) -> bool {
+        let flag = format!("--{}", key);
+        self.args.contains(&flag) || self.value(key).is_some_and(|v| matches!(v.as_str(), "true" | "1" | "yes"))
+    }
+
+    /// Parse a value with `parse`, naming the setting in the error
+    fn parse<T, E: std::fmt::Display>(&self, key: &str, parse: impl Fn(&str) -> Result<T, E>) -> Result<Option<T>, String> {
+        self.value(key)
+            .map(|value| parse(&value).map_err(|e| format!("Invalid --{}: {}", key, e)))
+            .transpose()
+    }
+}
+
+/// Build the effective ServerConfig from defaults, env, config file and CLI
+fn resolve_config(settings: &Settings) -> Result<ServerConfig, String> {
+    let defaults = ServerConfig::default();
+
+    let access_list = AccessList {
+        allow: settings.parse("allow", AccessList::parse_list)?.unwrap_or_default(),
+        deny: settings.parse("deny", AccessList::parse_list)?.unwrap_or_default(),
+    };
+    let secs = |value: &str| value.parse::<u64>().map(Duration::from_secs);
+    let server_ports = {
+        let ports = settings.parse("rtp-port", PortPair::parse)?.unwrap_or(defaults.server_ports);
+        match settings.parse("rtcp-port", |v| v.parse::<u16>())? {
+            Some(rtcp) => ports.with_rtcp(rtcp).map_err(|e| format!("Invalid --rtcp-port: {}", e))?,
+            None => ports,
+        }
+    };
+    let bitrate_adapt = settings.parse("bitrate-adapt", BitrateBounds::parse)?;
+    let source = settings.value("source").unwrap_or(defaults.source);
+
+    // --audio-port bật audio luôn; --audio dùng cặp ports sau video
+    let audio = match settings.parse("audio-port", PortPair::parse)? {
+        Some(ports) if ports.overlaps(server_ports) => {
+            return Err(format!("Invalid --audio-port: {}-{} overlaps the video ports {}-{}",
+                               ports.rtp, ports.rtcp, server_ports.rtp, server_ports.rtcp));
+        }
+        Some(ports) => Some(ports),
+        None if settings.flag("audio") => Some(server_ports.next_pair()),
+        None => defaults.audio,
+    };
+    // Audio pipeline chỉ đọc file: upstream RTSP feed chỉ re-publish video,
+    // nên không quảng bá track2 trong SDP và không nhận SETUP cho nó
+    let audio = match audio {
+        Some(_) if RtspPullSource::is_url(&source) => {
+            println!("🔇 Audio track disabled: {} is an RTSP feed (audio is only read from files)", source);
+            None
+        }
+        audio => audio,
+    };
+
+    Ok(ServerConfig {
+        source,
+        rtsp_addr: settings.value("rtsp-addr").unwrap_or(defaults.rtsp_addr),
+        default_mount: settings.value("default-mount").unwrap_or(defaults.default_mount),
+        mounts: settings.parse("mounts", config::parse_mounts)?.unwrap_or(defaults.mounts),
+        rtcp_mux: settings.flag("rtcp-mux"),
+        idle_policy: if settings.flag("idle-pause") {
+            IdlePolicy::PauseReads
+        } else {
+            defaults.idle_policy
+        },
+        rtx: settings.flag("rtx"),
+        rtx_history: settings
+            .parse("rtx-history", |v| v.parse::<usize>())?
+            .filter(|packets| *packets > 0)
+            .unwrap_or(defaults.rtx_history),
+        nalu_resync: settings.flag("nalu-resync"),
+        impairment: settings.parse("impair", ImpairmentConfig::parse)?,
+        access_list,
+        status_addr: settings.value("status"),
+        parameter_sets: settings.parse("sprop", ParameterSets::parse)?,
+        // --stall-timeout 0 tắt watchdog
+        stall_timeout: match settings.parse("stall-timeout", secs)? {
+            Some(Duration::ZERO) => None,
+            Some(timeout) => Some(timeout),
+            None => defaults.stall_timeout,
+        },
+        frame_drop: settings.parse("frame-drop", FrameDropPolicy::parse)?.unwrap_or(defaults.frame_drop),
+        session_timeout: settings.parse("session-timeout", secs)?.unwrap_or(defaults.session_timeout),
+        // Probe `ffmpeg -encoders` một lần lúc khởi động
+        encoder: EncoderConfig {
+            encoder: settings.parse("encoder", Encoder::parse)?.unwrap_or(defaults.encoder.encoder),
+            vaapi_device: settings.value("vaapi-device").unwrap_or(defaults.encoder.vaapi_device),
+            fps: settings.parse("fps", EncoderConfig::parse_fps)?.unwrap_or(defaults.encoder.fps),
+            // Adaptation bắt đầu ở max
+            bitrate: bitrate_adapt.map(|bounds| bounds.max),
+        }
+        .resolve(),
+        initial_burst: settings
+            .parse("initial-burst-ms", |ms| ms.parse::<u64>())?
+            .filter(|ms| *ms > 0)
+            .map(Duration::from_millis),
+        media_epoch: settings
+            .parse("media-epoch", |v| parse_clock_time(v).ok_or("expected YYYYMMDDThhmmssZ"))?
+            .unwrap_or(defaults.media_epoch),
+        access_log: settings.parse("access-log", AccessLogFormat::parse)?.unwrap_or(defaults.access_log),
+        max_session_duration: settings
+            .parse("max-session-duration", secs)?
+            .filter(|d| !d.is_zero())
+            .or(defaults.max_session_duration),
+        tcp_nodelay: defaults.tcp_nodelay && !settings.flag("no-tcp-nodelay"),
+        tcp_send_buffer: settings
+            .parse("tcp-send-buffer", |v| v.parse::<u32>())?
+            .filter(|bytes| *bytes > 0)
+            .or(defaults.tcp_send_buffer),
+        // --parameter-set-timeout 0 tắt check
+        parameter_set_timeout: match settings.parse("parameter-set-timeout", secs)? {
+            Some(Duration::ZERO) => None,
+            Some(timeout) => Some(timeout),
+            None => defaults.parameter_set_timeout,
+        },
+        require_parameter_sets: settings.flag("require-parameter-sets"),
+        placeholder: settings.parse("placeholder", Placeholder::parse)?.unwrap_or(defaults.placeholder),
+        // --keepalive-interval 0 tắt keepalive
+        keepalive_interval: match settings.parse("keepalive-interval", secs)? {
+            Some(Duration::ZERO) => None,
+            Some(interval) => Some(interval),
+            None => defaults.keepalive_interval,
+        },
+        // --loss-evict-reports 0 tắt eviction
+        loss_evict_reports: match settings.parse("loss-evict-reports", |v| v.parse::<u32>())? {
+            Some(0) => None,
+            Some(reports) => Some(reports),
+            None => defaults.loss_evict_reports,
+        },
+        bitrate_adapt,
+        server_ports,
+        session_ports: settings.parse("session-ports", PortAllocator::parse_range)?,
+        multicast: {
+            let (group, port) = settings
+                .parse("multicast-group", MulticastConfig::parse_group)?
+                .unwrap_or((defaults.multicast.group, defaults.multicast.port));
+            let ttl = settings.parse("multicast-ttl", |v| v.parse::<u8>())?.unwrap_or(defaults.multicast.ttl);
+            MulticastConfig { group, port, ttl }
+        },
+        audio,
+        audio_codec: settings.parse("audio-codec", AudioCodec::parse)?.unwrap_or(defaults.audio_codec),
+        fragment_limit: match settings.parse("max-fragments", |v| v.parse::<usize>())?.filter(|max| *max > 0) {
+            Some(max) => Some(FragmentLimit {
+                max,
+                policy: settings.parse("oversize-policy", OversizePolicy::parse)?.unwrap_or_default(),
+            }),
+            None => defaults.fragment_limit,
+        },
+        clock: defaults.clock.clone(),
+    })
+}
+
+/// Start video streaming từ MP4 file hoặc upstream RTSP feed
+async fn start_video_streaming(state: SharedState, config: Arc<ServerConfig>) -> std::io::Result<()> {
+    // Shared UDP pipeline phát default mount của registry
+    let video_path = state
+        .read()
+        .await
+        .mounts
+        .get(&config.default_mount)
+        .cloned()
+        .unwrap_or_else(|| config.source.clone());
+    let video_path = video_path.as_str();
 
     println!("Debug: requested video_path = {:?}", video_path);
 
+    let mut primary = config.source_for(video_path, None);
+    let placeholder = config
+        .placeholder
+        .clone()
+        .map(|placeholder| FileSource::placeholder(placeholder, config.encoder.clone()));
+    let mut use_placeholder = false;
+
     // Check if file exists
-    if !std::path::Path::new(video_path).exists() {
+    if !primary.is_available() {
         eprintln!("⚠️  Video file not found: {}", video_path);
         // Extra debug: show pointer to Path, and list contents of `videos/` folder if present
         let p = std::path::Path::new(video_path);
@@ -75,39 +422,38 @@ async fn start_video_streaming(state: SharedState) -> std::io::Result<()> {
             Err(e) => println!("Debug: cannot read 'videos/' dir: {}", e),
         }
 
-        println!("   Server will run but no video stream available");
-        println!("✅ Ready to accept RTSP connections");
-        println!("   URL: rtsp://127.0.0.1:8554/cam");
+        if placeholder.is_none() {
+            println!("   Server will run but no video stream available");
+            println!("✅ Ready to accept RTSP connections");
+            println!("   URL: rtsp://127.0.0.1:8554/cam");
 
-        // Keep task alive
-        loop {
-            tokio::time::sleep(Duration::from_secs(60)).await;
+            // Keep task alive
+            loop {
+                tokio::time::sleep(Duration::from_secs(60)).await;
+            }
         }
+        use_placeholder = true;
+    } else {
+        println!("📁 Video source: {}", primary.describe());
     }
 
-    println!("📁 Video source: {}", video_path);
-
-    // Create video source
-    let source = FileSource::new(video_path.to_string());
-
     // Start FFmpeg process
-    let mut child = source.start_ffmpeg()?;
-    println!("Debug: FileSource addr = {:p}", &source);
+    let (child, stdout, on_placeholder) = spawn_source(primary.as_ref(), placeholder.as_ref().map(|p| p as &dyn Source), use_placeholder)?;
+    let mut on_placeholder = on_placeholder;
+    let mut last_primary_retry = std::time::Instant::now();
 
-    // Đọc stderr trong background thread để không block
-    if let Some(mut stderr) = child.stderr.take() {
-        tokio::spawn(async move {
-            let mut buf = String::new();
-            if stderr.read_to_string(&mut buf).is_ok() && !buf.is_empty() {
-                println!("Debug: FFmpeg stderr output:\n{}", buf);
-            }
-        });
+    let duration = if on_placeholder { None } else { primary.probe_duration() };
+    match duration {
+        Some(d) => println!("⏱️  Media duration: {:.3}s", d),
+        None if on_placeholder => {}
+        None => println!("⚠️  No media duration (live source, or ffprobe missing?)"),
     }
+    state.write().await.media_duration = duration;
 
-    let stdout = child.stdout.take().ok_or_else(|| {
-        std::io::Error::new(std::io::ErrorKind::Other, "Failed to capture FFmpeg stdout")
-    })?;
+    println!("Debug: Source addr = {:p}", &primary);
     println!("Debug: Child process addr = {:p}", &child);
+    // Watchdog cần kill được FFmpeg từ task khác
+    let child = Arc::new(std::sync::Mutex::new(child));
     println!("Debug: FFmpeg stdout (ChildStdout) addr = {:p}", &stdout as *const _);
 
     println!("✅ FFmpeg started");
@@ -118,48 +464,260 @@ async fn start_video_streaming(state: SharedState) -> std::io::Result<()> {
     println!("\n🎬 Streaming...");
 
     // Setup UDP sockets cho RTP/RTCP
-    let rtp_socket = Arc::new(UdpSocket::bind("0.0.0.0:6000").await?);
+    let ports = config.server_ports;
+    let rtp_socket = Arc::new(UdpSocket::bind(("0.0.0.0", ports.rtp)).await?);
     println!("RTP socket address: {:p}", Arc::as_ptr(&rtp_socket));
-    let rtcp_socket = Arc::new(UdpSocket::bind("0.0.0.0:6001").await?);
+    let rtcp_socket = Arc::new(UdpSocket::bind(("0.0.0.0", ports.rtcp)).await?);
     println!("RTCP socket address: {:p}", Arc::as_ptr(&rtcp_socket));
 
-    println!("📡 RTP socket: 0.0.0.0:6000");
-    println!("📡 RTCP socket: 0.0.0.0:6001");
+    // Clients đối chiếu source port với server_port trong SETUP response
+    let bound = (rtp_socket.local_addr()?.port(), rtcp_socket.local_addr()?.port());
+    if bound != (ports.rtp, ports.rtcp) {
+        return Err(std::io::Error::other(format!("RTP/RTCP sockets bound to unpaired ports {:?}", bound)));
+    }
+
+    // Multicast members (SETUP `multicast`) nhận RTP, SR và BYE từ cùng sockets
+    rtp_socket.set_multicast_ttl_v4(config.multicast.ttl as u32)?;
+    rtcp_socket.set_multicast_ttl_v4(config.multicast.ttl as u32)?;
+
+    println!("📡 RTP socket: 0.0.0.0:{}", ports.rtp);
+    println!("📡 RTCP socket: 0.0.0.0:{}", ports.rtcp);
 
     // RTP Packetizer
-    let packetizer = Arc::new(Mutex::new(H264Packetizer::new(0x12345678)));
+    let mut packetizer = H264Packetizer::new();
+    packetizer.set_frame_ticks(config.encoder.frame_ticks());
+    let packetizer = Arc::new(Mutex::new(packetizer));
+    packetizer.lock().await.set_fragment_limit(config.fragment_limit);
+    // RTP-Info trước access unit đầu tiên: vị trí bắt đầu (random) của stream
+    let start = packetizer.lock().await.snapshot();
+    state.write().await.track_positions.insert(VIDEO_TRACK.to_string(), (start.sequence, start.timestamp));
     println!("Packetizer address: {:p}", Arc::as_ptr(&packetizer));
 
-    // RTCP Sender Report
-    let sender_report = Arc::new(Mutex::new(SenderReport::new(0x12345678)));
-    println!("Sender report address: {:p}", Arc::as_ptr(&sender_report));
+    // Mỗi UDP client có SSRC/sequence/timestamp + SR riêng; RTX (RFC 4588)
+    // cho clients báo NACK nếu bật
+    let udp_sender = Arc::new(UdpSender::new(
+        rtp_socket.clone(),
+        config.rtx.then_some(config.rtx_history),
+        config.impairment.clone(),
+        config.initial_burst,
+    ));
+
+    // Nhận RTCP feedback từ clients. With rtcp-mux, client RTCP arrives on
+    // the RTP port and is demuxed by packet type
+    let mut rtcp_receivers = vec![(rtcp_socket.clone(), false)];
+    if config.rtcp_mux {
+        rtcp_receivers.push((rtp_socket.clone(), true));
+    }
+    for (socket, muxed) in rtcp_receivers {
+        let udp_sender = udp_sender.clone();
+        let state = state.clone();
+        let clock = config.clock.clone();
+        let loss_evict_reports = config.loss_evict_reports;
+        tokio::spawn(async move {
+            let mut buf = [0u8; 1500];
+            loop {
+                match socket.recv_from(&mut buf).await {
+                    Ok((n, from)) => {
+                        if muxed {
+                            if !rtcp::is_rtcp_packet(&buf[..n]) {
+                                continue;
+                            }
+                            println!("📥 RTCP (muxed) from {} - PT: {}, {} bytes", from, buf[1], n);
+                        }
+                        handle_rtcp_feedback(&buf[..n], from, &udp_sender, &state, clock.wall(), loss_evict_reports).await;
+                    }
+                    Err(e) => eprintln!("⚠️  RTCP socket recv error: {}", e),
+                }
+            }
+        });
+    }
+
+    // Per-session ports: RTCP nhận trên sockets của từng session đi qua
+    // cùng feedback handler
+    if let Some((first, last)) = config.session_ports {
+        let (feedback, mut reports) = tokio::sync::mpsc::unbounded_channel();
+        let allocator = PortAllocator::new(first, last, Some(feedback));
+        println!("🎟️  Session ports {}-{}: {} UDP pairs", first, last, allocator.available());
+        state.write().await.port_allocator = Some(allocator);
+
+        let udp_sender = udp_sender.clone();
+        let state = state.clone();
+        let clock = config.clock.clone();
+        let loss_evict_reports = config.loss_evict_reports;
+        tokio::spawn(async move {
+            while let Some((data, from)) = reports.recv().await {
+                handle_rtcp_feedback(&data, from, &udp_sender, &state, clock.wall(), loss_evict_reports).await;
+            }
+        });
+    }
 
-    // Spawn RTCP sender (gửi SR mỗi 5 giây)
+    // Spawn RTCP sender: SR mỗi 5 giây, nhanh dần (tới 1 giây) cho clients
+    // ngừng gửi RR hoặc có RTT tăng; UDP clients im lặng quá session timeout
+    // (--session-timeout, mặc định 60s) bị reap để ngừng unicast tới địa chỉ chết
+    let rtp_socket_clone = rtp_socket.clone();
     let rtcp_socket_clone = rtcp_socket.clone();
-    let sender_report_clone = sender_report.clone();
+    let udp_sender_clone = udp_sender.clone();
     let state_clone = state.clone();
+    let sr_packetizer = packetizer.clone();
+    let session_timeout = config.session_timeout;
+    let clock = config.clock.clone();
     tokio::spawn(async move {
+        let mut last_stats = clock.now();
         loop {
-            tokio::time::sleep(Duration::from_secs(5)).await;
+            tokio::time::sleep(SR_MIN_INTERVAL / 4).await;
 
-            let sr = sender_report_clone.lock().await;
-            let sr_packet = sr.to_bytes();
+            let now = clock.now();
+            let evicted = udp_sender_clone.take_evicted().await;
+            let queues = udp_sender_clone.queue_stats().await;
+            let (byes, rtcp_targets) = {
+                let mut st = state_clone.write().await;
+                for rtp_addr in evicted {
+                    st.remove_udp_client(rtp_addr);
+                }
+                st.record_udp_output(&queues);
+                for id in st.reap_stale(now, session_timeout) {
+                    println!("💀 Session {} timed out (no RTSP keepalive or RTCP RR for {:?})", id, session_timeout);
+                }
+                let byes = std::mem::take(&mut st.pending_byes);
+                (byes, st.take_sr_targets(now))
+            };
+
+            for (rtcp_addr, rtcp_mux, ssrc, reason) in byes {
+                let socket = if rtcp_mux { &rtp_socket_clone } else { &rtcp_socket_clone };
+                let bye = Goodbye::new(ssrc, Some(reason.as_str()));
+                match socket.send_to(&bye.to_bytes(), rtcp_addr).await {
+                    Ok(_) => println!("👋 RTCP BYE sent to {} - SSRC: {:08x} ({})", rtcp_addr, ssrc, reason.as_str()),
+                    Err(e) => eprintln!("⚠️  RTCP BYE send error to {}: {}", rtcp_addr, e),
+                }
+            }
+            if rtcp_targets.is_empty() && now - last_stats < SR_INTERVAL {
+                continue;
+            }
+            // Timestamp của access unit đang phát, đọc ngay trước khi lấy
+            // wall clock cho NTP timestamp của các SRs
+            let shared_timestamp = sr_packetizer.lock().await.timestamp();
+            let reports = udp_sender_clone.sender_reports(shared_timestamp).await;
 
-            // Gửi đến tất cả UDP playing clients
-            let udp_clients = state_clone.read().await.get_udp_clients();
-            for (_rtp_addr, rtcp_addr) in udp_clients {
-                if let Err(e) = rtcp_socket_clone.send_to(&sr_packet, rtcp_addr).await {
+            // Gửi SR riêng (SSRC + counters của client) đến từng UDP playing
+            // client (muxed clients nhận SR từ RTP socket, sessions có ports
+            // riêng từ sockets của chúng)
+            for (rtp_addr, rtcp_addr, rtcp_mux, session_socket) in rtcp_targets {
+                let Some(sr) = reports.get(&rtp_addr) else {
+                    continue;
+                };
+                let socket = match &session_socket {
+                    Some(socket) => socket,
+                    None if rtcp_mux => &rtp_socket_clone,
+                    None => &rtcp_socket_clone,
+                };
+                if let Err(e) = socket.send_to(&sr.to_bytes(clock.wall()), rtcp_addr).await {
                     eprintln!("⚠️  RTCP send error to {}: {}", rtcp_addr, e);
                 } else {
-                    println!("📊 RTCP SR sent to {} - packets: {}, bytes: {}",
-                             rtcp_addr, sr.packet_count, sr.octet_count);
+                    let (packets, octets) = sr.counts();
+                    println!("📊 RTCP SR sent to {} - SSRC: {:08x}, packets: {}, bytes: {}",
+                             rtcp_addr, sr.ssrc, packets, octets);
                 }
             }
+
+            if now - last_stats < SR_INTERVAL {
+                continue;
+            }
+            last_stats = now;
+
+            for queue in queues.iter().filter(|q| q.depth > 0 || q.dropped > 0) {
+                println!("📦 UDP queue {}: depth {}, dropped {}", queue.rtp_addr, queue.depth, queue.dropped);
+            }
+
+            let (stamping_time, stamped) = udp_sender_clone.take_stamping_stats().await;
+            if stamped > 0 {
+                println!("⏱️  Per-client stamping: {} packets in {:?} ({:?}/packet)",
+                         stamped, stamping_time, stamping_time / stamped as u32);
+            }
         }
     });
 
+    // Watchdog: restart pipeline nếu không có packet nào trong khi clients đang play
+    let watchdog = Arc::new(Watchdog::new(config.stall_timeout.unwrap_or(Duration::MAX)));
+    if let Some(timeout) = config.stall_timeout {
+        let watchdog = watchdog.clone();
+        let child = child.clone();
+        let state = state.clone();
+        tokio::spawn(async move {
+            loop {
+                tokio::time::sleep((timeout / 4).max(Duration::from_millis(250))).await;
+
+                let clients_playing = !state.read().await.get_udp_clients().is_empty();
+                if watchdog.check(clients_playing) {
+                    let mut st = state.write().await;
+                    st.stalls += 1;
+                    eprintln!("🐕 Source stalled: no packets for {:?} while clients are playing (stall #{}), restarting FFmpeg",
+                              timeout, st.stalls);
+                    drop(st);
+                    let _ = child.lock().unwrap().kill();
+                }
+            }
+        });
+    }
+
+    // Bitrate adaptation: mỗi ADAPT_INTERVAL đọc loss từ RRs mới của UDP
+    // clients; đổi target thì kill FFmpeg để loop respawn ở bitrate mới
+    let bitrate_target = Arc::new(BitrateTarget::new());
+    if let Some(bounds) = config.bitrate_adapt {
+        let bitrate_target = bitrate_target.clone();
+        let child = child.clone();
+        let state = state.clone();
+        tokio::spawn(async move {
+            let mut controller = BitrateController::new(bounds);
+            state.write().await.target_bitrate = Some(controller.current());
+            println!("📶 Bitrate adaptation: {}-{}kbps in {}kbps steps, starting at {}kbps (UDP pipeline only; TCP viewers keep the configured bitrate)",
+                     bounds.min, bounds.max, bounds.step, controller.current());
+            loop {
+                tokio::time::sleep(ADAPT_INTERVAL).await;
+
+                let loss = state.write().await.take_udp_loss();
+                let Some(kbps) = controller.on_loss(loss) else {
+                    continue;
+                };
+                println!("📶 Loss {}/256: video bitrate → {}kbps, restarting FFmpeg",
+                         loss.unwrap_or_default(), kbps);
+                state.write().await.target_bitrate = Some(kbps);
+                bitrate_target.request(kbps);
+                let _ = child.lock().unwrap().kill();
+            }
+        });
+    }
+
+    // Keepalive: filler NAL khi không có RTP nào đi ra trong `interval`
+    // (GOP dài / ít chuyển động), để NAT binding của UDP clients không hết hạn
+    if let Some(interval) = config.keepalive_interval {
+        let udp_sender = udp_sender.clone();
+        let packetizer = packetizer.clone();
+        let state = state.clone();
+        tokio::spawn(async move {
+            loop {
+                tokio::time::sleep((interval / 4).max(Duration::from_millis(250))).await;
+
+                if udp_sender.idle_for().await.is_none_or(|idle| idle < interval) {
+                    continue;
+                }
+                let udp_clients = state.read().await.get_udp_clients();
+                if udp_clients.is_empty() {
+                    continue;
+                }
+
+                let packet = packetizer.lock().await.keepalive();
+                udp_sender.send(&[packet], &udp_clients).await;
+                let mut st = state.write().await;
+                st.keepalives_sent += 1;
+                println!("💓 Stream idle for {:?}, sent keepalive to {} UDP client(s) (#{})",
+                         interval, udp_clients.len(), st.keepalives_sent);
+            }
+        });
+    }
+
     // Parse NALUs và gửi qua RTP
-    let mut parser = NaluParser::new();
+    let mut parser = if config.nalu_resync { NaluParser::tolerant() } else { NaluParser::new() };
+    let mut splitter = AccessUnitSplitter::new();
     let mut reader = std::io::BufReader::new(stdout);
     let mut buffer = [0u8; 8192];
 
@@ -171,224 +729,619 @@ async fn start_video_streaming(state: SharedState) -> std::io::Result<()> {
     let mut last_udp_clients_count = 0;
     let mut sps_pps_sent = false; // Track if we've sent initial SPS/PPS
 
+    // IdlePolicy::PauseReads state
+    let mut paused = false;
+    let mut resyncing = false;
+    let mut override_warned = false;
+    let mut param_monitor = ParameterSetMonitor::new(config.parameter_set_timeout);
+
+    // Cùng nhịp với timestamp delta của packetizer
+    let frame_duration = packetizer.lock().await.frame_duration();
+    let mut dropper = FrameDropper::new(config.frame_drop, frame_duration);
+    let mut backoff = RestartBackoff::new();
+
     loop {
-        // Đọc data từ FFmpeg
-        match reader.read(&mut buffer) {
-            Ok(0) => {
-                println!("📹 FFmpeg stream ended (loop will restart)");
-                break;
+        if config.idle_policy == IdlePolicy::PauseReads {
+            if state.read().await.get_udp_clients().is_empty() {
+                if !paused {
+                    println!("⏸️  No UDP clients playing, pausing FFmpeg reads");
+                    paused = true;
+                }
+                tokio::time::sleep(Duration::from_millis(100)).await;
+                continue;
             }
-            Ok(n) => {
-                // Parse NALUs
-                let nalus = parser.parse(&buffer[..n]);
+            if paused {
+                println!("▶️  UDP client joined, resuming reads (skipping to next SPS/IDR)");
+                paused = false;
+                resyncing = true;
+            }
+        }
 
-                if nalus.is_empty() {
-                    continue;
+        // Đọc data từ FFmpeg. Blocking read: block_in_place nhường worker cho
+        // các task khác (UDP drain tasks, keepalive) trong lúc source im lặng
+        let read = tokio::task::block_in_place(|| reader.read(&mut buffer));
+        let failed = matches!(read, Ok(0) | Err(_));
+
+        let retry_primary = on_placeholder && last_primary_retry.elapsed() >= PRIMARY_RETRY_INTERVAL;
+        if retry_primary {
+            last_primary_retry = std::time::Instant::now();
+        }
+
+        // Watchdog đã kill FFmpeg: respawn cùng source. Source lỗi thì chuyển
+        // sang placeholder; đang placeholder thì định kỳ thử lại source
+        let respawn = if failed && watchdog.take_fired() {
+            println!("🐕 Restarting FFmpeg after stall");
+            Some(on_placeholder)
+        } else if let Some(kbps) = failed.then(|| bitrate_target.take_changed()).flatten() {
+            // Controlled restart: không backoff, packetizer giữ nguyên
+            primary.set_bitrate(kbps);
+            Some(on_placeholder)
+        } else if failed && !on_placeholder && placeholder.is_some() {
+            eprintln!("📺 Source FFmpeg exited, switching to placeholder");
+            Some(true)
+        } else if retry_primary && primary.is_available() {
+            println!("📺 Source {} is available, switching back from placeholder", video_path);
+            Some(false)
+        } else if failed {
+            // FFmpeg tự thoát: respawn cùng source sau backoff, packetizer giữ
+            // nguyên nên clients đang xem thấy sequence/timestamp liên tục
+            match &read {
+                Err(e) => eprintln!("❌ Read error: {}", e),
+                _ => eprintln!("📹 FFmpeg stream ended unexpectedly"),
+            }
+            let delay = backoff.next_delay(std::time::Instant::now());
+            if backoff.in_storm() {
+                eprintln!("🌪️  FFmpeg keeps exiting, backing off {:?} before restart #{}", delay, backoff.restarts());
+            } else {
+                println!("🔁 Restarting FFmpeg in {:?} (restart #{})", delay, backoff.restarts());
+            }
+            tokio::time::sleep(delay).await;
+            state.write().await.restarts += 1;
+            Some(on_placeholder)
+        } else {
+            None
+        };
+
+        // Respawn và parse lại từ đầu
+        if let Some(want_placeholder) = respawn {
+            {
+                let mut child = child.lock().unwrap();
+                let _ = child.kill();
+                let _ = child.wait();
+            }
+            let (new_child, stdout, now_placeholder) = loop {
+                match spawn_source(primary.as_ref(), placeholder.as_ref().map(|p| p as &dyn Source), want_placeholder) {
+                    Ok(spawned) => break spawned,
+                    Err(e) => {
+                        let delay = backoff.next_delay(std::time::Instant::now());
+                        eprintln!("❌ FFmpeg respawn failed ({}), retrying in {:?} (restart #{})",
+                                  e, delay, backoff.restarts());
+                        tokio::time::sleep(delay).await;
+                    }
                 }
+            };
+            if now_placeholder != on_placeholder {
+                on_placeholder = now_placeholder;
+                state.write().await.media_duration = if on_placeholder { None } else { primary.probe_duration() };
+            }
+            *child.lock().unwrap() = new_child;
+            reader = std::io::BufReader::new(stdout);
+            parser = if config.nalu_resync { NaluParser::tolerant() } else { NaluParser::new() };
+            splitter = AccessUnitSplitter::new();
+            param_monitor = ParameterSetMonitor::new(config.parameter_set_timeout);
+            resyncing = true;
+            dropper.reset();
+            watchdog.feed();
+            println!("✅ FFmpeg restarted ({})", if on_placeholder { "placeholder" } else { "source" });
+            continue;
+        }
 
-                // Get UDP playing clients (TCP clients are handled by their own sessions)
-                let udp_clients = state.read().await.get_udp_clients();
+        // Reads thất bại đã được respawn ở trên
+        let Ok(n) = read else {
+            continue;
+        };
 
-                if udp_clients.is_empty() {
-                    // No UDP clients playing, just consume the data
+        // Parse NALUs
+        let mut nalus = parser.parse(&buffer[..n]);
+        if let Some(sets) = &config.parameter_sets {
+            sets.substitute(&mut nalus, &mut override_warned);
+        }
+
+        // Source không có SPS/PPS: dùng override (nếu có) như thể stream đã gửi
+        param_monitor.observe(&nalus);
+        if param_monitor.check(std::time::Instant::now()) {
+            if let Some(sets) = &config.parameter_sets {
+                println!("🧬 Synthesizing SPS/PPS from the --sprop override");
+                sps = Some(sets.sps.clone());
+                pps = Some(sets.pps.clone());
+                let mut st = state.write().await;
+                st.cache_parameter_set(&sets.sps);
+                st.cache_parameter_set(&sets.pps);
+            }
+        }
+
+        // Discard data buffered in the pipe while paused, so the
+        // newcomer starts from a clean SPS or IDR
+        if resyncing {
+            match nalus.iter().position(|nalu| !nalu.is_empty() && matches!(nalu[0] & 0x1F, 5 | 7)) {
+                Some(pos) => {
+                    nalus.drain(..pos);
+                    resyncing = false;
+                }
+                None => continue,
+            }
+        }
+
+        if nalus.is_empty() {
+            continue;
+        }
+
+        // Publish parameter sets for the status endpoint
+        if nalus.iter().any(|nalu| matches!(nalu.first().map(|b| b & 0x1F), Some(7 | 8))) {
+            let mut st = state.write().await;
+            for nalu in &nalus {
+                st.cache_parameter_set(nalu);
+            }
+        }
+
+        // Group NALUs into access units (pictures). The splitter carries
+        // partial AUs across reads, so AUs never end at a buffer boundary
+        let access_units: Vec<Vec<Vec<u8>>> =
+            nalus.into_iter().filter_map(|nalu| splitter.push(nalu)).collect();
+
+        // Get UDP playing clients (TCP clients are handled by their own sessions)
+        let (udp_clients, udp_blocksize) = {
+            let st = state.read().await;
+            (st.get_udp_clients(), st.get_udp_blocksize())
+        };
+
+        if udp_clients.is_empty() {
+            // No UDP clients playing, just consume the data
+            watchdog.feed();
+            dropper.reset();
+            continue;
+        }
+
+        packetizer.lock().await.set_blocksize(udp_blocksize);
+
+        // Detect new clients and send SPS/PPS
+        if udp_clients.len() > last_udp_clients_count {
+            if let (Some(ref sps_data), Some(ref pps_data)) = (&sps, &pps) {
+                println!("📡 New UDP client detected, sending SPS/PPS");
+
+                // SPS + PPS trong một STAP-A packet
+                let packets = packetizer.lock().await.packetize_stap_a(&[sps_data, pps_data]);
+                udp_sender.send(&packets, &udp_clients).await;
+            }
+        }
+        last_udp_clients_count = udp_clients.len();
+
+        // Process each access unit
+        for au in &access_units {
+            // Parameter sets đi cùng keyframe, không bao giờ drop
+            let is_keyframe_au = au
+                .iter()
+                .any(|nalu| matches!(nalu.first().map(|b| b & 0x1F), Some(5 | 7 | 8)));
+            if dropper.should_drop(is_keyframe_au, config.clock.now()) {
+                state.write().await.frames_dropped += 1;
+                if dropper.dropped.is_multiple_of(30) {
+                    println!("🐢 Dropped {} of {} access units ({:.1}%)",
+                             dropper.dropped, dropper.total, dropper.drop_rate() * 100.0);
+                }
+                // Timestamp vẫn tiến để timeline không bị co lại
+                packetizer.lock().await.end_access_unit();
+                continue;
+            }
+
+            // Process NALUs in this access unit
+            for (i, nalu) in au.iter().enumerate() {
+                if nalu.is_empty() {
                     continue;
                 }
 
-                // Detect new clients and send SPS/PPS
-                if udp_clients.len() > last_udp_clients_count {
-                    if let (Some(ref sps_data), Some(ref pps_data)) = (&sps, &pps) {
-                        println!("📡 New UDP client detected, sending SPS/PPS");
-
-                        // Send SPS
-                        let mut pac = packetizer.lock().await;
-                        let sps_packets = pac.packetize(sps_data, false);
-                        for packet in sps_packets {
-                            let data = packet.to_bytes();
-                            for (rtp_addr, _) in &udp_clients {
-                                let _ = rtp_socket.send_to(&data, rtp_addr).await;
-                            }
+                let nalu_type = nalu[0] & 0x1F;
+
+                // Cache SPS (type 7) and PPS (type 8)
+                match nalu_type {
+                    7 => {
+                        sps = Some(nalu.clone());
+                        println!("📦 Cached SPS (size: {} bytes)", nalu.len());
+
+                        // If we have both SPS and PPS and haven't sent yet, send to all clients
+                        if let Some(pps_data) = pps.as_ref().filter(|_| !sps_pps_sent && !udp_clients.is_empty()) {
+                            println!("🚀 Sending initial SPS/PPS to UDP clients");
+                            let packets = packetizer.lock().await.packetize_stap_a(&[nalu, pps_data]);
+                            udp_sender.send(&packets, &udp_clients).await;
+                            sps_pps_sent = true;
                         }
+                    }
+                    8 => {
+                        pps = Some(nalu.clone());
+                        println!("📦 Cached PPS (size: {} bytes)", nalu.len());
 
-                        // Send PPS
-                        let pps_packets = pac.packetize(pps_data, false);
-                        for packet in pps_packets {
-                            let data = packet.to_bytes();
-                            for (rtp_addr, _) in &udp_clients {
-                                let _ = rtp_socket.send_to(&data, rtp_addr).await;
-                            }
+                        // If we have both SPS and PPS and haven't sent yet, send to all clients
+                        if let Some(sps_data) = sps.as_ref().filter(|_| !sps_pps_sent && !udp_clients.is_empty()) {
+                            println!("🚀 Sending initial SPS/PPS to UDP clients");
+                            let packets = packetizer.lock().await.packetize_stap_a(&[sps_data, nalu]);
+                            udp_sender.send(&packets, &udp_clients).await;
+                            sps_pps_sent = true;
                         }
                     }
+                    5 => {
+                        // IDR frame - always resend SPS/PPS before it
+                        if let (Some(ref sps_data), Some(ref pps_data)) = (&sps, &pps) {
+                            let packets = packetizer.lock().await.packetize_stap_a(&[sps_data, pps_data]);
+                            udp_sender.send(&packets, &udp_clients).await;
+                        }
+                    }
+                    _ => {}
                 }
-                last_udp_clients_count = udp_clients.len();
 
-                // Group NALUs into access units (frames)
-                // An access unit ends when we see a new IDR/SPS, or at end of NALU list
-                let mut access_unit_indices = vec![];
-                let mut current_au_start = 0;
+                let is_keyframe = nalu_type == 5;
+
+                // Marker bit only on the last RTP packet of the last
+                // slice of the picture (the packetizer puts it on the
+                // final FU-A fragment when that slice is fragmented)
+                let is_last_nalu_in_au = i == au.len() - 1;
+
+                let (packets, schedule) = {
+                    let mut pac = packetizer.lock().await;
+                    let packets = pac.packetize(nalu, is_last_nalu_in_au);
+                    let schedule = pac.pacing_schedule(packets.len());
+                    (packets, schedule)
+                };
 
-                for (i, nalu) in nalus.iter().enumerate() {
-                    if nalu.is_empty() {
-                        continue;
+                // Gửi các RTP packets đến tất cả UDP playing clients
+                // (SR counters được cập nhật per-client khi stamp).
+                // NALU quá cỡ: queue của từng client trải batches trên frame
+                // interval, loop này không chờ
+                match schedule {
+                    Some(offsets) => {
+                        println!("🐌 Pacing oversized NALU: {} fragments over {:?}", packets.len(), offsets.last().copied().unwrap_or_default());
+                        udp_sender.send_scheduled(&packets, &offsets, &udp_clients).await;
                     }
-                    let nalu_type = nalu[0] & 0x1F;
+                    None => udp_sender.send(&packets, &udp_clients).await,
+                }
+                watchdog.feed();
 
-                    // New access unit starts at SPS (7), IDR (5), or I-frame (not after PPS)
-                    if i > 0 && (nalu_type == 7 || (nalu_type == 5 && i > current_au_start + 2)) {
-                        access_unit_indices.push((current_au_start, i));
-                        current_au_start = i;
+                if is_keyframe {
+                    frame_count += 1;
+                    if frame_count.is_multiple_of(30) {
+                        let rtp = packetizer.lock().await.snapshot();
+                        println!("🎬 Sent {} frames to {} UDP client(s) (SSRC {:08x}, seq {}, ts {}, cycles {})",
+                                 frame_count, udp_clients.len(), rtp.ssrc, rtp.sequence, rtp.timestamp, rtp.cycles);
                     }
                 }
-                // Add last access unit
-                if current_au_start < nalus.len() {
-                    access_unit_indices.push((current_au_start, nalus.len()));
+            }
+
+            // Increment timestamp ONCE per access unit (frame)
+            packetizer.lock().await.end_access_unit();
+        }
+
+        // Vị trí hiện tại của shared stream, cho RTP-Info trong PLAY
+        let rtp = packetizer.lock().await.snapshot();
+        state.write().await.track_positions.insert(VIDEO_TRACK.to_string(), (rtp.sequence, rtp.timestamp));
+    }
+}
+
+/// Audio của default mount (AAC hoặc Opus theo `--audio-codec`): một FFmpeg
+/// riêng (`-re` tự pace theo real time), packetize một lần rồi fan-out tới
+/// các clients đã SETUP audio track, stamp theo audio identity của từng client
+/// như video UDP. FFmpeg thoát thì respawn với backoff; packetizer giữ nguyên
+/// nên seq/timestamp liên tục
+async fn start_audio_streaming(state: SharedState, config: Arc<ServerConfig>, ports: PortPair) -> std::io::Result<()> {
+    let path = state
+        .read()
+        .await
+        .mounts
+        .get(&config.default_mount)
+        .cloned()
+        .unwrap_or_else(|| config.source.clone());
+    if RtspPullSource::is_url(&path) {
+        println!("🔇 No audio for {}: RTSP feeds are video-only", path);
+        return Ok(());
+    }
+    let source = FileSource::new(path, config.encoder.clone());
+
+    let rtp_socket = Arc::new(UdpSocket::bind(("0.0.0.0", ports.rtp)).await?);
+    // RTCP port chỉ được giữ cho khớp server_port trong SETUP; audio RR bị bỏ qua
+    let _rtcp_socket = UdpSocket::bind(("0.0.0.0", ports.rtcp)).await?;
+    println!("🔊 Audio track {} ({:?}): RTP socket 0.0.0.0:{}, RTCP 0.0.0.0:{}",
+             AUDIO_TRACK, config.audio_codec, ports.rtp, ports.rtcp);
+
+    let sender = UdpSender::new(rtp_socket, None, None, None);
+    let mut packetizer = AudioPacketizer::new(config.audio_codec);
+    let mut backoff = RestartBackoff::new();
+
+    loop {
+        // RTP-Info của audio: vị trí packet kế tiếp
+        state.write().await.track_positions.insert(AUDIO_TRACK.to_string(), packetizer.position());
+
+        // Placeholder không có audio: chờ file xuất hiện
+        if !std::path::Path::new(&source.file_path).exists() {
+            tokio::time::sleep(PRIMARY_RETRY_INTERVAL).await;
+            continue;
+        }
+
+        let mut child = match packetizer.spawn(&source) {
+            Ok(child) => child,
+            Err(e) => {
+                let delay = backoff.next_delay(std::time::Instant::now());
+                eprintln!("❌ Audio FFmpeg failed to start ({}), retrying in {:?}", e, delay);
+                tokio::time::sleep(delay).await;
+                continue;
+            }
+        };
+        let Some(stdout) = child.stdout.take() else {
+            return Err(std::io::Error::other("Failed to capture audio FFmpeg stdout"));
+        };
+        let mut reader = packetizer.frames(stdout);
+
+        loop {
+            let frames = match tokio::task::block_in_place(|| reader.next()) {
+                Ok(Some(frames)) => frames,
+                Ok(None) => break,
+                Err(e) => {
+                    eprintln!("❌ Audio read error: {}", e);
+                    break;
                 }
+            };
 
-                // Process each access unit
-                for (au_idx, (au_start, au_end)) in access_unit_indices.iter().enumerate() {
-                    let is_last_au = au_idx == access_unit_indices.len() - 1;
+            let packets: Vec<_> = frames.iter().flat_map(|frame| packetizer.packetize(frame)).collect();
+            let mut st = state.write().await;
+            let audio_clients = st.get_audio_clients();
+            st.track_positions.insert(AUDIO_TRACK.to_string(), packetizer.position());
+            drop(st);
+            sender.send(&packets, &audio_clients).await;
+        }
 
-                    // Process NALUs in this access unit
-                    for i in *au_start..*au_end {
-                        let nalu = &nalus[i];
-                        if nalu.is_empty() {
-                            continue;
-                        }
+        let _ = child.kill();
+        let _ = child.wait();
+        let delay = backoff.next_delay(std::time::Instant::now());
+        eprintln!("🔇 Audio FFmpeg exited, restarting in {:?} (restart #{})", delay, backoff.restarts());
+        tokio::time::sleep(delay).await;
+    }
+}
 
-                        let nalu_type = nalu[0] & 0x1F;
-
-                        // Cache SPS (type 7) and PPS (type 8)
-                        match nalu_type {
-                            7 => {
-                                sps = Some(nalu.clone());
-                                println!("📦 Cached SPS (size: {} bytes)", nalu.len());
-
-                                // If we have both SPS and PPS and haven't sent yet, send to all clients
-                                if !sps_pps_sent && pps.is_some() && !udp_clients.is_empty() {
-                                    println!("🚀 Sending initial SPS/PPS to UDP clients");
-                                    let mut pac = packetizer.lock().await;
-                                    let sps_packets = pac.packetize(nalu, false);
-                                    for packet in sps_packets {
-                                        let data = packet.to_bytes();
-                                        for (rtp_addr, _) in &udp_clients {
-                                            let _ = rtp_socket.send_to(&data, rtp_addr).await;
-                                        }
-                                    }
-                                    // Send PPS
-                                    if let Some(ref pps_data) = pps {
-                                        let pps_packets = pac.packetize(pps_data, false);
-                                        for packet in pps_packets {
-                                            let data = packet.to_bytes();
-                                            for (rtp_addr, _) in &udp_clients {
-                                                let _ = rtp_socket.send_to(&data, rtp_addr).await;
-                                            }
-                                        }
-                                    }
-                                    sps_pps_sent = true;
-                                }
-                            }
-                            8 => {
-                                pps = Some(nalu.clone());
-                                println!("📦 Cached PPS (size: {} bytes)", nalu.len());
-
-                                // If we have both SPS and PPS and haven't sent yet, send to all clients
-                                if !sps_pps_sent && sps.is_some() && !udp_clients.is_empty() {
-                                    println!("🚀 Sending initial SPS/PPS to UDP clients");
-                                    let mut pac = packetizer.lock().await;
-                                    // Send SPS
-                                    if let Some(ref sps_data) = sps {
-                                        let sps_packets = pac.packetize(sps_data, false);
-                                        for packet in sps_packets {
-                                            let data = packet.to_bytes();
-                                            for (rtp_addr, _) in &udp_clients {
-                                                let _ = rtp_socket.send_to(&data, rtp_addr).await;
-                                            }
-                                        }
-                                    }
-                                    // Send PPS
-                                    let pps_packets = pac.packetize(nalu, false);
-                                    for packet in pps_packets {
-                                        let data = packet.to_bytes();
-                                        for (rtp_addr, _) in &udp_clients {
-                                            let _ = rtp_socket.send_to(&data, rtp_addr).await;
-                                        }
-                                    }
-                                    sps_pps_sent = true;
-                                }
-                            }
-                            5 => {
-                                // IDR frame - always resend SPS/PPS before it
-                                if let (Some(ref sps_data), Some(ref pps_data)) = (&sps, &pps) {
-                                    let mut pac = packetizer.lock().await;
-                                    // Send SPS
-                                    let sps_packets = pac.packetize(sps_data, false);
-                                    for packet in sps_packets {
-                                        let data = packet.to_bytes();
-                                        for (rtp_addr, _) in &udp_clients {
-                                            let _ = rtp_socket.send_to(&data, rtp_addr).await;
-                                        }
-                                    }
-                                    // Send PPS
-                                    let pps_packets = pac.packetize(pps_data, false);
-                                    for packet in pps_packets {
-                                        let data = packet.to_bytes();
-                                        for (rtp_addr, _) in &udp_clients {
-                                            let _ = rtp_socket.send_to(&data, rtp_addr).await;
-                                        }
-                                    }
-                                }
-                            }
-                            _ => {}
-                        }
+/// Packetizer của audio track theo codec, kèm FFmpeg command và framing
+/// tương ứng (ADTS cho AAC, Ogg cho Opus)
+enum AudioPacketizer {
+    Aac(AacPacketizer),
+    #[cfg(feature = "opus")]
+    Opus(OpusPacketizer),
+}
 
-                        let is_keyframe = nalu_type == 5;
+impl AudioPacketizer {
+    fn new(codec: AudioCodec) -> Self {
+        match codec {
+            AudioCodec::Aac => Self::Aac(AacPacketizer::new()),
+            #[cfg(feature = "opus")]
+            AudioCodec::Opus => Self::Opus(OpusPacketizer::new()),
+        }
+    }
 
-                        // Marker bit is set on the last NALU of the access unit
-                        let is_last_nalu_in_au = i == *au_end - 1;
+    /// (sequence, timestamp) của packet kế tiếp
+    fn position(&self) -> (u16, u32) {
+        match self {
+            Self::Aac(packetizer) => (packetizer.sequence(), packetizer.timestamp()),
+            #[cfg(feature = "opus")]
+            Self::Opus(packetizer) => (packetizer.sequence(), packetizer.timestamp()),
+        }
+    }
 
-                        let mut pac = packetizer.lock().await;
-                        let packets = pac.packetize(nalu, is_last_nalu_in_au);
+    fn packetize(&mut self, frame: &[u8]) -> Vec<RtpPacket> {
+        match self {
+            Self::Aac(packetizer) => packetizer.packetize(frame),
+            #[cfg(feature = "opus")]
+            Self::Opus(packetizer) => vec![packetizer.packetize(frame)],
+        }
+    }
 
-                        // Gửi các RTP packets đến tất cả UDP playing clients
-                        for packet in packets {
-                            let data = packet.to_bytes();
-                            let size = data.len();
+    fn spawn(&self, source: &FileSource) -> std::io::Result<Child> {
+        match self {
+            Self::Aac(_) => source.start_ffmpeg_aac(),
+            #[cfg(feature = "opus")]
+            Self::Opus(_) => source.start_ffmpeg_opus(),
+        }
+    }
 
-                            for (rtp_addr, _rtcp_addr) in &udp_clients {
-                                if let Err(e) = rtp_socket.send_to(&data, rtp_addr).await {
-                                    eprintln!("⚠️  RTP send error to {}: {}", rtp_addr, e);
-                                }
-                            }
+    fn frames(&self, stdout: ChildStdout) -> AudioFrames {
+        let reader = std::io::BufReader::new(stdout);
+        match self {
+            Self::Aac(_) => AudioFrames::Adts { reader, parser: AdtsParser::new(), buffer: vec![0u8; 4096] },
+            #[cfg(feature = "opus")]
+            Self::Opus(_) => AudioFrames::Ogg(OggPacketReader::new(reader)),
+        }
+    }
+}
 
-                            // Update RTCP statistics
-                            let mut sr = sender_report.lock().await;
-                            sr.add_packet(size);
-                        }
+/// Audio frames từ stdout của audio FFmpeg
+enum AudioFrames {
+    Adts { reader: std::io::BufReader<ChildStdout>, parser: AdtsParser, buffer: Vec<u8> },
+    #[cfg(feature = "opus")]
+    Ogg(OggPacketReader<std::io::BufReader<ChildStdout>>),
+}
 
-                        if is_keyframe {
-                            frame_count += 1;
-                            if frame_count % 30 == 0 {
-                                println!("🎬 Sent {} frames to {} UDP client(s)",
-                                         frame_count, udp_clients.len());
-                            }
-                        }
-                    }
+impl AudioFrames {
+    /// Blocking read: frames hoàn chỉnh của lần đọc kế tiếp (có thể rỗng),
+    /// None at end of stream
+    fn next(&mut self) -> std::io::Result<Option<Vec<Vec<u8>>>> {
+        match self {
+            Self::Adts { reader, parser, buffer } => match reader.read(buffer)? {
+                0 => Ok(None),
+                n => Ok(Some(parser.parse(&buffer[..n]))),
+            },
+            // OpusHead/OpusTags không phải audio
+            #[cfg(feature = "opus")]
+            Self::Ogg(reader) => Ok(reader.next_packet()?.map(|packet| {
+                if is_opus_header(&packet) { Vec::new() } else { vec![packet] }
+            })),
+        }
+    }
+}
 
-                    // Increment timestamp ONCE per access unit (frame)
-                    // 90000 Hz / 30 fps = 3000 ticks per frame
-                    let mut pac = packetizer.lock().await;
-                    pac.increment_timestamp(3000);
-                }
+/// Spawn FFmpeg for the primary source, or for the placeholder if
+/// `use_placeholder`. A primary that fails to start falls back to the
+/// placeholder; the returned flag says whether the placeholder is running
+fn spawn_source(
+    primary: &dyn Source,
+    placeholder: Option<&dyn Source>,
+    use_placeholder: bool,
+) -> std::io::Result<(Child, ChildStdout, bool)> {
+    let fallback = match placeholder {
+        Some(placeholder) if use_placeholder => {
+            println!("📺 Serving placeholder ({})", placeholder.describe());
+            return spawn_ffmpeg(placeholder).map(|(child, stdout)| (child, stdout, true));
+        }
+        fallback => fallback,
+    };
+
+    match (spawn_ffmpeg(primary), fallback) {
+        (Ok((child, stdout)), _) => Ok((child, stdout, false)),
+        (Err(e), Some(placeholder)) => {
+            eprintln!("❌ Source FFmpeg failed to start ({}), serving placeholder ({})", e, placeholder.describe());
+            spawn_ffmpeg(placeholder).map(|(child, stdout)| (child, stdout, true))
+        }
+        (Err(e), None) => Err(e),
+    }
+}
+
+/// Spawn FFmpeg for `source`, returning the child and its stdout
+fn spawn_ffmpeg(source: &dyn Source) -> std::io::Result<(Child, ChildStdout)> {
+    let mut child = source.start_ffmpeg()?;
+
+    // Đọc stderr trong background thread để không block
+    if let Some(mut stderr) = child.stderr.take() {
+        tokio::spawn(async move {
+            let mut buf = String::new();
+            if stderr.read_to_string(&mut buf).is_ok() && !buf.is_empty() {
+                println!("Debug: FFmpeg stderr output:\n{}", buf);
             }
-            Err(e) => {
-                eprintln!("❌ Read error: {}", e);
-                break;
+        });
+    }
+
+    let stdout = child.stdout.take().ok_or_else(|| {
+        std::io::Error::other("Failed to capture FFmpeg stdout")
+    })?;
+    Ok((child, stdout))
+}
+
+/// Xử lý RTCP feedback (compound packet) từ một UDP client. Session có
+/// `loss_evict_reports` RRs liên tiếp báo mất toàn bộ packets bị bỏ
+async fn handle_rtcp_feedback(
+    data: &[u8],
+    from: SocketAddr,
+    udp_sender: &UdpSender,
+    state: &SharedState,
+    received: SystemTime,
+    loss_evict_reports: Option<u32>,
+) {
+    let arrival = SenderReport::ntp_middle32(received);
+
+    for packet in rtcp::split_compound(data) {
+        if let Some(rr) = ReceiverReport::parse(packet) {
+            let rtt = rr.reports.first().and_then(|report| report.round_trip_time(arrival));
+            let mut state = state.write().await;
+            if let Some((session, lossy_reports)) = state.record_receiver_report(from, &rr, rtt) {
+                println!("📨 RR from {} (session {}, SSRC {:08x}): RTT {:?}", from, session, rr.sender_ssrc, rtt);
+                for report in &rr.reports {
+                    println!("   ↳ media {:08x}: lost {}/256 (total {}), highest seq {}, jitter {}",
+                             report.ssrc, report.fraction_lost, report.cumulative_lost,
+                             report.highest_sequence, report.jitter);
+                }
+                if loss_evict_reports.is_some_and(|limit| lossy_reports >= limit) {
+                    println!("🚫 UDP session {} evicted: {} consecutive RRs report total loss", session, lossy_reports);
+                    state.remove_client(&session, EndReason::Error);
+                }
             }
+            continue;
         }
-    }
 
-    // Cleanup
-    let _ = child.kill();
+        let Some(nack) = GenericNack::parse(packet) else {
+            continue;
+        };
+        let Some(rtp_addr) = state.read().await.find_udp_rtp_addr(from) else {
+            continue;
+        };
 
-    Ok(())
+        let retransmitted = udp_sender.retransmit(rtp_addr, &nack.lost).await;
+        println!("🔁 NACK from {} (SSRC {:08x} → {:08x}): {} lost, {} retransmitted",
+                 from, nack.sender_ssrc, nack.media_ssrc, nack.lost.len(), retransmitted);
+    }
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rtsp::sdp::generate_sdp;
+
+    /// Settings với CLI args, env vars và (nếu có) nội dung của `--config` file
+    fn settings(args: &[&str], env: &[(&str, &str)], file: Option<&str>) -> Result<Settings, String> {
+        let mut argv = vec!["simulation-media-server".to_string()];
+        argv.extend(args.iter().map(|arg| arg.to_string()));
+        let env = env.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect();
+        let file = file.map(str::to_string);
+        Settings::from_sources(argv, env, move |_| {
+            file.clone().ok_or_else(|| std::io::Error::from(std::io::ErrorKind::NotFound))
+        })
+    }
+
+    fn config(args: &[&str], env: &[(&str, &str)], file: Option<&str>) -> ServerConfig {
+        resolve_config(&settings(args, env, file).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn precedence_is_defaults_env_file_cli() {
+        let defaults = ServerConfig::default();
+        let env = [("SMS_SOURCE", "/env.mp4"), ("SMS_RTSP_ADDR", "0.0.0.0:7000"), ("SMS_CONFIG", "/etc/sms.conf")];
+        let file = "# comment\nsource = /file.mp4\n\nsession-timeout = 30\n";
+
+        let resolved = config(&[], &[], None);
+        assert_eq!((resolved.source, resolved.rtsp_addr), (defaults.source.clone(), defaults.rtsp_addr.clone()));
+
+        // env > defaults
+        let resolved = config(&[], &env[..2], None);
+        assert_eq!((resolved.source.as_str(), resolved.rtsp_addr.as_str()), ("/env.mp4", "0.0.0.0:7000"));
+
+        // config file > env; keys thiếu trong file vẫn lấy từ env
+        let resolved = config(&[], &env, Some(file));
+        assert_eq!((resolved.source.as_str(), resolved.rtsp_addr.as_str()), ("/file.mp4", "0.0.0.0:7000"));
+        assert_eq!(resolved.session_timeout, Duration::from_secs(30));
+
+        // CLI > mọi thứ
+        let resolved = config(&["--source", "/cli.mp4", "--rtsp-addr", "127.0.0.1:9000"], &env, Some(file));
+        assert_eq!((resolved.source.as_str(), resolved.rtsp_addr.as_str()), ("/cli.mp4", "127.0.0.1:9000"));
+        assert_eq!(resolved.session_timeout, Duration::from_secs(30));
+    }
+
+    #[test]
+    fn flags_come_from_any_layer() {
+        assert!(!config(&[], &[], None).rtcp_mux);
+        assert!(config(&["--rtcp-mux"], &[], None).rtcp_mux);
+        assert!(config(&[], &[("SMS_RTCP_MUX", "1")], None).rtcp_mux);
+        assert!(config(&["--config", "sms.conf"], &[], Some("rtcp-mux = true")).rtcp_mux);
+    }
+
+    #[test]
+    fn audio_is_disabled_for_rtsp_sources() {
+        let file = config(&["--audio"], &[], None);
+        assert!(file.audio.is_some());
+        assert!(generate_sdp(&file, "npt=0-", None).contains("a=control:track2"));
+
+        let feed = config(&["--audio", "--source", "rtsp://10.0.0.9/stream1"], &[], None);
+        assert_eq!(feed.audio, None);
+        let sdp = generate_sdp(&feed, "npt=now-", None);
+        assert!(!sdp.contains("m=audio") && !sdp.contains("track2"), "{}", sdp);
+        assert_eq!(config(&["--audio-port", "7000", "--source", "RTSP://cam/live"], &[], None).audio, None);
+    }
+
+    #[test]
+    fn invalid_values_name_the_setting() {
+        let error = resolve_config(&settings(&["--session-timeout", "soon"], &[], None).unwrap()).unwrap_err();
+        assert!(error.contains("--session-timeout"), "{}", error);
+
+        let error = settings(&["--config", "sms.conf"], &[], Some("source /a.mp4")).err().unwrap();
+        assert_eq!(error, "sms.conf:1: expected key = value");
+        assert!(settings(&["--config", "missing.conf"], &[], None).is_err());
+    }
+}
@@ -0,0 +1,88 @@
+/// RTCP BYE (RFC 3550 §6.6): báo client rằng source dừng gửi, để player
+/// kết thúc ngay thay vì đợi timeout
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Goodbye {
+    pub ssrc: u32,
+    /// Optional reason for leaving (tối đa 255 bytes)
+    pub reason: Option<String>,
+}
+
+impl Goodbye {
+    pub fn new(ssrc: u32, reason: Option<&str>) -> Self {
+        Self {
+            ssrc,
+            reason: reason.map(str::to_string),
+        }
+    }
+
+    /// Serialize BYE packet: một SSRC, reason (nếu có) padded tới 32-bit
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(16);
+
+        // V=2, P=0, SC=1, PT=203 (BYE); length điền sau
+        buf.push(0x81);
+        buf.push(203);
+        buf.extend_from_slice(&[0, 0]);
+        buf.extend_from_slice(&self.ssrc.to_be_bytes());
+
+        if let Some(reason) = &self.reason {
+            // Length field chỉ có 8 bits: cắt ở char boundary để vẫn là UTF-8
+            let mut end = reason.len().min(255);
+            while !reason.is_char_boundary(end) {
+                end -= 1;
+            }
+            buf.push(end as u8);
+            buf.extend_from_slice(&reason.as_bytes()[..end]);
+            while buf.len() % 4 != 0 {
+                buf.push(0);
+            }
+        }
+
+        // Length in 32-bit words - 1
+        let words = (buf.len() / 4 - 1) as u16;
+        buf[2..4].copy_from_slice(&words.to_be_bytes());
+        buf
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn without_reason_is_header_and_ssrc() {
+        let bytes = Goodbye::new(0x0102_0304, None).to_bytes();
+        assert_eq!(bytes, [0x81, 203, 0, 1, 1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn reason_is_length_prefixed_and_padded_to_32_bits() {
+        // 1 + 8 bytes reason → 3 bytes padding, 4 words tổng
+        let bytes = Goodbye::new(7, Some("teardown")).to_bytes();
+        assert_eq!(bytes.len(), 20);
+        assert_eq!(&bytes[2..4], &[0, 4]);
+        assert_eq!(bytes[8], 8);
+        assert_eq!(&bytes[9..17], b"teardown");
+        assert_eq!(&bytes[17..], &[0, 0, 0]);
+
+        // 1 + 3 bytes vừa đủ một word: không padding
+        let bytes = Goodbye::new(7, Some("eof")).to_bytes();
+        assert_eq!(bytes.len(), 12);
+        assert_eq!(&bytes[2..4], &[0, 2]);
+    }
+
+    #[test]
+    fn long_reason_is_truncated_on_a_char_boundary() {
+        // 'é' là 2 bytes: 255 rơi vào giữa ký tự thứ 128
+        let reason = "é".repeat(200);
+        let bytes = Goodbye::new(7, Some(&reason)).to_bytes();
+        assert_eq!(bytes[8], 254);
+        assert!(std::str::from_utf8(&bytes[9..9 + 254]).is_ok());
+        assert_eq!(bytes.len() % 4, 0);
+        assert_eq!(u16::from_be_bytes([bytes[2], bytes[3]]) as usize, bytes.len() / 4 - 1);
+
+        let bytes = Goodbye::new(7, Some(&"x".repeat(300))).to_bytes();
+        assert_eq!(bytes[8], 255);
+        assert_eq!(bytes.len(), 8 + 256);
+    }
+}
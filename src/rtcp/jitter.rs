@@ -0,0 +1,137 @@
+use super::rr::ReceptionReport;
+use std::collections::HashMap;
+use std::time::Instant;
+
+/// Transit-time state của một SSRC nhận được
+#[derive(Clone, Copy, Debug)]
+struct Transit {
+    /// Transit của packet trước (arrival - RTP timestamp, đơn vị RTP clock)
+    last: u32,
+    /// Jitter scaled ×16 (RFC 3550 Appendix A.8), tránh floating point
+    jitter: u32,
+}
+
+/// Interarrival jitter (RFC 3550 §6.4.1) cho RTP nhận vào, theo từng SSRC
+///
+/// Dành cho ingest path (`--client`, sau này record/proxy): mỗi packet nhận
+/// được gọi `on_packet`, rồi `apply` điền jitter vào reception report block
+/// gửi ngược về sender.
+#[derive(Debug)]
+pub struct JitterEstimator {
+    clock_rate: u32,
+    /// Gốc thời gian để đổi arrival time sang đơn vị RTP clock
+    epoch: Instant,
+    sources: HashMap<u32, Transit>,
+}
+
+impl JitterEstimator {
+    pub fn new(clock_rate: u32) -> Self {
+        Self {
+            clock_rate,
+            epoch: Instant::now(),
+            sources: HashMap::new(),
+        }
+    }
+
+    /// RTP clock rate mà jitter được tính theo
+    pub fn clock_rate(&self) -> u32 {
+        self.clock_rate
+    }
+
+    /// Record one packet of `ssrc` with RTP timestamp `rtp_timestamp`
+    /// arriving at `arrival`
+    pub fn on_packet(&mut self, ssrc: u32, rtp_timestamp: u32, arrival: Instant) {
+        let elapsed = arrival.saturating_duration_since(self.epoch);
+        let arrival_ticks = (elapsed.as_nanos() * self.clock_rate as u128 / 1_000_000_000) as u32;
+        // Cả hai đều wrap mod 2^32, nên hiệu wrapping là transit hợp lệ
+        let transit = arrival_ticks.wrapping_sub(rtp_timestamp);
+
+        match self.sources.get_mut(&ssrc) {
+            Some(state) => {
+                // D(i-1, i) = (Rj - Ri) - (Sj - Si) = transit_j - transit_i
+                let d = (transit.wrapping_sub(state.last) as i32).unsigned_abs();
+                // J += (|D| - J) / 16, với J lưu dưới dạng ×16
+                state.jitter = state.jitter.wrapping_add(d).wrapping_sub((state.jitter + 8) >> 4);
+                state.last = transit;
+            }
+            None => {
+                self.sources.insert(ssrc, Transit { last: transit, jitter: 0 });
+            }
+        }
+    }
+
+    /// Current interarrival jitter of `ssrc`, in RTP timestamp units
+    pub fn jitter(&self, ssrc: u32) -> Option<u32> {
+        self.sources.get(&ssrc).map(|state| state.jitter >> 4)
+    }
+
+    /// Fill the jitter field of a report block about `report.ssrc`
+    pub fn apply(&self, report: &mut ReceptionReport) {
+        if let Some(jitter) = self.jitter(report.ssrc) {
+            report.jitter = jitter;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    /// 8kHz clock: một tick = 125µs, nên arrival times là số tick nguyên
+    const RATE: u32 = 8000;
+
+    fn estimator_with(ssrc: u32, packets: &[(u32, u64)]) -> JitterEstimator {
+        let mut estimator = JitterEstimator::new(RATE);
+        let epoch = estimator.epoch;
+        for &(timestamp, arrival_ticks) in packets {
+            estimator.on_packet(ssrc, timestamp, epoch + Duration::from_micros(arrival_ticks * 125));
+        }
+        estimator
+    }
+
+    #[test]
+    fn single_transit_change_is_one_sixteenth() {
+        // D = (320 - 160) - (160 - 160) = 160 → J = 160 / 16
+        let estimator = estimator_with(1, &[(0, 1000), (160, 1320)]);
+        assert_eq!(estimator.jitter(1), Some(10));
+        assert_eq!(estimator.jitter(2), None);
+    }
+
+    #[test]
+    fn matches_rfc3550_a8_formula() {
+        let timestamps = (0..12).map(|i| 5000 + i * 160);
+        let arrivals = [0u64, 170, 310, 480, 700, 800, 960, 1200, 1290, 1440, 1700, 1760];
+        let packets: Vec<(u32, u64)> = timestamps.zip(arrivals).collect();
+        let estimator = estimator_with(9, &packets);
+
+        // J(i) = J(i-1) + (|D(i-1,i)| - J(i-1)) / 16, D = (Rj - Ri) - (Sj - Si)
+        let mut expected = 0.0f64;
+        for pair in packets.windows(2) {
+            let d = (pair[1].1 as f64 - pair[0].1 as f64) - (pair[1].0 as f64 - pair[0].0 as f64);
+            expected += (d.abs() - expected) / 16.0;
+        }
+        let jitter = estimator.jitter(9).unwrap() as f64;
+        assert!((jitter - expected).abs() <= 1.0, "jitter {} vs RFC {}", jitter, expected);
+        assert!(expected > 20.0);
+    }
+
+    #[test]
+    fn steady_stream_across_timestamp_wrap_has_no_jitter() {
+        let packets: Vec<(u32, u64)> = (0..20u32).map(|i| (u32::MAX - 1000u32).wrapping_add(i * 160)).zip((0..20u64).map(|i| 40 + i * 160)).collect();
+        assert_eq!(estimator_with(3, &packets).jitter(3), Some(0));
+    }
+
+    #[test]
+    fn apply_fills_the_matching_report_block() {
+        let estimator = estimator_with(1, &[(0, 1000), (160, 1320)]);
+        let mut block = ReceptionReport { ssrc: 1, fraction_lost: 0, cumulative_lost: 0, highest_sequence: 0, jitter: 0, lsr: 0, dlsr: 0 };
+        estimator.apply(&mut block);
+        assert_eq!(block.jitter, 10);
+
+        let mut other = ReceptionReport { ssrc: 2, ..block.clone() };
+        other.jitter = 0;
+        estimator.apply(&mut other);
+        assert_eq!(other.jitter, 0);
+    }
+}
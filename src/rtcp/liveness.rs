@@ -0,0 +1,80 @@
+use std::time::{Duration, Instant};
+
+/// SR interval khi client khỏe (RR đều, RTT ổn định)
+pub const SR_INTERVAL: Duration = Duration::from_secs(5);
+/// SR interval nhỏ nhất khi đang probe client im lặng
+pub const SR_MIN_INTERVAL: Duration = Duration::from_secs(1);
+
+/// RTCP-driven liveness và SR timing của một UDP client
+///
+/// RR (hoặc bất kỳ RTSP request nào) giữ session sống. Khi RR ngừng tới, SR
+/// interval giảm một nửa sau mỗi SR_INTERVAL im lặng cho tới SR_MIN_INTERVAL,
+/// để probe client trước khi session timeout tuyên bố client chết. RTT tăng
+/// gấp đôi so với mức thấp nhất đã thấy cũng rút ngắn interval.
+#[derive(Clone, Debug)]
+pub struct RtcpLiveness {
+    /// Last RTSP request or RTCP RR from this client
+    pub last_seen: Instant,
+    last_rr: Option<Instant>,
+    last_sr: Option<Instant>,
+    pub rtt: Option<Duration>,
+    min_rtt: Option<Duration>,
+    since: Instant,
+}
+
+impl RtcpLiveness {
+    pub fn new(now: Instant) -> Self {
+        Self {
+            last_seen: now,
+            last_rr: None,
+            last_sr: None,
+            rtt: None,
+            min_rtt: None,
+            since: now,
+        }
+    }
+
+    /// RTSP keepalive (any request on the session)
+    pub fn touch(&mut self, now: Instant) {
+        self.last_seen = now;
+    }
+
+    pub fn on_receiver_report(&mut self, rtt: Option<Duration>, now: Instant) {
+        self.last_rr = Some(now);
+        self.last_seen = now;
+        if let Some(rtt) = rtt {
+            self.rtt = Some(rtt);
+            self.min_rtt = Some(self.min_rtt.map_or(rtt, |min| min.min(rtt)));
+        }
+    }
+
+    /// Current SR interval for this client
+    pub fn sr_interval(&self, now: Instant) -> Duration {
+        let silence = now.saturating_duration_since(self.last_rr.unwrap_or(self.since));
+        let halvings = (silence.as_secs_f64() / SR_INTERVAL.as_secs_f64()) as u32;
+        let mut interval = SR_INTERVAL / 2u32.saturating_pow(halvings);
+
+        if let (Some(rtt), Some(min_rtt)) = (self.rtt, self.min_rtt) {
+            if rtt > min_rtt * 2 && rtt > Duration::from_millis(10) {
+                interval /= 2;
+            }
+        }
+        interval.max(SR_MIN_INTERVAL)
+    }
+
+    /// Whether an SR is due now; records it as sent if so
+    pub fn take_sr_due(&mut self, now: Instant) -> bool {
+        let due = self
+            .last_sr
+            .is_none_or(|sent| now.saturating_duration_since(sent) >= self.sr_interval(now));
+        if due {
+            self.last_sr = Some(now);
+        }
+        due
+    }
+
+    /// No RTSP request and no RR for `timeout`
+    pub fn is_dead(&self, now: Instant, timeout: Duration) -> bool {
+        now.saturating_duration_since(self.last_seen) > timeout
+    }
+}
@@ -1 +1,32 @@
+pub mod bye;
+pub mod jitter;
+pub mod liveness;
+pub mod nack;
+pub mod rr;
 pub mod sr;
+
+/// Check whether a datagram received on a muxed RTP/RTCP port is RTCP.
+/// RTCP packet types SR/RR/SDES/BYE/APP occupy 200–204 in the second byte,
+/// which never collides with the dynamic RTP payload types we send (RFC 5761 §4)
+pub fn is_rtcp_packet(data: &[u8]) -> bool {
+    data.len() >= 4 && (200..=204).contains(&data[1])
+}
+
+/// Split a compound RTCP packet into individual packets by walking the
+/// length fields. Stops at the first truncated or non-version-2 packet
+pub fn split_compound(data: &[u8]) -> Vec<&[u8]> {
+    let mut packets = Vec::new();
+    let mut rest = data;
+
+    while rest.len() >= 4 && rest[0] >> 6 == 2 {
+        // Length in 32-bit words - 1
+        let len = (u16::from_be_bytes([rest[2], rest[3]]) as usize + 1) * 4;
+        if len > rest.len() {
+            break;
+        }
+        packets.push(&rest[..len]);
+        rest = &rest[len..];
+    }
+
+    packets
+}
@@ -0,0 +1,42 @@
+/// RTCP Generic NACK (RFC 4585 §6.2.1): PT=205 (RTPFB), FMT=1
+/// Client báo các RTP sequence bị mất để server gửi lại
+#[derive(Debug)]
+pub struct GenericNack {
+    pub sender_ssrc: u32,
+    pub media_ssrc: u32,
+    pub lost: Vec<u16>,
+}
+
+impl GenericNack {
+    /// Parse one RTCP packet (already split out of a compound packet).
+    /// Return None nếu không phải Generic NACK hoặc bị truncated
+    pub fn parse(packet: &[u8]) -> Option<Self> {
+        if packet.len() < 12 || packet[1] != 205 || packet[0] & 0x1F != 1 {
+            return None;
+        }
+
+        let sender_ssrc = u32::from_be_bytes([packet[4], packet[5], packet[6], packet[7]]);
+        let media_ssrc = u32::from_be_bytes([packet[8], packet[9], packet[10], packet[11]]);
+
+        // Mỗi FCI entry 4 bytes: PID (16) + BLP (16)
+        // BLP bit i set => PID + i + 1 cũng bị mất
+        let mut lost = Vec::new();
+        for fci in packet[12..].chunks_exact(4) {
+            let pid = u16::from_be_bytes([fci[0], fci[1]]);
+            let blp = u16::from_be_bytes([fci[2], fci[3]]);
+
+            lost.push(pid);
+            for i in 0..16 {
+                if blp & (1 << i) != 0 {
+                    lost.push(pid.wrapping_add(i + 1));
+                }
+            }
+        }
+
+        Some(Self {
+            sender_ssrc,
+            media_ssrc,
+            lost,
+        })
+    }
+}
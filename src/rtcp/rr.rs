@@ -0,0 +1,248 @@
+use std::time::Duration;
+
+/// One reception report block (RFC 3550 §6.4.1), from an RR or SR
+#[derive(Clone, Debug)]
+pub struct ReceptionReport {
+    pub ssrc: u32,
+    pub fraction_lost: u8,
+    pub cumulative_lost: u32,
+    pub highest_sequence: u32,
+    pub jitter: u32,
+    /// Middle 32 bits of the NTP timestamp of the last SR received (0 = none)
+    pub lsr: u32,
+    /// Delay since that SR, in units of 1/65536 s
+    pub dlsr: u32,
+}
+
+impl ReceptionReport {
+    /// Round-trip time theo RFC 3550 §6.4.1: A - LSR - DLSR, với A là
+    /// middle 32 bits của NTP time lúc nhận. None khi client chưa nhận SR nào
+    pub fn round_trip_time(&self, arrival_ntp_middle: u32) -> Option<Duration> {
+        if self.lsr == 0 {
+            return None;
+        }
+        let rtt = arrival_ntp_middle.wrapping_sub(self.lsr).wrapping_sub(self.dlsr);
+        // Giá trị "âm" (clock lệch, LSR lạ) wrap thành rất lớn → bỏ qua
+        if rtt > 0x8000_0000 {
+            return None;
+        }
+        Some(Duration::from_micros(rtt as u64 * 1_000_000 / 65536))
+    }
+}
+
+/// RTCP Receiver Report (RFC 3550 §6.4.2): PT=201
+#[derive(Debug)]
+pub struct ReceiverReport {
+    pub sender_ssrc: u32,
+    pub reports: Vec<ReceptionReport>,
+}
+
+impl ReceiverReport {
+    /// Parse one RTCP packet (already split out of a compound packet).
+    /// Return None nếu không phải RR hoặc bị truncated
+    pub fn parse(packet: &[u8]) -> Option<Self> {
+        if packet.len() < 8 || packet[1] != 201 {
+            return None;
+        }
+        let count = (packet[0] & 0x1F) as usize;
+        if packet.len() < 8 + count * 24 {
+            return None;
+        }
+
+        let word = |at: usize| u32::from_be_bytes([packet[at], packet[at + 1], packet[at + 2], packet[at + 3]]);
+
+        let reports = (0..count)
+            .map(|i| {
+                let at = 8 + i * 24;
+                ReceptionReport {
+                    ssrc: word(at),
+                    fraction_lost: packet[at + 4],
+                    cumulative_lost: word(at + 4) & 0x00FF_FFFF,
+                    highest_sequence: word(at + 8),
+                    jitter: word(at + 12),
+                    lsr: word(at + 16),
+                    dlsr: word(at + 20),
+                }
+            })
+            .collect();
+
+        Some(Self {
+            sender_ssrc: word(4),
+            reports,
+        })
+    }
+
+    /// Serialize RR (một report block cho mỗi entry trong `reports`)
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(8 + self.reports.len() * 24);
+
+        // V=2, P=0, RC=count, PT=201, length = words - 1
+        buf.push(0x80 | (self.reports.len() as u8 & 0x1F));
+        buf.push(201);
+        buf.extend_from_slice(&(1 + 6 * self.reports.len() as u16).to_be_bytes());
+        buf.extend_from_slice(&self.sender_ssrc.to_be_bytes());
+
+        for report in &self.reports {
+            buf.extend_from_slice(&report.ssrc.to_be_bytes());
+            // fraction lost (8 bits) + cumulative lost (24 bits)
+            buf.extend_from_slice(&((report.fraction_lost as u32) << 24 | (report.cumulative_lost & 0x00FF_FFFF)).to_be_bytes());
+            buf.extend_from_slice(&report.highest_sequence.to_be_bytes());
+            buf.extend_from_slice(&report.jitter.to_be_bytes());
+            buf.extend_from_slice(&report.lsr.to_be_bytes());
+            buf.extend_from_slice(&report.dlsr.to_be_bytes());
+        }
+
+        buf
+    }
+}
+
+/// Sequence-based reception statistics của một SSRC nhận được (RFC 3550
+/// Appendix A.3), cho loss fields của report block mà receiver gửi đi
+///
+/// Extended highest sequence đếm wrap của 16-bit sequence; packet đến trễ
+/// (reordered) vẫn được đếm là received nhưng không lùi highest sequence.
+#[derive(Debug, Default)]
+pub struct ReceptionStats {
+    base_sequence: u16,
+    max_sequence: u16,
+    /// Số lần sequence wrap, shifted (<< 16)
+    cycles: u32,
+    received: u32,
+    expected_prior: u32,
+    received_prior: u32,
+    started: bool,
+}
+
+impl ReceptionStats {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record one received packet
+    pub fn on_packet(&mut self, sequence: u16) {
+        if !self.started {
+            self.started = true;
+            self.base_sequence = sequence;
+            self.max_sequence = sequence;
+        } else if sequence.wrapping_sub(self.max_sequence) < 0x8000 {
+            if sequence < self.max_sequence {
+                self.cycles = self.cycles.wrapping_add(1 << 16);
+            }
+            self.max_sequence = sequence;
+        }
+        self.received = self.received.wrapping_add(1);
+    }
+
+    /// Extended highest sequence number received
+    pub fn highest_sequence(&self) -> u32 {
+        self.cycles | self.max_sequence as u32
+    }
+
+    /// Packets expected từ packet đầu tiên tới highest sequence
+    fn expected(&self) -> u32 {
+        self.highest_sequence().wrapping_sub(self.base_sequence as u32).wrapping_add(1)
+    }
+
+    /// Report block về `ssrc` (jitter/LSR/DLSR để 0 cho caller điền). Fraction
+    /// lost tính trên khoảng từ report trước, nên mỗi lần gọi bắt đầu interval mới.
+    /// None khi chưa nhận packet nào
+    pub fn report(&mut self, ssrc: u32) -> Option<ReceptionReport> {
+        if !self.started {
+            return None;
+        }
+        let expected = self.expected();
+        let expected_interval = expected.wrapping_sub(self.expected_prior);
+        let received_interval = self.received.wrapping_sub(self.received_prior);
+        self.expected_prior = expected;
+        self.received_prior = self.received;
+
+        let lost_interval = expected_interval as i64 - received_interval as i64;
+        let fraction_lost = if expected_interval == 0 || lost_interval <= 0 {
+            0
+        } else {
+            ((lost_interval << 8) / expected_interval as i64) as u8
+        };
+        // Cumulative lost là signed 24-bit; duplicates có thể làm nó âm → clamp 0
+        let cumulative_lost = (expected as i64 - self.received as i64).clamp(0, 0x7F_FFFF) as u32;
+
+        Some(ReceptionReport {
+            ssrc,
+            fraction_lost,
+            cumulative_lost,
+            highest_sequence: self.highest_sequence(),
+            jitter: 0,
+            lsr: 0,
+            dlsr: 0,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn report(ssrc: u32) -> ReceptionReport {
+        ReceptionReport {
+            ssrc,
+            fraction_lost: 64,
+            cumulative_lost: 0x01_2345,
+            highest_sequence: 0x0001_0010,
+            jitter: 377,
+            lsr: 0xAABB_CCDD,
+            dlsr: 65536,
+        }
+    }
+
+    #[test]
+    fn to_bytes_round_trips_through_parse() {
+        let rr = ReceiverReport { sender_ssrc: 0x1111_2222, reports: vec![report(0xDEAD_BEEF), report(7)] };
+        let bytes = rr.to_bytes();
+        assert_eq!(bytes.len(), 8 + 2 * 24);
+        assert_eq!(bytes[0], 0x82);
+        assert_eq!(u16::from_be_bytes([bytes[2], bytes[3]]) as usize, bytes.len() / 4 - 1);
+
+        let parsed = ReceiverReport::parse(&bytes).unwrap();
+        assert_eq!(parsed.sender_ssrc, 0x1111_2222);
+        assert_eq!(parsed.reports.len(), 2);
+        let block = &parsed.reports[0];
+        assert_eq!(block.ssrc, 0xDEAD_BEEF);
+        assert_eq!((block.fraction_lost, block.cumulative_lost), (64, 0x01_2345));
+        assert_eq!((block.highest_sequence, block.jitter), (0x0001_0010, 377));
+        assert_eq!((block.lsr, block.dlsr), (0xAABB_CCDD, 65536));
+    }
+
+    #[test]
+    fn stats_count_loss_per_interval() {
+        let mut stats = ReceptionStats::new();
+        assert!(stats.report(1).is_none());
+
+        // 10..=19 với 12 và 13 mất: 8 của 10 expected
+        for sequence in (10..20).filter(|s| *s != 12 && *s != 13) {
+            stats.on_packet(sequence);
+        }
+        let block = stats.report(1).unwrap();
+        assert_eq!(block.highest_sequence, 19);
+        assert_eq!(block.cumulative_lost, 2);
+        assert_eq!(block.fraction_lost, 51); // 2/10 × 256
+
+        // Interval tiếp theo không mất gì: fraction về 0, cumulative giữ nguyên
+        for sequence in 20..30 {
+            stats.on_packet(sequence);
+        }
+        let block = stats.report(1).unwrap();
+        assert_eq!((block.fraction_lost, block.cumulative_lost), (0, 2));
+    }
+
+    #[test]
+    fn stats_extend_sequence_across_wrap() {
+        let mut stats = ReceptionStats::new();
+        for sequence in [65534, 65535, 0, 1] {
+            stats.on_packet(sequence);
+        }
+        // Packet trễ từ trước wrap không lùi highest sequence
+        stats.on_packet(65535);
+        let block = stats.report(1).unwrap();
+        assert_eq!(block.highest_sequence, (1 << 16) | 1);
+        assert_eq!(block.cumulative_lost, 0);
+    }
+}
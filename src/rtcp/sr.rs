@@ -1,31 +1,78 @@
+use std::sync::atomic::{AtomicU32, Ordering};
 use std::time::{SystemTime, UNIX_EPOCH};
 
+// NTP epoch is 1900, Unix epoch is 1970 (70 years difference)
+const NTP_OFFSET: u64 = 2_208_988_800;
+
 /// RTCP Sender Report (SR)
 /// Gửi thống kê về stream để client không timeout
+///
+/// Packet/octet counts là 32-bit và wrap về 0 khi tràn (RFC 3550 §6.4.1:
+/// receiver tự xử lý wrap), nên một stream chạy lâu không bao giờ panic.
+/// Counters là atomic để metrics đọc được mà không cần lock của sender.
+///
+/// RTP timestamp trong SR là timestamp thật của stream (`set_rtp_timestamp`,
+/// lấy từ packetizer ngay trước khi build SR), để receiver map được NTP ↔ RTP
+/// cho lip-sync và jitter.
 #[derive(Debug)]
 pub struct SenderReport {
     pub ssrc: u32,
-    pub packet_count: u32,
-    pub octet_count: u32,
+    packet_count: AtomicU32,
+    octet_count: AtomicU32,
+    rtp_timestamp: AtomicU32,
+}
+
+impl Clone for SenderReport {
+    fn clone(&self) -> Self {
+        let (packets, octets) = self.counts();
+        Self {
+            ssrc: self.ssrc,
+            packet_count: AtomicU32::new(packets),
+            octet_count: AtomicU32::new(octets),
+            rtp_timestamp: AtomicU32::new(self.rtp_timestamp()),
+        }
+    }
 }
 
 impl SenderReport {
     pub fn new(ssrc: u32) -> Self {
         Self {
             ssrc,
-            packet_count: 0,
-            octet_count: 0,
+            packet_count: AtomicU32::new(0),
+            octet_count: AtomicU32::new(0),
+            rtp_timestamp: AtomicU32::new(0),
         }
     }
 
-    /// Update counters
-    pub fn add_packet(&mut self, size: usize) {
-        self.packet_count += 1;
-        self.octet_count += size as u32;
+    /// RTP timestamp (trong timestamp space của SSRC này) tương ứng với
+    /// thời điểm SR sắp được build
+    pub fn set_rtp_timestamp(&self, timestamp: u32) {
+        self.rtp_timestamp.store(timestamp, Ordering::Relaxed);
+    }
+
+    pub fn rtp_timestamp(&self) -> u32 {
+        self.rtp_timestamp.load(Ordering::Relaxed)
     }
 
-    /// Serialize SR packet theo RFC 3550
-    pub fn to_bytes(&self) -> Vec<u8> {
+    /// Update counters (wrapping, payload octets only)
+    pub fn add_packet(&self, size: usize) {
+        // fetch_add trên atomic luôn wrap, không panic ở debug build
+        self.packet_count.fetch_add(1, Ordering::Relaxed);
+        self.octet_count.fetch_add(size as u32, Ordering::Relaxed);
+    }
+
+    /// Current (packet_count, octet_count), lock-free
+    pub fn counts(&self) -> (u32, u32) {
+        (
+            self.packet_count.load(Ordering::Relaxed),
+            self.octet_count.load(Ordering::Relaxed),
+        )
+    }
+
+    /// Serialize SR packet theo RFC 3550: NTP timestamp từ `now`
+    /// (`config.clock.wall()`), RTP timestamp từ `set_rtp_timestamp`. Caller
+    /// set cả hai cùng lúc để cặp NTP/RTP chỉ cùng một instant
+    pub fn to_bytes(&self, now: SystemTime) -> Vec<u8> {
         let mut buf = Vec::with_capacity(28);
         
         // RTCP Header
@@ -40,32 +87,35 @@ impl SenderReport {
         buf.extend_from_slice(&self.ssrc.to_be_bytes());
         
         // NTP Timestamp (64 bits)
-        let (ntp_secs, ntp_frac) = Self::get_ntp_timestamp();
+        let (ntp_secs, ntp_frac) = Self::ntp_timestamp(now);
         buf.extend_from_slice(&ntp_secs.to_be_bytes());
         buf.extend_from_slice(&ntp_frac.to_be_bytes());
         
         // RTP Timestamp (32 bits) - tương ứng với NTP
-        let rtp_ts = Self::ntp_to_rtp_timestamp(ntp_secs, ntp_frac);
-        buf.extend_from_slice(&rtp_ts.to_be_bytes());
+        buf.extend_from_slice(&self.rtp_timestamp().to_be_bytes());
         
+        let (packet_count, octet_count) = self.counts();
+
         // Sender's packet count
-        buf.extend_from_slice(&self.packet_count.to_be_bytes());
+        buf.extend_from_slice(&packet_count.to_be_bytes());
         
         // Sender's octet count
-        buf.extend_from_slice(&self.octet_count.to_be_bytes());
+        buf.extend_from_slice(&octet_count.to_be_bytes());
         
         buf
     }
 
-    /// Get NTP timestamp (seconds, fractional seconds)
-    fn get_ntp_timestamp() -> (u32, u32) {
-        let now = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .unwrap();
-        
-        // NTP epoch is 1900, Unix epoch is 1970 (70 years difference)
-        const NTP_OFFSET: u64 = 2_208_988_800;
-        
+    /// Middle 32 bits of the NTP time `now` (the LSR/DLSR time base)
+    pub fn ntp_middle32(now: SystemTime) -> u32 {
+        let (secs, frac) = Self::ntp_timestamp(now);
+        (secs << 16) | (frac >> 16)
+    }
+
+    /// NTP timestamp (seconds, fractional seconds); NTP seconds wrap ở u32
+    /// (era 1, từ 2036) giống RTP timestamp
+    fn ntp_timestamp(now: SystemTime) -> (u32, u32) {
+        let now = now.duration_since(UNIX_EPOCH).unwrap_or_default();
+
         let secs = now.as_secs() + NTP_OFFSET;
         let nanos = now.subsec_nanos();
         
@@ -74,15 +124,55 @@ impl SenderReport {
         
         (secs as u32, frac as u32)
     }
+}
 
-    /// Convert NTP to RTP timestamp (90kHz)
-    fn ntp_to_rtp_timestamp(ntp_secs: u32, ntp_frac: u32) -> u32 {
-        // Simplified: chỉ lấy giây * 90000
-        // Trong production nên chính xác hơn
-        let secs = ntp_secs as u64;
-        let frac = (ntp_frac as u64) * 1_000_000_000 >> 32;
-        let total_nanos = secs * 1_000_000_000 + frac;
-        
-        ((total_nanos * 90) / 1_000_000) as u32
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn word(bytes: &[u8], at: usize) -> u32 {
+        u32::from_be_bytes([bytes[at], bytes[at + 1], bytes[at + 2], bytes[at + 3]])
+    }
+
+    #[test]
+    fn counters_wrap_at_u32_max() {
+        let report = SenderReport::new(0xCAFE_F00D);
+        report.packet_count.store(u32::MAX - 1, Ordering::Relaxed);
+        report.octet_count.store(u32::MAX - 100, Ordering::Relaxed);
+
+        report.add_packet(1200);
+        assert_eq!(report.counts(), (u32::MAX, 1099));
+        report.add_packet(1);
+        assert_eq!(report.counts(), (0, 1100));
+
+        // Wire fields mang giá trị đã wrap
+        let bytes = report.to_bytes(UNIX_EPOCH);
+        assert_eq!((word(&bytes, 20), word(&bytes, 24)), (0, 1100));
+    }
+
+    #[test]
+    fn clone_snapshots_counters() {
+        let report = SenderReport::new(7);
+        report.add_packet(10);
+        report.set_rtp_timestamp(90_000);
+        let copy = report.clone();
+        report.add_packet(10);
+        assert_eq!(copy.counts(), (1, 10));
+        assert_eq!(copy.rtp_timestamp(), 90_000);
+        assert_eq!(report.counts(), (2, 20));
+    }
+
+    #[test]
+    fn layout_is_a_28_byte_sender_report() {
+        let report = SenderReport::new(0x0102_0304);
+        report.set_rtp_timestamp(0xDEAD_BEEF);
+        // 1.5s sau Unix epoch: NTP seconds = offset + 1, fraction = 2^31
+        let bytes = report.to_bytes(UNIX_EPOCH + std::time::Duration::from_millis(1500));
+        assert_eq!(bytes.len(), 28);
+        assert_eq!(&bytes[..4], [0x80, 200, 0, 6]);
+        assert_eq!(word(&bytes, 4), 0x0102_0304);
+        assert_eq!(word(&bytes, 8), (NTP_OFFSET + 1) as u32);
+        assert_eq!(word(&bytes, 12), 0x8000_0000);
+        assert_eq!(word(&bytes, 16), 0xDEAD_BEEF);
     }
 }
@@ -0,0 +1,117 @@
+use super::packet::{RtpHeader, RtpPacket};
+use super::stamp::RtpIdentity;
+
+/// Payload type động cho AAC (H.264 dùng 96)
+pub const AAC_PAYLOAD_TYPE: u8 = 97;
+/// Samples mỗi AAC-LC access unit (một raw frame)
+pub const SAMPLES_PER_FRAME: u32 = 1024;
+/// Sample rate / channels FFmpeg được yêu cầu xuất, nên SDP biết trước config
+pub const SAMPLE_RATE: u32 = 48000;
+pub const CHANNELS: u8 = 2;
+
+/// Max RTP payload, như H.264 packetizer
+const MTU: usize = 1400;
+/// AU-headers-length (16 bits) + một AU-header (sizelength 13 + indexlength 3)
+const AU_HEADER_SECTION: usize = 4;
+/// AU-size là 13 bits
+const MAX_AU_SIZE: usize = (1 << 13) - 1;
+
+/// AAC RTP Packetizer theo RFC 3640 (mode AAC-hbr)
+///
+/// Mỗi RTP packet mang một access unit, sau AU header section gồm đúng một
+/// AU-header. AU lớn hơn MTU được chia thành fragments (RFC 3640 §3.2.3):
+/// mọi fragment mang AU-size của cả AU, marker chỉ ở fragment cuối.
+/// RTP clock là sample rate.
+pub struct AacPacketizer {
+    sequence: u16,
+    timestamp: u32,
+    ssrc: u32,
+    payload_type: u8,
+}
+
+impl AacPacketizer {
+    /// Random SSRC, initial sequence và timestamp base
+    pub fn new() -> Self {
+        let identity = RtpIdentity::random();
+        Self {
+            sequence: identity.seq_offset,
+            timestamp: identity.ts_offset,
+            ssrc: identity.ssrc,
+            payload_type: AAC_PAYLOAD_TYPE,
+        }
+    }
+
+    /// Sequence number của packet kế tiếp
+    pub fn sequence(&self) -> u16 {
+        self.sequence
+    }
+
+    /// RTP timestamp của access unit kế tiếp
+    pub fn timestamp(&self) -> u32 {
+        self.timestamp
+    }
+
+    /// Packetize one raw AAC frame (ADTS header stripped), then advance the
+    /// timestamp by one frame. Frames không biểu diễn được bằng AU-size
+    /// 13 bits bị bỏ (trả về rỗng)
+    pub fn packetize(&mut self, frame: &[u8]) -> Vec<RtpPacket> {
+        if frame.is_empty() || frame.len() > MAX_AU_SIZE {
+            return Vec::new();
+        }
+
+        // AU-headers-length = 16 bits; AU-header = AU-size(13) | AU-Index(3) = 0
+        let mut section = [0u8; AU_HEADER_SECTION];
+        section[..2].copy_from_slice(&16u16.to_be_bytes());
+        section[2..].copy_from_slice(&((frame.len() as u16) << 3).to_be_bytes());
+
+        let chunks: Vec<&[u8]> = frame.chunks(MTU - AU_HEADER_SECTION).collect();
+        let packets = chunks
+            .iter()
+            .enumerate()
+            .map(|(i, chunk)| {
+                let mut header = RtpHeader::new(self.payload_type, self.sequence, self.timestamp, self.ssrc);
+                header.marker = i == chunks.len() - 1;
+                self.sequence = self.sequence.wrapping_add(1);
+
+                let mut payload = Vec::with_capacity(AU_HEADER_SECTION + chunk.len());
+                payload.extend_from_slice(&section);
+                payload.extend_from_slice(chunk);
+                RtpPacket::new(header, payload)
+            })
+            .collect();
+
+        self.timestamp = self.timestamp.wrapping_add(SAMPLES_PER_FRAME);
+        packets
+    }
+}
+
+impl Default for AacPacketizer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// AudioSpecificConfig (ISO 14496-3 §1.6.2.1) của AAC-LC: object type 2,
+/// sampling frequency index, channel configuration. None nếu sample rate
+/// không có index
+pub fn audio_specific_config(sample_rate: u32, channels: u8) -> Option<u16> {
+    const RATES: [u32; 13] = [96000, 88200, 64000, 48000, 44100, 32000, 24000, 22050, 16000, 12000, 11025, 8000, 7350];
+    let index = RATES.iter().position(|rate| *rate == sample_rate)? as u16;
+    Some((2 << 11) | (index << 7) | ((channels as u16 & 0x0F) << 3))
+}
+
+/// SDP media section cho audio track (mpeg4-generic, AAC-hbr)
+pub fn sdp_media(control: &str) -> String {
+    let config = audio_specific_config(SAMPLE_RATE, CHANNELS).unwrap_or_default();
+    format!(
+        "m=audio 0 RTP/AVP {pt}\r\n\
+         a=rtpmap:{pt} mpeg4-generic/{rate}/{channels}\r\n\
+         a=fmtp:{pt} streamtype=5;profile-level-id=1;mode=AAC-hbr;sizelength=13;indexlength=3;indexdeltalength=3;config={config:04x}\r\n\
+         a=control:{control}\r\n",
+        pt = AAC_PAYLOAD_TYPE,
+        rate = SAMPLE_RATE,
+        channels = CHANNELS,
+        config = config,
+        control = control
+    )
+}
@@ -0,0 +1,249 @@
+use super::packet::RtpPacket;
+use std::time::{Duration, Instant};
+
+/// FU-A chưa xong lâu hơn mức này thì bị bỏ (`H264Depacketizer::expire`)
+pub const REASSEMBLY_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Lỗi khi reassemble H.264 payload
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DepacketizeError {
+    /// Payload rỗng hoặc STAP-A/FU-A ngắn hơn header của nó
+    Truncated,
+    /// FU header có cả S và E bit
+    InvalidFuHeader,
+    /// FU-A middle/end fragment không có start fragment đi trước
+    UnexpectedFragment,
+    /// NAL unit type không hỗ trợ (STAP-B, MTAP, FU-B, ...)
+    Unsupported(u8),
+}
+
+/// H.264 RTP depacketizer (RFC 6184): Single NAL Unit, STAP-A và FU-A
+///
+/// Ngược lại của `H264Packetizer`: packet theo thứ tự sequence vào, NALUs
+/// hoàn chỉnh (không start code) ra. Dùng cho round-trip validation.
+///
+/// FU-A chưa xong bị bỏ (đếm vào `lost_nalus`) khi packet kế tiếp không phải
+/// fragment tiếp theo của nó: sequence nhảy, timestamp đổi (access unit mới),
+/// hoặc NALU khác bắt đầu (kể cả fragment mang NAL type khác với start
+/// fragment), hoặc quá `REASSEMBLY_TIMEOUT` (`expire`). Các fragment còn
+/// lại của NALU đó sau đó trả `UnexpectedFragment`.
+#[derive(Default)]
+pub struct H264Depacketizer {
+    /// FU-A đang reassemble
+    fragment: Option<Fragment>,
+    lost_nalus: u64,
+}
+
+/// Partial FU-A: NAL header đã dựng lại + data, và vị trí fragment kế tiếp
+struct Fragment {
+    nalu: Vec<u8>,
+    timestamp: u32,
+    next_sequence: u16,
+    started: Instant,
+}
+
+impl H264Depacketizer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// FU-A reassemblies bị bỏ vì thiếu fragment
+    pub fn lost_nalus(&self) -> u64 {
+        self.lost_nalus
+    }
+
+    /// Drop an FU-A reassembly older than `REASSEMBLY_TIMEOUT` at `now` (gọi
+    /// theo arrival time, hoặc khi stream ngừng); true nếu có NALU bị bỏ
+    pub fn expire(&mut self, now: Instant) -> bool {
+        let stale = self.fragment.as_ref().is_some_and(|f| now.saturating_duration_since(f.started) > REASSEMBLY_TIMEOUT);
+        if stale {
+            self.fragment = None;
+            self.lost_nalus += 1;
+        }
+        stale
+    }
+
+    /// Feed one packet; returns the NALUs it completes
+    pub fn push(&mut self, packet: &RtpPacket) -> Result<Vec<Vec<u8>>, DepacketizeError> {
+        let payload = &packet.payload;
+        let header = *payload.first().ok_or(DepacketizeError::Truncated)?;
+        let continues = |f: &Fragment| {
+            header & 0x1F == 28
+                && payload.get(1).is_some_and(|fu| fu & 0x80 == 0 && fu & 0x1F == f.nalu[0] & 0x1F)
+                && f.timestamp == packet.header.timestamp
+                && f.next_sequence == packet.header.sequence
+        };
+        if self.fragment.as_ref().is_some_and(|f| !continues(f)) {
+            self.fragment = None;
+            self.lost_nalus += 1;
+        }
+
+        match header & 0x1F {
+            1..=23 => Ok(vec![payload.clone()]),
+            24 => Self::stap_a(&payload[1..]),
+            28 => self.fu_a(header, &payload[1..], packet),
+            other => Err(DepacketizeError::Unsupported(other)),
+        }
+    }
+
+    /// STAP-A: chuỗi (16-bit size, NALU)
+    fn stap_a(mut data: &[u8]) -> Result<Vec<Vec<u8>>, DepacketizeError> {
+        let mut nalus = Vec::new();
+        while !data.is_empty() {
+            let size = data.get(..2).ok_or(DepacketizeError::Truncated)?;
+            let size = u16::from_be_bytes([size[0], size[1]]) as usize;
+            let nalu = data.get(2..2 + size).ok_or(DepacketizeError::Truncated)?;
+            nalus.push(nalu.to_vec());
+            data = &data[2 + size..];
+        }
+        Ok(nalus)
+    }
+
+    fn fu_a(&mut self, indicator: u8, data: &[u8], packet: &RtpPacket) -> Result<Vec<Vec<u8>>, DepacketizeError> {
+        let fu_header = *data.first().ok_or(DepacketizeError::Truncated)?;
+        let (start, end) = (fu_header & 0x80 != 0, fu_header & 0x40 != 0);
+        if start && end {
+            return Err(DepacketizeError::InvalidFuHeader);
+        }
+
+        if start {
+            // NAL header = F/NRI của FU indicator + type của FU header
+            let mut nalu = vec![(indicator & 0xE0) | (fu_header & 0x1F)];
+            nalu.extend_from_slice(&data[1..]);
+            self.fragment = Some(Fragment {
+                nalu,
+                timestamp: packet.header.timestamp,
+                next_sequence: packet.header.sequence.wrapping_add(1),
+                started: Instant::now(),
+            });
+            return Ok(Vec::new());
+        }
+
+        let fragment = self.fragment.as_mut().ok_or(DepacketizeError::UnexpectedFragment)?;
+        fragment.nalu.extend_from_slice(&data[1..]);
+        fragment.next_sequence = fragment.next_sequence.wrapping_add(1);
+        if end {
+            return Ok(self.fragment.take().map(|f| f.nalu).into_iter().collect());
+        }
+        Ok(Vec::new())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rtp::h264::H264Packetizer;
+
+    /// NALU cần đúng `fragments` FU-A packets ở MTU mặc định (1400)
+    fn fu_a(packetizer: &mut H264Packetizer, fragments: usize) -> (Vec<u8>, Vec<RtpPacket>) {
+        let mut nalu = vec![0x65];
+        nalu.extend((0..1398 * fragments).map(|i| (i % 251) as u8));
+        let packets = packetizer.packetize(&nalu, true);
+        assert_eq!(packets.len(), fragments);
+        (nalu, packets)
+    }
+
+    fn push_all(depacketizer: &mut H264Depacketizer, packets: &[RtpPacket]) -> Vec<Result<Vec<Vec<u8>>, DepacketizeError>> {
+        packets.iter().map(|packet| depacketizer.push(packet)).collect()
+    }
+
+    #[test]
+    fn missing_middle_fragment_drops_the_nalu() {
+        let mut packetizer = H264Packetizer::with_ssrc(1);
+        let (_, mut packets) = fu_a(&mut packetizer, 3);
+        packets.remove(1);
+        let mut depacketizer = H264Depacketizer::new();
+
+        let results = push_all(&mut depacketizer, &packets);
+        assert_eq!(results[0], Ok(Vec::new()));
+        // Sequence nhảy: partial bị bỏ, end fragment không còn start
+        assert_eq!(results[1], Err(DepacketizeError::UnexpectedFragment));
+        assert_eq!(depacketizer.lost_nalus(), 1);
+    }
+
+    #[test]
+    fn missing_end_fragment_is_dropped_when_the_next_access_unit_starts() {
+        let mut packetizer = H264Packetizer::with_ssrc(1);
+        let (_, mut packets) = fu_a(&mut packetizer, 3);
+        packets.pop();
+        packetizer.end_access_unit();
+        let mut next = packetizer.packetize(&[0x41, 0x9A, 0x02], true);
+        // Packet mất không tiêu sequence ở receiver: AU mới tới ngay sau fragment 2
+        next[0].header.sequence = packets[1].header.sequence.wrapping_add(1);
+        let mut depacketizer = H264Depacketizer::new();
+
+        assert!(push_all(&mut depacketizer, &packets).iter().all(|r| r == &Ok(Vec::new())));
+        assert_eq!(depacketizer.push(&next[0]), Ok(vec![vec![0x41, 0x9A, 0x02]]));
+        assert_eq!(depacketizer.lost_nalus(), 1);
+    }
+
+    #[test]
+    fn stale_reassembly_expires_after_the_timeout() {
+        let mut packetizer = H264Packetizer::with_ssrc(1);
+        let (nalu, packets) = fu_a(&mut packetizer, 3);
+        let mut depacketizer = H264Depacketizer::new();
+        depacketizer.push(&packets[0]).unwrap();
+
+        assert!(!depacketizer.expire(Instant::now()));
+        assert!(depacketizer.expire(Instant::now() + REASSEMBLY_TIMEOUT + Duration::from_millis(1)));
+        assert!(!depacketizer.expire(Instant::now() + REASSEMBLY_TIMEOUT * 10));
+        assert_eq!(depacketizer.lost_nalus(), 1);
+        assert_eq!(depacketizer.push(&packets[1]), Err(DepacketizeError::UnexpectedFragment));
+
+        // Reassembly kế tiếp không bị ảnh hưởng
+        let mut depacketizer = H264Depacketizer::new();
+        let results = push_all(&mut depacketizer, &packets);
+        assert_eq!(results[2], Ok(vec![nalu]));
+        assert_eq!(depacketizer.lost_nalus(), 0);
+    }
+
+    /// Packetize → serialize → `RtpPacket::parse` → depacketize
+    fn round_trip(nalu: &[u8]) -> (usize, Vec<Vec<u8>>) {
+        let packets = H264Packetizer::with_ssrc(1).packetize(nalu, true);
+        let mut depacketizer = H264Depacketizer::new();
+        let mut nalus = Vec::new();
+        for packet in &packets {
+            let parsed = RtpPacket::parse(&packet.to_bytes()).unwrap();
+            nalus.extend(depacketizer.push(&parsed).unwrap());
+        }
+        assert_eq!(depacketizer.lost_nalus(), 0);
+        (packets.len(), nalus)
+    }
+
+    #[test]
+    fn nalus_round_trip_at_and_just_past_the_mtu() {
+        let nalu = |len: usize| -> Vec<u8> { std::iter::once(0x65).chain((1..len).map(|i| (i % 253) as u8)).collect() };
+
+        // Vừa MTU: single NAL; MTU + 1: hai FU-A (fragment cuối 1 byte data)
+        assert_eq!(round_trip(&nalu(1400)), (1, vec![nalu(1400)]));
+        assert_eq!(round_trip(&nalu(1401)), (2, vec![nalu(1401)]));
+        assert_eq!(round_trip(&nalu(1 + 1398 * 2)), (2, vec![nalu(1 + 1398 * 2)]));
+        assert_eq!(round_trip(&nalu(2 + 1398 * 2)), (3, vec![nalu(2 + 1398 * 2)]));
+    }
+
+    #[test]
+    fn fu_a_type_mismatch_breaks_the_reassembly() {
+        let mut packetizer = H264Packetizer::with_ssrc(1);
+        let (_, mut packets) = fu_a(&mut packetizer, 3);
+        // Middle fragment mang NAL type khác (non-IDR) với start fragment
+        packets[1].payload[1] = (packets[1].payload[1] & !0x1F) | 1;
+        let mut depacketizer = H264Depacketizer::new();
+
+        let results = push_all(&mut depacketizer, &packets);
+        assert_eq!(results[0], Ok(Vec::new()));
+        assert_eq!(results[1], Err(DepacketizeError::UnexpectedFragment));
+        assert_eq!(results[2], Err(DepacketizeError::UnexpectedFragment));
+        assert_eq!(depacketizer.lost_nalus(), 1);
+    }
+
+    #[test]
+    fn malformed_payloads_are_errors() {
+        let packet = |payload: Vec<u8>| RtpPacket::new(crate::rtp::packet::RtpHeader::new(96, 0, 0, 1), payload);
+        let mut depacketizer = H264Depacketizer::new();
+        assert_eq!(depacketizer.push(&packet(vec![])), Err(DepacketizeError::Truncated));
+        assert_eq!(depacketizer.push(&packet(vec![0x7C])), Err(DepacketizeError::Truncated));
+        assert_eq!(depacketizer.push(&packet(vec![0x7C, 0xC5, 0x00])), Err(DepacketizeError::InvalidFuHeader));
+        assert_eq!(depacketizer.push(&packet(vec![0x78, 0x00, 0x05, 0x67])), Err(DepacketizeError::Truncated));
+        assert_eq!(depacketizer.push(&packet(vec![0x79, 0x00])), Err(DepacketizeError::Unsupported(25)));
+    }
+}
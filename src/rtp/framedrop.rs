@@ -0,0 +1,86 @@
+use crate::config::FrameDropPolicy;
+use std::time::{Duration, Instant};
+
+/// Số access unit liên tiếp trễ quá budget trước khi bắt đầu drop
+const OVER_BUDGET_STREAK: u32 = 5;
+
+/// Frame-drop decision cho UDP broadcast khi host không theo kịp
+///
+/// So sánh pacing clock (số AU × frame duration kể từ `reset`) với wall
+/// clock. Khi lag vượt một frame duration liên tục, drop non-keyframe AUs
+/// trước packetization cho tới keyframe kế tiếp (P-frames phía sau một
+/// frame bị drop không decode đúng được nữa), rồi đánh giá lại.
+pub struct FrameDropper {
+    policy: FrameDropPolicy,
+    frame_duration: Duration,
+    start: Instant,
+    frames: u64,
+    streak: u32,
+    skipping_to_keyframe: bool,
+    pub dropped: u64,
+    pub total: u64,
+}
+
+impl FrameDropper {
+    pub fn new(policy: FrameDropPolicy, frame_duration: Duration) -> Self {
+        Self {
+            policy,
+            frame_duration,
+            start: Instant::now(),
+            frames: 0,
+            streak: 0,
+            skipping_to_keyframe: false,
+            dropped: 0,
+            total: 0,
+        }
+    }
+
+    /// Restart the pacing clock (after idle periods or a pipeline restart)
+    pub fn reset(&mut self) {
+        self.start = Instant::now();
+        self.frames = 0;
+        self.streak = 0;
+        self.skipping_to_keyframe = false;
+    }
+
+    /// Decide for the next access unit. Dropped AUs still advance the pacing clock
+    pub fn should_drop(&mut self, is_keyframe: bool, now: Instant) -> bool {
+        let due = self.start + self.frame_duration * u32::try_from(self.frames).unwrap_or(u32::MAX);
+        let lag = now.saturating_duration_since(due);
+        self.frames += 1;
+        self.total += 1;
+
+        if self.policy == FrameDropPolicy::Never {
+            return false;
+        }
+
+        if lag > self.frame_duration {
+            self.streak += 1;
+        } else {
+            self.streak = 0;
+        }
+
+        if is_keyframe {
+            self.skipping_to_keyframe = false;
+            return false;
+        }
+        if self.streak >= OVER_BUDGET_STREAK && !self.skipping_to_keyframe {
+            println!("🐢 {:?} behind the pacing clock, dropping P-frames until next keyframe", lag);
+            self.skipping_to_keyframe = true;
+        }
+
+        if self.skipping_to_keyframe {
+            self.dropped += 1;
+        }
+        self.skipping_to_keyframe
+    }
+
+    /// Fraction of access units dropped so far
+    pub fn drop_rate(&self) -> f64 {
+        if self.total == 0 {
+            0.0
+        } else {
+            self.dropped as f64 / self.total as f64
+        }
+    }
+}
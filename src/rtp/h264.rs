@@ -1,35 +1,206 @@
 use super::packet::{RtpHeader, RtpPacket};
+use super::stamp::RtpIdentity;
+use std::time::Duration;
 
 const MTU: usize = 1400; // Max RTP payload size (để tránh fragmentation)
+const MIN_MTU: usize = 64; // Sàn cho Blocksize quá nhỏ từ client
+const RTP_HEADER_LEN: usize = 12;
+/// `Blocksize` nhỏ nhất packetizer tôn trọng được (RTP header + MIN_MTU)
+pub const MIN_BLOCKSIZE: usize = RTP_HEADER_LEN + MIN_MTU;
+/// RTP clock của H.264 (RFC 6184)
+pub const CLOCK_RATE: u32 = 90_000;
+/// Frame rate FFmpeg output khi không cấu hình khác
+pub const DEFAULT_FPS: u32 = 30;
+
+/// Xử lý NALU cần nhiều FU-A fragments hơn giới hạn
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum OversizePolicy {
+    /// Gửi hết fragments nhưng trải theo batches trên frame interval (default)
+    #[default]
+    Pace,
+    /// Log và bỏ NALU (source lỗi sinh NALU vô lý); NALU cuối của AU được
+    /// thay bằng filler NAL mang marker
+    Drop,
+}
+
+impl OversizePolicy {
+    /// Parse `pace` / `drop`
+    pub fn parse(value: &str) -> Result<Self, String> {
+        match value {
+            "pace" => Ok(Self::Pace),
+            "drop" => Ok(Self::Drop),
+            _ => Err(format!("expected pace or drop, got '{}'", value)),
+        }
+    }
+}
+
+/// Giới hạn FU-A fragments mỗi NALU, chống burst hàng nghìn packets
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FragmentLimit {
+    pub max: usize,
+    pub policy: OversizePolicy,
+}
+
+/// RTP state của packetizer tại một thời điểm (cho logging / SR tự build)
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PacketizerState {
+    pub ssrc: u32,
+    /// Sequence number của packet kế tiếp
+    pub sequence: u16,
+    pub timestamp: u32,
+    /// Số lần sequence đã wrap qua 0 (extended seq = cycles << 16 | sequence)
+    pub cycles: u32,
+}
 
 /// H.264 RTP Packetizer theo RFC 6184
+///
+/// Getters và `snapshot` chỉ cần `&self` nên không lock gì; packetizer không
+/// có interior mutability, nên khi dùng chung giữa tasks (UDP path: sau
+/// `tokio::sync::Mutex`) snapshot phải đọc trong cùng lock với `packetize`
+/// để nhất quán với packet vừa gửi.
 pub struct H264Packetizer {
     sequence: u16,
     timestamp: u32,
     ssrc: u32,
+    cycles: u32,
     payload_type: u8,
+    mtu: usize,
+    /// Timestamp delta mỗi access unit (CLOCK_RATE / fps)
+    frame_ticks: u32,
+    fragment_limit: Option<FragmentLimit>,
+}
+
+impl Default for H264Packetizer {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl H264Packetizer {
-    pub fn new(ssrc: u32) -> Self {
+    /// Random SSRC, initial sequence và timestamp base (RFC 3550 §5.1)
+    pub fn new() -> Self {
+        Self::with_identity(RtpIdentity::random())
+    }
+
+    /// SSRC, initial sequence và timestamp base đã chọn sẵn (vd. lúc SETUP,
+    /// để RTP-Info của PLAY báo đúng seq/rtptime của packet đầu tiên)
+    pub fn with_identity(identity: RtpIdentity) -> Self {
+        let mut packetizer = Self::with_ssrc(identity.ssrc);
+        packetizer.sequence = identity.seq_offset;
+        packetizer.timestamp = identity.ts_offset;
+        packetizer
+    }
+
+    /// Deterministic: SSRC cố định, sequence và timestamp từ 0 (self-test)
+    pub fn with_ssrc(ssrc: u32) -> Self {
         Self {
             sequence: 0,
             timestamp: 0,
             ssrc,
+            cycles: 0,
             payload_type: 96, // Dynamic payload type cho H.264
+            mtu: MTU,
+            frame_ticks: CLOCK_RATE / DEFAULT_FPS,
+            fragment_limit: None,
         }
     }
 
+    pub fn ssrc(&self) -> u32 {
+        self.ssrc
+    }
+
+    /// Sequence number của packet kế tiếp
+    pub fn sequence(&self) -> u16 {
+        self.sequence
+    }
+
+    /// Sequence number wraparounds so far
+    pub fn cycles(&self) -> u32 {
+        self.cycles
+    }
+
+    /// SSRC, next sequence, current timestamp và cycles cùng lúc
+    pub fn snapshot(&self) -> PacketizerState {
+        PacketizerState {
+            ssrc: self.ssrc(),
+            sequence: self.sequence(),
+            timestamp: self.timestamp(),
+            cycles: self.cycles(),
+        }
+    }
+
+    fn advance_sequence(&mut self) {
+        self.sequence = self.sequence.wrapping_add(1);
+        if self.sequence == 0 {
+            self.cycles = self.cycles.wrapping_add(1);
+        }
+    }
+
+    /// RTP timestamp của access unit hiện tại
+    pub fn timestamp(&self) -> u32 {
+        self.timestamp
+    }
+
+    /// `Blocksize` the packetizer actually honors for a client request: the
+    /// request capped at the default MTU, or None below `MIN_BLOCKSIZE`
+    /// (SETUP rejects it rather than send larger packets than asked for)
+    pub fn effective_blocksize(requested: usize) -> Option<usize> {
+        (requested >= MIN_BLOCKSIZE).then(|| requested.min(RTP_HEADER_LEN + MTU))
+    }
+
+    /// Limit RTP packets to `blocksize` bytes (RTSP `Blocksize`, RTP header
+    /// included). FU-A overhead is accounted for when fragmenting.
+    /// `None` restores the default MTU
+    pub fn set_blocksize(&mut self, blocksize: Option<usize>) {
+        self.mtu = match blocksize {
+            Some(size) => size.saturating_sub(RTP_HEADER_LEN).clamp(MIN_MTU, MTU),
+            None => MTU,
+        };
+    }
+
+    pub fn set_fragment_limit(&mut self, limit: Option<FragmentLimit>) {
+        self.fragment_limit = limit;
+    }
+
+    /// Send offset của từng packet khi một NALU cần `fragments` > max packets
+    /// (chỉ với `OversizePolicy::Pace`): batches tối đa `max` packets, trải
+    /// đều trên frame interval. None = gửi ngay cả NALU
+    pub fn pacing_schedule(&self, fragments: usize) -> Option<Vec<Duration>> {
+        let batch = self
+            .fragment_limit
+            .filter(|limit| limit.policy == OversizePolicy::Pace)
+            .map(|limit| limit.max.max(1))
+            .filter(|batch| fragments > *batch)?;
+        let spacing = self.frame_duration() / fragments.div_ceil(batch) as u32;
+        Some((0..fragments).map(|i| spacing * (i / batch) as u32).collect())
+    }
+
     /// Packetize một NALU thành 1 hoặc nhiều RTP packets
     pub fn packetize(&mut self, nalu: &[u8], is_last: bool) -> Vec<RtpPacket> {
         if nalu.is_empty() {
             return Vec::new();
         }
 
+        if let Some(limit) = self.fragment_limit {
+            let fragments = if nalu.len() <= self.mtu { 1 } else { (nalu.len() - 1).div_ceil(self.mtu - 2) };
+            if fragments > limit.max && limit.policy == OversizePolicy::Drop {
+                println!("🚫 Dropped oversized NALU (type {}, {} bytes): {} FU-A fragments > max {}",
+                         nalu[0] & 0x1F, nalu.len(), fragments, limit.max);
+                if !is_last {
+                    return Vec::new();
+                }
+                // Packets trước của AU đã được gửi: filler NAL mang marker
+                // thay cho NALU cuối để receiver vẫn thấy AU kết thúc
+                let mut filler = self.keepalive();
+                filler.header.marker = true;
+                return vec![filler];
+            }
+        }
+
         let mut packets = Vec::new();
 
         // NALU nhỏ: gửi trọn trong 1 RTP packet (Single NAL Unit mode)
-        if nalu.len() <= MTU {
+        if nalu.len() <= self.mtu {
             let mut header = RtpHeader::new(
                 self.payload_type,
                 self.sequence,
@@ -41,7 +212,7 @@ impl H264Packetizer {
             let packet = RtpPacket::new(header, nalu.to_vec());
             packets.push(packet);
             
-            self.sequence = self.sequence.wrapping_add(1);
+            self.advance_sequence();
         } else {
             // NALU lớn: chia nhỏ bằng FU-A (Fragmentation Unit)
             packets = self.fragment_nalu(nalu, is_last);
@@ -50,6 +221,40 @@ impl H264Packetizer {
         packets
     }
 
+    /// Gom nhiều NALUs nhỏ (SPS + PPS) vào một STAP-A packet (RFC 6184
+    /// §5.7.1): STAP-A NAL header rồi mỗi NALU có 16-bit size prefix. Nếu tổng
+    /// vượt MTU thì fallback gửi từng NALU (single NAL / FU-A)
+    pub fn packetize_stap_a(&mut self, nalus: &[&[u8]]) -> Vec<RtpPacket> {
+        let nalus: Vec<&[u8]> = nalus.iter().copied().filter(|nalu| !nalu.is_empty()).collect();
+        let size = 1 + nalus.iter().map(|nalu| 2 + nalu.len()).sum::<usize>();
+        if nalus.len() < 2 || size > self.mtu || nalus.iter().any(|nalu| nalu.len() > u16::MAX as usize) {
+            return nalus.iter().flat_map(|nalu| self.packetize(nalu, false)).collect();
+        }
+
+        // F = OR của F bits, NRI = NRI lớn nhất, type 24
+        let forbidden = nalus.iter().fold(0, |f, nalu| f | (nalu[0] & 0x80));
+        let nri = nalus.iter().map(|nalu| nalu[0] & 0x60).max().unwrap_or(0);
+        let mut payload = Vec::with_capacity(size);
+        payload.push(forbidden | nri | 24);
+        for nalu in &nalus {
+            payload.extend_from_slice(&(nalu.len() as u16).to_be_bytes());
+            payload.extend_from_slice(nalu);
+        }
+
+        let header = RtpHeader::new(self.payload_type, self.sequence, self.timestamp, self.ssrc);
+        self.advance_sequence();
+        vec![RtpPacket::new(header, payload)]
+    }
+
+    /// RTP packet chứa một filler data NAL (type 12) ở timestamp hiện tại:
+    /// decoder bỏ qua nó, nhưng nó giữ NAT binding của UDP path còn sống
+    pub fn keepalive(&mut self) -> RtpPacket {
+        let header = RtpHeader::new(self.payload_type, self.sequence, self.timestamp, self.ssrc);
+        self.advance_sequence();
+        // NAL header (nal_ref_idc 0, type 12) + 0xFF filler + rbsp_trailing_bits
+        RtpPacket::new(header, vec![0x0C, 0xFF, 0xFF, 0x80])
+    }
+
     /// Fragment NALU lớn thành nhiều FU-A packets
     fn fragment_nalu(&mut self, nalu: &[u8], is_last_nalu: bool) -> Vec<RtpPacket> {
         let mut packets = Vec::new();
@@ -62,7 +267,7 @@ impl H264Packetizer {
         
         // Chia payload thành chunks
         let chunks: Vec<&[u8]> = nalu_payload
-            .chunks(MTU - 2) // -2 cho FU indicator + FU header
+            .chunks(self.mtu - 2) // -2 cho FU indicator + FU header
             .collect();
         
         for (i, chunk) in chunks.iter().enumerate() {
@@ -95,14 +300,31 @@ impl H264Packetizer {
             header.marker = is_last && is_last_nalu;
             
             packets.push(RtpPacket::new(header, payload));
-            self.sequence = self.sequence.wrapping_add(1);
+            self.advance_sequence();
         }
         
         packets
     }
 
+    /// Timestamp delta mỗi access unit, khớp output frame rate của FFmpeg
+    /// (`EncoderConfig::frame_ticks`)
+    pub fn set_frame_ticks(&mut self, ticks: u32) {
+        self.frame_ticks = ticks.max(1);
+    }
+
+    /// Wall-clock duration của một access unit, để pacing khớp với timestamps
+    pub fn frame_duration(&self) -> Duration {
+        Duration::from_secs_f64(self.frame_ticks as f64 / CLOCK_RATE as f64)
+    }
+
+    /// Advance to the next access unit: exactly one frame delta, gọi đúng
+    /// một lần sau mỗi AU (kể cả AU bị drop) để timeline đều nhau
+    pub fn end_access_unit(&mut self) {
+        self.increment_timestamp(self.frame_ticks);
+    }
+
     /// Tăng timestamp (gọi sau mỗi frame)
-    /// Với 30fps: timestamp += 90000/30 = 3000
+    /// Với 30fps: timestamp += 90000/30 = 3000 (xem `end_access_unit`)
     pub fn increment_timestamp(&mut self, duration_90khz: u32) {
         self.timestamp = self.timestamp.wrapping_add(duration_90khz);
     }
@@ -110,4 +332,216 @@ impl H264Packetizer {
     pub fn set_timestamp(&mut self, ts: u32) {
         self.timestamp = ts;
     }
+
+    /// Test hook: đặt sequence kế tiếp (vd. 0xFFF0) để ép wrap sớm
+    #[cfg(test)]
+    pub fn set_sequence(&mut self, sequence: u16) {
+        self.sequence = sequence;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::source::file::{AccessUnitSplitter, NaluParser};
+
+    fn limited(max: usize, policy: OversizePolicy) -> H264Packetizer {
+        let mut packetizer = H264Packetizer::with_ssrc(1);
+        packetizer.set_fragment_limit(Some(FragmentLimit { max, policy }));
+        packetizer
+    }
+
+    /// IDR NALU cần đúng `fragments` FU-A packets ở MTU mặc định
+    fn oversized_nalu(fragments: usize) -> Vec<u8> {
+        let mut nalu = vec![0x65];
+        nalu.resize(1 + (MTU - 2) * fragments, 0xAB);
+        nalu
+    }
+
+    #[test]
+    fn pace_policy_schedules_bounded_batches_over_frame_interval() {
+        let mut packetizer = limited(4, OversizePolicy::Pace);
+        let packets = packetizer.packetize(&oversized_nalu(10), true);
+        assert_eq!(packets.len(), 10);
+        assert!(packets.iter().all(|packet| packet.payload.len() <= MTU));
+
+        let schedule = packetizer.pacing_schedule(packets.len()).unwrap();
+        assert_eq!(schedule.len(), packets.len());
+        let spacing = packetizer.frame_duration() / 3;
+        assert_eq!(schedule[..4], [Duration::ZERO; 4]);
+        assert_eq!(schedule[4..8], [spacing; 4]);
+        assert_eq!(schedule[8..], [spacing * 2; 2]);
+        assert!(*schedule.last().unwrap() < packetizer.frame_duration());
+    }
+
+    #[test]
+    fn small_nalus_and_unlimited_packetizers_send_immediately() {
+        assert_eq!(limited(4, OversizePolicy::Pace).pacing_schedule(4), None);
+        assert_eq!(H264Packetizer::with_ssrc(1).pacing_schedule(1000), None);
+        assert_eq!(limited(4, OversizePolicy::Drop).pacing_schedule(1000), None);
+    }
+
+    #[test]
+    fn drop_policy_discards_oversized_nalu_without_consuming_sequence() {
+        let mut packetizer = limited(4, OversizePolicy::Drop);
+        assert!(packetizer.packetize(&oversized_nalu(5), false).is_empty());
+        assert_eq!(packetizer.sequence(), 0);
+        assert_eq!(packetizer.packetize(&oversized_nalu(4), true).len(), 4);
+    }
+
+    #[test]
+    fn drop_policy_keeps_the_marker_when_the_last_slice_is_dropped() {
+        let mut packetizer = limited(4, OversizePolicy::Drop);
+        let mut packets = packetizer.packetize(&[0x65, 0x88, 0x84], false);
+        packets.extend(packetizer.packetize(&oversized_nalu(5), true));
+
+        assert_eq!(packets.len(), 2);
+        assert!(!packets[0].header.marker);
+        let last = &packets[1];
+        assert!(last.header.marker);
+        assert_eq!(last.payload[0] & 0x1F, 12); // filler data NAL
+        assert_eq!(last.header.timestamp, packets[0].header.timestamp);
+        assert_eq!(last.header.sequence, packets[0].header.sequence.wrapping_add(1));
+    }
+
+    /// Packetize an access unit the way the UDP loop does: marker candidate
+    /// = last NALU of the AU
+    fn packetize_au(packetizer: &mut H264Packetizer, au: &[Vec<u8>]) -> Vec<RtpPacket> {
+        au.iter().enumerate().flat_map(|(i, nalu)| packetizer.packetize(nalu, i == au.len() - 1)).collect()
+    }
+
+    #[test]
+    fn marker_is_only_on_the_last_packet_of_a_two_slice_picture() {
+        // Slice 1: first_mb_in_slice = 0 (bit '1'); slice 2 tiếp tục picture
+        // (first_mb ≠ 0), lớn tới mức phải FU-A
+        let first_slice = vec![0x65, 0x88, 0x84, 0x21];
+        let mut second_slice = vec![0x65, 0x40];
+        second_slice.resize(1 + (MTU - 2) * 3, 0x5A);
+        let next_picture = vec![0x41, 0x9A, 0x02];
+
+        let mut splitter = AccessUnitSplitter::new();
+        assert!(splitter.push(first_slice.clone()).is_none());
+        assert!(splitter.push(second_slice.clone()).is_none());
+        let au = splitter.push(next_picture).expect("new picture ends the AU");
+        assert_eq!(au, [first_slice, second_slice]);
+
+        let mut packetizer = H264Packetizer::with_ssrc(1);
+        let packets = packetize_au(&mut packetizer, &au);
+        assert_eq!(packets.len(), 1 + 3);
+        let markers: Vec<bool> = packets.iter().map(|p| p.header.marker).collect();
+        assert_eq!(markers, [false, false, false, true]);
+        assert!(packets.iter().all(|p| p.header.timestamp == packets[0].header.timestamp));
+
+        // Slice cuối là single NAL: marker nằm trên chính packet đó
+        let packets = packetize_au(&mut packetizer, &[vec![0x41, 0x9A, 0x02], vec![0x41, 0x40, 0x01]]);
+        assert_eq!(packets.iter().map(|p| p.header.marker).collect::<Vec<_>>(), [false, true]);
+    }
+
+    #[test]
+    fn timestamps_advance_one_frame_per_access_unit_across_read_boundaries() {
+        // 12 pictures ở 30fps: SPS + PPS + IDR, rồi non-IDR (một picture có
+        // hai slices, một picture đủ lớn để FU-A)
+        let mut stream = Vec::new();
+        for picture in 0..12u8 {
+            let mut nalus = match picture {
+                0 => vec![vec![0x67, 0x42, 0xC0, 0x1F], vec![0x68, 0xCE, 0x3C, 0x80], vec![0x65, 0x88, 0x84, 0x21]],
+                _ => vec![vec![0x41, 0x9A, picture, 0x11]],
+            };
+            if picture == 4 {
+                nalus.push(vec![0x41, 0x40, 0x22, 0x33]);
+            }
+            if picture == 7 {
+                nalus[0].resize(3 * MTU, 0x5A);
+            }
+            for nalu in nalus {
+                stream.extend_from_slice(&[0, 0, 0, 1]);
+                stream.extend_from_slice(&nalu);
+            }
+        }
+
+        for read_size in [1, 5, 7, 64, 4096] {
+            let mut parser = NaluParser::new();
+            let mut splitter = AccessUnitSplitter::new();
+            let mut packetizer = H264Packetizer::with_ssrc(1);
+            let mut timestamps: Vec<u32> = Vec::new();
+            for read in stream.chunks(read_size) {
+                for au in parser.parse(read).into_iter().filter_map(|nalu| splitter.push(nalu)) {
+                    let packets = packetize_au(&mut packetizer, &au);
+                    assert!(packets.iter().all(|p| p.header.timestamp == packets[0].header.timestamp));
+                    assert_eq!(packets.iter().filter(|p| p.header.marker).count(), 1);
+                    timestamps.push(packets[0].header.timestamp);
+                    packetizer.end_access_unit();
+                }
+            }
+
+            // NALU cuối còn trong parser (chờ start code kế tiếp), picture
+            // trước nó còn trong splitter (chờ NALU đầu của AU kế tiếp)
+            assert_eq!(timestamps.len(), 10, "read size {}", read_size);
+            for pair in timestamps.windows(2) {
+                assert_eq!(pair[1].wrapping_sub(pair[0]), CLOCK_RATE / DEFAULT_FPS, "read size {}", read_size);
+            }
+        }
+    }
+
+    #[test]
+    fn stap_a_prefixes_each_nalu_with_its_16_bit_size() {
+        let sps = [0x67, 0x42, 0xC0, 0x1F];
+        let pps = [0x68, 0xCE, 0x3C, 0x80, 0x01];
+        let sei = [0x06, 0x05, 0xFF];
+        let mut packetizer = H264Packetizer::with_ssrc(1);
+
+        let packets = packetizer.packetize_stap_a(&[&sps, &pps, &sei, &[]]);
+        assert_eq!(packets.len(), 1);
+        let payload = &packets[0].payload;
+        // NRI = max (SPS/PPS: 3), F = 0, type 24
+        assert_eq!(payload[0], 0x60 | 24);
+        assert_eq!(payload.len(), 1 + (2 + sps.len()) + (2 + pps.len()) + (2 + sei.len()));
+        let expected = [&[0x00, 0x04][..], &sps, &[0x00, 0x05], &pps, &[0x00, 0x03], &sei].concat();
+        assert_eq!(&payload[1..], expected);
+        assert!(!packets[0].header.marker);
+        assert_eq!(packetizer.sequence(), 1);
+
+        // F bit của bất kỳ NALU nào được giữ; NRI lấy giá trị lớn nhất
+        let packets = packetizer.packetize_stap_a(&[&[0x26, 0x01], &[0x86, 0x02]]);
+        assert_eq!(packets[0].payload[0], 0x80 | 0x20 | 24);
+    }
+
+    #[test]
+    fn stap_a_falls_back_to_single_nal_packets_above_the_mtu() {
+        let mut packetizer = H264Packetizer::with_ssrc(1);
+        packetizer.set_blocksize(Some(RTP_HEADER_LEN + 100));
+        let sps = [0x67; 60];
+        let pps = [0x68; 60];
+
+        let packets = packetizer.packetize_stap_a(&[&sps, &pps]);
+        assert_eq!(packets.len(), 2);
+        assert_eq!(packets[0].payload, sps);
+        assert_eq!(packets[1].payload, pps);
+        assert!(packets.iter().all(|p| !p.header.marker && p.payload.len() <= 100));
+
+        // Một NALU không cần aggregate
+        assert_eq!(packetizer.packetize_stap_a(&[&sps[..10]])[0].payload, sps[..10]);
+    }
+
+    #[test]
+    fn no_packet_exceeds_an_accepted_blocksize() {
+        assert_eq!(H264Packetizer::effective_blocksize(MIN_BLOCKSIZE - 1), None);
+        assert_eq!(H264Packetizer::effective_blocksize(9000), Some(RTP_HEADER_LEN + MTU));
+
+        let idr = oversized_nalu(3);
+        for requested in [MIN_BLOCKSIZE, MIN_BLOCKSIZE + 1, 200, 576, 1412, 9000] {
+            let blocksize = H264Packetizer::effective_blocksize(requested).unwrap();
+            assert!(blocksize <= requested);
+            let mut packetizer = H264Packetizer::with_ssrc(1);
+            packetizer.set_blocksize(Some(blocksize));
+
+            let mut packets = packetizer.packetize_stap_a(&[&[0x67; 40], &[0x68; 30]]);
+            packets.extend(packetizer.packetize(&idr, true));
+            packets.extend(packetizer.packetize(&[0x41; 100], true));
+            packets.push(packetizer.keepalive());
+            for packet in &packets {
+                assert!(packet.to_bytes().len() <= blocksize, "{} bytes > Blocksize {}", packet.to_bytes().len(), blocksize);
+            }
+        }
+    }
 }
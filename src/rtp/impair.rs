@@ -0,0 +1,154 @@
+use std::time::{Duration, Instant};
+
+/// Debug-only network impairment cho outgoing RTP (test client resilience)
+#[derive(Clone, Debug, PartialEq)]
+pub struct ImpairmentConfig {
+    /// Probability a packet is held back and sent after the next one
+    pub reorder: f64,
+    /// Probability a packet is sent twice
+    pub duplicate: f64,
+    /// Probability a packet is delayed by up to `max_delay`
+    pub delay: f64,
+    pub max_delay: Duration,
+    /// Seed cho PRNG; mỗi session bắt đầu từ cùng seed nên kết quả reproducible
+    pub seed: u64,
+}
+
+impl Default for ImpairmentConfig {
+    fn default() -> Self {
+        Self {
+            reorder: 0.0,
+            duplicate: 0.0,
+            delay: 0.0,
+            max_delay: Duration::from_millis(100),
+            seed: 1,
+        }
+    }
+}
+
+impl ImpairmentConfig {
+    /// Parse `reorder=0.01,duplicate=0.01,delay=0.05,max_delay_ms=200,seed=42`
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let mut config = Self::default();
+
+        for entry in spec.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+            let (key, value) = entry
+                .split_once('=')
+                .ok_or_else(|| format!("expected key=value, got '{}'", entry))?;
+            let invalid = || format!("invalid value for {}: '{}'", key, value);
+
+            let probability = || {
+                value
+                    .parse::<f64>()
+                    .ok()
+                    .filter(|p| (0.0..=1.0).contains(p))
+                    .ok_or_else(invalid)
+            };
+
+            match key {
+                "reorder" => config.reorder = probability()?,
+                "duplicate" => config.duplicate = probability()?,
+                "delay" => config.delay = probability()?,
+                "max_delay_ms" => config.max_delay = Duration::from_millis(value.parse().map_err(|_| invalid())?),
+                "seed" => config.seed = value.parse().map_err(|_| invalid())?,
+                _ => return Err(format!("unknown impairment option '{}'", key)),
+            }
+        }
+
+        Ok(config)
+    }
+}
+
+/// Counts of injected impairments
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ImpairmentStats {
+    pub packets: u64,
+    pub reordered: u64,
+    pub duplicated: u64,
+    pub delayed: u64,
+}
+
+/// Impairment stage giữa packetization và send, mỗi session một instance
+///
+/// Delayed packets nằm trong queue và được release ở lần `process` sau khi
+/// tới hạn; packet bị reorder được release ngay sau packet kế tiếp.
+pub struct Impairer {
+    config: ImpairmentConfig,
+    rng: u64,
+    delayed: Vec<(Instant, Vec<u8>)>,
+    held: Option<Vec<u8>>,
+    pub stats: ImpairmentStats,
+}
+
+impl Impairer {
+    pub fn new(config: ImpairmentConfig) -> Self {
+        Self {
+            // xorshift không chạy được với state = 0
+            rng: config.seed.max(1),
+            config,
+            delayed: Vec::new(),
+            held: None,
+            stats: ImpairmentStats::default(),
+        }
+    }
+
+    /// Feed one serialized packet; returns the packets to send now, in order
+    pub fn process(&mut self, packet: Vec<u8>, now: Instant) -> Vec<Vec<u8>> {
+        self.stats.packets += 1;
+
+        let mut incoming = vec![packet];
+        if self.chance(self.config.duplicate) {
+            self.stats.duplicated += 1;
+            incoming.push(incoming[0].clone());
+        }
+
+        // Release delayed packets đã tới hạn trước (chúng cũ hơn)
+        let mut out = Vec::new();
+        self.delayed.sort_by_key(|(due, _)| *due);
+        let ready = self.delayed.partition_point(|(due, _)| *due <= now);
+        out.extend(self.delayed.drain(..ready).map(|(_, p)| p));
+
+        let held = self.held.take();
+        for packet in incoming {
+            if self.held.is_none() && self.chance(self.config.reorder) {
+                self.stats.reordered += 1;
+                self.held = Some(packet);
+            } else if self.chance(self.config.delay) {
+                self.stats.delayed += 1;
+                let delay = self.config.max_delay.mul_f64(self.next_f64());
+                self.delayed.push((now + delay, packet));
+            } else {
+                out.push(packet);
+            }
+        }
+
+        // Packet bị giữ lại từ lần trước đi sau packet hiện tại → reorder
+        out.extend(held);
+
+        if self.stats.packets.is_multiple_of(1000) {
+            println!("🧪 Impairment stats: {:?}", self.stats);
+        }
+        out
+    }
+
+    /// Release everything still held back (end of stream), in send order
+    pub fn drain(&mut self) -> Vec<Vec<u8>> {
+        self.delayed.sort_by_key(|(due, _)| *due);
+        let mut out: Vec<Vec<u8>> = self.delayed.drain(..).map(|(_, p)| p).collect();
+        out.extend(self.held.take());
+        out
+    }
+
+    fn chance(&mut self, probability: f64) -> bool {
+        probability > 0.0 && self.next_f64() < probability
+    }
+
+    /// xorshift64*: đủ tốt cho simulation, không cần crate rand
+    fn next_f64(&mut self) -> f64 {
+        self.rng ^= self.rng >> 12;
+        self.rng ^= self.rng << 25;
+        self.rng ^= self.rng >> 27;
+        let value = self.rng.wrapping_mul(0x2545_F491_4F6C_DD1D);
+        (value >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
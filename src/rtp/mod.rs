@@ -1,2 +1,28 @@
 pub mod packet;
+pub mod ports;
 pub mod h264;
+pub mod aac;
+pub mod depacketize;
+pub mod framedrop;
+pub mod impair;
+pub mod rtx;
+#[cfg(feature = "opus")]
+pub mod opus;
+pub mod stamp;
+pub mod udp;
+
+/// Random u64 từ std (RandomState được seed ngẫu nhiên mỗi process),
+/// đủ cho SSRC/sequence offsets mà không cần crate rand
+pub fn random_u64() -> u64 {
+    use std::collections::hash_map::RandomState;
+    use std::hash::{BuildHasher, Hasher};
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    let mut hasher = RandomState::new().build_hasher();
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos())
+        .unwrap_or_default();
+    hasher.write_u128(nanos);
+    hasher.finish()
+}
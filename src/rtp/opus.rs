@@ -0,0 +1,97 @@
+use super::packet::{RtpHeader, RtpPacket};
+use super::stamp::RtpIdentity;
+
+/// Payload type động cho Opus
+pub const OPUS_PAYLOAD_TYPE: u8 = 111;
+/// Opus RTP clock luôn là 48kHz (RFC 7587 §4.1), bất kể sample rate thực
+pub const OPUS_CLOCK_RATE: u32 = 48000;
+/// ptime 20ms → 960 samples mỗi frame
+pub const SAMPLES_PER_FRAME: u32 = OPUS_CLOCK_RATE / 1000 * 20;
+
+/// Opus RTP Packetizer theo RFC 7587: mỗi RTP packet chứa đúng 1 Opus frame
+///
+/// FFmpeg được yêu cầu `-frame_duration 20`, nên mỗi packet tăng timestamp
+/// đúng `SAMPLES_PER_FRAME` (ptime 20 trong SDP).
+pub struct OpusPacketizer {
+    sequence: u16,
+    timestamp: u32,
+    ssrc: u32,
+    payload_type: u8,
+}
+
+impl OpusPacketizer {
+    /// Random SSRC, initial sequence và timestamp base
+    pub fn new() -> Self {
+        let identity = RtpIdentity::random();
+        Self {
+            sequence: identity.seq_offset,
+            timestamp: identity.ts_offset,
+            ssrc: identity.ssrc,
+            payload_type: OPUS_PAYLOAD_TYPE,
+        }
+    }
+
+    /// Sequence number của packet kế tiếp
+    pub fn sequence(&self) -> u16 {
+        self.sequence
+    }
+
+    /// RTP timestamp của frame kế tiếp
+    pub fn timestamp(&self) -> u32 {
+        self.timestamp
+    }
+
+    /// Packetize one Opus frame, then advance the timestamp by one frame
+    pub fn packetize(&mut self, frame: &[u8]) -> RtpPacket {
+        let header = RtpHeader::new(self.payload_type, self.sequence, self.timestamp, self.ssrc);
+        let packet = RtpPacket::new(header, frame.to_vec());
+
+        self.sequence = self.sequence.wrapping_add(1);
+        self.timestamp = self.timestamp.wrapping_add(SAMPLES_PER_FRAME);
+
+        packet
+    }
+}
+
+/// SDP media section cho Opus audio track (`opus/48000/2`: RFC 7587 luôn
+/// quảng bá 2 channels, kể cả stream mono)
+pub fn sdp_media(control: &str) -> String {
+    format!(
+        "m=audio 0 RTP/AVP {pt}\r\n\
+         a=rtpmap:{pt} opus/{clock}/2\r\n\
+         a=fmtp:{pt} minptime=10;useinbandfec=1\r\n\
+         a=ptime:20\r\n\
+         a=control:{control}\r\n",
+        pt = OPUS_PAYLOAD_TYPE,
+        clock = OPUS_CLOCK_RATE,
+        control = control
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn one_frame_per_packet_advancing_20ms() {
+        let mut packetizer = OpusPacketizer::new();
+        let (sequence, timestamp) = (packetizer.sequence(), packetizer.timestamp());
+
+        let first = packetizer.packetize(&[0xFC, 1, 2, 3]);
+        let second = packetizer.packetize(&[0xFC, 4]);
+        assert_eq!(first.header.payload_type, OPUS_PAYLOAD_TYPE);
+        assert_eq!((first.header.sequence, first.header.timestamp), (sequence, timestamp));
+        assert_eq!(second.header.sequence, sequence.wrapping_add(1));
+        assert_eq!(second.header.timestamp, timestamp.wrapping_add(960));
+        assert_eq!(first.payload, [0xFC, 1, 2, 3]);
+        assert_eq!(packetizer.timestamp(), timestamp.wrapping_add(2 * 960));
+    }
+
+    #[test]
+    fn sdp_advertises_opus_48000_2() {
+        let sdp = sdp_media("track2");
+        assert!(sdp.starts_with("m=audio 0 RTP/AVP 111\r\n"));
+        assert!(sdp.contains("a=rtpmap:111 opus/48000/2\r\n"));
+        assert!(sdp.ends_with("a=control:track2\r\n"));
+    }
+}
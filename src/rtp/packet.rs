@@ -1,7 +1,8 @@
-use bytes::{BytesMut, BufMut};
-
 /// RTP Header (12 bytes chuẩn)
-#[derive(Debug, Clone)]
+///
+/// `padding` / `extension` chỉ là flag bits; pad count và extension data
+/// nằm trong `RtpPacket`, `RtpPacket::to_bytes` ghi chúng khi flag bật.
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct RtpHeader {
     pub version: u8,         // 2 bits, luôn = 2
     pub padding: bool,       // 1 bit
@@ -29,7 +30,7 @@ impl RtpHeader {
         }
     }
 
-    /// Serialize header thành bytes
+    /// Serialize fixed header thành bytes (không CSRCs / extension)
     pub fn to_bytes(&self) -> [u8; 12] {
         let mut header = [0u8; 12];
         
@@ -55,23 +56,237 @@ impl RtpHeader {
     }
 }
 
-/// RTP Packet = Header + Payload
-#[derive(Debug)]
+/// Header extension (RFC 3550 §5.3.1): 16-bit profile-defined id (vd.
+/// 0xBEDE cho one-byte extensions của RFC 8285) + data
+///
+/// Trên wire data là bội của 32 bits: `to_bytes` pad zero cho đủ word, nên
+/// parse lại trả data đã pad.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HeaderExtension {
+    pub profile: u16,
+    pub data: Vec<u8>,
+}
+
+impl HeaderExtension {
+    pub fn new(profile: u16, data: Vec<u8>) -> Self {
+        Self { profile, data }
+    }
+
+    /// Length field: data tính bằng 32-bit words (làm tròn lên)
+    fn words(&self) -> usize {
+        self.data.len().div_ceil(4)
+    }
+}
+
+/// RTP Packet = Header + [Extension] + Payload + [Padding]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct RtpPacket {
     pub header: RtpHeader,
     pub payload: Vec<u8>,
+    /// Ghi khi `header.extension`; flag bật mà None thì là extension rỗng
+    /// (profile 0, length 0) để packet vẫn hợp lệ
+    pub extension: Option<HeaderExtension>,
+    /// Pad bytes ghi khi `header.padding` (byte cuối = số pad bytes, tối thiểu 1)
+    pub padding: u8,
 }
 
 impl RtpPacket {
     pub fn new(header: RtpHeader, payload: Vec<u8>) -> Self {
-        Self { header, payload }
+        Self { header, payload, extension: None, padding: 0 }
+    }
+
+    /// Attach a header extension (and set the X bit)
+    pub fn with_extension(mut self, extension: HeaderExtension) -> Self {
+        self.header.extension = true;
+        self.extension = Some(extension);
+        self
+    }
+
+    /// Append `count` pad bytes (P bit); 0 removes padding
+    pub fn with_padding(mut self, count: u8) -> Self {
+        self.header.padding = count > 0;
+        self.padding = count;
+        self
     }
 
     /// Serialize toàn bộ packet
     pub fn to_bytes(&self) -> Vec<u8> {
-        let mut buf = Vec::with_capacity(12 + self.payload.len());
+        let extension = self.header.extension.then(|| self.extension.clone().unwrap_or(HeaderExtension::new(0, Vec::new())));
+        let padding = if self.header.padding { self.padding.max(1) as usize } else { 0 };
+        let extension_len = extension.as_ref().map_or(0, |ext| 4 + 4 * ext.words());
+
+        let mut buf = Vec::with_capacity(12 + extension_len + self.payload.len() + padding);
         buf.extend_from_slice(&self.header.to_bytes());
+        if let Some(ext) = extension {
+            buf.extend_from_slice(&ext.profile.to_be_bytes());
+            buf.extend_from_slice(&(ext.words() as u16).to_be_bytes());
+            buf.extend_from_slice(&ext.data);
+            buf.resize(12 + extension_len, 0);
+        }
         buf.extend_from_slice(&self.payload);
+        if padding > 0 {
+            buf.resize(buf.len() + padding - 1, 0);
+            buf.push(padding as u8);
+        }
         buf
     }
 }
+
+/// Lỗi khi parse RTP packet nhận được
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParseError {
+    /// Ngắn hơn fixed header + CSRC list / extension đã khai báo
+    Truncated,
+    /// Version khác 2
+    BadVersion(u8),
+    /// Pad count bằng 0 hoặc lớn hơn payload
+    BadPadding,
+}
+
+impl RtpPacket {
+    /// Parse a serialized RTP packet (RFC 3550 §5.1). CSRCs are skipped; the
+    /// header extension and pad count are kept, padding is stripped from the payload
+    pub fn parse(data: &[u8]) -> Result<Self, ParseError> {
+        if data.len() < 12 {
+            return Err(ParseError::Truncated);
+        }
+        let version = data[0] >> 6;
+        if version != 2 {
+            return Err(ParseError::BadVersion(version));
+        }
+
+        let header = RtpHeader {
+            version,
+            padding: data[0] & 0x20 != 0,
+            extension: data[0] & 0x10 != 0,
+            csrc_count: data[0] & 0x0F,
+            marker: data[1] & 0x80 != 0,
+            payload_type: data[1] & 0x7F,
+            sequence: u16::from_be_bytes([data[2], data[3]]),
+            timestamp: u32::from_be_bytes([data[4], data[5], data[6], data[7]]),
+            ssrc: u32::from_be_bytes([data[8], data[9], data[10], data[11]]),
+        };
+
+        let mut start = 12 + 4 * header.csrc_count as usize;
+        let mut extension = None;
+        if header.extension {
+            // Extension header: profile (16) + length in 32-bit words (16)
+            let ext = data.get(start..start + 4).ok_or(ParseError::Truncated)?;
+            let profile = u16::from_be_bytes([ext[0], ext[1]]);
+            let len = 4 * u16::from_be_bytes([ext[2], ext[3]]) as usize;
+            let ext_data = data.get(start + 4..start + 4 + len).ok_or(ParseError::Truncated)?;
+            extension = Some(HeaderExtension::new(profile, ext_data.to_vec()));
+            start += 4 + len;
+        }
+        let mut end = data.len();
+        if start > end {
+            return Err(ParseError::Truncated);
+        }
+        let mut padding = 0;
+        if header.padding {
+            padding = data[end - 1];
+            if padding == 0 || padding as usize > end - start {
+                return Err(ParseError::BadPadding);
+            }
+            end -= padding as usize;
+        }
+
+        Ok(Self { header, payload: data[start..end].to_vec(), extension, padding })
+    }
+}
+
+/// Offset của payload trong một serialized packet (sau CSRCs và extension),
+/// cho code sửa payload tại chỗ; None nếu packet bị cắt
+pub fn payload_offset(data: &[u8]) -> Option<usize> {
+    let first = *data.first()?;
+    let mut offset = 12 + 4 * (first & 0x0F) as usize;
+    if first & 0x10 != 0 {
+        let ext = data.get(offset..offset + 4)?;
+        offset += 4 + 4 * u16::from_be_bytes([ext[2], ext[3]]) as usize;
+    }
+    (offset <= data.len()).then_some(offset)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn packet(payload: &[u8]) -> RtpPacket {
+        let mut header = RtpHeader::new(96, 0x1234, 0xDEAD_BEEF, 0x0102_0304);
+        header.marker = true;
+        RtpPacket::new(header, payload.to_vec())
+    }
+
+    #[test]
+    fn plain_packet_round_trips() {
+        let original = packet(&[0x65, 0x88, 0x84]);
+        let bytes = original.to_bytes();
+        assert_eq!(bytes[..12], [0x80, 0xE0, 0x12, 0x34, 0xDE, 0xAD, 0xBE, 0xEF, 1, 2, 3, 4]);
+        assert_eq!(RtpPacket::parse(&bytes), Ok(original));
+        assert_eq!(payload_offset(&bytes), Some(12));
+    }
+
+    #[test]
+    fn extension_is_padded_to_words_and_round_trips() {
+        // One-byte extension (RFC 8285): 3 bytes data → 1 word
+        let original = packet(&[0x41, 0x9A]).with_extension(HeaderExtension::new(0xBEDE, vec![0x10, 0xAB, 0xCD]));
+        let bytes = original.to_bytes();
+        assert_eq!(bytes[0], 0x90);
+        assert_eq!(bytes[12..20], [0xBE, 0xDE, 0, 1, 0x10, 0xAB, 0xCD, 0]);
+        assert_eq!(payload_offset(&bytes), Some(20));
+
+        let parsed = RtpPacket::parse(&bytes).unwrap();
+        assert_eq!(parsed.payload, [0x41, 0x9A]);
+        assert_eq!(parsed.extension, Some(HeaderExtension::new(0xBEDE, vec![0x10, 0xAB, 0xCD, 0])));
+
+        // X bit không có extension: extension rỗng vẫn hợp lệ
+        let mut empty = packet(&[0x41]);
+        empty.header.extension = true;
+        let parsed = RtpPacket::parse(&empty.to_bytes()).unwrap();
+        assert_eq!(parsed.extension, Some(HeaderExtension::new(0, Vec::new())));
+        assert_eq!(parsed.payload, [0x41]);
+    }
+
+    #[test]
+    fn padding_is_stripped_and_round_trips() {
+        let original = packet(&[0x41, 0x9A, 0x02]).with_padding(5);
+        let bytes = original.to_bytes();
+        assert_eq!(bytes[0], 0xA0);
+        assert_eq!(bytes.len(), 12 + 3 + 5);
+        assert_eq!(bytes[15..], [0, 0, 0, 0, 5]);
+        assert_eq!(RtpPacket::parse(&bytes), Ok(original));
+
+        let both = packet(&[0x41]).with_extension(HeaderExtension::new(0x1000, vec![1, 2, 3, 4])).with_padding(3);
+        let parsed = RtpPacket::parse(&both.to_bytes()).unwrap();
+        assert_eq!((parsed.payload.as_slice(), parsed.padding), (&[0x41][..], 3));
+        assert_eq!(parsed, both);
+    }
+
+    #[test]
+    fn malformed_padding_and_extensions_are_rejected() {
+        let mut bytes = packet(&[0x41, 0x9A]).with_padding(2).to_bytes();
+        // Pad count 0
+        *bytes.last_mut().unwrap() = 0;
+        assert_eq!(RtpPacket::parse(&bytes), Err(ParseError::BadPadding));
+        // Pad count dài hơn payload + padding còn lại
+        *bytes.last_mut().unwrap() = 5;
+        assert_eq!(RtpPacket::parse(&bytes), Err(ParseError::BadPadding));
+        // Pad count bằng đúng payload + padding: payload rỗng nhưng hợp lệ
+        *bytes.last_mut().unwrap() = 4;
+        assert_eq!(RtpPacket::parse(&bytes).unwrap().payload, Vec::<u8>::new());
+
+        // P bit không có byte nào sau header
+        let mut header_only = packet(&[]).to_bytes();
+        header_only[0] |= 0x20;
+        assert_eq!(RtpPacket::parse(&header_only), Err(ParseError::BadPadding));
+
+        // Extension length vượt quá packet
+        let mut bytes = packet(&[0x41]).with_extension(HeaderExtension::new(0xBEDE, vec![1, 2, 3, 4])).to_bytes();
+        bytes[15] = 9;
+        assert_eq!(RtpPacket::parse(&bytes), Err(ParseError::Truncated));
+        assert_eq!(RtpPacket::parse(&bytes[..14]), Err(ParseError::Truncated));
+
+        assert_eq!(RtpPacket::parse(&[0x80; 11]), Err(ParseError::Truncated));
+        assert_eq!(RtpPacket::parse(&[0x40; 12]), Err(ParseError::BadVersion(1)));
+    }
+}
@@ -0,0 +1,217 @@
+use crate::config::PortPair;
+use crate::rtcp;
+use std::collections::BTreeSet;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use tokio::net::UdpSocket;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+
+/// RTCP datagram nhận trên socket của một session: (packet, source address)
+pub type RtcpFeedback = (Vec<u8>, SocketAddr);
+
+/// Pool of even/odd server port pairs cho UDP unicast sessions
+/// (`--session-ports first-last`)
+///
+/// SETUP lease một pair cho mỗi UDP session: lease bind RTP/RTCP sockets
+/// riêng trên pair đó, SETUP response quảng bá pair trong `server_port`, và
+/// RTP, SR, BYE của session đi ra từ các sockets này. `remove_client` nhả
+/// lease trên mọi exit path (TEARDOWN, kick, reap, max duration, disconnect)
+/// nên sockets được đóng và pair quay về pool. Hết pair thì SETUP bị từ chối
+/// với 453, nên range cũng là giới hạn số UDP sessions đồng thời.
+#[derive(Debug)]
+pub struct PortAllocator {
+    /// RTP ports (chẵn) còn trống
+    free: Mutex<BTreeSet<u16>>,
+    /// RTCP nhận trên sockets của sessions được chuyển về đây (None: bỏ qua)
+    feedback: Option<mpsc::UnboundedSender<RtcpFeedback>>,
+}
+
+impl PortAllocator {
+    /// Pairs `first..=last` (RTP ports rounded to even, RTCP = RTP + 1)
+    pub fn new(first: u16, last: u16, feedback: Option<mpsc::UnboundedSender<RtcpFeedback>>) -> Arc<Self> {
+        // u32: làm tròn 65535 lên chẵn không overflow
+        let first = (first as u32).div_ceil(2) * 2;
+        let free = (first..last as u32)
+            .step_by(2)
+            .filter_map(|rtp| u16::try_from(rtp).ok())
+            .filter(|rtp| PortPair::new(*rtp).is_ok())
+            .collect();
+        Arc::new(Self { free: Mutex::new(free), feedback })
+    }
+
+    /// Parse `first-last` (`--session-ports 30000-30999`)
+    pub fn parse_range(value: &str) -> Result<(u16, u16), String> {
+        let range = value.split_once('-').and_then(|(first, last)| {
+            Some((first.trim().parse::<u16>().ok()?, last.trim().parse::<u16>().ok()?))
+        });
+        match range {
+            Some((first, last)) if first > 0 && first < last => Ok((first, last)),
+            _ => Err(format!("expected first-last ports with 0 < first < last, got '{}'", value)),
+        }
+    }
+
+    /// Lowest free pair whose sockets bind, or None when the range is
+    /// exhausted. Pair đang bị process khác giữ được bỏ qua nhưng vẫn ở trong
+    /// pool. Phải gọi trong tokio runtime
+    pub fn lease(self: &Arc<Self>) -> Option<PortLease> {
+        let mut free = self.free.lock().unwrap();
+        let (ports, rtp, rtcp) = free.iter().find_map(|rtp| {
+            let ports = PortPair::new(*rtp).ok()?;
+            match (bind(ports.rtp), bind(ports.rtcp)) {
+                (Ok(rtp), Ok(rtcp)) => Some((ports, Arc::new(rtp), Arc::new(rtcp))),
+                (Err(e), _) | (_, Err(e)) => {
+                    eprintln!("⚠️  Session ports {}-{} unavailable: {}", ports.rtp, ports.rtcp, e);
+                    None
+                }
+            }
+        })?;
+        free.remove(&ports.rtp);
+        drop(free);
+
+        // RTCP của client tới RTCP port, hoặc RTP port khi rtcp-mux
+        let receivers = match &self.feedback {
+            Some(feedback) => vec![
+                forward_rtcp(rtcp.clone(), feedback.clone(), false),
+                forward_rtcp(rtp.clone(), feedback.clone(), true),
+            ],
+            None => Vec::new(),
+        };
+        Some(PortLease { ports, rtp, rtcp, receivers, allocator: self.clone() })
+    }
+
+    pub fn available(&self) -> usize {
+        self.free.lock().unwrap().len()
+    }
+}
+
+fn bind(port: u16) -> std::io::Result<UdpSocket> {
+    let socket = std::net::UdpSocket::bind(("0.0.0.0", port))?;
+    socket.set_nonblocking(true)?;
+    UdpSocket::from_std(socket)
+}
+
+/// Chuyển RTCP nhận trên `socket` về feedback channel; `muxed` = socket là
+/// RTP port, chỉ giữ datagrams là RTCP
+fn forward_rtcp(socket: Arc<UdpSocket>, feedback: mpsc::UnboundedSender<RtcpFeedback>, muxed: bool) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut buf = [0u8; 1500];
+        loop {
+            match socket.recv_from(&mut buf).await {
+                Ok((n, from)) => {
+                    if muxed && !rtcp::is_rtcp_packet(&buf[..n]) {
+                        continue;
+                    }
+                    if feedback.send((buf[..n].to_vec(), from)).is_err() {
+                        return;
+                    }
+                }
+                Err(e) => eprintln!("⚠️  Session RTCP socket recv error: {}", e),
+            }
+        }
+    })
+}
+
+/// RAII lease của một port pair và sockets bound trên nó; drop dừng
+/// receivers, đóng sockets (khi caller không còn giữ clone nào) và trả pair
+/// về allocator
+#[derive(Debug)]
+pub struct PortLease {
+    pub ports: PortPair,
+    pub rtp: Arc<UdpSocket>,
+    pub rtcp: Arc<UdpSocket>,
+    receivers: Vec<JoinHandle<()>>,
+    allocator: Arc<PortAllocator>,
+}
+
+impl PortLease {
+    /// Socket gửi RTCP (SR, BYE) cho session: RTP socket khi rtcp-mux
+    pub fn rtcp_socket(&self, rtcp_mux: bool) -> &Arc<UdpSocket> {
+        if rtcp_mux { &self.rtp } else { &self.rtcp }
+    }
+}
+
+impl Drop for PortLease {
+    fn drop(&mut self) {
+        for receiver in &self.receivers {
+            receiver.abort();
+        }
+        self.allocator.free.lock().unwrap().insert(self.ports.rtp);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn range_rounds_to_even_pairs_without_overflow() {
+        assert_eq!(PortAllocator::new(30001, 30006, None).available(), 2); // 30002, 30004
+        assert_eq!(PortAllocator::new(65534, 65535, None).available(), 1);
+        assert_eq!(PortAllocator::new(65535, 65535, None).available(), 0);
+        assert_eq!(PortAllocator::new(0, 3, None).available(), 1); // port 0 bị bỏ
+    }
+
+    #[tokio::test]
+    async fn leases_bind_the_pair_and_are_returned_on_drop() {
+        let allocator = PortAllocator::new(39100, 39103, None);
+        let first = allocator.lease().unwrap();
+        let second = allocator.lease().unwrap();
+        assert_eq!((first.ports.rtp, first.ports.rtcp), (39100, 39101));
+        assert_eq!(first.rtp.local_addr().unwrap().port(), 39100);
+        assert_eq!(first.rtcp.local_addr().unwrap().port(), 39101);
+        assert_eq!(second.ports.rtp, 39102);
+        assert!(allocator.lease().is_none());
+
+        drop(first);
+        assert_eq!(allocator.available(), 1);
+        assert!(std::net::UdpSocket::bind(("0.0.0.0", 39100)).is_ok());
+        assert_eq!(allocator.lease().unwrap().ports.rtp, 39100);
+        for _ in 0..100 {
+            drop(allocator.lease().unwrap());
+        }
+        assert_eq!(allocator.available(), 1);
+    }
+
+    #[tokio::test]
+    async fn busy_pairs_are_skipped_but_kept_in_the_pool() {
+        let allocator = PortAllocator::new(39110, 39113, None);
+        let busy = std::net::UdpSocket::bind(("0.0.0.0", 39111)).unwrap();
+
+        let lease = allocator.lease().unwrap();
+        assert_eq!(lease.ports.rtp, 39112);
+        assert!(allocator.lease().is_none());
+        assert_eq!(allocator.available(), 1);
+
+        drop(busy);
+        assert_eq!(allocator.lease().unwrap().ports.rtp, 39110);
+    }
+
+    #[tokio::test]
+    async fn rtcp_on_either_socket_is_forwarded() {
+        let (feedback, mut reports) = mpsc::unbounded_channel();
+        let allocator = PortAllocator::new(39120, 39121, Some(feedback));
+        let lease = allocator.lease().unwrap();
+        let client = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let rr = [0x80, 201, 0x00, 0x01, 0, 0, 0, 7];
+
+        client.send_to(&rr, ("127.0.0.1", lease.ports.rtcp)).await.unwrap();
+        let (data, from) = tokio::time::timeout(Duration::from_secs(2), reports.recv()).await.unwrap().unwrap();
+        assert_eq!((data.as_slice(), from), (&rr[..], client.local_addr().unwrap()));
+
+        // RTP port chỉ chuyển RTCP (rtcp-mux), bỏ RTP
+        client.send_to(&[0x80, 96, 0, 1], ("127.0.0.1", lease.ports.rtp)).await.unwrap();
+        client.send_to(&rr, ("127.0.0.1", lease.ports.rtp)).await.unwrap();
+        let (data, _) = tokio::time::timeout(Duration::from_secs(2), reports.recv()).await.unwrap().unwrap();
+        assert_eq!(data, rr);
+    }
+
+    #[test]
+    fn parses_ranges() {
+        assert_eq!(PortAllocator::parse_range("30000-30999"), Ok((30000, 30999)));
+        assert!(PortAllocator::parse_range("30999-30000").is_err());
+        assert!(PortAllocator::parse_range("30000").is_err());
+        assert!(PortAllocator::parse_range("0-10").is_err());
+    }
+}
@@ -0,0 +1,111 @@
+use super::packet::{RtpHeader, RtpPacket};
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+/// Payload type của RTX stream (a=rtpmap:98 rtx/90000, apt=96)
+pub const RTX_PAYLOAD_TYPE: u8 = 98;
+
+/// History mặc định (packets), ~0.5s ở bitrate mặc định
+pub const DEFAULT_HISTORY_PACKETS: usize = 512;
+const HISTORY_AGE: Duration = Duration::from_secs(1);
+
+/// RTP retransmission theo RFC 4588 (SSRC-multiplexed RTX stream)
+///
+/// Giữ lại các packet vừa gửi (ring buffer `capacity` packets, và không quá
+/// `HISTORY_AGE`) và khi
+/// nhận NACK thì đóng gói lại thành RTX: payload = original sequence number
+/// (OSN, 2 bytes) + original payload. History dùng chung cho mọi UDP client,
+/// nên RTX SSRC và sequence number do `RtpStamper::stamp_rtx` của từng
+/// client ghi vào.
+pub struct Retransmitter {
+    history: VecDeque<(Instant, RtpPacket)>,
+    capacity: usize,
+    ssrc: u32,
+}
+
+impl Retransmitter {
+    pub fn new(ssrc: u32, capacity: usize) -> Self {
+        let capacity = capacity.max(1);
+        Self {
+            history: VecDeque::with_capacity(capacity),
+            capacity,
+            ssrc,
+        }
+    }
+
+    /// Remember a packet that was just sent on the original stream
+    pub fn record(&mut self, packet: &RtpPacket) {
+        let now = Instant::now();
+        while self.history.len() >= self.capacity
+            || self.history.front().is_some_and(|(sent, _)| now - *sent > HISTORY_AGE)
+        {
+            self.history.pop_front();
+        }
+        self.history.push_back((now, packet.clone()));
+    }
+
+    /// Build RTX packets for the requested sequence numbers.
+    /// Packets no longer in the history are skipped
+    pub fn retransmit(&self, lost: &[u16]) -> Vec<RtpPacket> {
+        let now = Instant::now();
+        let mut packets = Vec::new();
+
+        for seq in lost {
+            let original = self
+                .history
+                .iter()
+                .rev()
+                .find(|(sent, p)| p.header.sequence == *seq && now - *sent <= HISTORY_AGE);
+
+            if let Some((_, original)) = original {
+                let mut header = RtpHeader::new(RTX_PAYLOAD_TYPE, 0, original.header.timestamp, self.ssrc);
+                header.marker = original.header.marker;
+
+                let mut payload = Vec::with_capacity(2 + original.payload.len());
+                payload.extend_from_slice(&original.header.sequence.to_be_bytes());
+                payload.extend_from_slice(&original.payload);
+
+                packets.push(RtpPacket::new(header, payload));
+            }
+        }
+
+        packets
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn packet(sequence: u16) -> RtpPacket {
+        RtpPacket::new(RtpHeader::new(96, sequence, 3000 * sequence as u32, 7), vec![0x41, sequence as u8])
+    }
+
+    #[test]
+    fn full_history_evicts_the_oldest_packet() {
+        let mut rtx = Retransmitter::new(7, 3);
+        for seq in 10..14 {
+            rtx.record(&packet(seq));
+        }
+
+        // 10 đã bị đẩy ra khi 13 vào; NACK cho nó bị bỏ qua
+        assert!(rtx.retransmit(&[10]).is_empty());
+        let resent = rtx.retransmit(&[10, 11, 13]);
+        let osns: Vec<u16> = resent.iter().map(|p| u16::from_be_bytes([p.payload[0], p.payload[1]])).collect();
+        assert_eq!(osns, [11, 13]);
+    }
+
+    #[test]
+    fn rtx_packet_carries_osn_and_original_payload() {
+        let mut rtx = Retransmitter::new(7, 8);
+        let mut original = packet(0xFFFF);
+        original.header.marker = true;
+        rtx.record(&original);
+
+        let [resent] = rtx.retransmit(&[0xFFFF]).try_into().unwrap();
+        assert_eq!(resent.header.payload_type, RTX_PAYLOAD_TYPE);
+        assert_eq!(resent.header.timestamp, original.header.timestamp);
+        assert!(resent.header.marker);
+        assert_eq!(resent.payload, [&[0xFF, 0xFF][..], &original.payload[..]].concat());
+    }
+}
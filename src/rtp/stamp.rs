@@ -0,0 +1,107 @@
+use super::packet::payload_offset;
+use super::random_u64;
+use crate::rtcp::sr::SenderReport;
+
+/// SSRC + sequence/timestamp offsets của một client, chọn lúc SETUP để
+/// PLAY có thể báo RTP-Info đúng trong sequence space của client
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RtpIdentity {
+    pub ssrc: u32,
+    pub seq_offset: u16,
+    pub ts_offset: u32,
+}
+
+impl RtpIdentity {
+    pub fn random() -> Self {
+        let r = random_u64();
+        Self {
+            ssrc: r as u32,
+            seq_offset: (r >> 32) as u16,
+            ts_offset: random_u64() as u32,
+        }
+    }
+
+    /// Shared-stream (sequence, timestamp) mapped into this client's space
+    pub fn map(&self, sequence: u16, timestamp: u32) -> (u16, u32) {
+        (sequence.wrapping_add(self.seq_offset), timestamp.wrapping_add(self.ts_offset))
+    }
+}
+
+/// Per-client RTP identity trên một packet stream dùng chung
+///
+/// UDP clients dùng chung một H264Packetizer (packetize một lần), rồi mỗi
+/// client được "stamp" lại SSRC/sequence/timestamp với offset cố định riêng.
+/// Vì offset không đổi, RR/NACK của client map 1-1 về shared stream.
+#[derive(Debug)]
+pub struct RtpStamper {
+    seq_offset: u16,
+    ts_offset: u32,
+    /// Sequence kế tiếp của RTX stream của client (space riêng, RFC 4588 §4)
+    rtx_sequence: u16,
+    /// Thống kê SR của riêng client này (SSRC của client)
+    pub report: SenderReport,
+}
+
+impl RtpStamper {
+    pub fn new(identity: RtpIdentity) -> Self {
+        Self {
+            seq_offset: identity.seq_offset,
+            ts_offset: identity.ts_offset,
+            rtx_sequence: random_u64() as u16,
+            report: SenderReport::new(identity.ssrc),
+        }
+    }
+
+    pub fn ssrc(&self) -> u32 {
+        self.report.ssrc
+    }
+
+    /// SSRC của RTX stream đi kèm (SSRC-multiplexed)
+    pub fn rtx_ssrc(&self) -> u32 {
+        self.report.ssrc.wrapping_add(1)
+    }
+
+    /// Rewrite SSRC/sequence/timestamp of a serialized RTP packet for this client
+    pub fn stamp(&mut self, packet: &mut [u8]) {
+        if packet.len() < 12 {
+            return;
+        }
+        let seq = u16::from_be_bytes([packet[2], packet[3]]).wrapping_add(self.seq_offset);
+        packet[2..4].copy_from_slice(&seq.to_be_bytes());
+        self.rewrite(packet, self.ssrc());
+        self.report.add_packet(packet.len() - 12);
+    }
+
+    /// Same for an RTX packet: RTX SSRC, the client's next RTX sequence
+    /// number, and the OSN (first 2 payload bytes) moved into this client's
+    /// sequence space
+    pub fn stamp_rtx(&mut self, packet: &mut [u8]) {
+        // OSN là 2 bytes đầu payload (sau extension nếu có)
+        let Some(at) = payload_offset(packet).filter(|at| at + 2 <= packet.len()) else {
+            return;
+        };
+        packet[2..4].copy_from_slice(&self.rtx_sequence.to_be_bytes());
+        self.rtx_sequence = self.rtx_sequence.wrapping_add(1);
+        self.rewrite(packet, self.rtx_ssrc());
+        let osn = u16::from_be_bytes([packet[at], packet[at + 1]]).wrapping_add(self.seq_offset);
+        packet[at..at + 2].copy_from_slice(&osn.to_be_bytes());
+    }
+
+    /// Shared-stream RTP timestamp in this client's timestamp space
+    pub fn timestamp(&self, shared: u32) -> u32 {
+        shared.wrapping_add(self.ts_offset)
+    }
+
+    /// Map a sequence number this client saw back to the shared stream
+    pub fn shared_sequence(&self, client_seq: u16) -> u16 {
+        client_seq.wrapping_sub(self.seq_offset)
+    }
+
+    /// Timestamp offset + SSRC
+    fn rewrite(&self, packet: &mut [u8], ssrc: u32) {
+        let ts = self.timestamp(u32::from_be_bytes([packet[4], packet[5], packet[6], packet[7]]));
+
+        packet[4..8].copy_from_slice(&ts.to_be_bytes());
+        packet[8..12].copy_from_slice(&ssrc.to_be_bytes());
+    }
+}
@@ -0,0 +1,612 @@
+use super::impair::{Impairer, ImpairmentConfig};
+use super::packet::RtpPacket;
+use super::rtx::Retransmitter;
+use super::stamp::{RtpIdentity, RtpStamper};
+use crate::rtcp::sr::SenderReport;
+use std::collections::{HashMap, HashSet};
+use std::net::SocketAddr;
+use std::sync::{Arc, Weak};
+use std::time::{Duration, Instant};
+use tokio::net::UdpSocket;
+use tokio::sync::mpsc::{self, error::TrySendError};
+use tokio::sync::Mutex;
+
+/// Outbound queue capacity per client (packets, ~0.5s ở bitrate mặc định)
+const QUEUE_PACKETS: usize = 512;
+/// Client overflow liên tục lâu hơn mức này thì bị loại khỏi fan-out
+const OVERFLOW_EVICT_AFTER: Duration = Duration::from_secs(5);
+
+/// Packet trong outbound queue; `not_before` dùng cho initial burst pacing
+/// và pacing của NALU quá cỡ
+type Queued = (Option<Instant>, Vec<u8>);
+
+/// Một UDP client của fan-out
+#[derive(Clone, Debug)]
+pub struct UdpDestination {
+    pub rtp_addr: SocketAddr,
+    /// RTP identity chọn lúc SETUP
+    pub identity: RtpIdentity,
+    /// Socket riêng của session (`--session-ports`); None = socket chung
+    /// của sender
+    pub socket: Option<Arc<UdpSocket>>,
+}
+
+impl UdpDestination {
+    pub fn new(rtp_addr: SocketAddr, identity: RtpIdentity) -> Self {
+        Self { rtp_addr, identity, socket: None }
+    }
+}
+
+/// Output state riêng của từng UDP client
+struct ClientOutput {
+    stamper: RtpStamper,
+    impairer: Option<Impairer>,
+    /// Bounded queue, drained bởi task riêng của client
+    queue: mpsc::Sender<Queued>,
+    /// Initial burst window; None khi tắt hoặc keyframe đầu đã được gửi
+    burst_window: Option<Duration>,
+    /// Packets của keyframe AU đầu tiên, gom lại tới marker rồi mới pace
+    burst: Option<Vec<Vec<u8>>>,
+    /// Queue đầy: bỏ packets cho tới keyframe kế tiếp
+    skipping_to_keyframe: bool,
+    overflow_since: Option<Instant>,
+    dropped: u64,
+    /// Packets/bytes accepted into the queue (RTP header included)
+    packets_sent: u64,
+    bytes_sent: u64,
+}
+
+impl ClientOutput {
+    /// Drain task chỉ giữ `Weak`: socket của session đóng ngay khi lease
+    /// được nhả, không đợi lần fan-out kế tiếp bỏ client
+    fn new(
+        socket: Weak<UdpSocket>,
+        rtp_addr: SocketAddr,
+        identity: RtpIdentity,
+        impairment: Option<ImpairmentConfig>,
+        burst_window: Option<Duration>,
+    ) -> Self {
+        let (queue, mut packets) = mpsc::channel::<Queued>(QUEUE_PACKETS);
+
+        // Client chậm/unreachable chỉ block task của chính nó
+        tokio::spawn(async move {
+            while let Some((not_before, data)) = packets.recv().await {
+                if let Some(at) = not_before {
+                    tokio::time::sleep_until(at.into()).await;
+                }
+                let Some(socket) = socket.upgrade() else {
+                    return;
+                };
+                if let Err(e) = socket.send_to(&data, rtp_addr).await {
+                    eprintln!("⚠️  RTP send error to {}: {}", rtp_addr, e);
+                }
+            }
+        });
+
+        let stamper = RtpStamper::new(identity);
+        println!("🆔 UDP client {} gets SSRC {:08x}", rtp_addr, stamper.ssrc());
+        Self {
+            stamper,
+            impairer: impairment.map(Impairer::new),
+            queue,
+            burst_window,
+            burst: None,
+            skipping_to_keyframe: false,
+            overflow_since: None,
+            dropped: 0,
+            packets_sent: 0,
+            bytes_sent: 0,
+        }
+    }
+
+    /// Enqueue one stamped packet, sent no earlier than `not_before`. On
+    /// overflow, drop until the next keyframe
+    fn enqueue(&mut self, data: Vec<u8>, not_before: Option<Instant>, starts_keyframe: bool, now: Instant) {
+        // Initial burst: gom keyframe AU đầu tiên (tới packet có marker) rồi
+        // trải đều các packets của nó trên burst window
+        if let Some(window) = self.burst_window {
+            if self.burst.is_none() && starts_keyframe {
+                self.burst = Some(Vec::new());
+            }
+            if let Some(burst) = self.burst.as_mut() {
+                let marker = data.get(1).is_some_and(|b| b & 0x80 != 0);
+                burst.push(data);
+                if marker || burst.len() >= QUEUE_PACKETS {
+                    let burst = self.burst.take().unwrap_or_default();
+                    self.burst_window = None;
+                    let spacing = window / burst.len() as u32;
+                    println!("🐇 Pacing first keyframe ({} packets) over {:?}", burst.len(), window);
+                    for (i, data) in burst.into_iter().enumerate() {
+                        self.push(Some(now + spacing * i as u32), data, true, now);
+                    }
+                }
+                return;
+            }
+        }
+
+        self.push(not_before, data, starts_keyframe, now);
+    }
+
+    fn push(&mut self, not_before: Option<Instant>, data: Vec<u8>, starts_keyframe: bool, now: Instant) {
+        if self.skipping_to_keyframe && !starts_keyframe {
+            self.dropped += 1;
+            return;
+        }
+        let len = data.len() as u64;
+        match self.queue.try_send((not_before, data)) {
+            Ok(()) => {
+                self.packets_sent += 1;
+                self.bytes_sent += len;
+                self.skipping_to_keyframe = false;
+                self.overflow_since = None;
+            }
+            Err(TrySendError::Full(_)) => {
+                self.dropped += 1;
+                self.skipping_to_keyframe = true;
+                self.overflow_since.get_or_insert(now);
+            }
+            Err(TrySendError::Closed(_)) => self.dropped += 1,
+        }
+    }
+
+    fn depth(&self) -> usize {
+        self.queue.max_capacity() - self.queue.capacity()
+    }
+}
+
+/// Queue metrics của một UDP client
+#[derive(Clone, Copy, Debug)]
+pub struct QueueStats {
+    pub rtp_addr: SocketAddr,
+    pub depth: usize,
+    pub dropped: u64,
+    pub packets_sent: u64,
+    pub bytes_sent: u64,
+}
+
+#[derive(Default)]
+struct Outputs {
+    clients: HashMap<SocketAddr, ClientOutput>,
+    /// Thời gian CPU dành cho per-client stamping (đo overhead)
+    stamping_time: Duration,
+    stamped_packets: u64,
+    /// Clients bị loại do overflow, chờ caller bỏ session
+    evicted: Vec<SocketAddr>,
+    /// Không tạo lại output cho client đã bị loại khi session còn tồn tại
+    blocked: HashSet<SocketAddr>,
+    /// Lần cuối `send` có packet (cho keepalive injection)
+    last_send: Option<Instant>,
+}
+
+/// UDP fan-out: gửi RTP packets đến tất cả UDP playing clients
+///
+/// Packetize một lần trên shared stream, rồi stamp SSRC/sequence/timestamp
+/// riêng cho từng client (key theo RTP address) và đẩy vào bounded queue
+/// của client đó; mỗi queue có task gửi riêng nên client chậm không làm
+/// trễ các client khác.
+pub struct UdpSender {
+    socket: Arc<UdpSocket>,
+    retransmitter: Option<Mutex<Retransmitter>>,
+    impairment: Option<ImpairmentConfig>,
+    /// Opt-in pacing window cho keyframe AU đầu tiên của mỗi client
+    initial_burst: Option<Duration>,
+    outputs: Mutex<Outputs>,
+}
+
+impl UdpSender {
+    /// `rtx_history`: RTX history capacity (packets), None tắt RTX
+    pub fn new(
+        socket: Arc<UdpSocket>,
+        rtx_history: Option<usize>,
+        impairment: Option<ImpairmentConfig>,
+        initial_burst: Option<Duration>,
+    ) -> Self {
+        Self {
+            socket,
+            retransmitter: rtx_history.map(|capacity| Mutex::new(Retransmitter::new(0, capacity))),
+            impairment,
+            initial_burst,
+            outputs: Mutex::new(Outputs::default()),
+        }
+    }
+
+    /// Fan packets out to `udp_clients` (RTP address + the identity chosen at
+    /// SETUP, from the session's own socket if it has one). Clients evicted after sustained queue overflow are reported
+    /// by `take_evicted`
+    pub async fn send(&self, packets: &[RtpPacket], udp_clients: &[UdpDestination]) {
+        self.send_scheduled(packets, &[], udp_clients).await;
+    }
+
+    /// `send`, nhưng packet thứ i rời queue của mỗi client không sớm hơn
+    /// `offsets[i]` kể từ bây giờ (vd. `H264Packetizer::pacing_schedule`);
+    /// thiếu offset = gửi ngay. Caller không phải chờ: drain task của client
+    /// pace, packets sau đó vẫn xếp hàng phía sau theo đúng thứ tự
+    pub async fn send_scheduled(
+        &self,
+        packets: &[RtpPacket],
+        offsets: &[Duration],
+        udp_clients: &[UdpDestination],
+    ) {
+        let mut outputs = self.outputs.lock().await;
+        let Outputs { clients, stamping_time, stamped_packets, evicted, blocked, last_send } = &mut *outputs;
+
+        // Client mới được gán RTP identity + queue riêng; client đã rời thì bỏ
+        // (drop Sender → drain task kết thúc)
+        clients.retain(|addr, _| udp_clients.iter().any(|dest| dest.rtp_addr == *addr));
+        blocked.retain(|addr| udp_clients.iter().any(|dest| dest.rtp_addr == *addr));
+        for dest in udp_clients.iter().filter(|dest| !blocked.contains(&dest.rtp_addr)) {
+            clients.entry(dest.rtp_addr).or_insert_with(|| {
+                ClientOutput::new(
+                    Arc::downgrade(dest.socket.as_ref().unwrap_or(&self.socket)),
+                    dest.rtp_addr,
+                    dest.identity,
+                    self.impairment.clone(),
+                    self.initial_burst,
+                )
+            });
+        }
+
+        let now = Instant::now();
+        if !packets.is_empty() {
+            *last_send = Some(now);
+        }
+        for (i, packet) in packets.iter().enumerate() {
+            let data = packet.to_bytes();
+            let keyframe = starts_keyframe(&packet.payload);
+            let not_before = offsets.get(i).filter(|offset| !offset.is_zero()).map(|offset| now + *offset);
+            for dest in udp_clients {
+                let Some(client) = clients.get_mut(&dest.rtp_addr) else {
+                    continue;
+                };
+
+                let started = Instant::now();
+                let mut data = data.clone();
+                client.stamper.stamp(&mut data);
+                *stamping_time += started.elapsed();
+                *stamped_packets += 1;
+
+                let outgoing = match client.impairer.as_mut() {
+                    Some(impairer) => impairer.process(data, now),
+                    None => vec![data],
+                };
+
+                for data in outgoing {
+                    client.enqueue(data, not_before, keyframe, now);
+                }
+            }
+        }
+
+        clients.retain(|addr, client| {
+            let overflowing = client.overflow_since.is_some_and(|since| now - since > OVERFLOW_EVICT_AFTER);
+            if overflowing {
+                println!("🚫 UDP client {} evicted: outbound queue overflowing for {:?}", addr, OVERFLOW_EVICT_AFTER);
+                evicted.push(*addr);
+                blocked.insert(*addr);
+            }
+            !overflowing
+        });
+
+        // Ghi lại cho RTX nếu bật
+        if let Some(retransmitter) = &self.retransmitter {
+            let mut retransmitter = retransmitter.lock().await;
+            for packet in packets {
+                retransmitter.record(packet);
+            }
+        }
+    }
+
+    /// Retransmit the sequence numbers a client reported lost (in its own
+    /// sequence space). Returns how many packets were resent
+    pub async fn retransmit(&self, rtp_addr: SocketAddr, lost: &[u16]) -> usize {
+        let Some(retransmitter) = &self.retransmitter else {
+            return 0;
+        };
+        let mut outputs = self.outputs.lock().await;
+        let Some(client) = outputs.clients.get_mut(&rtp_addr) else {
+            return 0;
+        };
+
+        let shared: Vec<u16> = lost.iter().map(|seq| client.stamper.shared_sequence(*seq)).collect();
+        let rtx_packets = retransmitter.lock().await.retransmit(&shared);
+
+        // RTX đi cùng queue với original stream của client
+        let now = Instant::now();
+        for packet in &rtx_packets {
+            let mut data = packet.to_bytes();
+            client.stamper.stamp_rtx(&mut data);
+            client.enqueue(data, None, false, now);
+        }
+        rtx_packets.len()
+    }
+
+    /// Time since RTP was last fanned out (None if nothing was sent yet)
+    pub async fn idle_for(&self) -> Option<Duration> {
+        self.outputs.lock().await.last_send.map(|at| at.elapsed())
+    }
+
+    /// RTP addresses of clients evicted since the last call
+    pub async fn take_evicted(&self) -> Vec<SocketAddr> {
+        std::mem::take(&mut self.outputs.lock().await.evicted)
+    }
+
+    /// Current queue depth, drop count and sent totals per client
+    pub async fn queue_stats(&self) -> Vec<QueueStats> {
+        self.outputs
+            .lock()
+            .await
+            .clients
+            .iter()
+            .map(|(addr, client)| QueueStats {
+                rtp_addr: *addr,
+                depth: client.depth(),
+                dropped: client.dropped,
+                packets_sent: client.packets_sent,
+                bytes_sent: client.bytes_sent,
+            })
+            .collect()
+    }
+
+    /// Per-client sender report (client SSRC + counters), keyed by RTP
+    /// address. `shared_timestamp` (packetizer timestamp lúc này) được map
+    /// vào timestamp space của từng client làm RTP timestamp của SR
+    pub async fn sender_reports(&self, shared_timestamp: u32) -> HashMap<SocketAddr, SenderReport> {
+        self.outputs
+            .lock()
+            .await
+            .clients
+            .iter()
+            .map(|(addr, client)| {
+                let report = client.stamper.report.clone();
+                report.set_rtp_timestamp(client.stamper.timestamp(shared_timestamp));
+                (*addr, report)
+            })
+            .collect()
+    }
+
+    /// Stamping overhead since the last call: (total time, packets stamped)
+    pub async fn take_stamping_stats(&self) -> (Duration, u64) {
+        let mut outputs = self.outputs.lock().await;
+        let stats = (outputs.stamping_time, outputs.stamped_packets);
+        outputs.stamping_time = Duration::ZERO;
+        outputs.stamped_packets = 0;
+        stats
+    }
+}
+
+/// Packet bắt đầu một keyframe: SPS, IDR, FU-A start fragment của IDR, hoặc
+/// STAP-A mà NALU đầu tiên (sau 16-bit size) là SPS/IDR (SPS+PPS trước IDR)
+fn starts_keyframe(payload: &[u8]) -> bool {
+    match payload.first().map(|b| b & 0x1F) {
+        Some(5 | 7) => true,
+        Some(24) => payload.get(3).is_some_and(|nal| matches!(nal & 0x1F, 5 | 7)),
+        Some(28) => payload.get(1).is_some_and(|fu| fu & 0x80 != 0 && fu & 0x1F == 5),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rtp::h264::{FragmentLimit, H264Packetizer, OversizePolicy, CLOCK_RATE};
+
+    #[test]
+    fn stap_a_with_parameter_sets_starts_a_keyframe() {
+        let (sps, pps) = ([0x67, 0x42, 0xC0, 0x1F], [0x68, 0xCE, 0x3C, 0x80]);
+        let packets = H264Packetizer::with_ssrc(1).packetize_stap_a(&[&sps, &pps]);
+        let payload = &packets[0].payload;
+        // STAP-A header (type 24, NRI của SPS) rồi size-prefixed NALUs
+        assert_eq!(payload[..], [0x78, 0, 4, 0x67, 0x42, 0xC0, 0x1F, 0, 4, 0x68, 0xCE, 0x3C, 0x80]);
+        assert!(starts_keyframe(payload));
+
+        // STAP-A gom NALUs khác (SEI + PPS) không phải keyframe start
+        assert!(!starts_keyframe(&[0x78, 0, 2, 0x06, 0x05, 0, 4, 0x68, 0xCE, 0x3C, 0x80]));
+        assert!(!starts_keyframe(&[0x78, 0]));
+    }
+
+    #[test]
+    fn keyframe_starts_for_single_nal_and_fu_a() {
+        assert!(starts_keyframe(&[0x65, 0x88]));
+        assert!(starts_keyframe(&[0x67, 0x42]));
+        assert!(!starts_keyframe(&[0x41, 0x9A]));
+        assert!(starts_keyframe(&[0x7C, 0x85, 0x88])); // FU-A start, IDR
+        assert!(!starts_keyframe(&[0x7C, 0x45, 0x88])); // FU-A end, IDR
+        assert!(!starts_keyframe(&[0x5C, 0x81, 0x9A])); // FU-A start, non-IDR
+    }
+
+    #[tokio::test]
+    async fn oversized_nalu_is_paced_by_the_client_queue() {
+        let receiver = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let socket = Arc::new(UdpSocket::bind("127.0.0.1:0").await.unwrap());
+        let sender = UdpSender::new(socket, None, None, None);
+        let clients = [UdpDestination::new(receiver.local_addr().unwrap(), RtpIdentity { ssrc: 7, seq_offset: 0, ts_offset: 0 })];
+
+        let mut packetizer = H264Packetizer::with_ssrc(1);
+        packetizer.set_frame_ticks(9_000); // 100ms frame interval
+        packetizer.set_fragment_limit(Some(FragmentLimit { max: 2, policy: OversizePolicy::Pace }));
+        let packets = packetizer.packetize(&vec![0x65; 4 * 1398 + 1], true);
+        let offsets = packetizer.pacing_schedule(packets.len()).unwrap();
+        assert_eq!(packets.len(), 4);
+
+        // Caller không bị chặn: pacing nằm hoàn toàn trong drain task
+        let started = Instant::now();
+        sender.send_scheduled(&packets, &offsets, &clients).await;
+        assert!(started.elapsed() < Duration::from_millis(40));
+
+        let mut arrivals = Vec::new();
+        let mut buf = [0u8; 1500];
+        for _ in 0..packets.len() {
+            let len = tokio::time::timeout(Duration::from_secs(2), receiver.recv(&mut buf)).await.unwrap().unwrap();
+            assert!(len <= 1400 + 12);
+            arrivals.push(started.elapsed());
+        }
+        // Batch thứ hai (packets 2-3) không sớm hơn nửa frame interval
+        assert!(arrivals[1] < Duration::from_millis(50));
+        assert!(arrivals[2] >= Duration::from_millis(50));
+        assert!(arrivals[3] >= Duration::from_millis(50));
+    }
+
+    #[tokio::test]
+    async fn rtx_sequence_is_contiguous_per_client() {
+        let socket = Arc::new(UdpSocket::bind("127.0.0.1:0").await.unwrap());
+        let sender = UdpSender::new(socket, Some(64), None, None);
+        let receivers = [UdpSocket::bind("127.0.0.1:0").await.unwrap(), UdpSocket::bind("127.0.0.1:0").await.unwrap()];
+        let identities = [
+            RtpIdentity { ssrc: 0x1000, seq_offset: 100, ts_offset: 0 },
+            RtpIdentity { ssrc: 0x2000, seq_offset: 65_000, ts_offset: 0 },
+        ];
+        let clients: Vec<UdpDestination> = receivers
+            .iter()
+            .zip(identities)
+            .map(|(receiver, identity)| UdpDestination::new(receiver.local_addr().unwrap(), identity))
+            .collect();
+
+        let mut packetizer = H264Packetizer::with_ssrc(9);
+        let mut shared = Vec::new();
+        for i in 0..8u8 {
+            let packets = packetizer.packetize(&[0x41, i], true);
+            shared.push(packets[0].header.sequence);
+            sender.send(&packets, &clients).await;
+        }
+
+        // Hai clients NACK xen kẽ: mỗi client vẫn có RTX sequence space riêng
+        for (round, seq) in shared.iter().take(6).enumerate() {
+            let client = &clients[round % 2];
+            let lost = seq.wrapping_add(client.identity.seq_offset);
+            assert_eq!(sender.retransmit(client.rtp_addr, &[lost]).await, 1);
+        }
+
+        let mut buf = [0u8; 1500];
+        for (i, (receiver, identity)) in receivers.iter().zip(identities).enumerate() {
+            let mut rtx = Vec::new();
+            for _ in 0..8 + 3 {
+                let len = tokio::time::timeout(Duration::from_secs(2), receiver.recv(&mut buf)).await.unwrap().unwrap();
+                if buf[1] & 0x7F == crate::rtp::rtx::RTX_PAYLOAD_TYPE {
+                    assert_eq!(u32::from_be_bytes([buf[8], buf[9], buf[10], buf[11]]), identity.ssrc + 1);
+                    let osn = u16::from_be_bytes([buf[12], buf[13]]);
+                    rtx.push((u16::from_be_bytes([buf[2], buf[3]]), osn));
+                    assert!(len > 14);
+                }
+            }
+            assert_eq!(rtx.len(), 3);
+            assert_eq!(rtx[0].1, shared[i].wrapping_add(identity.seq_offset));
+            for pair in rtx.windows(2) {
+                assert_eq!(pair[1].0, pair[0].0.wrapping_add(1), "RTX seqs not contiguous: {:?}", rtx);
+                assert_eq!(pair[1].1, pair[0].1.wrapping_add(2), "OSNs: {:?}", rtx);
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn first_keyframe_is_spread_over_the_burst_window() {
+        let window = Duration::from_millis(100);
+        let receiver = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let socket = Arc::new(UdpSocket::bind("127.0.0.1:0").await.unwrap());
+        let sender = UdpSender::new(socket, None, None, Some(window));
+        let clients = [UdpDestination::new(receiver.local_addr().unwrap(), RtpIdentity { ssrc: 7, seq_offset: 0, ts_offset: 0 })];
+
+        let mut packetizer = H264Packetizer::with_ssrc(1);
+        let slice = packetizer.packetize(&[0x41, 0x9A, 0x02], true);
+        packetizer.end_access_unit();
+        let keyframe = packetizer.packetize(&vec![0x65; 10 * 1400], true);
+        packetizer.end_access_unit();
+        let next_keyframe = packetizer.packetize(&vec![0x65; 4 * 1400], true);
+        assert!(keyframe.len() >= 10);
+
+        // Slice trước keyframe đầu không bị giữ lại; caller không bị chặn
+        let started = Instant::now();
+        sender.send(&slice, &clients).await;
+        sender.send(&keyframe, &clients).await;
+        assert!(started.elapsed() < Duration::from_millis(40));
+
+        /// Thời điểm (tính từ `started`) packet kế tiếp tới
+        async fn arrival(receiver: &UdpSocket, started: Instant) -> Duration {
+            let mut buf = [0u8; 1500];
+            tokio::time::timeout(Duration::from_secs(2), receiver.recv(&mut buf)).await.unwrap().unwrap();
+            started.elapsed()
+        }
+        let recv = || arrival(&receiver, started);
+        assert!(recv().await < Duration::from_millis(40));
+        // Packet thứ i không rời queue trước i * window / n
+        let spacing = window / keyframe.len() as u32;
+        let mut arrivals = Vec::new();
+        for i in 0..keyframe.len() {
+            let arrival = recv().await;
+            assert!(arrival >= spacing * i as u32, "packet {} at {:?}", i, arrival);
+            arrivals.push(arrival);
+        }
+        assert!(arrivals[0] < Duration::from_millis(40));
+        assert!(arrivals[keyframe.len() - 1] < window + Duration::from_millis(500));
+
+        // Chỉ AU đầu tiên: keyframe sau đó gửi liền một mạch
+        let started_next = started.elapsed();
+        sender.send(&next_keyframe, &clients).await;
+        for _ in 0..next_keyframe.len() {
+            assert!(recv().await - started_next < Duration::from_millis(40));
+        }
+    }
+
+    /// SR của mỗi client: RTP timestamp đúng bằng timestamp mà stamper của
+    /// client đó sẽ gán cho access unit phát tại NTP time của SR
+    #[tokio::test]
+    async fn sender_report_timestamps_follow_each_clients_stamper() {
+        use crate::clock::{Clock, ManualClock};
+        use std::time::UNIX_EPOCH;
+
+        let clock = ManualClock::new(UNIX_EPOCH + Duration::from_secs(1_700_000_000));
+        let socket = Arc::new(UdpSocket::bind("127.0.0.1:0").await.unwrap());
+        let sender = UdpSender::new(socket, None, None, None);
+        let receivers = [UdpSocket::bind("127.0.0.1:0").await.unwrap(), UdpSocket::bind("127.0.0.1:0").await.unwrap()];
+        let identities = [
+            RtpIdentity { ssrc: 0x1000, seq_offset: 10, ts_offset: 12_345 },
+            // Timestamp space của client này wrap giữa hai SRs
+            RtpIdentity { ssrc: 0x2000, seq_offset: 20, ts_offset: u32::MAX - 120_000 },
+        ];
+        let clients: Vec<UdpDestination> = receivers
+            .iter()
+            .zip(identities)
+            .map(|(receiver, identity)| UdpDestination::new(receiver.local_addr().unwrap(), identity))
+            .collect();
+
+        let mut packetizer = H264Packetizer::with_ssrc(9);
+        let mut reports = Vec::new();
+        for frame in 1..=60 {
+            sender.send(&packetizer.packetize(&[0x41, 0x9A, frame as u8], true), &clients).await;
+            packetizer.end_access_unit();
+            clock.advance(packetizer.frame_duration());
+            if frame % 30 == 0 {
+                // Như SR loop: timestamp đọc ngay trước wall clock của SR
+                let shared = packetizer.timestamp();
+                let by_client = sender.sender_reports(shared).await;
+                reports.push((shared, clients.iter().map(|c| by_client[&c.rtp_addr].to_bytes(clock.wall())).collect::<Vec<_>>()));
+            }
+        }
+
+        let word = |b: &[u8], at: usize| u32::from_be_bytes([b[at], b[at + 1], b[at + 2], b[at + 3]]);
+        let mut buf = [0u8; 1500];
+        for (i, (receiver, identity)) in receivers.iter().zip(identities).enumerate() {
+            let mut timestamps = Vec::new();
+            for _ in 0..60 {
+                tokio::time::timeout(Duration::from_secs(2), receiver.recv(&mut buf)).await.unwrap().unwrap();
+                assert_eq!(word(&buf, 8), identity.ssrc);
+                timestamps.push(word(&buf, 4));
+            }
+
+            for (n, (shared, sr)) in reports.iter().enumerate() {
+                let sr = &sr[i];
+                assert_eq!(word(sr, 4), identity.ssrc);
+                assert_eq!(word(sr, 16), shared.wrapping_add(identity.ts_offset));
+                // AU kế tiếp sau packet cuối client đã nhận trước SR
+                let last_sent = timestamps[30 * (n + 1) - 1];
+                assert_eq!(word(sr, 16), last_sent.wrapping_add(CLOCK_RATE / 30));
+                assert_eq!(word(sr, 20), 30 * (n as u32 + 1));
+            }
+
+            // RTP và NTP tiến cùng nhịp giữa hai SRs (90 kHz)
+            let (first, second) = (&reports[0].1[i], &reports[1].1[i]);
+            let ntp = |b: &[u8]| word(b, 8) as f64 + word(b, 12) as f64 / 2f64.powi(32);
+            let rtp_delta = word(second, 16).wrapping_sub(word(first, 16));
+            assert_eq!(rtp_delta, 30 * (CLOCK_RATE / 30));
+            assert!(((ntp(second) - ntp(first)) * CLOCK_RATE as f64 - rtp_delta as f64).abs() < 1.0);
+        }
+    }
+}
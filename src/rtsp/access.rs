@@ -0,0 +1,42 @@
+use super::state::{ClientInfo, EndReason, TransportMode};
+use crate::config::AccessLogFormat;
+use crate::status::json_string;
+use std::time::Instant;
+
+/// Access log summary cho một session đã kết thúc (một dòng / session)
+///
+/// Khác với per-request logging: chỉ ghi lifecycle + volume, đủ cho
+/// billing/ops (ai, mount nào, bao lâu, bao nhiêu bytes, vì sao kết thúc).
+pub fn access_line(client: &ClientInfo, reason: EndReason, format: AccessLogFormat, now: Instant) -> String {
+    let transport = match client.transport {
+        TransportMode::Udp { .. } => "udp",
+        TransportMode::TcpInterleaved { .. } => "tcp",
+        TransportMode::Multicast { .. } => "multicast",
+    };
+    let duration = now.saturating_duration_since(client.started).as_secs_f64();
+
+    match format {
+        AccessLogFormat::Plain => format!(
+            "📒 access session={} ip={} mount={} transport={} duration={:.3}s bytes={} packets={} reason={}",
+            client.id,
+            client.client_ip,
+            client.mount,
+            transport,
+            duration,
+            client.bytes_sent,
+            client.packets_sent,
+            reason.as_str()
+        ),
+        AccessLogFormat::Json => format!(
+            "{{\"session\":{},\"ip\":{},\"mount\":{},\"transport\":\"{}\",\"duration\":{:.3},\"bytes\":{},\"packets\":{},\"reason\":\"{}\"}}",
+            json_string(&client.id),
+            json_string(&client.client_ip),
+            json_string(&client.mount),
+            transport,
+            duration,
+            client.bytes_sent,
+            client.packets_sent,
+            reason.as_str()
+        ),
+    }
+}
@@ -0,0 +1,162 @@
+use std::net::IpAddr;
+use std::str::FromStr;
+
+/// CIDR range, ví dụ `192.168.1.0/24` hoặc `fd00::/8`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Cidr {
+    network: IpAddr,
+    prefix_len: u8,
+}
+
+impl Cidr {
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        match (self.network, ip.to_canonical()) {
+            (IpAddr::V4(net), IpAddr::V4(ip)) => {
+                let mask = mask_u32(self.prefix_len);
+                u32::from(net) & mask == u32::from(ip) & mask
+            }
+            (IpAddr::V6(net), IpAddr::V6(ip)) => {
+                let mask = mask_u128(self.prefix_len);
+                u128::from(net) & mask == u128::from(ip) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+impl FromStr for Cidr {
+    type Err = String;
+
+    /// Parse `addr/len`; a bare address is a single-host range
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (addr, len) = match s.split_once('/') {
+            Some((addr, len)) => (addr, Some(len)),
+            None => (s, None),
+        };
+
+        let network: IpAddr = addr
+            .trim()
+            .parse()
+            .map_err(|_| format!("invalid address in CIDR '{}'", s))?;
+        let max_len = if network.is_ipv4() { 32 } else { 128 };
+
+        let prefix_len = match len {
+            Some(len) => len
+                .trim()
+                .parse::<u8>()
+                .ok()
+                .filter(|len| *len <= max_len)
+                .ok_or_else(|| format!("invalid prefix length in CIDR '{}'", s))?,
+            None => max_len,
+        };
+
+        Ok(Self { network: network.to_canonical(), prefix_len })
+    }
+}
+
+fn mask_u32(prefix_len: u8) -> u32 {
+    u32::MAX.checked_shl(32 - prefix_len as u32).unwrap_or(0)
+}
+
+fn mask_u128(prefix_len: u8) -> u128 {
+    u128::MAX.checked_shl(128 - prefix_len as u32).unwrap_or(0)
+}
+
+/// Client address allowlist/denylist, checked on every accepted connection.
+/// Deny wins over allow; an empty allowlist allows everyone not denied
+#[derive(Clone, Debug, Default)]
+pub struct AccessList {
+    pub allow: Vec<Cidr>,
+    pub deny: Vec<Cidr>,
+}
+
+impl AccessList {
+    pub fn is_allowed(&self, ip: IpAddr) -> bool {
+        if self.deny.iter().any(|cidr| cidr.contains(ip)) {
+            return false;
+        }
+        self.allow.is_empty() || self.allow.iter().any(|cidr| cidr.contains(ip))
+    }
+
+    /// Parse a comma-separated CIDR list, e.g. `10.0.0.0/8,192.168.1.5`
+    pub fn parse_list(list: &str) -> Result<Vec<Cidr>, String> {
+        list.split(',')
+            .map(str::trim)
+            .filter(|entry| !entry.is_empty())
+            .map(str::parse)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cidr(s: &str) -> Cidr {
+        s.parse().unwrap()
+    }
+
+    fn ip(s: &str) -> IpAddr {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn v4_ranges() {
+        let lan = cidr("192.168.1.0/24");
+        assert!(lan.contains(ip("192.168.1.0")));
+        assert!(lan.contains(ip("192.168.1.255")));
+        assert!(!lan.contains(ip("192.168.2.1")));
+        // IPv4-mapped IPv6 (dual-stack listener) khớp range v4
+        assert!(lan.contains(ip("::ffff:192.168.1.7")));
+        assert!(!lan.contains(ip("fe80::1")));
+    }
+
+    #[test]
+    fn v6_ranges() {
+        let ula = cidr("fd00::/8");
+        assert!(ula.contains(ip("fd12:3456::1")));
+        assert!(!ula.contains(ip("fe80::1")));
+        assert!(!ula.contains(ip("10.0.0.1")));
+        assert!(cidr("2001:db8::1/128").contains(ip("2001:db8::1")));
+        assert!(!cidr("2001:db8::1/128").contains(ip("2001:db8::2")));
+    }
+
+    #[test]
+    fn edge_prefixes() {
+        let any_v4 = cidr("0.0.0.0/0");
+        assert!(any_v4.contains(ip("1.2.3.4")));
+        assert!(any_v4.contains(ip("255.255.255.255")));
+        assert!(cidr("::/0").contains(ip("2001:db8::1")));
+
+        let host = cidr("10.1.2.3/32");
+        assert!(host.contains(ip("10.1.2.3")));
+        assert!(!host.contains(ip("10.1.2.4")));
+        // Bare address = /32 (/128 cho v6)
+        assert_eq!(cidr("10.1.2.3"), host);
+        assert_eq!(cidr("::1"), cidr("::1/128"));
+    }
+
+    #[test]
+    fn rejects_malformed_input() {
+        for bad in ["", "10.0.0/8", "10.0.0.0/33", "fd00::/129", "10.0.0.0/-1", "10.0.0.0/", "host/24", "10.0.0.0/8/8"] {
+            assert!(bad.parse::<Cidr>().is_err(), "{:?} should not parse", bad);
+        }
+        assert!(AccessList::parse_list("10.0.0.0/8, bogus").is_err());
+        assert_eq!(AccessList::parse_list(" 10.0.0.0/8 ,, 192.168.1.5,").unwrap().len(), 2);
+    }
+
+    #[test]
+    fn deny_wins_over_allow() {
+        let acl = AccessList {
+            allow: AccessList::parse_list("10.0.0.0/8").unwrap(),
+            deny: AccessList::parse_list("10.0.0.66").unwrap(),
+        };
+        assert!(acl.is_allowed(ip("10.0.0.65")));
+        assert!(!acl.is_allowed(ip("10.0.0.66")));
+        assert!(!acl.is_allowed(ip("192.168.1.1")), "not in a non-empty allowlist");
+
+        let deny_only = AccessList { allow: Vec::new(), deny: vec![cidr("192.168.0.0/16")] };
+        assert!(deny_only.is_allowed(ip("10.0.0.1")));
+        assert!(!deny_only.is_allowed(ip("192.168.3.4")));
+    }
+}
@@ -0,0 +1,70 @@
+/// Giới hạn một message đang buffer (headers + body) trước khi bỏ connection
+pub const MAX_MESSAGE_LEN: usize = 64 * 1024;
+
+/// Một message hoàn chỉnh trên RTSP connection
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Frame {
+    /// RTSP request: headers và body (theo `Content-Length`)
+    Request(String),
+    /// Interleaved binary data `$<channel><len>` (RFC 2326 §10.12), vd. RTCP RR
+    Interleaved { channel: u8, payload: Vec<u8> },
+}
+
+/// Tách byte stream của connection thành từng message: requests pipelined
+/// trong một read được trả lần lượt, request bị cắt qua nhiều reads chờ đủ
+/// bytes, và interleaved frames xen giữa requests được nhận diện bằng `$`
+#[derive(Debug, Default)]
+pub struct RtspFramer {
+    buffer: Vec<u8>,
+}
+
+impl RtspFramer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, data: &[u8]) {
+        self.buffer.extend_from_slice(data);
+    }
+
+    /// True khi message đang dở đã vượt `MAX_MESSAGE_LEN`
+    pub fn overflowed(&self) -> bool {
+        self.buffer.len() > MAX_MESSAGE_LEN
+    }
+
+    /// Message hoàn chỉnh kế tiếp, None nếu cần thêm bytes
+    pub fn next_frame(&mut self) -> Option<Frame> {
+        // CRLF thừa giữa các messages (một số clients dùng làm keepalive)
+        let skip = self.buffer.iter().take_while(|b| matches!(b, b'\r' | b'\n')).count();
+        self.buffer.drain(..skip);
+
+        if self.buffer.first() == Some(&b'$') {
+            let header = self.buffer.get(..4)?;
+            let (channel, len) = (header[1], u16::from_be_bytes([header[2], header[3]]) as usize);
+            let payload = self.buffer.get(4..4 + len)?.to_vec();
+            self.buffer.drain(..4 + len);
+            return Some(Frame::Interleaved { channel, payload });
+        }
+
+        let header_end = self.buffer.windows(4).position(|w| w == b"\r\n\r\n")? + 4;
+        let headers = String::from_utf8_lossy(&self.buffer[..header_end]);
+        let total = header_end + content_length(&headers);
+        if self.buffer.len() < total {
+            return None;
+        }
+
+        let request = String::from_utf8_lossy(&self.buffer[..total]).into_owned();
+        self.buffer.drain(..total);
+        Some(Frame::Request(request))
+    }
+}
+
+/// `Content-Length` của header block (0 nếu thiếu hoặc sai)
+fn content_length(headers: &str) -> usize {
+    headers
+        .lines()
+        .filter_map(|line| line.split_once(':'))
+        .find(|(name, _)| name.trim().eq_ignore_ascii_case("Content-Length"))
+        .and_then(|(_, value)| value.trim().parse().ok())
+        .unwrap_or(0)
+}
@@ -1,3 +1,10 @@
+pub mod access;
+pub mod acl;
+pub mod framing;
+pub mod range;
+pub mod response;
+pub mod sdp;
 pub mod session;
 pub(crate) mod server;
 pub mod state;
+pub mod uri;
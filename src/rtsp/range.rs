@@ -0,0 +1,264 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Giá trị `Range: npt=...` (RFC 2326 §3.6)
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum NptRange {
+    /// `npt=now-`: live point
+    Now,
+    /// `npt=<start>-[<end>]`, in seconds
+    From { start: f64, end: Option<f64> },
+}
+
+impl NptRange {
+    /// Parse a Range header value, e.g. `npt=10.5-`, `npt=0:01:30-0:02:00`, `npt=now-`
+    pub fn parse(value: &str) -> Option<Self> {
+        let spec = value.trim().strip_prefix("npt=")?;
+        // Bỏ qua `;time=...` nếu có
+        let spec = spec.split(';').next()?.trim();
+        let (start, end) = spec.split_once('-')?;
+
+        if start.trim() == "now" {
+            return Some(NptRange::Now);
+        }
+
+        let start = parse_npt_time(start)?;
+        let end = match end.trim() {
+            "" => None,
+            end => Some(parse_npt_time(end)?),
+        };
+
+        if end.is_some_and(|end| end < start) {
+            return None;
+        }
+        Some(NptRange::From { start, end })
+    }
+
+    /// Header value echoed in the PLAY response
+    pub fn to_header(self) -> String {
+        match self {
+            NptRange::Now => "npt=now-".to_string(),
+            NptRange::From { start, end: Some(end) } => format!("npt={:.3}-{:.3}", start, end),
+            NptRange::From { start, end: None } => format!("npt={:.3}-", start),
+        }
+    }
+}
+
+/// npt-sec (`12.5`) hoặc npt-hhmmss (`0:01:30.25`)
+fn parse_npt_time(value: &str) -> Option<f64> {
+    let parts: Vec<&str> = value.trim().split(':').collect();
+    let seconds = match parts.as_slice() {
+        [secs] => secs.parse::<f64>().ok()?,
+        [h, m, s] => {
+            let h: u64 = h.parse().ok()?;
+            let m: u64 = m.parse().ok()?;
+            let s: f64 = s.parse().ok()?;
+            if m >= 60 || s >= 60.0 {
+                return None;
+            }
+            (h * 3600 + m * 60) as f64 + s
+        }
+        _ => return None,
+    };
+    (seconds.is_finite() && seconds >= 0.0).then_some(seconds)
+}
+
+/// Giá trị `Range: clock=...` (RFC 2326 §3.7), absolute UTC time
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ClockRange {
+    pub start: SystemTime,
+    pub end: Option<SystemTime>,
+}
+
+impl ClockRange {
+    /// Parse e.g. `clock=20240101T120000Z-` hoặc `clock=20240101T120000.5Z-20240101T120100Z`
+    pub fn parse(value: &str) -> Option<Self> {
+        let spec = value.trim().strip_prefix("clock=")?;
+        let spec = spec.split(';').next()?.trim();
+        let (start, end) = spec.split_once('-')?;
+
+        let start = parse_clock_time(start)?;
+        let end = match end.trim() {
+            "" => None,
+            end => Some(parse_clock_time(end)?),
+        };
+
+        if end.is_some_and(|end| end < start) {
+            return None;
+        }
+        Some(ClockRange { start, end })
+    }
+
+    /// Map onto a media offset (seconds) given the wall-clock time at which
+    /// media position 0 plays (`epoch`). None nếu range nằm ngoài media
+    /// có sẵn: trước epoch, hoặc sau `duration` khi biết độ dài file
+    pub fn to_offset(self, epoch: SystemTime, duration: Option<f64>) -> Option<(f64, Option<f64>)> {
+        let offset = |time: SystemTime| time.duration_since(epoch).ok().map(|d| d.as_secs_f64());
+        let start = offset(self.start)?;
+        let end = match self.end {
+            Some(end) => Some(offset(end)?),
+            None => None,
+        };
+
+        let past_end = |t: f64| duration.is_some_and(|d| t > d);
+        if past_end(start) || end.is_some_and(past_end) {
+            return None;
+        }
+        Some((start, end))
+    }
+
+    /// Header value echoed in the PLAY response
+    pub fn to_header(self) -> String {
+        match self.end {
+            Some(end) => format!("clock={}-{}", format_clock_time(self.start), format_clock_time(end)),
+            None => format!("clock={}-", format_clock_time(self.start)),
+        }
+    }
+}
+
+/// ISO 8601 basic UTC: `YYYYMMDDThhmmss[.fraction]Z`
+pub fn parse_clock_time(value: &str) -> Option<SystemTime> {
+    let value = value.trim().strip_suffix('Z')?;
+    let (date, time) = value.split_once('T')?;
+    if date.len() != 8 || !date.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let (hms, fraction) = match time.split_once('.') {
+        Some((hms, fraction)) => (hms, Some(fraction)),
+        None => (time, None),
+    };
+    if hms.len() != 6 || !hms.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+
+    let year: i64 = date[0..4].parse().ok()?;
+    let month: u32 = date[4..6].parse().ok()?;
+    let day: u32 = date[6..8].parse().ok()?;
+    let hour: u64 = hms[0..2].parse().ok()?;
+    let minute: u64 = hms[2..4].parse().ok()?;
+    let second: u64 = hms[4..6].parse().ok()?;
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) || hour >= 24 || minute >= 60 || second >= 60 {
+        return None;
+    }
+    let nanos = match fraction {
+        Some(fraction) if !fraction.is_empty() && fraction.bytes().all(|b| b.is_ascii_digit()) => {
+            (format!("0.{}", fraction).parse::<f64>().ok()? * 1e9) as u32
+        }
+        Some(_) => return None,
+        None => 0,
+    };
+
+    let days = u64::try_from(days_from_civil(year, month, day)).ok()?;
+    let secs = days * 86_400 + hour * 3600 + minute * 60 + second;
+    Some(UNIX_EPOCH + Duration::new(secs, nanos))
+}
+
+/// Ngược lại của `parse_clock_time` (millisecond precision nếu có fraction)
+pub fn format_clock_time(time: SystemTime) -> String {
+    let since = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let secs = since.as_secs();
+    let (year, month, day) = civil_from_days((secs / 86_400) as i64);
+    let rem = secs % 86_400;
+    let base = format!(
+        "{:04}{:02}{:02}T{:02}{:02}{:02}",
+        year, month, day, rem / 3600, rem % 3600 / 60, rem % 60
+    );
+    match since.subsec_millis() {
+        0 => format!("{}Z", base),
+        millis => format!("{}.{:03}Z", base, millis),
+    }
+}
+
+/// Days since 1970-01-01 (Howard Hinnant's civil calendar algorithm)
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let yoe = year - era * 400;
+    let month = month as i64;
+    let doy = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + day as i64 - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146_097 + doe - 719_468
+}
+
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn npt_ranges_parse_seconds_and_hhmmss() {
+        assert_eq!(NptRange::parse("npt=now-"), Some(NptRange::Now));
+        assert_eq!(NptRange::parse(" npt=10.5-"), Some(NptRange::From { start: 10.5, end: None }));
+        assert_eq!(NptRange::parse("npt=0:01:30.25-0:02:00"), Some(NptRange::From { start: 90.25, end: Some(120.0) }));
+        assert_eq!(NptRange::parse("npt=1:00:00-;time=19970123T143720Z"), Some(NptRange::From { start: 3600.0, end: None }));
+
+        for invalid in ["npt=20-10", "npt=0:60:00-", "npt=0:00:60-", "npt=-5-", "npt=abc-", "npt=1:2-", "clock=20240101T000000Z-", "npt=10"] {
+            assert_eq!(NptRange::parse(invalid), None, "{}", invalid);
+        }
+
+        assert_eq!(NptRange::From { start: 1.5, end: Some(12.5) }.to_header(), "npt=1.500-12.500");
+        assert_eq!(NptRange::From { start: 0.0, end: None }.to_header(), "npt=0.000-");
+        assert_eq!(NptRange::Now.to_header(), "npt=now-");
+    }
+
+    #[test]
+    fn clock_times_round_trip() {
+        for time in ["19700101T000000Z", "20240101T120000Z", "20240229T235959.500Z", "20000229T000000Z", "21001231T010203.001Z"] {
+            assert_eq!(format_clock_time(parse_clock_time(time).unwrap()), time);
+        }
+        assert_eq!(parse_clock_time("19700102T000001Z"), Some(UNIX_EPOCH + Duration::from_secs(86_401)));
+        // 2024 là năm nhuận: 29/2 nằm giữa 28/2 và 1/3
+        let day = |date: &str| parse_clock_time(&format!("{}T000000Z", date)).unwrap();
+        assert_eq!(day("20240229").duration_since(day("20240228")).unwrap(), Duration::from_secs(86_400));
+        assert_eq!(day("20240301").duration_since(day("20240229")).unwrap(), Duration::from_secs(86_400));
+        // Fraction dài hơn milliseconds được cắt khi format
+        assert_eq!(format_clock_time(parse_clock_time("20240101T120000.123456Z").unwrap()), "20240101T120000.123Z");
+    }
+
+    #[test]
+    fn invalid_clock_times_are_rejected() {
+        for time in [
+            "20241301T000000Z", // tháng 13
+            "20240001T000000Z",
+            "20240100T000000Z", // ngày 0
+            "20240132T000000Z",
+            "20240101T240000Z", // giờ 24
+            "20240101T006000Z",
+            "20240101T000060Z",
+            "20240101T120000",  // thiếu Z
+            "20240101 120000Z",
+            "2024011T120000Z",
+            "20240101T120000.Z",
+            "20240101T120000.5xZ",
+            "19691231T235959Z", // trước UNIX epoch
+        ] {
+            assert_eq!(parse_clock_time(time), None, "{}", time);
+        }
+    }
+
+    #[test]
+    fn clock_ranges_map_onto_the_available_media() {
+        let epoch = parse_clock_time("20240101T120000Z").unwrap();
+        let range = ClockRange::parse("clock=20240101T120010.5Z-20240101T120100Z").unwrap();
+        assert_eq!(range.to_header(), "clock=20240101T120010.500Z-20240101T120100Z");
+        assert_eq!(range.to_offset(epoch, Some(120.0)), Some((10.5, Some(60.0))));
+        assert_eq!(range.to_offset(epoch, None), Some((10.5, Some(60.0))));
+
+        // Trước epoch, hoặc quá độ dài file
+        assert_eq!(ClockRange::parse("clock=20240101T115959Z-").unwrap().to_offset(epoch, None), None);
+        assert_eq!(range.to_offset(epoch, Some(30.0)), None);
+        assert_eq!(ClockRange::parse("clock=20240101T120100Z-20240101T120000Z"), None);
+        assert_eq!(ClockRange::parse("clock=20240101T120000Z"), None);
+    }
+}
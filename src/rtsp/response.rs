@@ -0,0 +1,104 @@
+/// Protocol-level failure của một RTSP request. Handlers trả về lỗi này thay
+/// vì tự format bytes; `status()` là nơi duy nhất map về status code/reason
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RtspError {
+    /// Request line hoặc body không parse được
+    BadRequest,
+    /// Mount hoặc track không tồn tại
+    NotFound,
+    /// Method server không hỗ trợ
+    MethodNotAllowed,
+    /// ANNOUNCE body không phải SDP, hoặc codec không hỗ trợ
+    UnsupportedMediaType,
+    /// `Session` header không khớp session của connection
+    SessionNotFound,
+    /// Method hợp lệ nhưng không phải lúc này (PLAY trước SETUP)
+    MethodNotValid,
+    /// Range ngoài media có sẵn hoặc sai cú pháp
+    InvalidRange,
+    /// Không còn tài nguyên cho session (hết `--session-ports`)
+    NotEnoughBandwidth,
+    /// Transport không dùng được (vd. `mode=record;append`)
+    UnsupportedTransport,
+    /// Tính năng RTSP chưa có (RECORD)
+    NotImplemented,
+    /// Stream chưa sẵn sàng (chưa có SPS/PPS)
+    ServiceUnavailable,
+}
+
+impl RtspError {
+    /// Status code + reason phrase (RFC 2326 §7.1.1)
+    pub fn status(self) -> (u16, &'static str) {
+        match self {
+            Self::BadRequest => (400, "Bad Request"),
+            Self::NotFound => (404, "Not Found"),
+            Self::MethodNotAllowed => (405, "Method Not Allowed"),
+            Self::UnsupportedMediaType => (415, "Unsupported Media Type"),
+            Self::NotEnoughBandwidth => (453, "Not Enough Bandwidth"),
+            Self::SessionNotFound => (454, "Session Not Found"),
+            Self::MethodNotValid => (455, "Method Not Valid in This State"),
+            Self::InvalidRange => (457, "Invalid Range"),
+            Self::UnsupportedTransport => (461, "Unsupported Transport"),
+            Self::NotImplemented => (501, "Not Implemented"),
+            Self::ServiceUnavailable => (503, "Service Unavailable"),
+        }
+    }
+}
+
+impl From<RtspError> for RtspResponse {
+    fn from(error: RtspError) -> Self {
+        let (code, reason) = error.status();
+        Self::new(code, reason)
+    }
+}
+
+/// RTSP response chưa serialize: status, headers theo thứ tự, body optional.
+/// `CSeq` và `Content-Length` được thêm lúc `render`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RtspResponse {
+    pub code: u16,
+    pub reason: &'static str,
+    pub headers: Vec<(&'static str, String)>,
+    pub body: String,
+}
+
+impl RtspResponse {
+    pub fn new(code: u16, reason: &'static str) -> Self {
+        Self {
+            code,
+            reason,
+            headers: Vec::new(),
+            body: String::new(),
+        }
+    }
+
+    pub fn ok() -> Self {
+        Self::new(200, "OK")
+    }
+
+    pub fn header(mut self, name: &'static str, value: impl Into<String>) -> Self {
+        self.headers.push((name, value.into()));
+        self
+    }
+
+    /// Body kèm `Content-Type`
+    pub fn body(self, content_type: &'static str, body: String) -> Self {
+        let mut response = self.header("Content-Type", content_type);
+        response.body = body;
+        response
+    }
+
+    /// Wire format, với `CSeq` ngay sau status line
+    pub fn render(&self, cseq: u32) -> String {
+        let mut out = format!("RTSP/1.0 {} {}\r\nCSeq: {}\r\n", self.code, self.reason, cseq);
+        for (name, value) in &self.headers {
+            out.push_str(&format!("{}: {}\r\n", name, value));
+        }
+        if !self.body.is_empty() {
+            out.push_str(&format!("Content-Length: {}\r\n", self.body.len()));
+        }
+        out.push_str("\r\n");
+        out.push_str(&self.body);
+        out
+    }
+}
@@ -0,0 +1,390 @@
+use crate::config::{AudioCodec, ServerConfig};
+use crate::rtp::aac;
+#[cfg(feature = "opus")]
+use crate::rtp::opus;
+use crate::rtp::rtx::RTX_PAYLOAD_TYPE;
+use super::state::SharedState;
+use std::time::{Duration, Instant};
+
+/// Control URL (relative) của video track
+pub const VIDEO_TRACK: &str = "track1";
+/// Control URL của audio track (AAC hoặc Opus, chỉ khi bật `--audio`)
+pub const AUDIO_TRACK: &str = "track2";
+
+/// DESCRIBE (và `GET /<mount>.sdp`) chờ tối đa chừng này cho SPS/PPS đầu
+/// tiên của shared stream
+pub const PARAMETER_SET_WAIT: Duration = Duration::from_secs(2);
+
+/// SDP của `mount` (đã resolve) cho DESCRIBE và status endpoint, để hai
+/// đường không bao giờ lệch nhau. SPS/PPS thật chỉ có cho default mount
+/// (shared pipeline); pipeline vừa start thì chờ một chút cho FFmpeg ra
+/// keyframe đầu tiên. None khi `--require-parameter-sets` mà vẫn chưa thấy
+/// SPS/PPS (503)
+pub async fn mount_sdp(config: &ServerConfig, state: &SharedState, mount: &str) -> Option<String> {
+    let shared = mount == config.default_mount;
+    let deadline = Instant::now() + PARAMETER_SET_WAIT;
+    while shared && config.parameter_sets.is_none() && Instant::now() < deadline {
+        if state.read().await.parameter_sets_ready() {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+
+    let state = state.read().await;
+    let stream_sets = state.parameter_sets().filter(|_| shared);
+    if shared && config.require_parameter_sets && config.parameter_sets.is_none() && stream_sets.is_none() {
+        return None;
+    }
+    Some(generate_sdp(config, &state.mount_range(mount, &config.default_mount), stream_sets))
+}
+
+/// Build the SDP served by DESCRIBE. `range` là giá trị `a=range` của mount
+/// (`ServerState::mount_range`).
+///
+/// `stream_sets` là SPS/PPS thật của stream (FFmpeg output); `--sprop`
+/// override được ưu tiên. Không có cả hai thì fmtp bỏ `sprop-parameter-sets`
+/// và `profile-level-id` (client lấy parameter sets in-band) thay vì quảng bá
+/// giá trị sai
+pub fn generate_sdp(config: &ServerConfig, range: &str, stream_sets: Option<(&[u8], &[u8])>) -> String {
+    let formats = if config.rtx {
+        format!("96 {}", RTX_PAYLOAD_TYPE)
+    } else {
+        "96".to_string()
+    };
+
+    let sets = config
+        .parameter_sets
+        .as_ref()
+        .map(|sets| (sets.sps.as_slice(), sets.pps.as_slice()))
+        .or(stream_sets);
+    let mut fmtp = "packetization-mode=1".to_string();
+    if let Some((sps, pps)) = sets {
+        if let Some(profile) = profile_level_id(sps) {
+            fmtp.push_str(&format!(";profile-level-id={}", profile));
+        }
+        fmtp.push_str(&format!(";sprop-parameter-sets={},{}", base64_encode(sps), base64_encode(pps)));
+    }
+
+    let mut sdp = format!(
+        "v=0\r\n\
+         o=- 0 0 IN IP4 127.0.0.1\r\n\
+         s=Simulation Media Server\r\n\
+         c=IN IP4 0.0.0.0\r\n\
+         t=0 0\r\n\
+         a=control:*\r\n\
+         a=range:{}\r\n\
+         m=video 0 RTP/AVP {}\r\n\
+         a=rtpmap:96 H264/90000\r\n\
+         a=fmtp:96 {}\r\n\
+         a=control:{}\r\n",
+        range, formats, fmtp, VIDEO_TRACK
+    );
+
+    if config.rtx {
+        sdp.push_str(&format!(
+            "a=rtcp-fb:96 nack\r\n\
+             a=rtpmap:{pt} rtx/90000\r\n\
+             a=fmtp:{pt} apt=96\r\n",
+            pt = RTX_PAYLOAD_TYPE
+        ));
+    }
+
+    if config.rtcp_mux {
+        sdp.push_str("a=rtcp-mux\r\n");
+    }
+
+    if config.audio.is_some() {
+        sdp.push_str(&match config.audio_codec {
+            AudioCodec::Aac => aac::sdp_media(AUDIO_TRACK),
+            #[cfg(feature = "opus")]
+            AudioCodec::Opus => opus::sdp_media(AUDIO_TRACK),
+        });
+    }
+
+    sdp
+}
+
+/// `profile-level-id` (hex profile_idc, constraint flags, level_idc): 3 byte
+/// sau NAL header của SPS
+pub fn profile_level_id(sps: &[u8]) -> Option<String> {
+    sps.get(1..4).map(|b| format!("{:02x}{:02x}{:02x}", b[0], b[1], b[2]))
+}
+
+/// One media format announced by a publisher (`m=` + `a=rtpmap`/`a=fmtp`)
+#[derive(Clone, Debug, PartialEq)]
+pub struct MediaFormat {
+    /// `video`, `audio`, ...
+    pub media: String,
+    pub payload_type: u8,
+    /// Encoding name from rtpmap (`H264`, `opus`, ...)
+    pub encoding: String,
+    pub clock_rate: u32,
+    /// Decoded `sprop-parameter-sets` (H.264 SPS/PPS), empty if absent
+    pub parameter_sets: Vec<Vec<u8>>,
+    /// `a=control` của media section (`track1`, hoặc absolute URL)
+    pub control: Option<String>,
+}
+
+impl MediaFormat {
+    /// Codecs the ingest side can depacketize
+    pub fn is_supported(&self) -> bool {
+        match self.encoding.to_ascii_uppercase().as_str() {
+            "H264" => self.media == "video",
+            #[cfg(feature = "opus")]
+            "OPUS" => self.media == "audio",
+            _ => false,
+        }
+    }
+}
+
+/// Parse the media formats of an offer SDP (ANNOUNCE body). Mỗi `m=` line
+/// dùng format đầu tiên; formats thiếu rtpmap (static PT) có encoding rỗng
+pub fn parse_media(sdp: &str) -> Vec<MediaFormat> {
+    let mut formats: Vec<MediaFormat> = Vec::new();
+
+    for line in sdp.lines().map(str::trim) {
+        if let Some(m) = line.strip_prefix("m=") {
+            let mut fields = m.split_whitespace();
+            let media = fields.next().unwrap_or_default().to_string();
+            let Some(payload_type) = fields.nth(2).and_then(|pt| pt.parse().ok()) else {
+                continue;
+            };
+            formats.push(MediaFormat {
+                media,
+                payload_type,
+                encoding: String::new(),
+                clock_rate: 0,
+                parameter_sets: Vec::new(),
+                control: None,
+            });
+            continue;
+        }
+
+        // a= attributes thuộc về m= section gần nhất
+        let Some(format) = formats.last_mut() else {
+            continue;
+        };
+        let attribute = |name| {
+            line.strip_prefix(name)
+                .and_then(|rest| rest.split_once(' '))
+                .filter(|(pt, _)| pt.parse() == Ok(format.payload_type))
+                .map(|(_, value)| value.trim())
+        };
+
+        if let Some(control) = line.strip_prefix("a=control:") {
+            format.control = Some(control.trim().to_string());
+        } else if let Some(rtpmap) = attribute("a=rtpmap:") {
+            let mut parts = rtpmap.split('/');
+            format.encoding = parts.next().unwrap_or_default().to_string();
+            format.clock_rate = parts.next().and_then(|c| c.parse().ok()).unwrap_or(0);
+        } else if let Some(fmtp) = attribute("a=fmtp:") {
+            let sprop = fmtp
+                .split(';')
+                .find_map(|param| param.trim().strip_prefix("sprop-parameter-sets="));
+            if let Some(sprop) = sprop {
+                format.parameter_sets = sprop.split(',').filter_map(base64_decode).collect();
+            }
+        }
+    }
+
+    formats
+}
+
+/// Available media range: `npt=0-<duration>` cho file
+pub fn npt_range(duration: Option<f64>) -> String {
+    match duration {
+        Some(duration) => format!("npt=0-{:.3}", duration),
+        None => "npt=0-".to_string(),
+    }
+}
+
+/// Standard base64 (RFC 4648) với padding, dùng cho sprop-parameter-sets
+pub fn base64_encode(data: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+    let mut out = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let b = [chunk[0], *chunk.get(1).unwrap_or(&0), *chunk.get(2).unwrap_or(&0)];
+        let n = ((b[0] as u32) << 16) | ((b[1] as u32) << 8) | b[2] as u32;
+
+        out.push(ALPHABET[(n >> 18) as usize & 0x3F] as char);
+        out.push(ALPHABET[(n >> 12) as usize & 0x3F] as char);
+        out.push(if chunk.len() > 1 { ALPHABET[(n >> 6) as usize & 0x3F] as char } else { '=' });
+        out.push(if chunk.len() > 2 { ALPHABET[n as usize & 0x3F] as char } else { '=' });
+    }
+    out
+}
+
+/// Decode standard base64 (padding optional). None on invalid input
+pub fn base64_decode(input: &str) -> Option<Vec<u8>> {
+    let value = |c: u8| match c {
+        b'A'..=b'Z' => Some(c - b'A'),
+        b'a'..=b'z' => Some(c - b'a' + 26),
+        b'0'..=b'9' => Some(c - b'0' + 52),
+        b'+' => Some(62),
+        b'/' => Some(63),
+        _ => None,
+    };
+
+    let input = input.trim_end_matches('=').as_bytes();
+    if input.len() % 4 == 1 {
+        return None;
+    }
+
+    let mut out = Vec::with_capacity(input.len() * 3 / 4);
+    for chunk in input.chunks(4) {
+        let mut n = 0u32;
+        for (i, &c) in chunk.iter().enumerate() {
+            n |= (value(c)? as u32) << (18 - 6 * i);
+        }
+        out.push((n >> 16) as u8);
+        if chunk.len() > 2 {
+            out.push((n >> 8) as u8);
+        }
+        if chunk.len() > 3 {
+            out.push(n as u8);
+        }
+    }
+    Some(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// SPS/PPS của một stream 1080p High profile (libx264)
+    const SPS: [u8; 27] = [
+        0x67, 0x64, 0x00, 0x28, 0xAC, 0xD9, 0x40, 0x78, 0x02, 0x27, 0xE5, 0xC0, 0x44, 0x00,
+        0x00, 0x03, 0x00, 0x04, 0x00, 0x00, 0x03, 0x00, 0xF0, 0x3C, 0x60, 0xC6, 0x58,
+    ];
+    const PPS: [u8; 6] = [0x68, 0xEB, 0xE3, 0xCB, 0x22, 0xC0];
+
+    #[test]
+    fn parses_an_ffmpeg_h264_offer() {
+        // ANNOUNCE body của `ffmpeg -f rtsp`: PT động, fmtp có khoảng trắng
+        let offer = "v=0\r\n\
+                     o=- 0 0 IN IP4 127.0.0.1\r\n\
+                     s=No Name\r\n\
+                     c=IN IP4 192.168.1.20\r\n\
+                     t=0 0\r\n\
+                     a=tool:libavformat 60.16.100\r\n\
+                     m=video 0 RTP/AVP 97\r\n\
+                     b=AS:2000\r\n\
+                     a=rtpmap:97 H264/90000\r\n\
+                     a=fmtp:97 packetization-mode=1; sprop-parameter-sets=Z2QAKKzZQHgCJ+XARAAAAwAEAAADAPA8YMZY,aOvjyyLA; profile-level-id=640028\r\n\
+                     a=control:streamid=0\r\n\
+                     m=audio 0 RTP/AVP 98\r\n\
+                     b=AS:128\r\n\
+                     a=rtpmap:98 opus/48000/2\r\n\
+                     a=fmtp:98 sprop-stereo=1\r\n\
+                     a=control:rtsp://192.168.1.20/live/streamid=1\r\n";
+
+        let formats = parse_media(offer);
+        assert_eq!(formats.len(), 2);
+        let video = &formats[0];
+        assert_eq!((video.media.as_str(), video.payload_type), ("video", 97));
+        assert_eq!((video.encoding.as_str(), video.clock_rate), ("H264", 90_000));
+        assert_eq!(video.parameter_sets, [SPS.to_vec(), PPS.to_vec()]);
+        assert_eq!(video.control.as_deref(), Some("streamid=0"));
+        assert!(video.is_supported());
+
+        let audio = &formats[1];
+        assert_eq!((audio.media.as_str(), audio.payload_type), ("audio", 98));
+        assert_eq!((audio.encoding.as_str(), audio.clock_rate), ("opus", 48_000));
+        assert!(audio.parameter_sets.is_empty());
+        assert_eq!(audio.control.as_deref(), Some("rtsp://192.168.1.20/live/streamid=1"));
+        assert_eq!(audio.is_supported(), cfg!(feature = "opus"));
+    }
+
+    #[test]
+    fn offer_attributes_only_apply_to_their_payload_type() {
+        let offer = "m=video 0 RTP/AVP 96 97\n\
+                     a=rtpmap:97 VP8/90000\n\
+                     a=rtpmap:96 H264/90000\n\
+                     a=fmtp:97 sprop-parameter-sets=Z2QAKKzZQHgCJ+XARAAAAwAEAAADAPA8YMZY\n\
+                     m=audio 0 RTP/AVP 0\n\
+                     m=video\n";
+
+        let formats = parse_media(offer);
+        assert_eq!(formats.len(), 2);
+        // Format đầu tiên của m= line; rtpmap/fmtp của PT khác bị bỏ qua
+        assert_eq!((formats[0].payload_type, formats[0].encoding.as_str()), (96, "H264"));
+        assert!(formats[0].parameter_sets.is_empty());
+        // Static PT không có rtpmap: encoding rỗng, không depacketize được
+        assert_eq!((formats[1].payload_type, formats[1].encoding.as_str()), (0, ""));
+        assert!(!formats[1].is_supported());
+    }
+
+    fn fmtp(sdp: &str) -> &str {
+        sdp.lines().find_map(|line| line.strip_prefix("a=fmtp:96 ")).unwrap()
+    }
+
+    #[test]
+    fn fmtp_is_built_from_the_stream_parameter_sets() {
+        let config = ServerConfig::default();
+        let sdp = generate_sdp(&config, "npt=0-", Some((&SPS, &PPS)));
+        assert_eq!(fmtp(&sdp), "packetization-mode=1;profile-level-id=640028;sprop-parameter-sets=Z2QAKKzZQHgCJ+XARAAAAwAEAAADAPA8YMZY,aOvjyyLA");
+        assert_eq!(parse_media(&sdp)[0].parameter_sets, [SPS.to_vec(), PPS.to_vec()]);
+
+        // Chưa biết parameter sets: không quảng bá giá trị đoán
+        let sdp = generate_sdp(&config, "npt=0-", None);
+        assert_eq!(fmtp(&sdp), "packetization-mode=1");
+        assert!(!sdp.contains("profile-level-id") && !sdp.contains("sprop-parameter-sets"), "{}", sdp);
+        // SPS quá ngắn: vẫn có sprop nhưng không có profile-level-id
+        let sdp = generate_sdp(&config, "npt=0-", Some((&[0x67, 0x42], &PPS)));
+        assert_eq!(fmtp(&sdp), "packetization-mode=1;sprop-parameter-sets=Z0I=,aOvjyyLA");
+    }
+
+    #[tokio::test]
+    async fn describe_waits_for_the_stream_parameter_sets() {
+        let config = ServerConfig { require_parameter_sets: true, ..ServerConfig::default() };
+        let state = crate::rtsp::state::create_shared_state(config.mount_table());
+
+        // SPS/PPS đến trong lúc chờ: SDP dùng giá trị thật
+        let writer = state.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(150)).await;
+            let mut state = writer.write().await;
+            state.sps = Some(SPS.to_vec());
+            state.pps = Some(PPS.to_vec());
+        });
+        let sdp = mount_sdp(&config, &state, "cam").await.unwrap();
+        assert!(fmtp(&sdp).contains("profile-level-id=640028;"), "{}", sdp);
+
+        // Không bao giờ có: None (DESCRIBE trả 503) sau PARAMETER_SET_WAIT
+        let state = crate::rtsp::state::create_shared_state(config.mount_table());
+        let started = Instant::now();
+        assert_eq!(mount_sdp(&config, &state, "cam").await, None);
+        assert!(started.elapsed() >= PARAMETER_SET_WAIT);
+    }
+
+    #[test]
+    fn base64_round_trips_every_padding_length() {
+        let data: Vec<u8> = (0..=255).collect();
+        for len in 0..=7 {
+            let encoded = base64_encode(&data[250 - len..250]);
+            assert_eq!(encoded.len() % 4, 0);
+            assert_eq!(base64_decode(&encoded), Some(data[250 - len..250].to_vec()), "{}", encoded);
+            // Padding là optional khi decode
+            assert_eq!(base64_decode(encoded.trim_end_matches('=')), Some(data[250 - len..250].to_vec()));
+        }
+        assert_eq!(base64_decode(&base64_encode(&data)), Some(data));
+        assert_eq!(base64_encode(b"\xfb\xff"), "+/8=");
+
+        for invalid in ["Z", "Z2QA!", "Z2Q-", "Z2 QA"] {
+            assert_eq!(base64_decode(invalid), None, "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn sprop_override_wins_over_the_stream_sets() {
+        let sets = crate::source::params::ParameterSets::parse("Z0IAH6tAUB7I,aM48gA==").unwrap();
+        let config = ServerConfig { parameter_sets: Some(sets.clone()), ..ServerConfig::default() };
+
+        for stream_sets in [Some((&SPS[..], &PPS[..])), None] {
+            let sdp = generate_sdp(&config, "npt=0-", stream_sets);
+            assert_eq!(fmtp(&sdp), "packetization-mode=1;profile-level-id=42001f;sprop-parameter-sets=Z0IAH6tAUB7I,aM48gA==");
+            assert_eq!(parse_media(&sdp)[0].parameter_sets, [sets.sps.clone(), sets.pps.clone()]);
+        }
+    }
+}
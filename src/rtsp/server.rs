@@ -1,33 +1,68 @@
-use tokio::net::TcpListener;
+use tokio::net::{lookup_host, TcpListener, TcpSocket};
 use super::session::RtspSession;
 use super::state::SharedState;
+use crate::config::ServerConfig;
+use std::sync::Arc;
 
 /// RTSP Server - xử lý control plane
 pub struct RtspServer {
     addr: String,
     state: SharedState,
+    config: Arc<ServerConfig>,
 }
 
 impl RtspServer {
-    pub fn new(addr: String, state: SharedState) -> Self {
-        Self { addr, state }
+    pub fn new(addr: String, state: SharedState, config: Arc<ServerConfig>) -> Self {
+        Self { addr, state, config }
     }
 
     pub async fn run(&self) -> std::io::Result<()> {
-        let listener = TcpListener::bind(&self.addr).await?;
+        let listener = self.bind().await?;
         println!("🎥 RTSP Server listening on {}", self.addr);
 
         loop {
             let (socket, peer) = listener.accept().await?;
+
+            if !self.config.access_list.is_allowed(peer.ip()) {
+                println!("🚫 Rejected connection from {} (access list)", peer);
+                drop(socket);
+                continue;
+            }
             println!("📡 Client connected: {}", peer);
 
+            if let Err(e) = socket.set_nodelay(self.config.tcp_nodelay) {
+                eprintln!("⚠️  Cannot set TCP_NODELAY for {}: {}", peer, e);
+            }
+            println!("Debug: {} TCP_NODELAY={}", peer, socket.nodelay().unwrap_or(false));
+
             let state = self.state.clone();
+            let config = self.config.clone();
             tokio::spawn(async move {
-                let mut session = RtspSession::new(socket, state);
+                let mut session = RtspSession::new(socket, state, config);
                 if let Err(e) = session.handle().await {
                     eprintln!("❌ Session error: {}", e);
                 }
             });
         }
     }
+
+    /// Listening socket. SO_SNDBUF được set trên listener để accepted
+    /// sockets kế thừa (Linux), không cần chỉnh từng connection
+    async fn bind(&self) -> std::io::Result<TcpListener> {
+        let addr = lookup_host(&self.addr)
+            .await?
+            .next()
+            .ok_or_else(|| std::io::Error::other(format!("cannot resolve {}", self.addr)))?;
+        let socket = if addr.is_ipv4() { TcpSocket::new_v4()? } else { TcpSocket::new_v6()? };
+        socket.set_reuseaddr(true)?;
+
+        if let Some(size) = self.config.tcp_send_buffer {
+            socket.set_send_buffer_size(size)?;
+            // Kernel có thể làm tròn/nhân đôi giá trị yêu cầu
+            println!("Debug: RTSP SO_SNDBUF requested {} bytes, applied {} bytes", size, socket.send_buffer_size()?);
+        }
+
+        socket.bind(addr)?;
+        socket.listen(1024)
+    }
 }
@@ -1,38 +1,125 @@
 use tokio::net::TcpStream;
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
-use super::state::{SharedState, ClientInfo, TransportMode};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use super::access::access_line;
+use super::framing::{Frame, RtspFramer, MAX_MESSAGE_LEN};
+use super::range::{ClockRange, NptRange};
+use super::response::{RtspError, RtspResponse};
+use super::sdp::{mount_sdp, parse_media, AUDIO_TRACK, VIDEO_TRACK};
+use super::uri;
+use super::state::{SharedState, ClientInfo, EndReason, ServerState, TrackTransport, TransportMode};
+use crate::config::ServerConfig;
+use crate::rtcp::bye::Goodbye;
+use crate::rtcp::liveness::{RtcpLiveness, SR_INTERVAL};
+use crate::rtcp::sr::SenderReport;
+use crate::rtp::h264::{H264Packetizer, PacketizerState, CLOCK_RATE, MIN_BLOCKSIZE};
+use crate::rtp::impair::Impairer;
+use crate::rtp::ports::PortLease;
+use crate::rtp::stamp::RtpIdentity;
+use crate::source::feed::FeedSubscription;
+use crate::source::file::FileSource;
+use crate::source::Source;
 use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
-use tokio::sync::Mutex;
+use std::time::{Duration, Instant};
+use tokio::sync::{Mutex, Notify};
+
+/// Outcome of checking for requests between access units of a TCP stream
+enum StreamControl {
+    Continue,
+    /// PLAY sau PAUSE: caller reset pacing
+    Resumed,
+    Stop,
+}
+
+/// Transport client yêu cầu trong SETUP, sau `RtspSession::parse_transport`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum TransportRequest {
+    /// RTP/AVP/TCP, `interleaved=x-y`
+    Tcp { rtp_channel: u8, rtcp_channel: u8 },
+    /// UDP unicast tới `client_port=x-y` (rtcp-mux: khi cả hai bên bật)
+    Udp { rtp_port: u16, rtcp_port: u16, rtcp_mux: bool },
+    /// Group của server (`MulticastConfig`), client_port tùy chọn
+    Multicast,
+}
+
+/// RTP timestamp (90 kHz, wrapping) của media offset `seek` giây
+fn seek_timestamp(seek: f64) -> u32 {
+    (seek * CLOCK_RATE as f64) as u64 as u32
+}
+
+/// Byte stream an RTSP session runs over: TcpStream in production, or an
+/// in-memory pipe (`tokio::io::duplex`) to drive a session without sockets
+pub trait RtspStream: AsyncRead + AsyncWrite + Unpin + Send {}
+
+impl<T: AsyncRead + AsyncWrite + Unpin + Send> RtspStream for T {}
 
 /// RTSP Session - xử lý các request từ 1 client
-pub struct RtspSession {
-    socket: Arc<Mutex<TcpStream>>,
+pub struct RtspSession<S: RtspStream = TcpStream> {
+    socket: Arc<Mutex<S>>,
     cseq: u32,
     session_id: String,
     client_ip: String,
     rtp_port: Option<u16>,
     rtcp_port: Option<u16>,
     transport_mode: Option<TransportMode>,
+    /// Bytes đã đọc nhưng chưa thành request hoàn chỉnh
+    framer: RtspFramer,
+    blocksize: Option<usize>,
+    /// Start offset (giây) từ PLAY `Range: clock=`, cho FFmpeg riêng của TCP session
+    seek: Option<f64>,
+    /// Mount của request gần nhất (đã resolve trong registry)
+    mount: String,
+    /// Next seq/timestamp của TCP stream đang pause, cho RTP-Info khi resume
+    tcp_resume: Option<(u16, u32)>,
+    /// Debug impairment stage cho interleaved RTP (None khi tắt)
+    impairer: Mutex<Option<Impairer>>,
+    /// Interleaved RTP packets/bytes written, cho access log
+    tcp_packets: AtomicU64,
+    tcp_bytes: AtomicU64,
+    /// SR counters của TCP stream đang phát (SSRC của packetizer), đếm
+    /// trước impairment stage; None trước lần PLAY đầu
+    tcp_report: Option<SenderReport>,
+    /// Server-side teardown (max duration): close the connection
+    abort: Arc<Notify>,
     state: SharedState,
+    config: Arc<ServerConfig>,
 }
 
-impl RtspSession {
-    pub fn new(socket: TcpStream, state: SharedState) -> Self {
+impl RtspSession<TcpStream> {
+    pub fn new(socket: TcpStream, state: SharedState, config: Arc<ServerConfig>) -> Self {
         let client_ip = socket
             .peer_addr()
             .map(|a| a.ip().to_string())
             .unwrap_or_else(|_| "127.0.0.1".to_string());
 
+        Self::with_stream(socket, client_ip, state, config)
+    }
+}
+
+impl<S: RtspStream> RtspSession<S> {
+    /// Session over any stream; `client_ip` is where UDP RTP will be sent
+    pub fn with_stream(stream: S, client_ip: String, state: SharedState, config: Arc<ServerConfig>) -> Self {
         Self {
-            socket: Arc::new(Mutex::new(socket)),
+            socket: Arc::new(Mutex::new(stream)),
             cseq: 0,
             session_id: Self::generate_session_id(),
             client_ip,
             rtp_port: None,
             rtcp_port: None,
             transport_mode: None,
+            framer: RtspFramer::new(),
+            blocksize: None,
+            seek: None,
+            mount: config.default_mount.clone(),
+            tcp_resume: None,
+            impairer: Mutex::new(config.impairment.clone().map(Impairer::new)),
+            tcp_packets: AtomicU64::new(0),
+            tcp_bytes: AtomicU64::new(0),
+            tcp_report: None,
+            abort: Arc::new(Notify::new()),
             state,
+            config,
         }
     }
 
@@ -45,255 +132,486 @@ impl RtspSession {
         format!("{:x}", timestamp)
     }
 
-    /// Get socket for TCP interleaved streaming
-    pub fn get_socket(&self) -> Arc<Mutex<TcpStream>> {
-        self.socket.clone()
+    /// Handle RTSP requests until the connection closes, then clean up the
+    /// session and write its access log line (mọi exit path đi qua đây)
+    pub async fn handle(&mut self) -> std::io::Result<()> {
+        let result = self.serve().await;
+        let reason = if result.is_ok() { EndReason::Normal } else { EndReason::Error };
+        self.finish(reason).await;
+        self.close().await;
+        result
     }
 
-    /// Handle RTSP requests
-    pub async fn handle(&mut self) -> std::io::Result<()> {
+    /// Orderly close: FIN sau byte cuối thay vì để socket drop ngầm
+    /// (peer có thể đã đóng, nên lỗi ở đây bỏ qua)
+    async fn close(&self) {
+        let mut sock = self.socket.lock().await;
+        let _ = sock.flush().await;
+        let _ = sock.shutdown().await;
+    }
+
+    /// Remove the session (if still active) and log every session this
+    /// connection ran, including ones already torn down or timed out
+    async fn finish(&self, reason: EndReason) {
+        let mut state = self.state.write().await;
+        let mut ended = state.finish_session(&self.session_id, reason);
+        for (client, _) in &mut ended {
+            if matches!(client.transport, TransportMode::TcpInterleaved { .. }) {
+                client.packets_sent = self.tcp_packets.load(Ordering::Relaxed);
+                client.bytes_sent = self.tcp_bytes.load(Ordering::Relaxed);
+            }
+        }
+        state.record_ended(&ended);
+        drop(state);
+
+        let now = Instant::now();
+        for (client, reason) in ended {
+            println!("{}", access_line(&client, reason, self.config.access_log, now));
+        }
+    }
+
+    async fn serve(&mut self) -> std::io::Result<()> {
         let mut buffer = vec![0u8; 4096];
 
         loop {
+            // Xử lý lần lượt mọi request hoàn chỉnh đã buffer (pipelining)
+            while let Some(request) = self.next_request() {
+                self.respond(&request).await?;
+                println!("📤 Response sent\n");
+
+                // If PLAY was called and we're using TCP interleaved, start streaming on this connection
+                if let Some(TransportMode::TcpInterleaved { rtp_channel, rtcp_channel }) = self.transport_mode {
+                    let playing = self.state.read().await.clients.get(&self.session_id).is_some_and(|c| c.is_playing);
+                    if playing {
+                        // Start TCP interleaved streaming
+                        self.start_tcp_streaming(rtp_channel, rtcp_channel).await?;
+                    }
+                }
+            }
+            if self.reject_oversized().await? {
+                break;
+            }
+
             let n = {
                 let mut sock = self.socket.lock().await;
-                sock.read(&mut buffer).await?
+                tokio::select! {
+                    n = sock.read(&mut buffer) => n?,
+                    _ = self.abort.notified() => {
+                        println!("⛔ Session {} terminated by server, closing connection", self.session_id);
+                        break;
+                    }
+                }
             };
 
             if n == 0 {
                 println!("🔌 Client disconnected");
-                self.state.write().await.remove_client(&self.session_id);
                 break;
             }
+            self.framer.push(&buffer[..n]);
+        }
 
-            let request = String::from_utf8_lossy(&buffer[..n]);
-            println!("📥 Request:\n{}", request);
-
-            let response = self.process_request(&request).await;
+        Ok(())
+    }
 
-            {
-                let mut sock = self.socket.lock().await;
-                sock.write_all(response.as_bytes()).await?;
-                sock.flush().await?;
+    /// RTSP request hoàn chỉnh kế tiếp trong buffer; interleaved frames từ
+    /// client (RTCP RR) xen giữa bị bỏ qua
+    fn next_request(&mut self) -> Option<String> {
+        loop {
+            match self.framer.next_frame()? {
+                Frame::Request(request) => return Some(request),
+                Frame::Interleaved { .. } => {}
             }
+        }
+    }
 
-            println!("📤 Response sent\n");
-
-            // If PLAY was called and we're using TCP interleaved, start streaming on this connection
-            if let Some(TransportMode::TcpInterleaved { rtp_channel, rtcp_channel }) = &self.transport_mode {
-                let state = self.state.read().await;
-                if let Some(client) = state.clients.get(&self.session_id) {
-                    if client.is_playing {
-                        drop(state);
-                        // Start TCP interleaved streaming
-                        self.start_tcp_streaming(*rtp_channel, *rtcp_channel).await?;
-                    }
-                }
-            }
+    /// Reply 400 và báo đóng connection khi request dở vượt `MAX_MESSAGE_LEN`
+    async fn reject_oversized(&mut self) -> std::io::Result<bool> {
+        if !self.framer.overflowed() {
+            return Ok(false);
         }
+        println!("⚠️  Request exceeds {} bytes without completing, closing connection", MAX_MESSAGE_LEN);
+        let response = RtspResponse::from(RtspError::BadRequest).render(self.cseq);
+        let mut sock = self.socket.lock().await;
+        sock.write_all(response.as_bytes()).await?;
+        sock.flush().await?;
+        Ok(true)
+    }
 
-        Ok(())
+    async fn respond(&mut self, request: &str) -> std::io::Result<()> {
+        println!("📥 Request:\n{}", request);
+        let response = self.process_request(request).await;
+        let mut sock = self.socket.lock().await;
+        sock.write_all(response.as_bytes()).await?;
+        sock.flush().await
     }
 
-    async fn start_tcp_streaming(&self, rtp_channel: u8, _rtcp_channel: u8) -> std::io::Result<()> {
-        use crate::source::file::{FileSource, NaluParser};
+    /// Stream FFmpeg output interleaved trên connection này. TCP sessions
+    /// cùng mount nhận access units từ một FFmpeg dùng chung (session seek
+    /// có FFmpeg riêng); requests đến giữa chừng (PAUSE, PLAY, TEARDOWN) được
+    /// xử lý giữa các AUs. PAUSE giữ subscription và packetizer nên PLAY sau
+    /// đó tiếp tục sequence và timestamp thay vì bắt đầu lại
+    async fn start_tcp_streaming(&mut self, rtp_channel: u8, rtcp_channel: u8) -> std::io::Result<()> {
+        use crate::source::feed::FeedRegistry;
         use crate::rtp::h264::H264Packetizer;
-        use std::io::Read;
-        use std::time::Duration;
-        use tokio::time::Instant;
+        use tokio::sync::broadcast::error::RecvError;
 
         println!("🎬 Starting TCP interleaved streaming on channel {}", rtp_channel);
 
-        let video_path = "./videos/example.mp4";
-        if !std::path::Path::new(video_path).exists() {
-            eprintln!("⚠️  Video file not found for TCP streaming");
-            return Ok(());
-        }
-
-        let source = FileSource::new(video_path.to_string());
-        let mut child = source.start_ffmpeg()?;
-
-        let stdout = child.stdout.take().ok_or_else(|| {
-            std::io::Error::new(std::io::ErrorKind::Other, "Failed to capture FFmpeg stdout")
-        })?;
-
-        let mut parser = NaluParser::new();
-        let mut packetizer = H264Packetizer::new(0x12345678);
-        let mut reader = std::io::BufReader::new(stdout);
-        let mut buffer = [0u8; 8192];
+        let (video_path, feeds, identity) = {
+            let state = self.state.read().await;
+            (
+                state.mounts.get(&self.mount).cloned().unwrap_or_default(),
+                state.feeds.clone(),
+                state.clients.get(&self.session_id).map_or_else(RtpIdentity::random, |c| c.rtp),
+            )
+        };
 
-        // Timing control
-        let start_time = Instant::now();
-        let mut frame_count: u64 = 0;
-        let frame_duration = Duration::from_micros(33333); // ~30fps
+        // Identity chọn lúc SETUP: RTP-Info của PLAY đã báo seq/rtptime này
+        let mut packetizer = H264Packetizer::with_identity(identity);
+        packetizer.set_blocksize(self.blocksize);
+        packetizer.set_fragment_limit(self.config.fragment_limit);
+        packetizer.set_frame_ticks(self.config.encoder.frame_ticks());
+        // rtptime của RTP-Info ứng với seek point
+        if let Some(seek) = self.seek {
+            packetizer.set_timestamp(identity.ts_offset.wrapping_add(seek_timestamp(seek)));
+        }
+        let frame_duration = packetizer.frame_duration();
+        self.tcp_report = Some(SenderReport::new(packetizer.ssrc()));
+        let mut last_sr = self.config.clock.now();
+
+        println!("🗂️  Mount /{} → {}", self.mount, video_path);
+        let subscription = if self.seek.is_some() {
+            // Vị trí riêng: không chia sẻ FFmpeg với sessions khác
+            match self.tcp_source(&video_path) {
+                Some(source) => Some(FeedRegistry::private(&self.config, frame_duration, source.as_ref())?),
+                None => None,
+            }
+        } else {
+            feeds.subscribe(&self.mount, &self.config, frame_duration, || self.tcp_source(&video_path))?
+        };
+        let Some(mut subscription) = subscription else {
+            return Ok(());
+        };
 
-        // Store SPS/PPS for re-sending before IDR
-        let mut cached_sps: Option<Vec<u8>> = None;
-        let mut cached_pps: Option<Vec<u8>> = None;
+        // Vào giữa stream: SPS/PPS của feed gửi ngay, rồi bỏ AUs tới IDR kế tiếp
+        let (mut cached_sps, mut cached_pps) = subscription.parameter_sets();
         let mut sps_pps_sent = false; // Track if we've sent initial SPS/PPS
+        if let (Some(sps), Some(pps)) = (&cached_sps, &cached_pps) {
+            println!("🚀 Sending cached SPS/PPS to late TCP subscriber");
+            for packet in packetizer.packetize_stap_a(&[sps, pps]) {
+                self.send_interleaved_rtp(&packet.to_bytes(), rtp_channel).await?;
+            }
+            sps_pps_sent = true;
+        }
+        let mut waiting_for_idr = true;
+        let mut forwarding = false;
+        let mut frame_count: u64 = 0;
 
         loop {
             // Check if client is still playing
-            {
-                let state = self.state.read().await;
-                if let Some(client) = state.clients.get(&self.session_id) {
-                    if !client.is_playing {
-                        println!("⏹️  Client stopped playing, ending TCP stream");
-                        break;
-                    }
-                } else {
-                    println!("⏹️  Client disconnected, ending TCP stream");
-                    break;
+            match self.stream_control(packetizer.snapshot(), &mut subscription).await? {
+                StreamControl::Stop => break,
+                StreamControl::Resumed if subscription.skip_to_live() => {
+                    println!("⏭️  Shared FFmpeg kept running while paused, skipping to the next IDR");
+                    waiting_for_idr = true;
                 }
+                StreamControl::Resumed | StreamControl::Continue => {}
             }
 
-            match reader.read(&mut buffer) {
-                Ok(0) => {
+            let au = match subscription.recv().await {
+                Ok(au) => au,
+                Err(RecvError::Lagged(skipped)) => {
+                    println!("🐢 TCP session {} fell {} access units behind, waiting for next IDR", self.session_id, skipped);
+                    // Timeline vẫn tiến qua các AUs bị bỏ
+                    for _ in 0..skipped {
+                        packetizer.end_access_unit();
+                    }
+                    waiting_for_idr = true;
+                    continue;
+                }
+                Err(RecvError::Closed) => {
                     println!("📹 FFmpeg stream ended");
                     break;
                 }
-                Ok(n) => {
-                    let nalus = parser.parse(&buffer[..n]);
+            };
 
-                    for nalu in nalus.iter() {
-                        if nalu.is_empty() {
-                            continue;
-                        }
+            if waiting_for_idr {
+                if !au.iter().any(|nalu| nalu.first().is_some_and(|b| b & 0x1F == 5)) {
+                    if forwarding {
+                        packetizer.end_access_unit();
+                    }
+                    continue;
+                }
+                waiting_for_idr = false;
+                forwarding = true;
+            }
 
-                        let nalu_type = nalu[0] & 0x1F;
-
-                        // Cache SPS/PPS
-                        match nalu_type {
-                            7 => { // SPS
-                                cached_sps = Some(nalu.clone());
-                                println!("📋 Cached SPS ({} bytes)", nalu.len());
-                                
-                                // If we have both SPS and PPS, and haven't sent them yet, send immediately
-                                if !sps_pps_sent && cached_pps.is_some() {
-                                    println!("🚀 Sending initial SPS/PPS to client");
-                                    // Send SPS
-                                    let packets = packetizer.packetize(nalu, false);
-                                    for packet in packets {
-                                        self.send_interleaved_rtp(&packet.to_bytes(), rtp_channel).await?;
-                                    }
-                                    // Send PPS
-                                    if let Some(ref pps) = cached_pps {
-                                        let packets = packetizer.packetize(pps, false);
-                                        for packet in packets {
-                                            self.send_interleaved_rtp(&packet.to_bytes(), rtp_channel).await?;
-                                        }
-                                    }
-                                    sps_pps_sent = true;
-                                }
-                            }
-                            8 => { // PPS
-                                cached_pps = Some(nalu.clone());
-                                println!("📋 Cached PPS ({} bytes)", nalu.len());
-                                
-                                // If we have both SPS and PPS, and haven't sent them yet, send immediately
-                                if !sps_pps_sent && cached_sps.is_some() {
-                                    println!("🚀 Sending initial SPS/PPS to client");
-                                    // Send SPS
-                                    if let Some(ref sps) = cached_sps {
-                                        let packets = packetizer.packetize(sps, false);
-                                        for packet in packets {
-                                            self.send_interleaved_rtp(&packet.to_bytes(), rtp_channel).await?;
-                                        }
-                                    }
-                                    // Send PPS
-                                    let packets = packetizer.packetize(nalu, false);
-                                    for packet in packets {
-                                        self.send_interleaved_rtp(&packet.to_bytes(), rtp_channel).await?;
-                                    }
-                                    sps_pps_sent = true;
-                                }
+            for (i, nalu) in au.iter().enumerate() {
+                if nalu.is_empty() {
+                    continue;
+                }
+
+                let nalu_type = nalu[0] & 0x1F;
+
+                // Cache SPS/PPS
+                match nalu_type {
+                    7 => { // SPS
+                        cached_sps = Some(nalu.clone());
+                        println!("📋 Cached SPS ({} bytes)", nalu.len());
+
+                        // If we have both SPS and PPS, and haven't sent them yet, send immediately
+                        if let Some(pps) = cached_pps.as_ref().filter(|_| !sps_pps_sent) {
+                            println!("🚀 Sending initial SPS/PPS to client");
+                            // SPS + PPS trong một STAP-A packet
+                            for packet in packetizer.packetize_stap_a(&[nalu, pps]) {
+                                self.send_interleaved_rtp(&packet.to_bytes(), rtp_channel).await?;
                             }
-                            5 => { // IDR - always send SPS/PPS first
-                                // Send cached SPS before IDR
-                                if let Some(ref sps) = cached_sps {
-                                    let packets = packetizer.packetize(sps, false);
-                                    for packet in packets {
-                                        self.send_interleaved_rtp(&packet.to_bytes(), rtp_channel).await?;
-                                    }
-                                }
-                                // Send cached PPS before IDR
-                                if let Some(ref pps) = cached_pps {
-                                    let packets = packetizer.packetize(pps, false);
-                                    for packet in packets {
-                                        self.send_interleaved_rtp(&packet.to_bytes(), rtp_channel).await?;
-                                    }
-                                }
+                            sps_pps_sent = true;
+                        }
+                    }
+                    8 => { // PPS
+                        cached_pps = Some(nalu.clone());
+                        println!("📋 Cached PPS ({} bytes)", nalu.len());
+
+                        // If we have both SPS and PPS, and haven't sent them yet, send immediately
+                        if let Some(sps) = cached_sps.as_ref().filter(|_| !sps_pps_sent) {
+                            println!("🚀 Sending initial SPS/PPS to client");
+                            for packet in packetizer.packetize_stap_a(&[sps, nalu]) {
+                                self.send_interleaved_rtp(&packet.to_bytes(), rtp_channel).await?;
                             }
-                            _ => {}
+                            sps_pps_sent = true;
                         }
+                    }
+                    5 => { // IDR - always send SPS/PPS first
+                        // Source không gửi SPS/PPS: feed có thể đã synthesize từ --sprop
+                        if cached_sps.is_none() || cached_pps.is_none() {
+                            let (sps, pps) = subscription.parameter_sets();
+                            cached_sps = cached_sps.or(sps);
+                            cached_pps = cached_pps.or(pps);
+                        }
+                        // Cached SPS/PPS (cái nào có) trước IDR
+                        let sets: Vec<&[u8]> = [&cached_sps, &cached_pps].into_iter().flatten().map(Vec::as_slice).collect();
+                        for packet in packetizer.packetize_stap_a(&sets) {
+                            self.send_interleaved_rtp(&packet.to_bytes(), rtp_channel).await?;
+                        }
+                    }
+                    _ => {}
+                }
 
-                        // Determine if this is last NALU of current Access Unit
-                        // For simplicity, treat each NALU with type 1-5 as end of AU
-                        let is_au_end = nalu_type >= 1 && nalu_type <= 5;
+                let packets = packetizer.packetize(nalu, i == au.len() - 1);
 
-                        let packets = packetizer.packetize(nalu, is_au_end);
+                // NALU quá cỡ: cùng schedule với UDP queues, mỗi packet chờ
+                // tới offset của nó tính từ đầu NALU (không cộng dồn sleeps)
+                let schedule = packetizer.pacing_schedule(packets.len()).unwrap_or_default();
+                let started = tokio::time::Instant::now();
+                for (n, packet) in packets.iter().enumerate() {
+                    if let Some(offset) = schedule.get(n).filter(|offset| !offset.is_zero()) {
+                        tokio::time::sleep_until(started + *offset).await;
+                    }
+                    self.send_interleaved_rtp(&packet.to_bytes(), rtp_channel).await?;
+                }
+            }
 
-                        for packet in packets {
-                            self.send_interleaved_rtp(&packet.to_bytes(), rtp_channel).await?;
-                        }
+            // Increment timestamp after each Access Unit (frame); feed đã
+            // pace AUs theo frame rate
+            frame_count += 1;
+            packetizer.end_access_unit();
 
-                        // Increment timestamp after each Access Unit (frame)
-                        if is_au_end {
-                            frame_count += 1;
-                            packetizer.increment_timestamp(3000); // 90000/30 = 3000
+            if frame_count.is_multiple_of(30) {
+                println!("🎬 TCP: Sent {} frames", frame_count);
+            }
 
-                            // Timing control - wait until next frame time
-                            let expected_time = start_time + frame_duration * frame_count as u32;
-                            let now = Instant::now();
-                            if expected_time > now {
-                                tokio::time::sleep(expected_time - now).await;
-                            }
+            // SR trên RTCP channel, giữa hai AUs nên không chặn RTP; cùng
+            // nhịp đó publish totals của session cho stats
+            if self.config.clock.now() - last_sr >= SR_INTERVAL {
+                last_sr = self.config.clock.now();
+                self.send_sender_report(packetizer.timestamp(), rtcp_channel).await?;
+                if let Some(client) = self.state.write().await.clients.get_mut(&self.session_id) {
+                    client.packets_sent = self.tcp_packets.load(Ordering::Relaxed);
+                    client.bytes_sent = self.tcp_bytes.load(Ordering::Relaxed);
+                }
+            }
+        }
 
-                            if frame_count % 30 == 0 {
-                                println!("🎬 TCP: Sent {} frames", frame_count);
-                            }
-                        }
-                    }
+        self.end_tcp_stream(packetizer.ssrc(), rtp_channel, rtcp_channel).await
+    }
+
+    /// Kết thúc interleaved stream của `ssrc`: packets impairment stage còn
+    /// giữ lại thuộc về frame cuối nên được gửi nốt (client nhận trọn access
+    /// unit cuối cùng), rồi BYE nếu session đã kết thúc server-side
+    async fn end_tcp_stream(&self, ssrc: u32, rtp_channel: u8, rtcp_channel: u8) -> std::io::Result<()> {
+        let remaining = self.impairer.lock().await.as_mut().map(Impairer::drain).unwrap_or_default();
+        self.write_interleaved(remaining, rtp_channel).await?;
+
+        // TEARDOWN, kick, max duration: BYE trên RTCP channel trước khi connection đóng
+        let ended = self.state.read().await.ended_reason(&self.session_id);
+        if let Some(reason) = ended.filter(|reason| reason.sends_bye()) {
+            let bye = Goodbye::new(ssrc, Some(reason.as_str())).to_bytes();
+            self.write_rtcp(&bye, rtcp_channel).await?;
+            println!("👋 RTCP BYE sent on channel {} ({})", rtcp_channel, reason.as_str());
+        }
+        self.socket.lock().await.flush().await
+    }
+
+    /// Source cho TCP FFmpeg của mount: file (với seek của session) hoặc
+    /// upstream feed, placeholder khi file thiếu; None nếu không có gì để phát
+    fn tcp_source(&self, video_path: &str) -> Option<Box<dyn Source>> {
+        let source = self.config.source_for(video_path, self.seek);
+        if source.is_available() {
+            Some(source)
+        } else if let Some(placeholder) = &self.config.placeholder {
+            println!("📺 Video file not found for TCP streaming, serving placeholder ({})", placeholder.describe());
+            Some(Box::new(FileSource::placeholder(placeholder.clone(), self.config.encoder.clone())))
+        } else {
+            eprintln!("⚠️  Video file not found for TCP streaming");
+            None
+        }
+    }
+
+    /// Handle requests arriving mid-stream. Khi session đang pause thì chờ
+    /// (FFmpeg và packetizer giữ nguyên) tới PLAY, hoặc tới khi session hay
+    /// connection kết thúc; `position` là next seq/timestamp cho RTP-Info
+    async fn stream_control(
+        &mut self,
+        position: PacketizerState,
+        subscription: &mut FeedSubscription,
+    ) -> std::io::Result<StreamControl> {
+        let mut paused = false;
+        loop {
+            let wait = if paused { Duration::from_millis(250) } else { Duration::ZERO };
+            if !self.poll_request(wait).await? {
+                return Ok(StreamControl::Stop);
+            }
+
+            let playing = self.state.read().await.clients.get(&self.session_id).map(|c| c.is_playing);
+            match playing {
+                None => {
+                    println!("⏹️  Client disconnected, ending TCP stream");
+                    return Ok(StreamControl::Stop);
                 }
-                Err(e) => {
-                    eprintln!("❌ Read error: {}", e);
-                    break;
+                Some(true) if paused => {
+                    self.tcp_resume = None;
+                    subscription.set_playing(true);
+                    println!("▶️  TCP stream resumed");
+                    return Ok(StreamControl::Resumed);
+                }
+                Some(true) => return Ok(StreamControl::Continue),
+                Some(false) if !paused => {
+                    paused = true;
+                    subscription.set_playing(false);
+                    self.tcp_resume = Some((position.sequence, position.timestamp));
+                    println!("⏸️  TCP stream paused at seq {} / ts {}", position.sequence, position.timestamp);
                 }
+                Some(false) => {}
             }
         }
+    }
 
-        let _ = child.kill();
+    /// Xử lý một RTSP request đến trong lúc TCP streaming: request đã buffer
+    /// trước, nếu không thì đọc chờ tối đa `wait`. Ok(false) khi client đã
+    /// đóng connection
+    async fn poll_request(&mut self, wait: Duration) -> std::io::Result<bool> {
+        if let Some(request) = self.next_request() {
+            self.respond(&request).await?;
+            return Ok(true);
+        }
+
+        let mut buffer = vec![0u8; 4096];
+        let read = {
+            let mut sock = self.socket.lock().await;
+            tokio::time::timeout(wait, sock.read(&mut buffer)).await
+        };
+        let n = match read {
+            Ok(n) => n?,
+            Err(_) => return Ok(true),
+        };
+        if n == 0 {
+            return Ok(false);
+        }
+
+        self.framer.push(&buffer[..n]);
+        if let Some(request) = self.next_request() {
+            self.respond(&request).await?;
+        }
+        Ok(!self.reject_oversized().await?)
+    }
+
+    /// SR với counters của `tcp_report` và `rtp_timestamp` (timestamp hiện
+    /// tại của packetizer), NTP timestamp lấy cùng lúc
+    async fn send_sender_report(&self, rtp_timestamp: u32, channel: u8) -> std::io::Result<()> {
+        let Some(report) = &self.tcp_report else {
+            return Ok(());
+        };
+        report.set_rtp_timestamp(rtp_timestamp);
+        self.write_rtcp(&report.to_bytes(self.config.clock.wall()), channel).await?;
+        let (packets, octets) = report.counts();
+        println!("📊 RTCP SR sent on channel {} - SSRC: {:08x}, packets: {}, bytes: {}",
+                 channel, report.ssrc, packets, octets);
         Ok(())
     }
 
+    /// Ghi một RTCP packet lên interleaved channel: không qua impairment
+    /// stage và không tính vào RTP counters của access log
+    async fn write_rtcp(&self, packet: &[u8], channel: u8) -> std::io::Result<()> {
+        let mut frame = vec![b'$', channel];
+        frame.extend_from_slice(&(packet.len() as u16).to_be_bytes());
+        frame.extend_from_slice(packet);
+        self.socket.lock().await.write_all(&frame).await
+    }
+
     async fn send_interleaved_rtp(&self, rtp_data: &[u8], channel: u8) -> std::io::Result<()> {
-        // TCP interleaved format: $<channel><length_high><length_low><data>
-        let mut interleaved = Vec::with_capacity(4 + rtp_data.len());
-        interleaved.push(b'$');
-        interleaved.push(channel);
-        interleaved.push((rtp_data.len() >> 8) as u8);
-        interleaved.push((rtp_data.len() & 0xFF) as u8);
-        interleaved.extend_from_slice(rtp_data);
+        if let Some(report) = &self.tcp_report {
+            report.add_packet(rtp_data.len().saturating_sub(12));
+        }
+        let outgoing = match self.impairer.lock().await.as_mut() {
+            Some(impairer) => impairer.process(rtp_data.to_vec(), Instant::now()),
+            None => vec![rtp_data.to_vec()],
+        };
+        self.write_interleaved(outgoing, channel).await
+    }
 
+    async fn write_interleaved(&self, outgoing: Vec<Vec<u8>>, channel: u8) -> std::io::Result<()> {
         let mut sock = self.socket.lock().await;
-        sock.write_all(&interleaved).await
+        for rtp_data in outgoing {
+            // TCP interleaved format: $<channel><length_high><length_low><data>
+            let mut interleaved = Vec::with_capacity(4 + rtp_data.len());
+            interleaved.push(b'$');
+            interleaved.push(channel);
+            interleaved.push((rtp_data.len() >> 8) as u8);
+            interleaved.push((rtp_data.len() & 0xFF) as u8);
+            interleaved.extend_from_slice(&rtp_data);
+
+            sock.write_all(&interleaved).await?;
+            self.tcp_packets.fetch_add(1, Ordering::Relaxed);
+            self.tcp_bytes.fetch_add(rtp_data.len() as u64, Ordering::Relaxed);
+        }
+        Ok(())
     }
 
     async fn process_request(&mut self, request: &str) -> String {
+        let response = self.dispatch(request).await.unwrap_or_else(|error| {
+            let (code, reason) = error.status();
+            println!("⚠️  Replying {} {}", code, reason);
+            error.into()
+        });
+        response.render(self.cseq)
+    }
+
+    async fn dispatch(&mut self, request: &str) -> Result<RtspResponse, RtspError> {
         let lines: Vec<&str> = request.lines().collect();
         if lines.is_empty() {
-            return self.error_response(400, "Bad Request");
+            return Err(RtspError::BadRequest);
         }
 
         let request_line = lines[0];
         let parts: Vec<&str> = request_line.split_whitespace().collect();
 
         if parts.len() < 2 {
-            return self.error_response(400, "Bad Request");
+            return Err(RtspError::BadRequest);
         }
 
         let method = parts[0];
-        let _url = parts[1];
+        let url = parts[1];
 
         // Parse CSeq
         for line in &lines {
@@ -304,114 +622,177 @@ impl RtspSession {
             }
         }
 
+        // Bất kỳ request nào trên connection cũng là keepalive của session
+        self.state.write().await.touch(&self.session_id);
+
+        // Mọi method trừ OPTIONS phải nhắm vào một mount tồn tại
+        if method != "OPTIONS" {
+            let state = self.state.read().await;
+            match uri::resolve_mount(url, &self.config.default_mount, |mount| state.mounts.contains_key(mount)) {
+                Some(mount) => self.mount = mount,
+                None => {
+                    println!("⚠️  Unknown mount: {}", uri::path(url));
+                    return Err(RtspError::NotFound);
+                }
+            }
+        }
+
+        // Session của connection này là session duy nhất request được nhắm tới
+        let session = lines.iter().find_map(|line| {
+            line.split_once(':')
+                .filter(|(name, _)| name.trim().eq_ignore_ascii_case("Session"))
+                .map(|(_, value)| value.split(';').next().unwrap_or("").trim())
+        });
+        if let Some(session) = session.filter(|id| *id != self.session_id) {
+            println!("⚠️  Unknown session: {}", session);
+            return Err(RtspError::SessionNotFound);
+        }
+
         match method {
-            "OPTIONS" => self.handle_options(),
-            "DESCRIBE" => self.handle_describe(),
-            "SETUP" => self.handle_setup(request).await,
-            "PLAY" => self.handle_play().await,
-            "TEARDOWN" => self.handle_teardown().await,
-            _ => self.error_response(405, "Method Not Allowed"),
-        }
-    }
-
-    fn handle_options(&self) -> String {
-        format!(
-            "RTSP/1.0 200 OK\r\n\
-             CSeq: {}\r\n\
-             Public: OPTIONS, DESCRIBE, SETUP, PLAY, TEARDOWN\r\n\
-             \r\n",
-            self.cseq
-        )
-    }
-
-    fn handle_describe(&self) -> String {
-        // SPS/PPS cho 640x480 baseline profile
-        let sps_base64 = "Z0IAH6tAUB7I";
-        let pps_base64 = "aM4wpIA=";
-
-        let sdp = format!(
-            "v=0\r\n\
-             o=- 0 0 IN IP4 127.0.0.1\r\n\
-             s=Simulation Media Server\r\n\
-             c=IN IP4 0.0.0.0\r\n\
-             t=0 0\r\n\
-             m=video 0 RTP/AVP 96\r\n\
-             a=rtpmap:96 H264/90000\r\n\
-             a=fmtp:96 packetization-mode=1;profile-level-id=42001f;sprop-parameter-sets={},{}\r\n\
-             a=control:track1\r\n",
-            sps_base64, pps_base64
-        );
+            "OPTIONS" => Ok(self.handle_options()),
+            "DESCRIBE" => self.handle_describe(url).await,
+            "ANNOUNCE" => self.handle_announce(request).await,
+            "SETUP" => self.handle_setup(request, url).await,
+            "PLAY" => self.handle_play(request, url).await,
+            "PAUSE" => self.handle_pause().await,
+            "TEARDOWN" => self.handle_teardown(url).await,
+            "GET_PARAMETER" => Ok(self.handle_get_parameter().await),
+            _ => Err(RtspError::MethodNotAllowed),
+        }
+    }
 
-        format!(
-            "RTSP/1.0 200 OK\r\n\
-             CSeq: {}\r\n\
-             Content-Type: application/sdp\r\n\
-             Content-Length: {}\r\n\
-             \r\n\
-             {}",
-            self.cseq,
-            sdp.len(),
-            sdp
-        )
+    fn handle_options(&self) -> RtspResponse {
+        RtspResponse::ok().header("Public", "OPTIONS, DESCRIBE, ANNOUNCE, SETUP, PLAY, PAUSE, TEARDOWN, GET_PARAMETER")
     }
 
-    async fn handle_setup(&mut self, request: &str) -> String {
-        // Parse Transport header
-        let mut is_tcp = false;
-        let mut interleaved_rtp: u8 = 0;
-        let mut interleaved_rtcp: u8 = 1;
-        let mut client_rtp_port: u16 = 5004;
-        let mut client_rtcp_port: u16 = 5005;
+    /// GET_PARAMETER: clients dùng làm keepalive trong lúc play. Lần đến đã
+    /// refresh liveness của session ở `dispatch`; không có parameter nào để
+    /// trả nên body luôn rỗng
+    async fn handle_get_parameter(&self) -> RtspResponse {
+        let response = RtspResponse::ok();
+        if self.state.read().await.clients.contains_key(&self.session_id) {
+            response.header("Session", self.session_id.clone())
+        } else {
+            response
+        }
+    }
+
+    async fn handle_describe(&self, url: &str) -> Result<RtspResponse, RtspError> {
+        let Some(sdp) = mount_sdp(&self.config, &self.state, &self.mount).await else {
+            println!("⏳ DESCRIBE refused: no SPS/PPS seen in the stream yet");
+            return Err(RtspError::ServiceUnavailable);
+        };
+
+        // Content-Base để client resolve a=control relative URLs
+        // (VLC và GStreamer build SETUP URL khác nhau khi thiếu header này)
+        let content_base = format!("{}/", url.trim_end_matches('/'));
+
+        Ok(RtspResponse::ok()
+            .header("Content-Base", content_base)
+            .body("application/sdp", sdp))
+    }
+
+    /// Record mode: học payload type / clock / parameter sets từ SDP của
+    /// publisher thay vì giả định PT 96 / 90kHz
+    async fn handle_announce(&self, request: &str) -> Result<RtspResponse, RtspError> {
+        let is_sdp = request.lines().any(|line| {
+            line.split_once(':').is_some_and(|(name, value)| {
+                name.eq_ignore_ascii_case("Content-Type") && value.trim().starts_with("application/sdp")
+            })
+        });
+        let body = request.split_once("\r\n\r\n").map_or("", |(_, body)| body);
+        if !is_sdp || body.trim().is_empty() {
+            return Err(RtspError::UnsupportedMediaType);
+        }
+
+        let formats = parse_media(body);
+        if formats.is_empty() {
+            return Err(RtspError::BadRequest);
+        }
+        if let Some(format) = formats.iter().find(|f| !f.is_supported()) {
+            println!("⚠️  ANNOUNCE with unsupported codec: {} PT {} {:?}",
+                     format.media, format.payload_type, format.encoding);
+            return Err(RtspError::UnsupportedMediaType);
+        }
+
+        for format in &formats {
+            println!("📥 ANNOUNCE {}: PT {} {}/{} ({} parameter sets)",
+                     format.media, format.payload_type, format.encoding,
+                     format.clock_rate, format.parameter_sets.len());
+        }
+
+        self.state.write().await.announced.insert(self.mount.clone(), formats);
 
+        Ok(RtspResponse::ok())
+    }
+
+    async fn handle_setup(&mut self, request: &str, url: &str) -> Result<RtspResponse, RtspError> {
+        // SETUP trên base URL (aggregate control) map về video track mặc định
+        let track = match uri::track(url) {
+            None => {
+                println!("🎯 SETUP on base URL, using {}", VIDEO_TRACK);
+                VIDEO_TRACK
+            }
+            Some(track) if track == VIDEO_TRACK => track,
+            Some(track) if track == AUDIO_TRACK && self.config.audio.is_some() => track,
+            Some(track) => {
+                println!("⚠️  SETUP for unknown track: {}", track);
+                return Err(RtspError::NotFound);
+            }
+        };
+
+        // Parse Transport header
+        let mut blocksize: Option<usize> = None;
         for line in request.lines() {
-            if line.starts_with("Transport:") {
-                let transport_value = &line["Transport:".len()..];
-                println!("📋 Transport header: {}", transport_value);
-
-                // Check if TCP interleaved
-                if transport_value.contains("TCP") || transport_value.contains("interleaved") {
-                    is_tcp = true;
-
-                    // Parse interleaved=x-y
-                    for part in transport_value.split(';') {
-                        let part = part.trim();
-                        if part.starts_with("interleaved=") {
-                            if let Some(channels) = part.strip_prefix("interleaved=") {
-                                let channel_parts: Vec<&str> = channels.split('-').collect();
-                                if !channel_parts.is_empty() {
-                                    interleaved_rtp = channel_parts[0].parse().unwrap_or(0);
-                                    if channel_parts.len() >= 2 {
-                                        interleaved_rtcp = channel_parts[1].parse().unwrap_or(1);
-                                    } else {
-                                        interleaved_rtcp = interleaved_rtp + 1;
-                                    }
-                                }
-                            }
-                        }
-                    }
-                } else {
-                    // UDP mode - parse client_port
-                    for part in transport_value.split(';') {
-                        let part = part.trim();
-                        if part.starts_with("client_port=") {
-                            if let Some(ports) = part.strip_prefix("client_port=") {
-                                let port_parts: Vec<&str> = ports.split('-').collect();
-                                if !port_parts.is_empty() {
-                                    client_rtp_port = port_parts[0].parse().unwrap_or(5004);
-                                    if port_parts.len() >= 2 {
-                                        client_rtcp_port = port_parts[1].parse().unwrap_or(client_rtp_port + 1);
-                                    } else {
-                                        client_rtcp_port = client_rtp_port + 1;
-                                    }
-                                }
-                            }
-                        }
-                    }
-                }
-                break;
+            if let Some(value) = line.strip_prefix("Blocksize:") {
+                blocksize = value.trim().parse().ok();
             }
         }
 
+        let Some(transport_value) = request.lines().find_map(|line| line.strip_prefix("Transport:")) else {
+            println!("⚠️  SETUP without Transport header");
+            return Err(RtspError::UnsupportedTransport);
+        };
+        println!("📋 Transport header: {}", transport_value);
+
+        // Some clients put blocksize in the Transport header instead
+        for part in transport_value.split(';') {
+            if let Some(size) = part.trim().strip_prefix("blocksize=") {
+                blocksize = size.parse().ok().or(blocksize);
+            }
+        }
+
+        let blocksize = match blocksize {
+            Some(size) => match H264Packetizer::effective_blocksize(size) {
+                Some(size) => Some(size),
+                None => {
+                    println!("⚠️  Blocksize {} below the minimum {}", size, MIN_BLOCKSIZE);
+                    return Err(RtspError::BadRequest);
+                }
+            },
+            None => None,
+        };
+
+        let requested = Self::parse_transport(transport_value, self.config.rtcp_mux)?;
+        let is_tcp = matches!(requested, TransportRequest::Tcp { .. });
+        let is_multicast = requested == TransportRequest::Multicast;
+        let (interleaved_rtp, interleaved_rtcp) = match requested {
+            TransportRequest::Tcp { rtp_channel, rtcp_channel } => (rtp_channel, rtcp_channel),
+            _ => (0, 1),
+        };
+        let (client_rtp_port, mut client_rtcp_port, rtcp_mux) = match requested {
+            TransportRequest::Udp { rtp_port, rtcp_port, rtcp_mux } => (rtp_port, rtcp_port, rtcp_mux),
+            _ => (0, 0, false),
+        };
+
+        if track == AUDIO_TRACK {
+            let unicast_udp = !is_tcp && !is_multicast && self.mount == self.config.default_mount;
+            return self.setup_audio(unicast_udp, client_rtp_port, client_rtcp_port).await;
+        }
+
+        // UDP unicast giữ pair đã lease qua các SETUP của cùng session;
+        // transport khác không giữ pair nào
+        let mut port_lease = None;
         let (transport_mode, transport_response) = if is_tcp {
             println!("🔌 TCP interleaved mode: channels {}-{}", interleaved_rtp, interleaved_rtcp);
 
@@ -425,9 +806,30 @@ impl RtspSession {
                 interleaved_rtp, interleaved_rtcp
             );
 
+            (mode, response)
+        } else if self.mount != self.config.default_mount {
+            // Shared UDP pipeline chỉ phát default mount; mounts khác cần
+            // FFmpeg riêng của TCP session
+            println!("⚠️  UDP SETUP for mount /{}: only /{} is served over UDP, use RTP/AVP/TCP",
+                     self.mount, self.config.default_mount);
+            return Err(RtspError::UnsupportedTransport);
+        } else if is_multicast {
+            // Shared UDP pipeline gửi một lần tới group cho mọi member
+            let multicast = self.config.multicast;
+            println!("📡 Multicast mode: group {}:{} ttl {}", multicast.group, multicast.port, multicast.ttl);
+
+            let mode = TransportMode::Multicast { group: multicast.group, port: multicast.port, ttl: multicast.ttl };
+            let response = format!("RTP/AVP;multicast;{}", multicast.transport_params());
+
             (mode, response)
         } else {
-            println!("📡 UDP mode: client ports {}-{}", client_rtp_port, client_rtcp_port);
+            if rtcp_mux {
+                // RTCP shares the RTP port on both sides
+                client_rtcp_port = client_rtp_port;
+                println!("📡 UDP mode: client port {} (rtcp-mux)", client_rtp_port);
+            } else {
+                println!("📡 UDP mode: client ports {}-{}", client_rtp_port, client_rtcp_port);
+            }
 
             self.rtp_port = Some(client_rtp_port);
             self.rtcp_port = Some(client_rtcp_port);
@@ -439,71 +841,1076 @@ impl RtspSession {
                 .parse()
                 .unwrap_or_else(|_| "127.0.0.1:5005".parse().unwrap());
 
-            let mode = TransportMode::Udp { rtp_addr, rtcp_addr };
-
-            let response = format!(
-                "RTP/AVP;unicast;client_port={}-{};server_port=6000-6001",
-                client_rtp_port, client_rtcp_port
-            );
+            let mode = TransportMode::Udp { rtp_addr, rtcp_addr, rtcp_mux };
+
+            port_lease = self.lease_ports().await?;
+            let server_ports = port_lease.as_ref().map_or(self.config.server_ports, |lease| lease.ports);
+            let server_port = server_ports.transport_param(rtcp_mux);
+            let response = if rtcp_mux {
+                format!(
+                    "RTP/AVP;unicast;client_port={};{};rtcp-mux",
+                    client_rtp_port, server_port
+                )
+            } else {
+                format!(
+                    "RTP/AVP;unicast;client_port={}-{};{}",
+                    client_rtp_port, client_rtcp_port, server_port
+                )
+            };
 
             (mode, response)
         };
 
+        let mut state = self.state.write().await;
+
         self.transport_mode = Some(transport_mode.clone());
+        self.blocksize = blocksize;
+
+        // Multicast members nhận cùng packets nên dùng identity của group
+        let group_rtp = matches!(transport_mode, TransportMode::Multicast { .. })
+            .then(|| *state.multicast.get_or_insert_with(RtpIdentity::random));
+
+        // Giữ lại các track đã SETUP trước đó trong cùng session (và start
+        // time + counters cho access log)
+        let previous = state.clients.get(&self.session_id);
+        let mut tracks = previous.map(|c| c.tracks.clone()).unwrap_or_default();
+        if !tracks.iter().any(|t| t == track) {
+            tracks.push(track.to_string());
+        }
+        let started = previous.map_or_else(Instant::now, |c| c.started);
+        let (packets_sent, bytes_sent) = previous.map_or((0, 0), |c| (c.packets_sent, c.bytes_sent));
+        let rtp = group_rtp.unwrap_or_else(|| previous.map_or_else(RtpIdentity::random, |c| c.rtp));
+        let mount = self.mount.clone();
 
         let client_info = ClientInfo {
             id: self.session_id.clone(),
             transport: transport_mode,
             is_playing: false,
+            blocksize,
+            tracks,
+            liveness: RtcpLiveness::new(Instant::now()),
+            client_ip: self.client_ip.clone(),
+            mount,
+            started,
+            packets_sent,
+            bytes_sent,
+            abort: self.abort.clone(),
+            rtp,
+            audio: previous.and_then(|c| c.audio.clone()),
+            reception: previous.and_then(|c| c.reception.clone()),
+            lossy_reports: previous.map_or(0, |c| c.lossy_reports),
+            unread_loss: previous.and_then(|c| c.unread_loss),
+            port_lease,
+        };
+
+        state.add_client(client_info);
+        drop(state);
+
+        let mut response = RtspResponse::ok()
+            .header("Session", format!("{};timeout={}", self.session_id, self.config.session_timeout.as_secs()))
+            .header("Transport", transport_response);
+
+        // Echo the Blocksize actually used (capped at the MTU) so the client
+        // knows what it gets
+        if let Some(size) = blocksize {
+            println!("📏 Blocksize: {} bytes", size);
+            response = response.header("Blocksize", size.to_string());
+        }
+        Ok(response)
+    }
+
+    /// Port pair (+ sockets) cho UDP unicast SETUP khi có `--session-ports`:
+    /// lease hiện có của session, hoặc một pair mới; hết pair thì 453
+    async fn lease_ports(&self) -> Result<Option<Arc<PortLease>>, RtspError> {
+        let state = self.state.read().await;
+        if let Some(lease) = state.clients.get(&self.session_id).and_then(|c| c.port_lease.clone()) {
+            return Ok(Some(lease));
+        }
+        let Some(allocator) = &state.port_allocator else {
+            return Ok(None);
+        };
+        let Some(lease) = allocator.lease() else {
+            println!("🎟️  Session ports exhausted, rejecting UDP SETUP from {}", self.client_ip);
+            return Err(RtspError::NotEnoughBandwidth);
+        };
+        println!("🎟️  Session {} leased ports {}-{} ({} left)",
+                 self.session_id, lease.ports.rtp, lease.ports.rtcp, allocator.available());
+        Ok(Some(Arc::new(lease)))
+    }
+
+    /// SETUP của audio track: UDP unicast từ shared audio pipeline (default
+    /// mount), sau video track của cùng session. Transport của session vẫn
+    /// là của video; audio có transport và RTP identity riêng
+    async fn setup_audio(
+        &mut self,
+        unicast_udp: bool,
+        client_rtp_port: u16,
+        client_rtcp_port: u16,
+    ) -> Result<RtspResponse, RtspError> {
+        let Some(ports) = self.config.audio else {
+            return Err(RtspError::NotFound);
+        };
+        if !unicast_udp {
+            println!("⚠️  {} is only streamed over UDP unicast on /{}", AUDIO_TRACK, self.config.default_mount);
+            return Err(RtspError::UnsupportedTransport);
+        }
+
+        let mut state = self.state.write().await;
+        let Some(client) = state.clients.get_mut(&self.session_id) else {
+            println!("⚠️  SETUP {} before {}", AUDIO_TRACK, VIDEO_TRACK);
+            return Err(RtspError::MethodNotValid);
+        };
+
+        let ip = self.client_ip.parse().unwrap_or(std::net::Ipv4Addr::LOCALHOST.into());
+        let (rtp_addr, rtcp_addr) = (SocketAddr::new(ip, client_rtp_port), SocketAddr::new(ip, client_rtcp_port));
+        let rtp = client.audio.as_ref().map_or_else(RtpIdentity::random, |audio| audio.rtp);
+        client.audio = Some(TrackTransport {
+            transport: TransportMode::Udp { rtp_addr, rtcp_addr, rtcp_mux: false },
+            rtp,
+        });
+        if !client.tracks.iter().any(|t| t == AUDIO_TRACK) {
+            client.tracks.push(AUDIO_TRACK.to_string());
+        }
+        println!("🔊 Audio track: client ports {}-{}, SSRC {:08x}", client_rtp_port, client_rtcp_port, rtp.ssrc);
+        drop(state);
+
+        Ok(RtspResponse::ok()
+            .header("Session", format!("{};timeout={}", self.session_id, self.config.session_timeout.as_secs()))
+            .header("Transport", format!(
+                "RTP/AVP;unicast;client_port={}-{};{}",
+                client_rtp_port, client_rtcp_port, ports.transport_param(false)
+            )))
+    }
+
+    /// Publisher SETUPs (`mode=record`) fail fast thay vì bị coi là player.
+    ///
+    /// Trong record mode chỉ `mode` và `append` được xét: `append` (RFC 2326
+    /// §12.39, ghi nối vào resource có sẵn) → 461 Unsupported Transport, các
+    /// record SETUP khác → 501 vì server chưa có RECORD/ingest path. Các
+    /// params còn lại (client_port, interleaved, rtcp-mux, blocksize, ttl,
+    /// destination, ssrc) không được xét cho record SETUP vì nó bị từ chối
+    /// trước. Mode mặc định (không có `mode=`) là PLAY.
+    fn reject_record_transport(transport: &str) -> Option<RtspError> {
+        let params: Vec<String> = transport
+            .split(';')
+            .map(|p| p.trim().to_ascii_lowercase().replace('"', ""))
+            .collect();
+        let record = params
+            .iter()
+            .any(|p| p.strip_prefix("mode=").is_some_and(|mode| mode.split(',').any(|m| m.trim() == "record")));
+        if !record {
+            return None;
+        }
+
+        if params.iter().any(|p| p == "append") {
+            println!("⚠️  Transport mode=record;append is not supported");
+            return Some(RtspError::UnsupportedTransport);
+        }
+        println!("⚠️  Transport mode=record: RECORD is not implemented");
+        Some(RtspError::NotImplemented)
+    }
+
+    /// Validate the SETUP `Transport` value. 461 khi thiếu `interleaved=x-y`
+    /// (TCP) hoặc `client_port=x-y` (UDP unicast), `mode=record` bị từ chối
+    /// như `reject_record_transport`. `rtcp-mux` chỉ được nhận khi server bật
+    /// `server_rtcp_mux`
+    fn parse_transport(transport: &str, server_rtcp_mux: bool) -> Result<TransportRequest, RtspError> {
+        if let Some(error) = Self::reject_record_transport(transport) {
+            return Err(error);
+        }
+        let parts = || transport.split(';').map(str::trim);
+
+        if transport.contains("TCP") || transport.contains("interleaved") {
+            // interleaved=x-y bắt buộc; channel không parse được là 461,
+            // không đoán 0-1
+            let channels = parts()
+                .find_map(|part| part.strip_prefix("interleaved="))
+                .and_then(Self::parse_range)
+                .and_then(|(rtp, rtcp)| Some((u8::try_from(rtp).ok()?, u8::try_from(rtcp).ok()?)));
+            let Some((rtp_channel, rtcp_channel)) = channels else {
+                println!("⚠️  Transport without a valid interleaved=x-y: {}", transport.trim());
+                return Err(RtspError::UnsupportedTransport);
+            };
+            return Ok(TransportRequest::Tcp { rtp_channel, rtcp_channel });
+        }
+
+        // destination/port/ttl client đề xuất bị bỏ qua: mọi member dùng
+        // chung group của server
+        let multicast = parts().any(|part| part.eq_ignore_ascii_case("multicast"));
+        let rtcp_mux = server_rtcp_mux && parts().any(|part| part.eq_ignore_ascii_case("rtcp-mux"));
+        let client_ports = parts()
+            .rev()
+            .find_map(|part| part.strip_prefix("client_port="))
+            .map(|ports| Self::parse_range(ports).filter(|(rtp, rtcp)| *rtp != 0 && *rtcp != 0));
+
+        // Unicast cần client_port hợp lệ, thay vì gửi RTP tới 5004 đoán mò;
+        // multicast dùng port của group nên client_port là tùy chọn
+        match client_ports {
+            Some(Some((rtp_port, rtcp_port))) if !multicast => Ok(TransportRequest::Udp { rtp_port, rtcp_port, rtcp_mux }),
+            Some(Some(_)) | None if multicast => Ok(TransportRequest::Multicast),
+            _ => {
+                println!("⚠️  Transport without a valid client_port=x-y: {}", transport.trim());
+                Err(RtspError::UnsupportedTransport)
+            }
+        }
+    }
+
+    /// `x-y` hoặc `x` (y = x + 1) của `client_port=` / `interleaved=`.
+    /// None khi một số không parse được hoặc `x + 1` tràn
+    fn parse_range(spec: &str) -> Option<(u16, u16)> {
+        let (first, second) = match spec.split_once('-') {
+            Some((first, second)) => (first, Some(second)),
+            None => (spec, None),
+        };
+        let first: u16 = first.trim().parse().ok()?;
+        let second = match second {
+            Some(second) => second.trim().parse().ok()?,
+            None => first.checked_add(1)?,
+        };
+        Some((first, second))
+    }
+
+    async fn handle_play(&mut self, request: &str, url: &str) -> Result<RtspResponse, RtspError> {
+        let (duration, mut range_response, set_up) = {
+            let state = self.state.read().await;
+            (
+                state.mount_duration(&self.mount, &self.config.default_mount),
+                state.mount_range(&self.mount, &self.config.default_mount),
+                state.clients.contains_key(&self.session_id),
+            )
+        };
+        if !set_up {
+            println!("⚠️  PLAY before SETUP");
+            return Err(RtspError::MethodNotValid);
+        }
+
+        // Validate Range against the available media. Stream luôn phát từ
+        // vị trí hiện tại, nên response echo range thực sự được phát
+        let range_header = request.lines().find_map(|line| line.strip_prefix("Range:"));
+        if let Some(value) = range_header.filter(|v| v.trim().starts_with("clock=")) {
+            // clock= map về offset so với media_epoch. Chỉ TCP session có FFmpeg
+            // riêng để seek; UDP clients dùng chung pipeline nên phát live point
+            let Some((range, (start, _))) = ClockRange::parse(value)
+                .and_then(|range| Some((range, range.to_offset(self.config.media_epoch, duration)?)))
+            else {
+                println!("⚠️  Clock range {} outside the available media", value.trim());
+                return Err(RtspError::InvalidRange);
+            };
+
+            if matches!(self.transport_mode, Some(TransportMode::TcpInterleaved { .. })) {
+                println!("⏩ Clock range {} → seek {:.3}s", range.to_header(), start);
+                self.seek = Some(start);
+                range_response = range.to_header();
+            } else {
+                println!("⏩ Clock range {} requested, shared UDP pipeline plays live", range.to_header());
+            }
+        } else if let Some(value) = range_header {
+            let range = NptRange::parse(value).ok_or(RtspError::InvalidRange)?;
+            // Chỉ TCP session bắt đầu stream mới seek được; PLAY resume một
+            // TCP stream đang pause tiếp tục từ chỗ pause, UDP phát live point
+            let seekable = matches!(self.transport_mode, Some(TransportMode::TcpInterleaved { .. }))
+                && self.tcp_resume.is_none();
+            match range {
+                NptRange::From { start, .. } if seekable => {
+                    // Source loop vô hạn (-stream_loop -1), nên seek quá duration
+                    // wrap về vị trí tương ứng trong vòng lặp đầu
+                    let start = match duration {
+                        Some(d) if d > 0.0 && start >= d => {
+                            println!("🔁 Range start {:.3}s beyond media duration {:.3}s, wrapping", start, d);
+                            start % d
+                        }
+                        _ => start,
+                    };
+                    println!("⏩ Range npt → seek {:.3}s", start);
+                    self.seek = Some(start).filter(|start| *start > 0.0);
+                    range_response = NptRange::From { start, end: duration }.to_header();
+                }
+                NptRange::Now if seekable => {
+                    self.seek = None;
+                    range_response = range.to_header();
+                }
+                range => println!("⏩ Range {} requested, stream plays from its current position", range.to_header()),
+            }
+        }
+
+        let mut state = self.state.write().await;
+        state.set_playing(&self.session_id, true);
+        let tcp_seek = self.seek.map_or(0, seek_timestamp);
+        let rtp_info = Self::rtp_info(&state, &self.session_id, url, self.tcp_resume, tcp_seek);
+        drop(state);
+
+        Ok(RtspResponse::ok()
+            .header("Session", self.session_id.clone())
+            .header("Range", range_response)
+            .header("RTP-Info", rtp_info))
+    }
+
+    /// PAUSE: dừng gửi nhưng giữ session (và với TCP, FFmpeg + packetizer)
+    /// để PLAY tiếp theo resume
+    async fn handle_pause(&self) -> Result<RtspResponse, RtspError> {
+        let mut state = self.state.write().await;
+        if !state.clients.contains_key(&self.session_id) {
+            println!("⚠️  PAUSE before SETUP");
+            return Err(RtspError::MethodNotValid);
+        }
+        state.set_playing(&self.session_id, false);
+        drop(state);
+
+        Ok(RtspResponse::ok().header("Session", self.session_id.clone()))
+    }
+
+    /// `RTP-Info` value: một entry `url=..;seq=..;rtptime=..` cho mỗi track
+    /// đã SETUP, với seq/rtptime của packet đầu tiên client sẽ nhận
+    /// (TCP: vị trí pause `tcp_resume`, hoặc identity của client cộng
+    /// `tcp_seek` ticks)
+    fn rtp_info(state: &ServerState, session_id: &str, url: &str, tcp_resume: Option<(u16, u32)>, tcp_seek: u32) -> String {
+        let Some(client) = state.clients.get(session_id) else {
+            return format!("url={};seq=0;rtptime=0", uri::control_url(url, VIDEO_TRACK));
+        };
+
+        client
+            .tracks
+            .iter()
+            .map(|track| {
+                // Audio: vị trí shared audio stream, trong identity audio của client
+                if let Some(audio) = client.audio.as_ref().filter(|_| track == AUDIO_TRACK) {
+                    let (seq, ts) = state.track_positions.get(track).copied().unwrap_or((0, 0));
+                    let (seq, rtptime) = audio.rtp.map(seq, ts);
+                    return format!("url={};seq={};rtptime={}", uri::control_url(url, track), seq, rtptime);
+                }
+                let (seq, rtptime) = match client.transport {
+                    // UDP / multicast: vị trí shared stream, trong sequence space của client
+                    TransportMode::Udp { .. } | TransportMode::Multicast { .. } => {
+                        let (seq, ts) = state.track_positions.get(track).copied().unwrap_or((0, 0));
+                        client.rtp.map(seq, ts)
+                    }
+                    // TCP session chạy packetizer riêng, bắt đầu từ identity
+                    // của SETUP (cộng seek) hoặc tiếp tục chỗ pause
+                    TransportMode::TcpInterleaved { .. } => {
+                        tcp_resume.unwrap_or_else(|| client.rtp.map(0, tcp_seek))
+                    }
+                };
+                format!("url={};seq={};rtptime={}", uri::control_url(url, track), seq, rtptime)
+            })
+            .collect::<Vec<_>>()
+            .join(",")
+    }
+
+    /// TEARDOWN trên một track URL chỉ bỏ track đó, session và các track còn
+    /// lại tiếp tục; TEARDOWN trên base/aggregate URL bỏ toàn bộ session
+    async fn handle_teardown(&self, url: &str) -> Result<RtspResponse, RtspError> {
+        let mut state = self.state.write().await;
+        match uri::track(url) {
+            Some(track) => {
+                let set_up = state
+                    .clients
+                    .get(&self.session_id)
+                    .is_some_and(|c| c.tracks.iter().any(|t| t == track));
+                if !set_up {
+                    println!("⚠️  TEARDOWN for track not set up: {}", track);
+                    return Err(RtspError::NotFound);
+                }
+
+                let remaining = state.remove_track(&self.session_id, track);
+                if !remaining.is_empty() {
+                    println!("✂️  Session {} keeps tracks {:?}", self.session_id, remaining);
+                }
+            }
+            None => state.remove_client(&self.session_id, EndReason::Normal),
+        }
+        drop(state);
+
+        Ok(RtspResponse::ok().header("Session", self.session_id.clone()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::PortPair;
+    use crate::rtp::ports::PortAllocator;
+    use crate::rtsp::state::create_shared_state;
+    use crate::source::params::ParameterSets;
+    use tokio::io::DuplexStream;
+    use tokio::task::JoinHandle;
+
+    /// Client end của một session chạy trên `tokio::io::duplex`
+    struct TestClient {
+        stream: DuplexStream,
+        framer: RtspFramer,
+        cseq: u32,
+    }
+
+    impl TestClient {
+        /// Gửi `METHOD url` với extra headers, trả về response (headers + body)
+        async fn request(&mut self, method: &str, url: &str, headers: &[&str]) -> String {
+            self.cseq += 1;
+            let mut request = format!("{} {} RTSP/1.0\r\nCSeq: {}\r\n", method, url, self.cseq);
+            for header in headers {
+                request.push_str(header);
+                request.push_str("\r\n");
+            }
+            request.push_str("\r\n");
+            self.stream.write_all(request.as_bytes()).await.unwrap();
+
+            let mut buffer = [0u8; 4096];
+            loop {
+                if let Some(Frame::Request(response)) = self.framer.next_frame() {
+                    assert!(response.contains(&format!("CSeq: {}\r\n", self.cseq)), "{}", response);
+                    return response;
+                }
+                let n = self.stream.read(&mut buffer).await.unwrap();
+                assert!(n > 0, "session closed the connection");
+                self.framer.push(&buffer[..n]);
+            }
+        }
+    }
+
+    fn test_config() -> ServerConfig {
+        ServerConfig {
+            mounts: vec![("lobby".to_string(), "videos/lobby.mp4".to_string())],
+            // Override để DESCRIBE không chờ SPS/PPS của shared pipeline
+            parameter_sets: Some(ParameterSets { sps: vec![0x67, 0x42, 0xC0, 0x1F], pps: vec![0x68, 0xCE, 0x3C, 0x80] }),
+            ..ServerConfig::default()
+        }
+    }
+
+    fn start_session(config: ServerConfig) -> (TestClient, SharedState) {
+        let state = create_shared_state(config.mount_table());
+        (connect(&state, Arc::new(config)), state)
+    }
+
+    /// Thêm một connection vào server state có sẵn
+    fn connect(state: &SharedState, config: Arc<ServerConfig>) -> TestClient {
+        spawn_session(state, config).0
+    }
+
+    /// Như `connect`, kèm task chạy `handle` của session
+    fn spawn_session(state: &SharedState, config: Arc<ServerConfig>) -> (TestClient, JoinHandle<std::io::Result<()>>) {
+        let (client, server) = tokio::io::duplex(64 * 1024);
+        let mut session = RtspSession::with_stream(server, "127.0.0.1".to_string(), state.clone(), config);
+        let handle = tokio::spawn(async move { session.handle().await });
+        (TestClient { stream: client, framer: RtspFramer::new(), cseq: 0 }, handle)
+    }
+
+    fn status(response: &str) -> &str {
+        response.lines().next().unwrap_or_default()
+    }
+
+    fn header<'a>(response: &'a str, name: &str) -> Option<&'a str> {
+        response
+            .lines()
+            .filter_map(|line| line.split_once(':'))
+            .find(|(header, _)| header.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.trim())
+    }
+
+    #[tokio::test]
+    async fn options_describe_setup_play_round_trip() {
+        let (mut client, state) = start_session(test_config());
+        let base = "rtsp://127.0.0.1:8554/cam";
+
+        let options = client.request("OPTIONS", base, &[]).await;
+        assert_eq!(status(&options), "RTSP/1.0 200 OK");
+        assert!(header(&options, "Public").unwrap().contains("PLAY"));
+
+        let describe = client.request("DESCRIBE", base, &["Accept: application/sdp"]).await;
+        assert_eq!(status(&describe), "RTSP/1.0 200 OK");
+        assert_eq!(header(&describe, "Content-Base"), Some("rtsp://127.0.0.1:8554/cam/"));
+        let sdp = describe.split_once("\r\n\r\n").unwrap().1;
+        let media = parse_media(sdp);
+        assert_eq!(media[0].encoding, "H264");
+        assert_eq!(media[0].control.as_deref(), Some(VIDEO_TRACK));
+        assert_eq!(media[0].parameter_sets.len(), 2);
+
+        let track = format!("{}/{}", base, VIDEO_TRACK);
+        let setup = client.request("SETUP", &track, &["Transport: RTP/AVP;unicast;client_port=5000-5001"]).await;
+        assert_eq!(status(&setup), "RTSP/1.0 200 OK");
+        assert!(header(&setup, "Transport").unwrap().starts_with("RTP/AVP;unicast;client_port=5000-5001;server_port="));
+        let session = header(&setup, "Session").unwrap().split(';').next().unwrap().to_string();
+        {
+            let state = state.read().await;
+            let client = &state.clients[&session];
+            assert!(!client.is_playing);
+            assert!(matches!(client.transport, TransportMode::Udp { rtp_addr, .. } if rtp_addr.port() == 5000));
+        }
+
+        let session_header = format!("Session: {}", session);
+        let play = client.request("PLAY", base, &[&session_header, "Range: npt=0.000-"]).await;
+        assert_eq!(status(&play), "RTSP/1.0 200 OK");
+        assert!(header(&play, "RTP-Info").unwrap().contains(&format!("url={}", track)));
+        assert!(state.read().await.clients[&session].is_playing);
+
+        let teardown = client.request("TEARDOWN", base, &[&session_header]).await;
+        assert_eq!(status(&teardown), "RTSP/1.0 200 OK");
+        assert!(!state.read().await.clients.contains_key(&session));
+    }
+
+    #[tokio::test]
+    async fn requests_out_of_order_are_rejected() {
+        let (mut client, _) = start_session(test_config());
+        let base = "rtsp://127.0.0.1:8554/cam";
+
+        let play = client.request("PLAY", base, &[]).await;
+        assert_eq!(status(&play), "RTSP/1.0 455 Method Not Valid in This State");
+        let unknown = client.request("PLAY", base, &["Session: deadbeef"]).await;
+        assert_eq!(status(&unknown), "RTSP/1.0 454 Session Not Found");
+    }
+
+    #[tokio::test]
+    async fn describe_resolves_mounts() {
+        let (mut client, _) = start_session(test_config());
+
+        for url in ["rtsp://127.0.0.1:8554/", "rtsp://127.0.0.1:8554/cam", "rtsp://127.0.0.1:8554/lobby"] {
+            let describe = client.request("DESCRIBE", url, &[]).await;
+            assert_eq!(status(&describe), "RTSP/1.0 200 OK", "{}", url);
+        }
+        let unknown = client.request("DESCRIBE", "rtsp://127.0.0.1:8554/unknown", &[]).await;
+        assert_eq!(status(&unknown), "RTSP/1.0 404 Not Found");
+        let setup = client
+            .request("SETUP", "rtsp://127.0.0.1:8554/unknown/track1", &["Transport: RTP/AVP/TCP;unicast;interleaved=0-1"])
+            .await;
+        assert_eq!(status(&setup), "RTSP/1.0 404 Not Found");
+    }
+
+    #[tokio::test]
+    async fn session_ports_are_released_on_every_disconnect() {
+        let config = Arc::new(test_config());
+        let state = create_shared_state(config.mount_table());
+        let allocator = PortAllocator::new(30000, 30001, None);
+        state.write().await.port_allocator = Some(allocator.clone());
+        let track = "rtsp://127.0.0.1:8554/cam/track1";
+        let udp = "Transport: RTP/AVP;unicast;client_port=5000-5001";
+
+        // Range chỉ có một pair: mỗi vòng chỉ SETUP được nếu vòng trước đã trả pair
+        for round in 0..20 {
+            let mut client = connect(&state, config.clone());
+            let setup = client.request("SETUP", track, &[udp]).await;
+            assert_eq!(status(&setup), "RTSP/1.0 200 OK", "round {}", round);
+            assert_eq!(allocator.available(), 0);
+
+            if round % 2 == 0 {
+                // Session thứ hai không còn pair (session IDs theo millisecond)
+                tokio::time::sleep(Duration::from_millis(2)).await;
+                let mut other = connect(&state, config.clone());
+                let rejected = other.request("SETUP", track, &[udp]).await;
+                assert_eq!(status(&rejected), "RTSP/1.0 453 Not Enough Bandwidth");
+                // TCP không cần pair
+                let tcp = other.request("SETUP", track, &["Transport: RTP/AVP/TCP;unicast;interleaved=0-1"]).await;
+                assert_eq!(status(&tcp), "RTSP/1.0 200 OK");
+            } else {
+                let session = header(&setup, "Session").unwrap().split(';').next().unwrap().to_string();
+                let teardown = client.request("TEARDOWN", track, &[&format!("Session: {}", session)]).await;
+                assert_eq!(status(&teardown), "RTSP/1.0 200 OK");
+            }
+            drop(client);
+
+            tokio::time::timeout(Duration::from_secs(2), async {
+                while allocator.available() == 0 {
+                    tokio::time::sleep(Duration::from_millis(5)).await;
+                }
+            })
+            .await
+            .unwrap_or_else(|_| panic!("round {}: port pair leaked", round));
+        }
+    }
+
+    /// Port `port` bind được lại (socket của session đã đóng)
+    async fn wait_port_released(port: u16) -> bool {
+        tokio::time::timeout(Duration::from_secs(2), async {
+            while std::net::UdpSocket::bind(("0.0.0.0", port)).is_err() {
+                tokio::time::sleep(Duration::from_millis(5)).await;
+            }
+        })
+        .await
+        .is_ok()
+    }
+
+    #[tokio::test]
+    async fn session_sockets_are_closed_on_every_exit_path() {
+        let config = Arc::new(test_config());
+        let state = create_shared_state(config.mount_table());
+        let allocator = PortAllocator::new(39200, 39201, None);
+        state.write().await.port_allocator = Some(allocator.clone());
+        let base = "rtsp://127.0.0.1:8554/cam";
+        let track = "rtsp://127.0.0.1:8554/cam/track1";
+        let udp = "Transport: RTP/AVP;unicast;client_port=5000-5001";
+        let later = Instant::now() + Duration::from_secs(3600);
+
+        for exit in ["teardown", "kick", "reap", "max-duration", "evicted", "disconnect"] {
+            tokio::time::sleep(Duration::from_millis(2)).await;
+            let mut client = connect(&state, config.clone());
+            let setup = client.request("SETUP", track, &[udp]).await;
+            assert_eq!(status(&setup), "RTSP/1.0 200 OK", "{}", exit);
+            assert!(header(&setup, "Transport").unwrap().contains("server_port=39200-39201"), "{}", setup);
+            assert!(std::net::UdpSocket::bind(("0.0.0.0", 39200)).is_err(), "{}: RTP socket not bound", exit);
+            assert!(std::net::UdpSocket::bind(("0.0.0.0", 39201)).is_err(), "{}: RTCP socket not bound", exit);
+
+            let id = header(&setup, "Session").unwrap().split(';').next().unwrap().to_string();
+            let session = format!("Session: {}", id);
+            assert_eq!(status(&client.request("PLAY", base, &[&session]).await), "RTSP/1.0 200 OK");
+
+            match exit {
+                "teardown" => {
+                    assert_eq!(status(&client.request("TEARDOWN", base, &[&session]).await), "RTSP/1.0 200 OK");
+                }
+                "kick" => assert!(state.write().await.kick(&id)),
+                "reap" => assert_eq!(state.write().await.reap_stale(later, Duration::from_secs(60)), vec![id.clone()]),
+                "max-duration" => state.write().await.expire_sessions(later, Duration::from_secs(60)),
+                "evicted" => state.write().await.remove_udp_client("127.0.0.1:5000".parse().unwrap()),
+                _ => drop(client),
+            }
+
+            assert!(wait_port_released(39200).await, "{}: RTP socket leaked", exit);
+            assert!(wait_port_released(39201).await, "{}: RTCP socket leaked", exit);
+            assert_eq!(allocator.available(), 1, "{}: port pair leaked", exit);
+            assert!(!state.read().await.clients.contains_key(&id), "{}", exit);
+        }
+    }
+
+    #[tokio::test]
+    async fn client_sees_rtp_from_the_even_port_and_rtcp_from_the_next() {
+        let config = Arc::new(test_config());
+        let state = create_shared_state(config.mount_table());
+        state.write().await.port_allocator = Some(PortAllocator::new(39211, 39215, None));
+        let client_rtp = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let client_rtcp = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let transport = format!(
+            "Transport: RTP/AVP;unicast;client_port={}-{}",
+            client_rtp.local_addr().unwrap().port(),
+            client_rtcp.local_addr().unwrap().port()
+        );
+
+        let mut client = connect(&state, config.clone());
+        let setup = client.request("SETUP", "rtsp://127.0.0.1:8554/cam/track1", &[&transport]).await;
+        // Range bắt đầu lẻ: pair đầu tiên là 39212-39213
+        assert!(header(&setup, "Transport").unwrap().ends_with("server_port=39212-39213"), "{}", setup);
+        let session = format!("Session: {}", header(&setup, "Session").unwrap().split(';').next().unwrap());
+        assert_eq!(status(&client.request("PLAY", "rtsp://127.0.0.1:8554/cam", &[&session]).await), "RTSP/1.0 200 OK");
+
+        // Fan-out và SR như video pipeline: UDP sender với socket chung khác pair
+        let shared = Arc::new(tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap());
+        let sender = crate::rtp::udp::UdpSender::new(shared, None, None, None);
+        let packets = crate::rtp::h264::H264Packetizer::with_ssrc(1).packetize(&[0x65, 0x88, 0x84], true);
+        sender.send(&packets, &state.read().await.get_udp_clients()).await;
+
+        let mut buf = [0u8; 1500];
+        let (_, from) = tokio::time::timeout(Duration::from_secs(2), client_rtp.recv_from(&mut buf)).await.unwrap().unwrap();
+        assert_eq!(from.port(), 39212);
+        assert_eq!(from.port() % 2, 0);
+
+        let targets = state.write().await.take_sr_targets(Instant::now() + Duration::from_secs(60));
+        let [(rtp_addr, rtcp_addr, false, Some(socket))] = targets.as_slice() else {
+            panic!("expected one SR target with a session socket: {:?}", targets);
+        };
+        let reports = sender.sender_reports(0).await;
+        socket.send_to(&reports[rtp_addr].to_bytes(std::time::SystemTime::now()), rtcp_addr).await.unwrap();
+
+        let (len, from) = tokio::time::timeout(Duration::from_secs(2), client_rtcp.recv_from(&mut buf)).await.unwrap().unwrap();
+        assert_eq!(buf[1], 200, "expected an SR, got {:?}", &buf[..len]);
+        assert_eq!(from.port(), 39212 + 1);
+    }
+
+    #[tokio::test]
+    async fn setup_echoes_the_blocksize_in_use_and_rejects_tiny_ones() {
+        let track = "rtsp://127.0.0.1:8554/cam/track1";
+        let tcp = "Transport: RTP/AVP/TCP;unicast;interleaved=0-1";
+
+        let (mut client, _) = start_session(test_config());
+        let setup = client.request("SETUP", track, &[tcp, "Blocksize: 9000"]).await;
+        assert_eq!(header(&setup, "Blocksize"), Some("1412"));
+        let setup = client.request("SETUP", track, &[tcp, "Blocksize: 500"]).await;
+        assert_eq!(header(&setup, "Blocksize"), Some("500"));
+
+        let (mut client, _) = start_session(test_config());
+        let setup = client.request("SETUP", track, &["Transport: RTP/AVP/TCP;unicast;interleaved=0-1;blocksize=40"]).await;
+        assert_eq!(status(&setup), "RTSP/1.0 400 Bad Request");
+    }
+
+    #[tokio::test]
+    async fn audio_track_is_only_set_up_when_advertised() {
+        let udp = "Transport: RTP/AVP;unicast;client_port=5000-5001";
+        let audio_udp = "Transport: RTP/AVP;unicast;client_port=5002-5003";
+        let base = "rtsp://127.0.0.1:8554/cam";
+
+        let (mut client, _) = start_session(ServerConfig { audio: Some(PortPair::new(7000).unwrap()), ..test_config() });
+        let setup = client.request("SETUP", &format!("{}/{}", base, VIDEO_TRACK), &[udp]).await;
+        let session = format!("Session: {}", header(&setup, "Session").unwrap().split(';').next().unwrap());
+        let audio = client.request("SETUP", &format!("{}/{}", base, AUDIO_TRACK), &[audio_udp, &session]).await;
+        assert_eq!(status(&audio), "RTSP/1.0 200 OK");
+
+        // Không có audio (vd. source là RTSP feed): track2 không tồn tại
+        let (mut client, _) = start_session(test_config());
+        let setup = client.request("SETUP", &format!("{}/{}", base, VIDEO_TRACK), &[udp]).await;
+        let session = format!("Session: {}", header(&setup, "Session").unwrap().split(';').next().unwrap());
+        let audio = client.request("SETUP", &format!("{}/{}", base, AUDIO_TRACK), &[audio_udp, &session]).await;
+        assert_eq!(status(&audio), "RTSP/1.0 404 Not Found");
+    }
+
+    #[tokio::test]
+    async fn play_lists_one_rtp_info_entry_per_track() {
+        let base = "rtsp://127.0.0.1:8554/cam";
+        let (mut client, state) = start_session(ServerConfig { audio: Some(PortPair::new(7000).unwrap()), ..test_config() });
+        let setup = client
+            .request("SETUP", &format!("{}/{}", base, VIDEO_TRACK), &["Transport: RTP/AVP;unicast;client_port=5000-5001"])
+            .await;
+        let id = header(&setup, "Session").unwrap().split(';').next().unwrap().to_string();
+        let session = format!("Session: {}", id);
+        let audio = client
+            .request("SETUP", &format!("{}/{}", base, AUDIO_TRACK), &["Transport: RTP/AVP;unicast;client_port=5002-5003", &session])
+            .await;
+        assert_eq!(status(&audio), "RTSP/1.0 200 OK");
+
+        // Shared streams đang chạy ở vị trí khác nhau cho mỗi track
+        let (video, audio) = {
+            let mut state = state.write().await;
+            state.track_positions.insert(VIDEO_TRACK.to_string(), (100, 90_000));
+            state.track_positions.insert(AUDIO_TRACK.to_string(), (7, 48_000));
+            let client = &state.clients[&id];
+            (client.rtp.map(100, 90_000), client.audio.as_ref().unwrap().rtp.map(7, 48_000))
+        };
+
+        let play = client.request("PLAY", base, &[&session]).await;
+        assert_eq!(status(&play), "RTSP/1.0 200 OK");
+        let entries: Vec<&str> = header(&play, "RTP-Info").unwrap().split(',').collect();
+        assert_eq!(
+            entries,
+            [
+                format!("url={}/{};seq={};rtptime={}", base, VIDEO_TRACK, video.0, video.1),
+                format!("url={}/{};seq={};rtptime={}", base, AUDIO_TRACK, audio.0, audio.1),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn teardown_of_one_track_keeps_the_session_playing() {
+        let config = Arc::new(ServerConfig { audio: Some(PortPair::new(7000).unwrap()), ..test_config() });
+        let state = create_shared_state(config.mount_table());
+        let allocator = PortAllocator::new(39230, 39231, None);
+        state.write().await.port_allocator = Some(allocator.clone());
+        let base = "rtsp://127.0.0.1:8554/cam";
+        let video = format!("{}/{}", base, VIDEO_TRACK);
+        let audio = format!("{}/{}", base, AUDIO_TRACK);
+
+        let mut client = connect(&state, config);
+        let setup = client.request("SETUP", &video, &["Transport: RTP/AVP;unicast;client_port=5000-5001"]).await;
+        let id = header(&setup, "Session").unwrap().split(';').next().unwrap().to_string();
+        let session = format!("Session: {}", id);
+        client.request("SETUP", &audio, &["Transport: RTP/AVP;unicast;client_port=5002-5003", &session]).await;
+        assert_eq!(status(&client.request("PLAY", base, &[&session]).await), "RTSP/1.0 200 OK");
+        assert_eq!(state.read().await.get_audio_clients().len(), 1);
+
+        // track2: chỉ audio dừng, video vẫn phát
+        assert_eq!(status(&client.request("TEARDOWN", &audio, &[&session]).await), "RTSP/1.0 200 OK");
+        {
+            let state = state.read().await;
+            let client = &state.clients[&id];
+            assert_eq!(client.tracks, [VIDEO_TRACK]);
+            assert!(client.audio.is_none());
+            assert!(client.is_playing);
+            assert_eq!(state.get_udp_clients().len(), 1);
+            assert!(state.get_audio_clients().is_empty());
+            assert_eq!(allocator.available(), 0);
+        }
+        // Track đã teardown không còn trong session
+        assert_eq!(status(&client.request("TEARDOWN", &audio, &[&session]).await), "RTSP/1.0 404 Not Found");
+
+        // Aggregate URL: toàn bộ session, kể cả port pair
+        assert_eq!(status(&client.request("TEARDOWN", base, &[&session]).await), "RTSP/1.0 200 OK");
+        let state = state.read().await;
+        assert!(!state.clients.contains_key(&id));
+        assert!(state.get_udp_clients().is_empty());
+        assert_eq!(allocator.available(), 1);
+    }
+
+    #[tokio::test]
+    async fn setup_on_the_base_url_and_the_track_url_selects_the_video_track() {
+        let base = "rtsp://127.0.0.1:8554/cam";
+        let (mut client, _) = start_session(test_config());
+        let describe = client.request("DESCRIBE", base, &[]).await;
+        let sdp = describe.split_once("\r\n\r\n").unwrap().1;
+        // Aggregate control ở session level, trước media section đầu tiên
+        let session_level = sdp.split("m=").next().unwrap();
+        assert!(session_level.contains("a=control:*\r\n"), "{}", sdp);
+        assert_eq!(parse_media(sdp)[0].control.as_deref(), Some(VIDEO_TRACK));
+
+        // VLC: control URL của track; GStreamer với `*`: Content-Base; vài
+        // clients khác dùng thẳng stream URL
+        let state = create_shared_state(test_config().mount_table());
+        let config = Arc::new(test_config());
+        for url in [format!("{}/{}", base, VIDEO_TRACK), format!("{}/", base), base.to_string()] {
+            tokio::time::sleep(Duration::from_millis(2)).await;
+            let mut client = connect(&state, config.clone());
+            let setup = client.request("SETUP", &url, &["Transport: RTP/AVP;unicast;client_port=5000-5001"]).await;
+            assert_eq!(status(&setup), "RTSP/1.0 200 OK", "{}", url);
+            let id = header(&setup, "Session").unwrap().split(';').next().unwrap().to_string();
+            assert_eq!(state.read().await.clients[&id].tracks, [VIDEO_TRACK], "{}", url);
+
+            let play = client.request("PLAY", base, &[&format!("Session: {}", id)]).await;
+            assert!(header(&play, "RTP-Info").unwrap().starts_with(&format!("url={}/{};", base, VIDEO_TRACK)), "{}", play);
+        }
+    }
+
+    #[tokio::test]
+    async fn kicked_session_closes_its_connection_promptly() {
+        let config = Arc::new(test_config());
+        let state = create_shared_state(config.mount_table());
+        let base = "rtsp://127.0.0.1:8554/cam";
+        let (mut client, handle) = spawn_session(&state, config);
+        let setup = client.request("SETUP", &format!("{}/{}", base, VIDEO_TRACK), &["Transport: RTP/AVP;unicast;client_port=5000-5001"]).await;
+        let id = header(&setup, "Session").unwrap().split(';').next().unwrap().to_string();
+        assert_eq!(status(&client.request("PLAY", base, &[&format!("Session: {}", id)]).await), "RTSP/1.0 200 OK");
+        assert!(!state.write().await.kick("deadbeef"));
+
+        assert!(state.write().await.kick(&id));
+        let result = tokio::time::timeout(Duration::from_secs(1), handle)
+            .await
+            .expect("kicked session kept serving");
+        assert!(result.unwrap().is_ok());
+
+        // Server đóng connection (EOF), session đã được log và dọn
+        let mut buffer = [0u8; 64];
+        assert_eq!(client.stream.read(&mut buffer).await.unwrap(), 0);
+        let state = state.read().await;
+        assert!(!state.clients.contains_key(&id));
+        assert_eq!(state.ended_reason(&id), None);
+    }
+
+    #[tokio::test]
+    async fn tcp_teardown_delivers_the_complete_final_access_unit() {
+        use crate::rtp::depacketize::H264Depacketizer;
+        use crate::rtp::impair::ImpairmentConfig;
+        use crate::rtp::packet::RtpPacket;
+
+        // reorder=1: mỗi packet bị giữ tới packet kế tiếp, nên packet cuối
+        // (marker) chỉ còn trong impairment stage khi stream dừng
+        let impairment = ImpairmentConfig { reorder: 1.0, ..ImpairmentConfig::default() };
+        let config = Arc::new(ServerConfig { impairment: Some(impairment), ..test_config() });
+        let state = create_shared_state(config.mount_table());
+        let (mut client, server) = tokio::io::duplex(64 * 1024);
+        let mut session = RtspSession::with_stream(server, "127.0.0.1".to_string(), state.clone(), config);
+        session
+            .respond("SETUP rtsp://127.0.0.1:8554/cam/track1 RTSP/1.0\r\nCSeq: 1\r\nTransport: RTP/AVP/TCP;unicast;interleaved=0-1\r\n\r\n")
+            .await
+            .unwrap();
+        let id = session.session_id.clone();
+
+        let mut idr = vec![0x65, 0x88, 0x84];
+        idr.resize(5000, 0xAB);
+        let mut packetizer = H264Packetizer::with_ssrc(0x5E55);
+        let packets = packetizer.packetize(&idr, true);
+        assert!(packets.len() > 2);
+        for packet in &packets {
+            session.send_interleaved_rtp(&packet.to_bytes(), 0).await.unwrap();
+        }
+        assert_eq!(session.tcp_packets.load(Ordering::Relaxed), packets.len() as u64 - 1);
+
+        // Kick giữa stream: frame cuối, BYE, rồi FIN
+        assert!(state.write().await.kick(&id));
+        session.end_tcp_stream(packetizer.ssrc(), 0, 1).await.unwrap();
+        session.close().await;
+
+        let mut received = Vec::new();
+        client.read_to_end(&mut received).await.unwrap();
+        let mut framer = RtspFramer::new();
+        framer.push(&received);
+        let mut frames = Vec::new();
+        while let Some(frame) = framer.next_frame() {
+            frames.push(frame);
+        }
+        assert!(matches!(&frames[0], Frame::Request(setup) if setup.starts_with("RTSP/1.0 200 OK")));
+
+        let rtp: Vec<RtpPacket> = frames
+            .iter()
+            .filter_map(|frame| match frame {
+                Frame::Interleaved { channel: 0, payload } => Some(RtpPacket::parse(payload).unwrap()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(rtp.len(), packets.len());
+        assert!(rtp.last().unwrap().header.marker);
+        let mut depacketizer = H264Depacketizer::new();
+        let nalus: Vec<Vec<u8>> = rtp.iter().flat_map(|p| depacketizer.push(p).unwrap()).collect();
+        assert_eq!(nalus, [idr]);
+
+        let Some(Frame::Interleaved { channel: 1, payload: bye }) = frames.last() else {
+            panic!("expected an RTCP BYE as the last frame");
         };
+        assert_eq!(bye[1], 203);
+    }
+
+    #[tokio::test]
+    async fn file_mounts_advertise_a_finite_range_and_live_feeds_now() {
+        let mut config = test_config();
+        config.mounts.push(("feed".to_string(), "rtsp://10.0.0.9/stream1".to_string()));
+        let (mut client, state) = start_session(config);
+        state.write().await.media_duration = Some(12.5);
+        let range = |describe: &str| describe.lines().find_map(|line| line.strip_prefix("a=range:")).map(str::to_string);
+
+        let cam = client.request("DESCRIBE", "rtsp://127.0.0.1:8554/cam", &[]).await;
+        assert_eq!(range(&cam).as_deref(), Some("npt=0-12.500"));
+        // File mount khác: không probe duration, nhưng vẫn bắt đầu từ 0
+        let lobby = client.request("DESCRIBE", "rtsp://127.0.0.1:8554/lobby", &[]).await;
+        assert_eq!(range(&lobby).as_deref(), Some("npt=0-"));
+        let feed = client.request("DESCRIBE", "rtsp://127.0.0.1:8554/feed", &[]).await;
+        assert_eq!(range(&feed).as_deref(), Some("npt=now-"));
+
+        // PLAY echo range của mount; Range không parse được là 457
+        let base = "rtsp://127.0.0.1:8554/cam";
+        let setup = client.request("SETUP", &format!("{}/{}", base, VIDEO_TRACK), &["Transport: RTP/AVP;unicast;client_port=5000-5001"]).await;
+        let session = format!("Session: {}", header(&setup, "Session").unwrap().split(';').next().unwrap());
+        let play = client.request("PLAY", base, &[&session]).await;
+        assert_eq!(header(&play, "Range"), Some("npt=0-12.500"));
+        for invalid in ["Range: npt=10-5", "Range: npt=0:61:00-", "Range: smpte=0:00:10-"] {
+            let play = client.request("PLAY", base, &[&session, invalid]).await;
+            assert_eq!(status(&play), "RTSP/1.0 457 Invalid Range", "{}", invalid);
+        }
+    }
 
-        self.state.write().await.add_client(client_info);
-
-        format!(
-            "RTSP/1.0 200 OK\r\n\
-             CSeq: {}\r\n\
-             Session: {}\r\n\
-             Transport: {}\r\n\
-             \r\n",
-            self.cseq,
-            self.session_id,
-            transport_response
-        )
-    }
-
-    async fn handle_play(&mut self) -> String {
-        self.state.write().await.set_playing(&self.session_id, true);
-
-        format!(
-            "RTSP/1.0 200 OK\r\n\
-             CSeq: {}\r\n\
-             Session: {}\r\n\
-             RTP-Info: url=rtsp://127.0.0.1:8554/cam/track1;seq=0;rtptime=0\r\n\
-             \r\n",
-            self.cseq,
-            self.session_id
-        )
-    }
-
-    async fn handle_teardown(&self) -> String {
-        self.state.write().await.remove_client(&self.session_id);
-
-        format!(
-            "RTSP/1.0 200 OK\r\n\
-             CSeq: {}\r\n\
-             Session: {}\r\n\
-             \r\n",
-            self.cseq,
-            self.session_id
-        )
-    }
-
-    fn error_response(&self, code: u16, reason: &str) -> String {
-        format!(
-            "RTSP/1.0 {} {}\r\n\
-             CSeq: {}\r\n\
-             \r\n",
-            code, reason, self.cseq
-        )
+    #[tokio::test]
+    async fn play_rejects_clock_ranges_outside_the_media() {
+        let config = ServerConfig {
+            media_epoch: std::time::UNIX_EPOCH + Duration::from_secs(1_704_110_400), // 20240101T120000Z
+            ..test_config()
+        };
+        let (mut client, state) = start_session(config);
+        state.write().await.media_duration = Some(12.5);
+        let base = "rtsp://127.0.0.1:8554/cam";
+        let setup = client.request("SETUP", &format!("{}/{}", base, VIDEO_TRACK), &["Transport: RTP/AVP;unicast;client_port=5000-5001"]).await;
+        let session = format!("Session: {}", header(&setup, "Session").unwrap().split(';').next().unwrap());
+
+        for outside in ["Range: clock=20240101T115959Z-", "Range: clock=20240101T120013Z-", "Range: clock=20240101T120000Z-20240101T120020Z", "Range: clock=20241301T120000Z-"] {
+            let play = client.request("PLAY", base, &[&session, outside]).await;
+            assert_eq!(status(&play), "RTSP/1.0 457 Invalid Range", "{}", outside);
+        }
+        // UDP dùng chung pipeline: range hợp lệ được chấp nhận, phát live point
+        let play = client.request("PLAY", base, &[&session, "Range: clock=20240101T120010.5Z-"]).await;
+        assert_eq!(status(&play), "RTSP/1.0 200 OK");
+        assert_eq!(header(&play, "Range"), Some("npt=0-12.500"));
+    }
+
+    #[tokio::test]
+    async fn describe_is_refused_until_an_sps_less_stream_has_parameter_sets() {
+        let config = ServerConfig { require_parameter_sets: true, parameter_sets: None, ..test_config() };
+        let (mut client, state) = start_session(config);
+        let base = "rtsp://127.0.0.1:8554/cam";
+
+        // Stream chỉ có slices: SDP thiếu sprop sẽ không decode được
+        let describe = client.request("DESCRIBE", base, &[]).await;
+        assert_eq!(status(&describe), "RTSP/1.0 503 Service Unavailable");
+        assert!(!state.read().await.parameter_sets_ready());
+
+        // --sprop synthesize (hoặc SPS/PPS đến muộn): DESCRIBE phục vụ lại
+        {
+            let mut state = state.write().await;
+            state.cache_parameter_set(&[0x67, 0x42, 0x00, 0x1F, 0xAB, 0x40, 0x50, 0x1E, 0xC8]);
+            state.cache_parameter_set(&[0x68, 0xCE, 0x3C, 0x80]);
+        }
+        let describe = client.request("DESCRIBE", base, &[]).await;
+        assert_eq!(status(&describe), "RTSP/1.0 200 OK");
+        assert!(describe.contains("sprop-parameter-sets=Z0IAH6tAUB7I,aM48gA=="), "{}", describe);
+    }
+
+    /// Một AU (3 FU-A packets) qua shared UDP pipeline, rồi publish vị trí
+    /// cho RTP-Info như video loop
+    async fn stream_au(packetizer: &mut H264Packetizer, state: &SharedState, sender: &crate::rtp::udp::UdpSender) {
+        let packets = packetizer.packetize(&[0x65; 3000], true);
+        sender.send(&packets, &state.read().await.get_udp_clients()).await;
+        packetizer.end_access_unit();
+        let position = packetizer.snapshot();
+        state.write().await.track_positions.insert(VIDEO_TRACK.to_string(), (position.sequence, position.timestamp));
+    }
+
+    #[tokio::test]
+    async fn rtp_info_matches_the_first_packet_of_a_client_joining_mid_stream() {
+        let config = Arc::new(test_config());
+        let state = create_shared_state(config.mount_table());
+        let sender = crate::rtp::udp::UdpSender::new(Arc::new(tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap()), None, None, None);
+        let mut packetizer = H264Packetizer::new();
+        let start = packetizer.snapshot();
+        state.write().await.track_positions.insert(VIDEO_TRACK.to_string(), (start.sequence, start.timestamp));
+
+        // (seq, rtptime) của RTP-Info trong PLAY, và của packet đầu tiên client nhận
+        async fn play(state: &SharedState, config: Arc<ServerConfig>, receiver: &tokio::net::UdpSocket) -> (TestClient, (u16, u32)) {
+            let port = receiver.local_addr().unwrap().port();
+            let mut client = connect(state, config);
+            let transport = format!("Transport: RTP/AVP;unicast;client_port={}-{}", port, port + 1);
+            let setup = client.request("SETUP", "rtsp://127.0.0.1:8554/cam/track1", &[&transport]).await;
+            let session = format!("Session: {}", header(&setup, "Session").unwrap().split(';').next().unwrap());
+            let play = client.request("PLAY", "rtsp://127.0.0.1:8554/cam", &[&session]).await;
+            let info = header(&play, "RTP-Info").unwrap();
+            let value = |name: &str| info.split(';').find_map(|p| p.strip_prefix(name)).unwrap().to_string();
+            let position = (value("seq=").parse().unwrap(), value("rtptime=").parse().unwrap());
+            (client, position)
+        }
+        async fn first_packet(receiver: &tokio::net::UdpSocket) -> (u16, u32) {
+            let mut buf = [0u8; 1500];
+            tokio::time::timeout(Duration::from_secs(2), receiver.recv(&mut buf)).await.unwrap().unwrap();
+            (u16::from_be_bytes([buf[2], buf[3]]), u32::from_be_bytes([buf[4], buf[5], buf[6], buf[7]]))
+        }
+
+        let first = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let (_first_client, first_info) = play(&state, config.clone(), &first).await;
+        for _ in 0..5 {
+            stream_au(&mut packetizer, &state, &sender).await;
+        }
+        assert_eq!(first_packet(&first).await, first_info);
+
+        // Client thứ hai vào giữa stream (session IDs theo millisecond)
+        tokio::time::sleep(Duration::from_millis(2)).await;
+        let second = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let (_second_client, second_info) = play(&state, config, &second).await;
+        stream_au(&mut packetizer, &state, &sender).await;
+        assert_eq!(first_packet(&second).await, second_info);
+        assert_eq!(state.read().await.get_udp_clients().len(), 2);
+    }
+
+    #[test]
+    fn transport_parsing_accepts_udp_and_tcp() {
+        let parse = RtspSession::<DuplexStream>::parse_transport;
+        assert_eq!(
+            parse("RTP/AVP;unicast;client_port=5000-5001", false),
+            Ok(TransportRequest::Udp { rtp_port: 5000, rtcp_port: 5001, rtcp_mux: false })
+        );
+        assert_eq!(
+            parse(" RTP/AVP/UDP;unicast;client_port=5000", false),
+            Ok(TransportRequest::Udp { rtp_port: 5000, rtcp_port: 5001, rtcp_mux: false })
+        );
+        // rtcp-mux chỉ khi server bật
+        assert_eq!(
+            parse("RTP/AVP;unicast;client_port=5000-5001;rtcp-mux", true),
+            Ok(TransportRequest::Udp { rtp_port: 5000, rtcp_port: 5001, rtcp_mux: true })
+        );
+        assert_eq!(
+            parse("RTP/AVP;unicast;client_port=5000-5001;rtcp-mux", false),
+            Ok(TransportRequest::Udp { rtp_port: 5000, rtcp_port: 5001, rtcp_mux: false })
+        );
+        assert_eq!(
+            parse("RTP/AVP/TCP;unicast;interleaved=2-3", false),
+            Ok(TransportRequest::Tcp { rtp_channel: 2, rtcp_channel: 3 })
+        );
+        assert_eq!(parse("RTP/AVP;multicast", false), Ok(TransportRequest::Multicast));
+        assert_eq!(parse("RTP/AVP;multicast;client_port=5000-5001", false), Ok(TransportRequest::Multicast));
+        assert_eq!(parse("RTP/AVP;unicast;client_port=5000-5001;mode=play", false).map(|_| ()), Ok(()));
+    }
+
+    #[test]
+    fn transport_parsing_rejects_with_461() {
+        let parse = RtspSession::<DuplexStream>::parse_transport;
+        for transport in [
+            "RTP/AVP;unicast",                               // không có client_port
+            "RTP/AVP;unicast;client_port=abc",               // không parse được
+            "RTP/AVP;unicast;client_port=0-1",               // port 0
+            "RTP/AVP;unicast;client_port=65535",             // x + 1 tràn
+            "RTP/AVP;multicast;client_port=x-y",             // multicast với client_port sai
+            "RTP/AVP/TCP;unicast",                           // không có interleaved
+            "RTP/AVP/TCP;unicast;interleaved=300-301",       // channel > u8
+            "RTP/AVP/TCP;unicast;interleaved=a-b",
+            "RTP/AVP;unicast;client_port=5000-5001;mode=\"record\";append",
+        ] {
+            assert_eq!(parse(transport, false), Err(RtspError::UnsupportedTransport), "{}", transport);
+        }
+        // RECORD không có append: 501 thay vì 461
+        assert_eq!(parse("RTP/AVP;unicast;client_port=5000-5001;mode=record", false), Err(RtspError::NotImplemented));
     }
 }
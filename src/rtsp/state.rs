@@ -1,7 +1,19 @@
+use super::range::NptRange;
+use super::sdp::{npt_range, MediaFormat, AUDIO_TRACK, VIDEO_TRACK};
+use crate::rtcp::bye::Goodbye;
+use crate::rtcp::liveness::RtcpLiveness;
+use crate::rtcp::rr::{ReceiverReport, ReceptionReport};
+use crate::rtp::ports::{PortAllocator, PortLease};
+use crate::rtp::stamp::RtpIdentity;
+use crate::rtp::udp::{QueueStats, UdpDestination};
+use crate::source::feed::FeedRegistry;
+use crate::source::rtsp_pull::RtspPullSource;
 use std::collections::HashMap;
-use std::net::SocketAddr;
+use std::net::{Ipv4Addr, SocketAddr};
 use std::sync::Arc;
-use tokio::sync::RwLock;
+use std::time::{Duration, Instant};
+use tokio::net::UdpSocket;
+use tokio::sync::{Notify, RwLock};
 
 /// Transport mode for RTP
 #[derive(Clone, Debug, PartialEq)]
@@ -9,34 +21,277 @@ pub enum TransportMode {
     Udp {
         rtp_addr: SocketAddr,
         rtcp_addr: SocketAddr,
+        /// RTCP được gửi/nhận trên cùng port với RTP (rtcp_addr == rtp_addr)
+        rtcp_mux: bool,
     },
     TcpInterleaved {
         rtp_channel: u8,
         rtcp_channel: u8,
     },
+    /// Nhận shared UDP stream qua multicast group (RTCP ở `port + 1`)
+    Multicast {
+        group: Ipv4Addr,
+        port: u16,
+        ttl: u8,
+    },
+}
+
+impl TransportMode {
+    /// (RTP, RTCP) destination của multicast group
+    pub fn multicast_addrs(&self) -> Option<(SocketAddr, SocketAddr)> {
+        match *self {
+            TransportMode::Multicast { group, port, .. } => {
+                Some((SocketAddr::from((group, port)), SocketAddr::from((group, port + 1))))
+            }
+            _ => None,
+        }
+    }
+
+    /// Nơi shared UDP pipeline gửi RTP cho client này (None với TCP)
+    pub fn udp_destination(&self) -> Option<SocketAddr> {
+        match *self {
+            TransportMode::Udp { rtp_addr, .. } => Some(rtp_addr),
+            TransportMode::Multicast { .. } => self.multicast_addrs().map(|(rtp_addr, _)| rtp_addr),
+            TransportMode::TcpInterleaved { .. } => None,
+        }
+    }
+}
+
+/// Why a session ended, for the access log
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EndReason {
+    /// TEARDOWN, hoặc client đóng RTSP connection
+    Normal,
+    /// No RTSP keepalive / RTCP RR within the session timeout
+    Timeout,
+    /// Connection error, hoặc client bị loại vì outbound queue overflow /
+    /// RRs liên tiếp báo mất toàn bộ packets
+    Error,
+    /// Playing longer than `max_session_duration`
+    MaxDuration,
+    /// Terminated by an operator through the status endpoint
+    Kicked,
+}
+
+impl EndReason {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Normal => "normal",
+            Self::Timeout => "timeout",
+            Self::Error => "error",
+            Self::MaxDuration => "max-duration",
+            Self::Kicked => "kicked",
+        }
+    }
+
+    /// Server-initiated ends that also close the client's RTSP connection
+    pub fn closes_connection(self) -> bool {
+        matches!(self, Self::MaxDuration | Self::Kicked)
+    }
+
+    /// Ends after which the client gets an RTCP BYE: TEARDOWN and
+    /// server-initiated ends (không gửi cho client đã timeout hoặc lỗi)
+    pub fn sends_bye(self) -> bool {
+        matches!(self, Self::Normal | Self::MaxDuration | Self::Kicked)
+    }
+}
+
+/// Transport + RTP identity riêng của một track phụ (audio) trong session
+#[derive(Clone, Debug)]
+pub struct TrackTransport {
+    pub transport: TransportMode,
+    pub rtp: RtpIdentity,
 }
 
 /// Client info sau khi SETUP
 #[derive(Clone, Debug)]
 pub struct ClientInfo {
     pub id: String,
+    /// Transport của video track (`track1`)
     pub transport: TransportMode,
+    /// Transport của audio track (`track2`) khi đã SETUP
+    pub audio: Option<TrackTransport>,
     pub is_playing: bool,
+    /// Max RTP packet size requested via `Blocksize` in SETUP
+    pub blocksize: Option<usize>,
+    /// Track controls (`track1`, ...) đã SETUP trong session này
+    pub tracks: Vec<String>,
+    /// RTSP keepalive + RTCP RR liveness, và SR timing của client
+    pub liveness: RtcpLiveness,
+    /// Peer IP + mount, cho access log
+    pub client_ip: String,
+    pub mount: String,
+    /// First SETUP of the session
+    pub started: Instant,
+    /// RTP packets/bytes sent to the client so far (UDP: refreshed by the SR loop)
+    pub packets_sent: u64,
+    pub bytes_sent: u64,
+    /// Signals the session's connection task to close (server-side teardown)
+    pub abort: Arc<Notify>,
+    /// SSRC + seq/timestamp offsets của UDP client trên shared stream
+    pub rtp: RtpIdentity,
+    /// Latest reception report about our stream (loss, jitter), from the
+    /// client's RTCP RR; input cho adaptation sau này
+    pub reception: Option<ReceptionReport>,
+    /// RRs liên tiếp báo fraction lost 255/256 (không nhận được gì)
+    pub lossy_reports: u32,
+    /// Fraction lost tệ nhất trong các RRs chưa được bitrate controller đọc
+    pub unread_loss: Option<u8>,
+    /// Port pair + sockets của UDP unicast session từ `--session-ports`;
+    /// `remove_client` nhả lease (sockets đóng, pair trả về pool)
+    pub port_lease: Option<Arc<PortLease>>,
+}
+
+/// Snapshot cho monitoring (`ServerState::stats`, `GET /stats`)
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ServerStats {
+    /// Sessions đã SETUP (chưa teardown)
+    pub clients: usize,
+    pub playing: usize,
+    /// Sessions theo transport của video track
+    pub udp: usize,
+    pub tcp: usize,
+    pub multicast: usize,
+    /// RTP packets/bytes (header included) gửi từ lúc server start: sessions
+    /// đang chạy cộng sessions đã kết thúc
+    pub packets_sent: u64,
+    pub bytes_sent: u64,
+    /// FFmpeg restarts của UDP pipeline: tự thoát (`restarts`) và do watchdog (`stalls`)
+    pub restarts: u64,
+    pub stalls: u64,
+    /// Video bitrate target của bitrate adaptation (kbps), None khi tắt
+    pub bitrate_kbps: Option<u32>,
 }
 
 /// Shared state giữa RTSP sessions và streaming task
 #[derive(Default)]
 pub struct ServerState {
     pub clients: HashMap<String, ClientInfo>,
+    /// SPS/PPS mới nhất thấy trong stream, cho SDP (DESCRIBE) và status endpoint
+    pub sps: Option<Vec<u8>>,
+    pub pps: Option<Vec<u8>>,
+    /// Độ dài video file (ffprobe), cho a=range và PLAY Range
+    pub media_duration: Option<f64>,
+    /// Số lần watchdog phát hiện source pipeline bị stall và restart
+    pub stalls: u64,
+    /// Số lần FFmpeg của UDP pipeline tự thoát và được respawn
+    pub restarts: u64,
+    /// Current target của bitrate controller (kbps), None khi tắt
+    pub target_bitrate: Option<u32>,
+    /// Access units dropped by the frame-drop policy
+    pub frames_dropped: u64,
+    /// Filler NAL keepalives sent while the UDP stream was idle
+    pub keepalives_sent: u64,
+    /// Latest per-client UDP outbound queue metrics (refreshed by the SR loop)
+    pub udp_queues: Vec<QueueStats>,
+    /// Formats negotiated from the latest ANNOUNCE, per mount
+    pub announced: HashMap<String, Vec<MediaFormat>>,
+    /// Sessions already removed (teardown/timeout/eviction) whose RTSP
+    /// connection is still open; the connection's cleanup logs them
+    pub ended: HashMap<String, Vec<(ClientInfo, EndReason)>>,
+    /// RTP (packets, bytes) của các sessions đã kết thúc, cho `stats`
+    pub ended_sent: (u64, u64),
+    /// Next (sequence, timestamp) of the shared UDP stream, per track control
+    pub track_positions: HashMap<String, (u16, u32)>,
+    /// RTCP BYEs owed to ended UDP clients (`EndReason::sends_bye`), sent by the SR loop
+    /// from the shared sockets: (RTCP destination, rtcp-mux, client SSRC, reason).
+    /// Sessions with their own ports send BYE from them in `remove_client`
+    pub pending_byes: Vec<(SocketAddr, bool, u32, EndReason)>,
+    /// Mount registry: mount name → file được phát
+    pub mounts: HashMap<String, String>,
+    /// FFmpeg dùng chung của TCP sessions, theo mount
+    pub feeds: Arc<FeedRegistry>,
+    /// RTP identity của multicast group khi có member: mọi member nhận cùng
+    /// một stream nên dùng chung SSRC/offsets
+    pub multicast: Option<RtpIdentity>,
+    /// Pool của `--session-ports` (None: UDP sessions không lease port)
+    pub port_allocator: Option<Arc<PortAllocator>>,
 }
 
 impl ServerState {
     pub fn new() -> Self {
         Self {
             clients: HashMap::new(),
+            sps: None,
+            pps: None,
+            media_duration: None,
+            stalls: 0,
+            restarts: 0,
+            frames_dropped: 0,
+            keepalives_sent: 0,
+            udp_queues: Vec::new(),
+            announced: HashMap::new(),
+            ended: HashMap::new(),
+            ended_sent: (0, 0),
+            target_bitrate: None,
+            track_positions: HashMap::new(),
+            pending_byes: Vec::new(),
+            mounts: HashMap::new(),
+            feeds: Arc::default(),
+            multicast: None,
+            port_allocator: None,
+        }
+    }
+
+    /// Media duration của `mount`: chỉ file của default mount (shared
+    /// pipeline) được probe, các mount khác không quảng bá duration
+    pub fn mount_duration(&self, mount: &str, default_mount: &str) -> Option<f64> {
+        self.media_duration.filter(|_| mount == default_mount)
+    }
+
+    /// `a=range` (và `Range` mặc định của PLAY) cho `mount`: upstream RTSP
+    /// feed là live nên `npt=now-`, file là `npt=0-[<duration>]`
+    pub fn mount_range(&self, mount: &str, default_mount: &str) -> String {
+        match self.mounts.get(mount) {
+            Some(location) if RtspPullSource::is_url(location) => NptRange::Now.to_header(),
+            _ => npt_range(self.mount_duration(mount, default_mount)),
         }
     }
 
+    /// Counters hiện tại. Totals của UDP clients được SR loop refresh, của
+    /// TCP sessions mỗi SR interval; sessions kết thúc cộng số cuối cùng
+    pub fn stats(&self) -> ServerStats {
+        let mut stats = ServerStats {
+            clients: self.clients.len(),
+            packets_sent: self.ended_sent.0,
+            bytes_sent: self.ended_sent.1,
+            restarts: self.restarts,
+            stalls: self.stalls,
+            bitrate_kbps: self.target_bitrate,
+            ..ServerStats::default()
+        };
+        for client in self.clients.values() {
+            stats.playing += usize::from(client.is_playing);
+            match client.transport {
+                TransportMode::Udp { .. } => stats.udp += 1,
+                TransportMode::TcpInterleaved { .. } => stats.tcp += 1,
+                TransportMode::Multicast { .. } => stats.multicast += 1,
+            }
+            stats.packets_sent += client.packets_sent;
+            stats.bytes_sent += client.bytes_sent;
+        }
+        stats
+    }
+
+    /// Remember the latest SPS (type 7) / PPS (type 8) seen in the stream
+    pub fn cache_parameter_set(&mut self, nalu: &[u8]) {
+        match nalu.first().map(|b| b & 0x1F) {
+            Some(7) => self.sps = Some(nalu.to_vec()),
+            Some(8) => self.pps = Some(nalu.to_vec()),
+            _ => {}
+        }
+    }
+
+    /// The stream has carried both an SPS and a PPS (or they were synthesized)
+    pub fn parameter_sets_ready(&self) -> bool {
+        self.sps.is_some() && self.pps.is_some()
+    }
+
+    /// (SPS, PPS) của shared stream, khi đã thấy cả hai
+    pub fn parameter_sets(&self) -> Option<(&[u8], &[u8])> {
+        Some((self.sps.as_deref()?, self.pps.as_deref()?))
+    }
+
     pub fn add_client(&mut self, info: ClientInfo) {
         println!("📝 Registered client: {} -> {:?}", info.id, info.transport);
         self.clients.insert(info.id.clone(), info);
@@ -49,36 +304,379 @@ impl ServerState {
         }
     }
 
-    pub fn remove_client(&mut self, session_id: &str) {
-        self.clients.remove(session_id);
+    /// Tear down one track of a session. The session itself is removed once
+    /// its last track is gone; returns the tracks still set up
+    pub fn remove_track(&mut self, session_id: &str, track: &str) -> Vec<String> {
+        let Some(client) = self.clients.get_mut(session_id) else {
+            return Vec::new();
+        };
+        client.tracks.retain(|t| t != track);
+        if track == AUDIO_TRACK {
+            client.audio = None;
+        }
+        println!("🗑️  Removed track {} from client {}", track, session_id);
+
+        let remaining = client.tracks.clone();
+        if remaining.is_empty() {
+            self.remove_client(session_id, EndReason::Normal);
+        }
+        remaining
+    }
+
+    /// RTSP request on a session: counts as keepalive
+    pub fn touch(&mut self, session_id: &str) {
+        if let Some(client) = self.clients.get_mut(session_id) {
+            client.liveness.touch(Instant::now());
+        }
+    }
+
+    /// Record an RR from the UDP client whose RTCP comes from `rtcp_from`.
+    /// Returns that client's session id and how many consecutive RRs (this
+    /// one included) reported total loss
+    pub fn record_receiver_report(&mut self, rtcp_from: SocketAddr, rr: &ReceiverReport, rtt: Option<Duration>) -> Option<(String, u32)> {
+        let client = self.clients.values_mut().find(|c| {
+            matches!(c.transport, TransportMode::Udp { rtcp_addr, .. } if rtcp_addr == rtcp_from)
+        })?;
+        client.liveness.on_receiver_report(rtt, Instant::now());
+        // Block về SSRC của client (shared stream); client có thể report cả
+        // sources khác
+        let ssrc = client.rtp.ssrc;
+        if let Some(report) = rr.reports.iter().find(|report| report.ssrc == ssrc) {
+            client.lossy_reports = if report.fraction_lost == u8::MAX { client.lossy_reports + 1 } else { 0 };
+            client.unread_loss = client.unread_loss.max(Some(report.fraction_lost));
+            client.reception = Some(report.clone());
+        }
+        Some((client.id.clone(), client.lossy_reports))
+    }
+
+    /// Worst fraction lost reported by playing UDP clients since the last
+    /// call (None: no new RR), cho bitrate controller. Session đã chuyển sang
+    /// TCP không được tính: loss cũ của nó không nói gì về shared UDP stream
+    pub fn take_udp_loss(&mut self) -> Option<u8> {
+        self.clients
+            .values_mut()
+            .filter(|c| c.is_playing && c.transport.udp_destination().is_some())
+            .filter_map(|c| c.unread_loss.take())
+            .max()
+    }
+
+    /// Remove the UDP session streaming to `rtp_addr` (evicted by the sender)
+    pub fn remove_udp_client(&mut self, rtp_addr: SocketAddr) {
+        let id = self.clients.values().find_map(|c| match c.transport {
+            TransportMode::Udp { rtp_addr: addr, .. } if addr == rtp_addr => Some(c.id.clone()),
+            _ => None,
+        });
+        if let Some(id) = id {
+            self.remove_client(&id, EndReason::Error);
+        }
+    }
+
+    /// Refresh per-client UDP queue metrics and sent totals
+    pub fn record_udp_output(&mut self, queues: &[QueueStats]) {
+        for client in self.clients.values_mut() {
+            let Some(rtp_addr) = client.transport.udp_destination() else {
+                continue;
+            };
+            if let Some(queue) = queues.iter().find(|q| q.rtp_addr == rtp_addr) {
+                client.packets_sent = queue.packets_sent;
+                client.bytes_sent = queue.bytes_sent;
+            }
+        }
+        self.udp_queues = queues.to_vec();
+    }
+
+    pub fn remove_client(&mut self, session_id: &str, reason: EndReason) {
+        if let Some(mut client) = self.clients.remove(session_id) {
+            if reason.closes_connection() {
+                client.abort.notify_one();
+            }
+            // Bản trong `ended` không giữ lease: sockets của session đóng ngay
+            let lease = client.port_lease.take();
+            if let TransportMode::Udp { rtcp_addr, rtcp_mux, .. } = client.transport {
+                if reason.sends_bye() {
+                    match &lease {
+                        Some(lease) => {
+                            let bye = Goodbye::new(client.rtp.ssrc, Some(reason.as_str()));
+                            match lease.rtcp_socket(rtcp_mux).try_send_to(&bye.to_bytes(), rtcp_addr) {
+                                Ok(_) => println!("👋 RTCP BYE sent to {} - SSRC: {:08x} ({})",
+                                                  rtcp_addr, client.rtp.ssrc, reason.as_str()),
+                                Err(e) => eprintln!("⚠️  RTCP BYE send error to {}: {}", rtcp_addr, e),
+                            }
+                        }
+                        None => self.pending_byes.push((rtcp_addr, rtcp_mux, client.rtp.ssrc, reason)),
+                    }
+                }
+            }
+            drop(lease);
+            // Member cuối rời group: BYE cho group, lần join sau là stream mới
+            if let Some((_, rtcp_addr)) = client.transport.multicast_addrs() {
+                let members = self.clients.values().any(|c| c.transport.multicast_addrs().is_some());
+                if !members {
+                    if reason.sends_bye() {
+                        self.pending_byes.push((rtcp_addr, false, client.rtp.ssrc, reason));
+                    }
+                    self.multicast = None;
+                }
+            }
+            self.ended.entry(session_id.to_string()).or_default().push((client, reason));
+        }
         println!("🗑️  Removed client: {}", session_id);
     }
 
-    pub fn get_playing_clients(&self) -> Vec<ClientInfo> {
+    /// Operator kick: tear the session down, send BYE, close its connection.
+    /// False if no such session is active
+    pub fn kick(&mut self, session_id: &str) -> bool {
+        if !self.clients.contains_key(session_id) {
+            return false;
+        }
+        println!("👢 Session {} kicked by operator", session_id);
+        self.remove_client(session_id, EndReason::Kicked);
+        true
+    }
+
+    /// Why `session_id` was ended server-side while its connection is still
+    /// open (None if it is active or ended by the client)
+    pub fn ended_reason(&self, session_id: &str) -> Option<EndReason> {
+        self.ended.get(session_id)?.last().map(|(_, reason)| *reason)
+    }
+
+    /// Connection of `session_id` closed: drop the session if still active
+    /// (with `reason`) and return every ended session to log
+    pub fn finish_session(&mut self, session_id: &str, reason: EndReason) -> Vec<(ClientInfo, EndReason)> {
+        if self.clients.contains_key(session_id) {
+            self.remove_client(session_id, reason);
+        }
+        self.ended.remove(session_id).unwrap_or_default()
+    }
+
+    /// Totals cuối cùng của sessions đã kết thúc vào lifetime counters của `stats`
+    pub fn record_ended(&mut self, ended: &[(ClientInfo, EndReason)]) {
+        for (client, _) in ended {
+            self.ended_sent.0 += client.packets_sent;
+            self.ended_sent.1 += client.bytes_sent;
+        }
+    }
+
+    /// RTP address + RTP identity of every playing UDP client; multicast
+    /// members count once, as their group
+    pub fn get_udp_clients(&self) -> Vec<UdpDestination> {
+        let mut destinations: Vec<UdpDestination> = Vec::new();
+        let video = |c: &&ClientInfo| c.is_playing && c.tracks.iter().any(|t| t == VIDEO_TRACK);
+        for c in self.clients.values().filter(video) {
+            match c.transport.udp_destination() {
+                Some(rtp_addr) if !destinations.iter().any(|dest| dest.rtp_addr == rtp_addr) => {
+                    let socket = c.port_lease.as_ref().map(|lease| lease.rtp.clone());
+                    destinations.push(UdpDestination { rtp_addr, identity: c.rtp, socket });
+                }
+                _ => {}
+            }
+        }
+        destinations
+    }
+
+    /// RTP address + audio RTP identity of every playing client with the
+    /// audio track set up (audio chỉ đi qua UDP unicast)
+    pub fn get_audio_clients(&self) -> Vec<UdpDestination> {
         self.clients
             .values()
             .filter(|c| c.is_playing)
-            .cloned()
+            .filter_map(|c| c.audio.as_ref())
+            .filter_map(|audio| Some(UdpDestination::new(audio.transport.udp_destination()?, audio.rtp)))
             .collect()
     }
 
-    pub fn get_udp_clients(&self) -> Vec<(SocketAddr, SocketAddr)> {
+    /// Smallest `Blocksize` among UDP playing clients.
+    ///
+    /// UDP clients share one packetizer (one encode, one packetization, fanned
+    /// out to every address), so rather than repacketizing per client the
+    /// broadcast honors the most restrictive request. TCP interleaved sessions
+    /// packetize on their own and apply their own `Blocksize`.
+    pub fn get_udp_blocksize(&self) -> Option<usize> {
+        self.clients
+            .values()
+            .filter(|c| c.is_playing && !matches!(c.transport, TransportMode::TcpInterleaved { .. }))
+            .filter_map(|c| c.blocksize)
+            .min()
+    }
+
+    /// RTP address of the UDP playing client whose RTCP comes from `rtcp_from`
+    pub fn find_udp_rtp_addr(&self, rtcp_from: SocketAddr) -> Option<SocketAddr> {
         self.clients
             .values()
             .filter(|c| c.is_playing)
-            .filter_map(|c| {
-                if let TransportMode::Udp { rtp_addr, rtcp_addr } = &c.transport {
-                    Some((*rtp_addr, *rtcp_addr))
-                } else {
-                    None
-                }
+            .find_map(|c| match &c.transport {
+                TransportMode::Udp { rtp_addr, rtcp_addr, .. } if *rtcp_addr == rtcp_from => Some(*rtp_addr),
+                _ => None,
             })
-            .collect()
+    }
+
+    /// Tear down playing sessions older than `max` (since their first SETUP)
+    /// and signal their connections to close
+    pub fn expire_sessions(&mut self, now: Instant, max: Duration) {
+        let expired: Vec<String> = self
+            .clients
+            .values()
+            .filter(|c| c.is_playing && now.saturating_duration_since(c.started) > max)
+            .map(|c| c.id.clone())
+            .collect();
+        for id in expired {
+            println!("⌛ Session {} reached max session duration {:?}", id, max);
+            self.remove_client(&id, EndReason::MaxDuration);
+        }
+    }
+
+    /// Remove UDP clients silent (no RTSP request, no RTCP RR) for longer
+    /// than `timeout` and return their ids. Multicast members chỉ có RTSP
+    /// keepalive (RR của họ gửi tới group). TCP sessions end with their
+    /// connection instead
+    pub fn reap_stale(&mut self, now: Instant, timeout: Duration) -> Vec<String> {
+        let stale: Vec<String> = self
+            .clients
+            .values()
+            .filter(|c| !matches!(c.transport, TransportMode::TcpInterleaved { .. }) && c.liveness.is_dead(now, timeout))
+            .map(|c| c.id.clone())
+            .collect();
+        for id in &stale {
+            self.remove_client(id, EndReason::Timeout);
+        }
+        stale
+    }
+
+    /// The (RTP, RTCP) destinations of playing UDP clients whose SR is due,
+    /// with whether RTCP is muxed onto the RTP port and the session's own
+    /// socket to send it from (`--session-ports`, mux đã tính). Multicast
+    /// group nhận một SR cho cả group
+    pub fn take_sr_targets(&mut self, now: Instant) -> Vec<SrTarget> {
+        let mut targets: Vec<SrTarget> = Vec::new();
+        for c in self.clients.values_mut().filter(|c| c.is_playing) {
+            let target = match c.transport {
+                TransportMode::Udp { rtp_addr, rtcp_addr, rtcp_mux } => {
+                    let socket = c.port_lease.as_ref().map(|lease| lease.rtcp_socket(rtcp_mux).clone());
+                    (rtp_addr, rtcp_addr, rtcp_mux, socket)
+                }
+                TransportMode::Multicast { .. } => match c.transport.multicast_addrs() {
+                    Some((rtp_addr, rtcp_addr)) => (rtp_addr, rtcp_addr, false, None),
+                    None => continue,
+                },
+                TransportMode::TcpInterleaved { .. } => continue,
+            };
+            if !targets.iter().any(|(rtp_addr, ..)| *rtp_addr == target.0) && c.liveness.take_sr_due(now) {
+                targets.push(target);
+            }
+        }
+        targets
     }
 }
 
+/// SR destination: (RTP address, RTCP address, rtcp-mux, session socket)
+pub type SrTarget = (SocketAddr, SocketAddr, bool, Option<Arc<UdpSocket>>);
+
 pub type SharedState = Arc<RwLock<ServerState>>;
 
-pub fn create_shared_state() -> SharedState {
-    Arc::new(RwLock::new(ServerState::new()))
+pub fn create_shared_state(mounts: HashMap<String, String>) -> SharedState {
+    let mut state = ServerState::new();
+    state.mounts = mounts;
+    Arc::new(RwLock::new(state))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn client(id: &str, transport: TransportMode, unread_loss: Option<u8>) -> ClientInfo {
+        ClientInfo {
+            id: id.to_string(),
+            transport,
+            audio: None,
+            is_playing: true,
+            blocksize: None,
+            tracks: vec![VIDEO_TRACK.to_string()],
+            liveness: RtcpLiveness::new(Instant::now()),
+            client_ip: "127.0.0.1".to_string(),
+            mount: "cam".to_string(),
+            started: Instant::now(),
+            packets_sent: 0,
+            bytes_sent: 0,
+            abort: Arc::new(Notify::new()),
+            rtp: RtpIdentity::random(),
+            reception: None,
+            lossy_reports: 0,
+            unread_loss,
+            port_lease: None,
+        }
+    }
+
+    #[test]
+    fn bitrate_loss_only_counts_udp_viewers() {
+        let mut state = ServerState::new();
+        let udp = TransportMode::Udp {
+            rtp_addr: "127.0.0.1:5000".parse().unwrap(),
+            rtcp_addr: "127.0.0.1:5001".parse().unwrap(),
+            rtcp_mux: false,
+        };
+        state.add_client(client("udp", udp, Some(20)));
+        // Loss cũ từ lúc session còn UDP, giờ đã SETUP lại qua TCP
+        state.add_client(client("tcp", TransportMode::TcpInterleaved { rtp_channel: 0, rtcp_channel: 1 }, Some(200)));
+
+        assert_eq!(state.take_udp_loss(), Some(20));
+        assert_eq!(state.take_udp_loss(), None);
+        assert_eq!(state.clients["tcp"].unread_loss, Some(200));
+    }
+
+    #[test]
+    fn stats_count_sessions_and_keep_totals_of_ended_ones() {
+        let mut state = ServerState::new();
+        let rtp_addr: SocketAddr = "127.0.0.1:5000".parse().unwrap();
+        let udp = TransportMode::Udp { rtp_addr, rtcp_addr: "127.0.0.1:5001".parse().unwrap(), rtcp_mux: false };
+        let multicast = TransportMode::Multicast { group: Ipv4Addr::new(239, 0, 0, 1), port: 5004, ttl: 1 };
+        state.add_client(client("udp", udp, None));
+        state.add_client(client("tcp", TransportMode::TcpInterleaved { rtp_channel: 0, rtcp_channel: 1 }, None));
+        state.add_client(client("group", multicast, None));
+        state.clients.get_mut("tcp").unwrap().is_playing = false;
+        state.clients.get_mut("tcp").unwrap().packets_sent = 7;
+        state.clients.get_mut("tcp").unwrap().bytes_sent = 700;
+        state.record_udp_output(&[QueueStats { rtp_addr, depth: 3, dropped: 0, packets_sent: 10, bytes_sent: 1000 }]);
+        state.restarts = 2;
+        state.target_bitrate = Some(1500);
+
+        let stats = state.stats();
+        assert_eq!((stats.clients, stats.playing), (3, 2));
+        assert_eq!((stats.udp, stats.tcp, stats.multicast), (1, 1, 1));
+        assert_eq!((stats.packets_sent, stats.bytes_sent), (17, 1700));
+        assert_eq!((stats.restarts, stats.stalls, stats.bitrate_kbps), (2, 0, Some(1500)));
+
+        // Session kết thúc: không còn được đếm, nhưng totals vẫn được giữ
+        let ended = state.finish_session("udp", EndReason::Normal);
+        state.record_ended(&ended);
+        let stats = state.stats();
+        assert_eq!((stats.clients, stats.playing, stats.udp), (2, 1, 0));
+        assert_eq!((stats.packets_sent, stats.bytes_sent), (17, 1700));
+
+        state.clients.get_mut("tcp").unwrap().packets_sent = 9;
+        state.clients.get_mut("tcp").unwrap().bytes_sent = 900;
+        assert_eq!((state.stats().packets_sent, state.stats().bytes_sent), (19, 1900));
+    }
+
+    #[test]
+    fn max_duration_only_expires_playing_sessions_past_the_limit() {
+        let mut state = ServerState::new();
+        let tcp = TransportMode::TcpInterleaved { rtp_channel: 0, rtcp_channel: 1 };
+        for id in ["old", "paused", "young"] {
+            state.add_client(client(id, tcp.clone(), None));
+        }
+        let start = state.clients["old"].started;
+        state.clients.get_mut("paused").unwrap().is_playing = false;
+        state.clients.get_mut("young").unwrap().started = start + Duration::from_secs(30);
+
+        // Đúng bằng giới hạn: chưa hết hạn
+        state.expire_sessions(start + Duration::from_secs(60), Duration::from_secs(60));
+        assert_eq!(state.clients.len(), 3);
+
+        state.expire_sessions(start + Duration::from_secs(61), Duration::from_secs(60));
+        assert!(!state.clients.contains_key("old"));
+        assert_eq!(state.ended_reason("old"), Some(EndReason::MaxDuration));
+        assert!(state.clients.contains_key("paused"));
+        assert!(state.clients.contains_key("young"));
+        assert_eq!(state.ended_reason("paused"), None);
+    }
 }
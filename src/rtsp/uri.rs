@@ -0,0 +1,92 @@
+/// Path của RTSP URL: `rtsp://host:8554/cam/track1?x=1` → `/cam/track1`
+pub fn path(url: &str) -> &str {
+    let without_scheme = url.split_once("://").map_or(url, |(_, rest)| rest);
+    let path = without_scheme.find('/').map_or("", |i| &without_scheme[i..]);
+    path.split(['?', '#']).next().unwrap_or("")
+}
+
+/// Track control named by a request URL (`.../cam/track1` → `track1`), or
+/// None for the aggregate/base URL (`.../cam`, `.../cam/`, `*`)
+pub fn track(url: &str) -> Option<&str> {
+    path(url)
+        .rsplit('/')
+        .find(|segment| !segment.is_empty())
+        .filter(|segment| {
+            segment
+                .strip_prefix("track")
+                .is_some_and(|n| !n.is_empty() && n.bytes().all(|b| b.is_ascii_digit()))
+        })
+}
+
+/// Absolute control URL of `track` for a request on `url` (aggregate or
+/// track URL), resolved like the client does against Content-Base
+pub fn control_url(url: &str, track: &str) -> String {
+    let base = url.split(['?', '#']).next().unwrap_or(url).trim_end_matches('/');
+    let base = match self::track(url) {
+        Some(current) => base.strip_suffix(current).unwrap_or(base).trim_end_matches('/'),
+        None => base,
+    };
+    format!("{}/{}", base, track)
+}
+
+/// Mount name của URL, bỏ track suffix: `/cam/track1` → `cam`, `/` → ``
+pub fn mount(url: &str) -> String {
+    let has_track = track(url).is_some();
+    let mut segments: Vec<&str> = path(url).split('/').filter(|s| !s.is_empty()).collect();
+    if has_track {
+        segments.pop();
+    }
+    segments.join("/")
+}
+
+/// Resolve the mount a request targets: an empty path (or `/`) maps to
+/// `default_mount`; None if the named mount doesn't exist
+pub fn resolve_mount(url: &str, default_mount: &str, exists: impl Fn(&str) -> bool) -> Option<String> {
+    let mount = mount(url);
+    let mount = if mount.is_empty() { default_mount.to_string() } else { mount };
+    exists(&mount).then_some(mount)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn resolve(url: &str) -> Option<String> {
+        resolve_mount(url, "cam", |mount| ["cam", "lobby", "site/door"].contains(&mount))
+    }
+
+    #[test]
+    fn root_maps_to_default_mount() {
+        assert_eq!(resolve("rtsp://host:8554").as_deref(), Some("cam"));
+        assert_eq!(resolve("rtsp://host:8554/").as_deref(), Some("cam"));
+        assert_eq!(resolve("rtsp://host:8554/track1").as_deref(), Some("cam"));
+        assert_eq!(resolve("*").as_deref(), Some("cam"));
+    }
+
+    #[test]
+    fn named_mounts_resolve_with_or_without_track() {
+        assert_eq!(resolve("rtsp://host:8554/cam").as_deref(), Some("cam"));
+        assert_eq!(resolve("rtsp://host:8554/cam/").as_deref(), Some("cam"));
+        assert_eq!(resolve("rtsp://host:8554/lobby/track2?token=x").as_deref(), Some("lobby"));
+        assert_eq!(resolve("rtsp://host:8554/site/door/track1").as_deref(), Some("site/door"));
+    }
+
+    #[test]
+    fn unknown_mount_is_none() {
+        assert_eq!(resolve("rtsp://host:8554/unknown"), None);
+        assert_eq!(resolve("rtsp://host:8554/unknown/track1"), None);
+        // Prefix của mount khác không phải là mount đó
+        assert_eq!(resolve("rtsp://host:8554/site"), None);
+    }
+
+    #[test]
+    fn path_track_and_control_url() {
+        assert_eq!(path("rtsp://host:8554/cam/track1?x=1#f"), "/cam/track1");
+        assert_eq!(path("rtsp://host:8554"), "");
+        assert_eq!(track("rtsp://host/cam/track12"), Some("track12"));
+        assert_eq!(track("rtsp://host/cam/trackx"), None);
+        assert_eq!(track("rtsp://host/cam/"), None);
+        assert_eq!(control_url("rtsp://host/cam/", "track1"), "rtsp://host/cam/track1");
+        assert_eq!(control_url("rtsp://host/cam/track1", "track2"), "rtsp://host/cam/track2");
+    }
+}
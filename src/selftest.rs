@@ -0,0 +1,316 @@
+use crate::rtp::depacketize::H264Depacketizer;
+use crate::rtp::h264::H264Packetizer;
+use crate::rtp::packet::{HeaderExtension, RtpPacket};
+use crate::source::file::NaluParser;
+
+/// Blocksizes cho từng pass: MTU mặc định, và nhỏ để ép FU-A fragmentation
+const PASSES: [Option<usize>; 2] = [None, Some(200)];
+/// Read sizes cho chunked parse pass (8192 = read buffer của pipelines)
+const CHUNK_SIZES: [usize; 3] = [1, 3, 8192];
+/// Blocksize của boundary pass: 212 - 12 (RTP header) = payload tối đa 200
+const BOUNDARY_BLOCKSIZE: usize = 212;
+const BOUNDARY_PAYLOAD: usize = BOUNDARY_BLOCKSIZE - 12;
+
+/// Self-test: `packetize` → `to_bytes` → `RtpPacket::parse` → depacketize
+/// trên một Annex-B H.264 file, so sánh từng NALU reassembled với bản gốc
+///
+/// Bắt drift giữa packetizer và depacketizer trước khi interop test.
+/// Returns the process exit code (0 = mọi NALU byte-match).
+pub fn run(path: &str) -> i32 {
+    match std::fs::read(path) {
+        Ok(data) => check(path, &data),
+        Err(e) => {
+            eprintln!("❌ Cannot read {}: {}", path, e);
+            1
+        }
+    }
+}
+
+fn check(name: &str, data: &[u8]) -> i32 {
+    // `finish` trả về NALU cuối cùng (không có start code theo sau)
+    let mut parser = NaluParser::new();
+    let mut nalus = parser.parse(data);
+    nalus.extend(parser.finish());
+    let offsets = nalu_offsets(data, &nalus);
+    println!("🧪 Self-test: {} NALUs from {} ({} bytes)", nalus.len(), name, data.len());
+    if nalus.is_empty() {
+        eprintln!("❌ No NALUs found: not an Annex-B H.264 stream");
+        return 1;
+    }
+
+    for blocksize in PASSES {
+        match round_trip_pass(&nalus, &offsets, blocksize, |_| {}) {
+            0 => {}
+            code => return code,
+        }
+    }
+    match chunking_pass(data, &nalus) {
+        0 => {}
+        code => return code,
+    }
+    match boundary_pass() {
+        0 => {}
+        code => return code,
+    }
+    match header_pass() {
+        0 => stap_a_pass(&nalus),
+        code => code,
+    }
+}
+
+/// Từng NALU ở `blocksize`; `wire` được áp lên bytes của mỗi packet trước
+/// khi parse lại (no-op, trừ test cố tình làm hỏng wire format)
+fn round_trip_pass(nalus: &[Vec<u8>], offsets: &[usize], blocksize: Option<usize>, wire: impl Fn(&mut Vec<u8>)) -> i32 {
+    let mut packetizer = H264Packetizer::with_ssrc(0x12345678);
+    packetizer.set_blocksize(blocksize);
+    let mut depacketizer = H264Depacketizer::new();
+    let (mut packets, mut fragmented) = (0usize, 0usize);
+
+    for (index, (nalu, offset)) in nalus.iter().zip(offsets).enumerate() {
+        let rtp = packetizer.packetize(nalu, true);
+        packets += rtp.len();
+        fragmented += usize::from(rtp.len() > 1);
+
+        let mut output = Vec::new();
+        for packet in &rtp {
+            let mut bytes = packet.to_bytes();
+            wire(&mut bytes);
+            let parsed = RtpPacket::parse(&bytes)
+                .map_err(|e| format!("RTP parse failed: {:?}", e))
+                .and_then(|p| depacketizer.push(&p).map_err(|e| format!("depacketize failed: {:?}", e)));
+            match parsed {
+                Ok(nalus) => output.extend(nalus),
+                Err(e) => {
+                    eprintln!("❌ NALU #{} at offset {:#x} (blocksize {:?}): {}", index, offset, blocksize, e);
+                    return 1;
+                }
+            }
+        }
+
+        if output.len() != 1 || output[0] != *nalu {
+            report_mismatch(index, *offset, nalu, &output, blocksize);
+            return 1;
+        }
+        packetizer.end_access_unit();
+    }
+
+    println!("✅ Blocksize {:?}: {} NALUs round-tripped in {} packets ({} fragmented)",
+             blocksize, nalus.len(), packets, fragmented);
+    0
+}
+
+/// Header extension + padding qua `to_bytes` → `RtpPacket::parse`: wire
+/// layout (length words, pad count ở byte cuối) và packet parse lại y hệt
+fn header_pass() -> i32 {
+    // RFC 8285 one-byte form: id 2, len 3 (abs-send-time), rồi 1 byte pad
+    // cho đủ word (parse trả data đã pad nên dùng data đủ 8 bytes)
+    let extension = HeaderExtension::new(0xBEDE, vec![0x22, 0x12, 0x34, 0x56, 0x00, 0x00, 0x00, 0x00]);
+    let base = H264Packetizer::with_ssrc(0x12345678).keepalive();
+    let cases = [
+        ("extension", base.clone().with_extension(extension.clone()), 12 + 4 + 8),
+        ("padding", base.clone().with_padding(3), 12 + 3),
+        ("extension + padding", base.clone().with_extension(extension).with_padding(5), 12 + 4 + 8 + 5),
+    ];
+
+    for (name, packet, header_bytes) in cases {
+        let bytes = packet.to_bytes();
+        let pad_ok = !packet.header.padding || bytes.last() == Some(&packet.padding);
+        match RtpPacket::parse(&bytes) {
+            Ok(parsed) if parsed == packet && bytes.len() == header_bytes + packet.payload.len() && pad_ok => {}
+            result => {
+                eprintln!("❌ RTP {}: {} bytes on the wire, parsed back as {:?}", name, bytes.len(), result);
+                return 1;
+            }
+        }
+    }
+    println!("✅ RTP header: extension and padding round-tripped");
+    0
+}
+
+/// NALUs tổng hợp quanh giới hạn payload: vừa khít single packet, dư một
+/// byte (FU-A), và vừa khít / dư một byte so với hai FU-A fragments
+fn boundary_pass() -> i32 {
+    let fragment = BOUNDARY_PAYLOAD - 2;
+    let sizes = [BOUNDARY_PAYLOAD - 1, BOUNDARY_PAYLOAD, BOUNDARY_PAYLOAD + 1, 1 + 2 * fragment, 2 + 2 * fragment];
+    let mut packetizer = H264Packetizer::with_ssrc(0x12345678);
+    packetizer.set_blocksize(Some(BOUNDARY_BLOCKSIZE));
+    let mut depacketizer = H264Depacketizer::new();
+
+    for size in sizes {
+        // IDR header + byte pattern để lệch một byte là thấy ngay
+        let nalu: Vec<u8> = std::iter::once(0x65).chain((1..size).map(|i| i as u8)).collect();
+        let rtp = packetizer.packetize(&nalu, true);
+        let mut output = Vec::new();
+        for packet in &rtp {
+            match RtpPacket::parse(&packet.to_bytes()).map(|p| depacketizer.push(&p)) {
+                Ok(Ok(nalus)) => output.extend(nalus),
+                result => {
+                    eprintln!("❌ {}-byte NALU: round trip failed: {:?}", size, result.err());
+                    return 1;
+                }
+            }
+        }
+        if output != [nalu] || rtp.iter().any(|p| p.payload.len() > BOUNDARY_PAYLOAD) {
+            eprintln!("❌ {}-byte NALU at a {}-byte payload limit: {} packet(s), {} NALU(s) back",
+                      size, BOUNDARY_PAYLOAD, rtp.len(), output.len());
+            return 1;
+        }
+        packetizer.end_access_unit();
+    }
+    println!("✅ Payload boundary: NALUs of {:?} bytes round-tripped at blocksize {}", sizes, BOUNDARY_BLOCKSIZE);
+    0
+}
+
+/// Cùng input cắt thành reads 1, 3 và 8192 bytes: parser phải trả đúng
+/// các NALUs như khi parse một lần (start code / NALU nằm vắt qua reads)
+fn chunking_pass(input: &[u8], nalus: &[Vec<u8>]) -> i32 {
+    for size in CHUNK_SIZES {
+        let mut parser = NaluParser::new();
+        let mut output: Vec<Vec<u8>> = input.chunks(size).flat_map(|chunk| parser.parse(chunk)).collect();
+        output.extend(parser.finish());
+        if output != nalus {
+            let diverged = output.iter().zip(nalus).position(|(a, b)| a != b).unwrap_or(output.len().min(nalus.len()));
+            eprintln!("❌ {}-byte reads: {} NALUs instead of {}, first difference at NALU #{}",
+                      size, output.len(), nalus.len(), diverged);
+            return 1;
+        }
+    }
+    println!("✅ Chunked parse: {:?}-byte reads give the same {} NALUs", CHUNK_SIZES, nalus.len());
+    0
+}
+
+/// SPS + PPS đầu tiên của file qua `packetize_stap_a`: một packet, và
+/// depacketize ra đúng hai NALUs theo thứ tự
+fn stap_a_pass(nalus: &[Vec<u8>]) -> i32 {
+    let find = |kind: u8| nalus.iter().find(|nalu| nalu.first().map(|b| b & 0x1F) == Some(kind));
+    let (Some(sps), Some(pps)) = (find(7), find(8)) else {
+        println!("⏭️  STAP-A: no SPS/PPS in the file, skipped");
+        return 0;
+    };
+
+    let rtp = H264Packetizer::with_ssrc(0x12345678).packetize_stap_a(&[sps, pps]);
+    let mut depacketizer = H264Depacketizer::new();
+    let mut output = Vec::new();
+    for packet in &rtp {
+        match RtpPacket::parse(&packet.to_bytes()).map(|p| depacketizer.push(&p)) {
+            Ok(Ok(nalus)) => output.extend(nalus),
+            result => {
+                eprintln!("❌ STAP-A round trip failed: {:?}", result.err());
+                return 1;
+            }
+        }
+    }
+
+    if rtp.len() != 1 || rtp[0].payload.len() != 1 + 2 + sps.len() + 2 + pps.len() || output != [sps.clone(), pps.clone()] {
+        eprintln!("❌ STAP-A: SPS ({} bytes) + PPS ({} bytes) → {} packet(s), {} NALU(s) back",
+                  sps.len(), pps.len(), rtp.len(), output.len());
+        return 1;
+    }
+    println!("✅ STAP-A: SPS + PPS in one {}-byte payload", rtp[0].payload.len());
+    0
+}
+
+/// Byte offset (trong file) của từng NALU, ngay sau start code của nó
+fn nalu_offsets(data: &[u8], nalus: &[Vec<u8>]) -> Vec<usize> {
+    let start_code_len = |at: usize| {
+        if data[at..].starts_with(&[0, 0, 1]) {
+            3
+        } else if data[at..].starts_with(&[0, 0, 0, 1]) {
+            4
+        } else {
+            0
+        }
+    };
+
+    let mut at = data.windows(3).position(|w| w == [0, 0, 1]).unwrap_or(0);
+    at += start_code_len(at);
+    let mut offsets = Vec::with_capacity(nalus.len());
+    for nalu in nalus {
+        // Bỏ qua các start code liền nhau (NALU rỗng bị parser bỏ)
+        while start_code_len(at) > 0 {
+            at += start_code_len(at);
+        }
+        offsets.push(at);
+        at += nalu.len();
+        at += start_code_len(at.min(data.len()));
+    }
+    offsets
+}
+
+fn report_mismatch(index: usize, offset: usize, expected: &[u8], output: &[Vec<u8>], blocksize: Option<usize>) {
+    eprintln!(
+        "❌ NALU #{} (type {}, {} bytes at offset {:#x}, blocksize {:?}) reassembled into {} NALU(s)",
+        index,
+        expected[0] & 0x1F,
+        expected.len(),
+        offset,
+        blocksize,
+        output.len()
+    );
+    if let Some(actual) = output.first() {
+        let diff = expected
+            .iter()
+            .zip(actual)
+            .position(|(a, b)| a != b)
+            .unwrap_or(expected.len().min(actual.len()));
+        eprintln!(
+            "   first difference at NALU byte {} (file offset {:#x}): expected {} bytes, got {}",
+            diff,
+            offset + diff,
+            expected.len(),
+            actual.len()
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// SPS, PPS, IDR lớn (FU-A ở mọi pass) và vài non-IDR slices
+    fn annex_b() -> Vec<u8> {
+        let mut idr = vec![0x65, 0x88, 0x84];
+        idr.extend((0..5000u32).map(|i| (i % 251) as u8 | 0x01));
+        let nalus = [vec![0x67, 0x42, 0xC0, 0x1F], vec![0x68, 0xCE, 0x3C, 0x80], idr, vec![0x41, 0x9A, 0x02], vec![0x41, 0x9A, 0x04]];
+        let mut stream = Vec::new();
+        for (i, nalu) in nalus.iter().enumerate() {
+            stream.extend_from_slice(if i % 2 == 0 { &[0, 0, 0, 1][..] } else { &[0, 0, 1][..] });
+            stream.extend_from_slice(nalu);
+        }
+        stream
+    }
+
+    #[test]
+    fn selftest_passes_on_a_synthetic_stream() {
+        let path = std::env::temp_dir().join(format!("selftest-{}.h264", std::process::id()));
+        std::fs::write(&path, annex_b()).unwrap();
+        let code = run(path.to_str().unwrap());
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(code, 0);
+    }
+
+    #[test]
+    fn selftest_detects_corruption() {
+        let data = annex_b();
+        let mut parser = NaluParser::new();
+        let mut nalus = parser.parse(&data);
+        nalus.extend(parser.finish());
+        let offsets = nalu_offsets(&data, &nalus);
+        assert_eq!(round_trip_pass(&nalus, &offsets, Some(200), |_| {}), 0);
+
+        // Một byte payload bị lật trên wire: reassembled NALU không khớp
+        let flip_last = |bytes: &mut Vec<u8>| *bytes.last_mut().unwrap() ^= 0xFF;
+        assert_eq!(round_trip_pass(&nalus, &offsets, None, flip_last), 1);
+        // FU header hỏng (cả S và E): depacketize lỗi
+        let break_fu = |bytes: &mut Vec<u8>| {
+            if bytes[12] & 0x1F == 28 {
+                bytes[13] |= 0xC0;
+            }
+        };
+        assert_eq!(round_trip_pass(&nalus, &offsets, Some(200), break_fu), 1);
+
+        // File không phải Annex-B
+        assert_eq!(check("garbage", &[0xDE, 0xAD, 0xBE, 0xEF]), 1);
+        assert_eq!(run("/nonexistent/selftest.h264"), 1);
+    }
+}
@@ -0,0 +1,50 @@
+/// ADTS header không CRC; với CRC (protection_absent = 0) thêm 2 bytes
+const ADTS_HEADER_LEN: usize = 7;
+
+/// Tách ADTS stream (FFmpeg `-f adts`) thành raw AAC frames
+///
+/// Frames vắt qua nhiều reads được giữ lại tới khi đủ bytes. Bytes không bắt
+/// đầu bằng syncword 0xFFF (stream hỏng, hoặc đọc từ giữa frame) bị bỏ tới
+/// syncword kế tiếp.
+#[derive(Debug, Default)]
+pub struct AdtsParser {
+    buffer: Vec<u8>,
+}
+
+impl AdtsParser {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Feed bytes; returns the raw AAC frames (ADTS header stripped) they complete
+    pub fn parse(&mut self, data: &[u8]) -> Vec<Vec<u8>> {
+        self.buffer.extend_from_slice(data);
+
+        let mut frames = Vec::new();
+        let mut at = 0;
+        while self.buffer.len() - at >= ADTS_HEADER_LEN {
+            let header = &self.buffer[at..at + ADTS_HEADER_LEN];
+            if header[0] != 0xFF || header[1] & 0xF0 != 0xF0 {
+                at += 1;
+                continue;
+            }
+
+            let header_len = if header[1] & 0x01 == 0 { ADTS_HEADER_LEN + 2 } else { ADTS_HEADER_LEN };
+            // frame_length (13 bits) tính cả header
+            let frame_len = ((header[3] as usize & 0x03) << 11) | ((header[4] as usize) << 3) | (header[5] as usize >> 5);
+            if frame_len <= header_len {
+                at += 1;
+                continue;
+            }
+            if self.buffer.len() - at < frame_len {
+                break;
+            }
+
+            frames.push(self.buffer[at + header_len..at + frame_len].to_vec());
+            at += frame_len;
+        }
+
+        self.buffer.drain(..at);
+        frames
+    }
+}
@@ -0,0 +1,173 @@
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::time::Duration;
+
+/// Khoảng giữa hai lần controller đánh giá loss (khớp nhịp RR thông thường)
+pub const ADAPT_INTERVAL: Duration = Duration::from_secs(5);
+/// Fraction lost (x/256) từ mức này là loss cao (~5%)
+const HIGH_LOSS: u8 = 13;
+/// Fraction lost tới mức này coi như không mất (~1%)
+const LOW_LOSS: u8 = 2;
+/// Evaluations liên tiếp cần để giảm / tăng một step: giảm nhanh, tăng chậm
+const DECREASE_AFTER: u32 = 2;
+const INCREASE_AFTER: u32 = 4;
+
+/// Bounds của `--bitrate-adapt min:max:step` (kbps)
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BitrateBounds {
+    pub min: u32,
+    pub max: u32,
+    pub step: u32,
+}
+
+impl BitrateBounds {
+    /// Parse `min:max:step` in kbps (`500:4000:250`)
+    pub fn parse(value: &str) -> Result<Self, String> {
+        let parts: Vec<Option<u32>> = value.split(':').map(|part| part.trim().parse().ok()).collect();
+        match parts[..] {
+            [Some(min), Some(max), Some(step)] if min > 0 && min <= max && step > 0 => Ok(Self { min, max, step }),
+            _ => Err(format!("expected min:max:step in kbps with 0 < min <= max and step > 0, got '{}'", value)),
+        }
+    }
+}
+
+/// Closed-loop bitrate control từ RTCP loss của UDP clients
+///
+/// Chỉ áp dụng cho shared UDP pipeline (unicast + multicast): đổi target là
+/// restart FFmpeg của pipeline đó. TCP-interleaved viewers đọc FFmpeg riêng
+/// của mount (`FeedRegistry`), luôn ở bitrate đã cấu hình và không bao giờ
+/// bị restart bởi controller; loss của họ cũng không được tính.
+///
+/// Mỗi `ADAPT_INTERVAL` controller nhận fraction lost tệ nhất trong các RRs
+/// mới. Loss cao `DECREASE_AFTER` lần liên tiếp thì giảm một step, gần 0
+/// `INCREASE_AFTER` lần liên tiếp thì tăng lại một step, luôn trong
+/// [min, max]. Streak reset sau mỗi thay đổi và khi loss ở giữa hai ngưỡng,
+/// nên bitrate không dao động theo từng report; không có report thì giữ nguyên.
+#[derive(Debug)]
+pub struct BitrateController {
+    bounds: BitrateBounds,
+    current: u32,
+    high_streak: u32,
+    low_streak: u32,
+}
+
+impl BitrateController {
+    /// Bắt đầu ở `max`: giảm khi mạng thực sự loss
+    pub fn new(bounds: BitrateBounds) -> Self {
+        Self { bounds, current: bounds.max, high_streak: 0, low_streak: 0 }
+    }
+
+    /// Current target (kbps)
+    pub fn current(&self) -> u32 {
+        self.current
+    }
+
+    /// One evaluation with the worst fresh fraction lost (None: no new RR).
+    /// Returns the new target when it changes
+    pub fn on_loss(&mut self, fraction_lost: Option<u8>) -> Option<u32> {
+        let Some(lost) = fraction_lost else {
+            self.high_streak = 0;
+            self.low_streak = 0;
+            return None;
+        };
+
+        let (high, low) = (lost >= HIGH_LOSS, lost <= LOW_LOSS);
+        self.high_streak = if high { self.high_streak + 1 } else { 0 };
+        self.low_streak = if low { self.low_streak + 1 } else { 0 };
+
+        let target = if self.high_streak >= DECREASE_AFTER {
+            self.current.saturating_sub(self.bounds.step).max(self.bounds.min)
+        } else if self.low_streak >= INCREASE_AFTER {
+            self.current.saturating_add(self.bounds.step).min(self.bounds.max)
+        } else {
+            return None;
+        };
+
+        self.high_streak = 0;
+        self.low_streak = 0;
+        if target == self.current {
+            return None;
+        }
+        self.current = target;
+        Some(target)
+    }
+}
+
+/// Target bitrate controller đặt cho streaming loop (cùng pattern với
+/// `Watchdog`): controller `request` rồi kill FFmpeg, loop thấy `take_changed`
+/// khi read thất bại và respawn ở bitrate mới
+#[derive(Debug, Default)]
+pub struct BitrateTarget {
+    /// kbps; 0 = không có thay đổi đang chờ
+    pending: AtomicU32,
+}
+
+impl BitrateTarget {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn request(&self, kbps: u32) {
+        self.pending.store(kbps, Ordering::SeqCst);
+    }
+
+    /// New target to restart with, if one was requested (clears it)
+    pub fn take_changed(&self) -> Option<u32> {
+        Some(self.pending.swap(0, Ordering::SeqCst)).filter(|kbps| *kbps > 0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BOUNDS: BitrateBounds = BitrateBounds { min: 500, max: 1000, step: 250 };
+
+    #[test]
+    fn steps_down_fast_and_up_slowly_within_bounds() {
+        let mut controller = BitrateController::new(BOUNDS);
+        assert_eq!(controller.current(), 1000);
+
+        // Loss cao: một report chưa đủ, hai report liên tiếp giảm một step
+        assert_eq!(controller.on_loss(Some(HIGH_LOSS)), None);
+        assert_eq!(controller.on_loss(Some(HIGH_LOSS)), Some(750));
+        assert_eq!(controller.on_loss(Some(200)), None);
+        assert_eq!(controller.on_loss(Some(200)), Some(500));
+        for _ in 0..10 {
+            assert_eq!(controller.on_loss(Some(u8::MAX)), None); // sàn min
+        }
+
+        // Gần 0 loss: cần INCREASE_AFTER reports
+        for _ in 1..INCREASE_AFTER {
+            assert_eq!(controller.on_loss(Some(0)), None);
+        }
+        assert_eq!(controller.on_loss(Some(LOW_LOSS)), Some(750));
+    }
+
+    #[test]
+    fn middling_or_missing_reports_reset_streaks() {
+        let mut controller = BitrateController::new(BOUNDS);
+        assert_eq!(controller.on_loss(Some(HIGH_LOSS)), None);
+        assert_eq!(controller.on_loss(None), None);
+        assert_eq!(controller.on_loss(Some(HIGH_LOSS)), None);
+        assert_eq!(controller.on_loss(Some(LOW_LOSS + 1)), None);
+        assert_eq!(controller.on_loss(Some(HIGH_LOSS)), None);
+        assert_eq!(controller.current(), 1000);
+    }
+
+    #[test]
+    fn target_is_taken_once() {
+        let target = BitrateTarget::new();
+        assert_eq!(target.take_changed(), None);
+        target.request(750);
+        assert_eq!(target.take_changed(), Some(750));
+        assert_eq!(target.take_changed(), None);
+    }
+
+    #[test]
+    fn bounds_parse() {
+        assert_eq!(BitrateBounds::parse("500:4000:250"), Ok(BitrateBounds { min: 500, max: 4000, step: 250 }));
+        assert!(BitrateBounds::parse("4000:500:250").is_err());
+        assert!(BitrateBounds::parse("500:4000:0").is_err());
+        assert!(BitrateBounds::parse("500:4000").is_err());
+    }
+}
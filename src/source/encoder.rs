@@ -0,0 +1,276 @@
+use crate::rtp::h264::{CLOCK_RATE, DEFAULT_FPS};
+use std::process::Command;
+
+/// H.264 encoder FFmpeg dùng cho live source
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Encoder {
+    /// Software x264 (default)
+    #[default]
+    Libx264,
+    /// NVIDIA NVENC
+    Nvenc,
+    /// VAAPI (Intel/AMD trên Linux)
+    Vaapi,
+    /// Intel Quick Sync
+    Qsv,
+}
+
+impl Encoder {
+    /// Parse the FFmpeg encoder name (`libx264`, `h264_nvenc`, `h264_vaapi`, `h264_qsv`)
+    pub fn parse(name: &str) -> Result<Self, String> {
+        match name {
+            "libx264" => Ok(Self::Libx264),
+            "h264_nvenc" => Ok(Self::Nvenc),
+            "h264_vaapi" => Ok(Self::Vaapi),
+            "h264_qsv" => Ok(Self::Qsv),
+            _ => Err(format!("unknown encoder '{}' (libx264, h264_nvenc, h264_vaapi, h264_qsv)", name)),
+        }
+    }
+
+    /// FFmpeg `-c:v` name
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Libx264 => "libx264",
+            Self::Nvenc => "h264_nvenc",
+            Self::Vaapi => "h264_vaapi",
+            Self::Qsv => "h264_qsv",
+        }
+    }
+}
+
+/// Encoder selection + device cho hardware encoders
+#[derive(Clone, Debug, PartialEq)]
+pub struct EncoderConfig {
+    pub encoder: Encoder,
+    /// DRM render node cho VAAPI
+    pub vaapi_device: String,
+    /// Output frame rate (`--fps`): FFmpeg ép `-r` bất kể fps của input, nên
+    /// packetizer timestamps (CLOCK_RATE / fps) và pacing khớp với stream
+    pub fps: u32,
+    /// Video bitrate target (kbps): `-b:v` / `-maxrate`, None để encoder tự
+    /// chọn. Bitrate adaptation đổi nó rồi restart FFmpeg
+    pub bitrate: Option<u32>,
+}
+
+impl Default for EncoderConfig {
+    fn default() -> Self {
+        Self {
+            encoder: Encoder::default(),
+            vaapi_device: "/dev/dri/renderD128".to_string(),
+            fps: DEFAULT_FPS,
+            bitrate: None,
+        }
+    }
+}
+
+impl EncoderConfig {
+    /// Parse `--fps`: integer 1..=120 (timestamp delta là CLOCK_RATE / fps)
+    pub fn parse_fps(value: &str) -> Result<u32, String> {
+        match value.trim().parse::<u32>() {
+            Ok(fps) if (1..=120).contains(&fps) => Ok(fps),
+            _ => Err(format!("expected frames per second in 1..=120, got '{}'", value)),
+        }
+    }
+
+    /// RTP timestamp delta của một frame ở output frame rate
+    pub fn frame_ticks(&self) -> u32 {
+        CLOCK_RATE / self.fps.max(1)
+    }
+
+    /// Fall back to libx264 (with a warning) if FFmpeg doesn't list the
+    /// selected encoder in `ffmpeg -encoders`
+    pub fn resolve(mut self) -> Self {
+        if self.encoder == Encoder::Libx264 {
+            return self;
+        }
+
+        let listed = Command::new("ffmpeg")
+            .args(["-hide_banner", "-encoders"])
+            .output()
+            .map(|output| {
+                String::from_utf8_lossy(&output.stdout)
+                    .lines()
+                    .any(|line| line.split_whitespace().nth(1) == Some(self.encoder.name()))
+            })
+            .unwrap_or(false);
+
+        if !listed {
+            println!("⚠️  Encoder {} not available in FFmpeg, falling back to libx264", self.encoder.name());
+            self.encoder = Encoder::Libx264;
+        }
+        self
+    }
+
+    /// Full FFmpeg argument vector: loop `input` in real time and write
+    /// Annex-B H.264 (baseline-compatible, one-second GOP, no B-frames) to stdout,
+    /// starting `seek` seconds into the file
+    pub fn ffmpeg_args(&self, input: &str, seek: Option<f64>) -> Vec<String> {
+        let mut input_args = vec![
+            "-re".to_string(),                  // Real-time mode
+            "-stream_loop".to_string(), "-1".to_string(), // Loop vô hạn
+        ];
+        if let Some(seek) = seek {
+            input_args.extend(["-ss".to_string(), format!("{:.3}", seek)]); // Input seek (trước -i)
+        }
+        input_args.extend(["-i".to_string(), input.to_string()]); // Input file
+        self.encode_args(input_args)
+    }
+
+    /// Same encoder/output settings for an arbitrary input (`input_args`
+    /// ends with `-i <input>`), e.g. the placeholder's lavfi source
+    pub fn encode_args(&self, input_args: Vec<String>) -> Vec<String> {
+        let fps = self.fps.to_string();
+        // VBV buffer 2s ở target: bám sát target mà keyframes vẫn đủ bits
+        let bitrate = self.bitrate.map(|kbps| (format!("{}k", kbps), format!("{}k", kbps.saturating_mul(2))));
+        let mut args: Vec<&str> = Vec::new();
+
+        // Hardware device / decode args phải đứng trước -i
+        match self.encoder {
+            Encoder::Libx264 => {}
+            Encoder::Nvenc => args.extend(["-hwaccel", "cuda", "-hwaccel_output_format", "cuda"]),
+            Encoder::Vaapi => args.extend(["-vaapi_device", &self.vaapi_device]),
+            Encoder::Qsv => args.extend(["-init_hw_device", "qsv=hw", "-filter_hw_device", "hw"]),
+        }
+
+        args.extend(input_args.iter().map(String::as_str));
+        args.extend([
+            "-an",                              // Không có audio
+            "-c:v", self.encoder.name(),        // H.264 encoder
+        ]);
+
+        match self.encoder {
+            Encoder::Libx264 => args.extend([
+                "-preset", "ultrafast",         // Encode nhanh
+                "-tune", "zerolatency",         // Low latency
+                "-profile:v", "baseline",       // Baseline profile cho compatibility
+                "-level", "3.1",                // H.264 level 3.1
+                "-pix_fmt", "yuv420p",          // Pixel format
+                "-x264-params", "nal-hrd=cbr:force-cfr=1", // Constant bitrate for stable streaming
+            ]),
+            Encoder::Nvenc => args.extend([
+                "-preset", "p1",                // Nhanh nhất
+                "-tune", "ll",                  // Low latency
+                "-profile:v", "baseline",
+                "-level", "3.1",
+                "-rc", "cbr",
+                "-zerolatency", "1",
+            ]),
+            Encoder::Vaapi => args.extend([
+                "-vf", "format=nv12,hwupload",  // Upload frames lên GPU
+                "-profile:v", "constrained_baseline",
+                "-rc_mode", "CBR",
+            ]),
+            Encoder::Qsv => args.extend([
+                "-vf", "hwupload=extra_hw_frames=64,format=qsv",
+                "-preset", "veryfast",
+                "-profile:v", "baseline",
+                "-look_ahead", "0",
+            ]),
+        }
+
+        if let Some((rate, bufsize)) = &bitrate {
+            args.extend([
+                "-b:v", rate,                   // Target bitrate
+                "-maxrate", rate,               // Trần cho CBR
+                "-bufsize", bufsize,            // VBV buffer
+            ]);
+        }
+        args.extend([
+            "-r", &fps,                         // Constant output frame rate
+            "-g", &fps,                         // GOP size (keyframe mỗi giây)
+            "-keyint_min", &fps,                // Minimum keyframe interval
+            "-bf", "0",                         // No B-frames cho low latency
+            "-f", "h264",                       // Format H.264 raw
+            "-bsf:v", "h264_mp4toannexb",       // Ensure Annex-B format
+            "pipe:1",                           // Output to stdout
+        ]);
+
+        args.into_iter().map(String::from).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(encoder: Encoder, fps: u32, bitrate: Option<u32>) -> Vec<String> {
+        let config = EncoderConfig { encoder, fps, bitrate, ..EncoderConfig::default() };
+        config.encode_args(vec!["-i".to_string(), "in.mp4".to_string()])
+    }
+
+    /// Value theo sau `flag` (lần xuất hiện đầu)
+    fn value<'a>(args: &'a [String], flag: &str) -> Option<&'a str> {
+        args.iter().position(|arg| arg == flag).map(|i| args[i + 1].as_str())
+    }
+
+    #[test]
+    fn every_encoder_writes_annexb_h264_to_stdout() {
+        for encoder in [Encoder::Libx264, Encoder::Nvenc, Encoder::Vaapi, Encoder::Qsv] {
+            let args = args(encoder, 30, None);
+            assert_eq!(value(&args, "-c:v"), Some(encoder.name()));
+            assert_eq!(value(&args, "-f"), Some("h264"));
+            assert_eq!(value(&args, "-bsf:v"), Some("h264_mp4toannexb"));
+            assert_eq!(value(&args, "-bf"), Some("0"));
+            assert_eq!(args.last().map(String::as_str), Some("pipe:1"));
+            assert!(args.contains(&"-an".to_string()));
+        }
+    }
+
+    #[test]
+    fn hardware_setup_precedes_input() {
+        let input = |args: &[String]| args.iter().position(|arg| arg == "-i").unwrap();
+
+        let nvenc = args(Encoder::Nvenc, 30, None);
+        assert!(nvenc.iter().position(|arg| arg == "-hwaccel").unwrap() < input(&nvenc));
+        assert_eq!(value(&nvenc, "-hwaccel"), Some("cuda"));
+
+        let vaapi = args(Encoder::Vaapi, 30, None);
+        assert!(vaapi.iter().position(|arg| arg == "-vaapi_device").unwrap() < input(&vaapi));
+        assert_eq!(value(&vaapi, "-vaapi_device"), Some("/dev/dri/renderD128"));
+        assert_eq!(value(&vaapi, "-vf"), Some("format=nv12,hwupload"));
+
+        let qsv = args(Encoder::Qsv, 30, None);
+        assert_eq!(value(&qsv, "-init_hw_device"), Some("qsv=hw"));
+        assert!(qsv.iter().position(|arg| arg == "-init_hw_device").unwrap() < input(&qsv));
+
+        let x264 = args(Encoder::Libx264, 30, None);
+        assert_eq!(input(&x264), 0, "software encoding needs no device args");
+        assert_eq!(value(&x264, "-profile:v"), Some("baseline"));
+    }
+
+    #[test]
+    fn fps_sets_rate_and_one_second_gop() {
+        for encoder in [Encoder::Libx264, Encoder::Nvenc, Encoder::Vaapi, Encoder::Qsv] {
+            let args = args(encoder, 25, None);
+            assert_eq!(value(&args, "-r"), Some("25"));
+            assert_eq!(value(&args, "-g"), Some("25"));
+            assert_eq!(value(&args, "-keyint_min"), Some("25"));
+        }
+        let config = EncoderConfig { fps: 25, ..EncoderConfig::default() };
+        assert_eq!(config.frame_ticks(), 3600);
+        assert_eq!(EncoderConfig::parse_fps("60"), Ok(60));
+        assert!(EncoderConfig::parse_fps("0").is_err());
+        assert!(EncoderConfig::parse_fps("121").is_err());
+    }
+
+    #[test]
+    fn bitrate_sets_target_cap_and_vbv_buffer() {
+        for encoder in [Encoder::Libx264, Encoder::Nvenc, Encoder::Vaapi, Encoder::Qsv] {
+            let args = args(encoder, 30, Some(1500));
+            assert_eq!(value(&args, "-b:v"), Some("1500k"));
+            assert_eq!(value(&args, "-maxrate"), Some("1500k"));
+            assert_eq!(value(&args, "-bufsize"), Some("3000k"));
+
+            let unset = self::args(encoder, 30, None);
+            assert!(value(&unset, "-b:v").is_none());
+        }
+    }
+
+    #[test]
+    fn file_input_loops_in_real_time_and_seeks_before_input() {
+        let args = EncoderConfig::default().ffmpeg_args("in.mp4", Some(12.5));
+        assert_eq!(&args[..5], ["-re", "-stream_loop", "-1", "-ss", "12.500"]);
+        assert_eq!(value(&args, "-i"), Some("in.mp4"));
+        assert!(!EncoderConfig::default().ffmpeg_args("in.mp4", None).contains(&"-ss".to_string()));
+    }
+}
@@ -0,0 +1,265 @@
+use crate::config::ServerConfig;
+use crate::source::file::{AccessUnitSplitter, NaluParser};
+use crate::source::params::ParameterSetMonitor;
+use crate::source::Source;
+use std::collections::{HashMap, VecDeque};
+use std::io::Read;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::broadcast;
+
+/// Access unit đã parse (NALUs theo thứ tự), chia sẻ giữa các subscribers
+pub type AccessUnit = Arc<Vec<Vec<u8>>>;
+
+/// (SPS, PPS) mới nhất, cái nào đã thấy
+pub type CachedSets = (Option<Vec<u8>>, Option<Vec<u8>>);
+
+/// AUs một subscriber được phép chậm hơn reader trước khi bị Lagged (~8s ở 30fps)
+const FEED_CAPACITY: usize = 256;
+/// Poll interval của reader khi không subscriber nào đang play
+const IDLE_POLL: Duration = Duration::from_millis(100);
+
+/// Một FFmpeg process cho một source, đọc bởi một reader task và broadcast
+/// từng access unit (đã pace theo frame rate) tới mọi TCP session đang xem
+pub struct SourceFeed {
+    /// None khi reader đã dừng: receivers nhận `Closed`
+    sender: Mutex<Option<broadcast::Sender<AccessUnit>>>,
+    /// SPS/PPS mới nhất reader thấy, cho subscribers vào giữa chừng
+    parameter_sets: Mutex<CachedSets>,
+    /// Subscribers đang play; về 0 thì reader ngừng đọc FFmpeg, nên một
+    /// client PAUSE rồi PLAY tiếp tục đúng chỗ dừng như khi FFmpeg là riêng
+    playing: AtomicUsize,
+}
+
+impl SourceFeed {
+    /// Spawn FFmpeg cho `source` và reader task, trả subscription đầu tiên.
+    /// `registry` (với key) được dọn khi reader dừng
+    fn start(
+        source: &dyn Source,
+        config: Arc<ServerConfig>,
+        frame_duration: Duration,
+        registry: Option<(Arc<FeedRegistry>, String)>,
+    ) -> std::io::Result<(Arc<Self>, FeedSubscription)> {
+        let mut child = source.start_ffmpeg()?;
+        let stdout = child.stdout.take().ok_or_else(|| {
+            std::io::Error::other("Failed to capture FFmpeg stdout")
+        })?;
+
+        let (sender, receiver) = broadcast::channel(FEED_CAPACITY);
+        let feed = Arc::new(Self {
+            sender: Mutex::new(Some(sender)),
+            parameter_sets: Mutex::new((None, None)),
+            playing: AtomicUsize::new(0),
+        });
+        let subscription = FeedSubscription::new(feed.clone(), receiver);
+
+        let reader_feed = feed.clone();
+        tokio::spawn(async move {
+            reader_feed.run(stdout, &config, frame_duration).await;
+            let _ = child.kill();
+            let _ = child.wait();
+            reader_feed.sender.lock().unwrap().take();
+            if let Some((registry, key)) = registry {
+                registry.remove(&key, &reader_feed);
+            }
+        });
+
+        Ok((feed, subscription))
+    }
+
+    /// Reader loop: parse FFmpeg output thành access units và phát từng AU
+    /// một frame interval, tới khi FFmpeg kết thúc hoặc hết subscribers
+    async fn run(&self, stdout: std::process::ChildStdout, config: &ServerConfig, frame_duration: Duration) {
+        let mut parser = if config.nalu_resync { NaluParser::tolerant() } else { NaluParser::new() };
+        let mut splitter = AccessUnitSplitter::new();
+        let mut param_monitor = ParameterSetMonitor::new(config.parameter_set_timeout);
+        let mut override_warned = false;
+        let mut reader = std::io::BufReader::new(stdout);
+        let mut buffer = [0u8; 8192];
+        let mut pending: VecDeque<AccessUnit> = VecDeque::new();
+
+        let clock = config.clock.clone();
+        let mut start_time = clock.now();
+        let mut frame_count: u64 = 0;
+        let mut paused = false;
+
+        loop {
+            if self.subscribers() == 0 {
+                println!("📴 No TCP subscribers left, stopping FFmpeg");
+                return;
+            }
+            if self.playing.load(Ordering::Relaxed) == 0 {
+                paused = true;
+                tokio::time::sleep(IDLE_POLL).await;
+                continue;
+            }
+            if paused {
+                // Pacing tính lại từ lúc resume, không bù thời gian pause
+                paused = false;
+                start_time = clock.now();
+                frame_count = 0;
+            }
+
+            let Some(au) = pending.pop_front() else {
+                // Blocking read: block_in_place nhường worker như UDP pipeline
+                let n = match tokio::task::block_in_place(|| reader.read(&mut buffer)) {
+                    Ok(0) => {
+                        println!("📹 Feed FFmpeg exited");
+                        return;
+                    }
+                    Ok(n) => n,
+                    Err(e) => {
+                        eprintln!("❌ Read error: {}", e);
+                        return;
+                    }
+                };
+
+                let mut nalus = parser.parse(&buffer[..n]);
+                if let Some(sets) = &config.parameter_sets {
+                    sets.substitute(&mut nalus, &mut override_warned);
+                }
+                param_monitor.observe(&nalus);
+                if param_monitor.check(clock.now()) {
+                    if let Some(sets) = &config.parameter_sets {
+                        println!("🧬 Synthesizing SPS/PPS from the --sprop override");
+                        *self.parameter_sets.lock().unwrap() = (Some(sets.sps.clone()), Some(sets.pps.clone()));
+                    }
+                }
+
+                for nalu in &nalus {
+                    let mut sets = self.parameter_sets.lock().unwrap();
+                    match nalu.first().map(|b| b & 0x1F) {
+                        Some(7) => sets.0 = Some(nalu.clone()),
+                        Some(8) => sets.1 = Some(nalu.clone()),
+                        _ => {}
+                    }
+                }
+                pending.extend(nalus.into_iter().filter_map(|nalu| splitter.push(nalu)).map(Arc::new));
+                continue;
+            };
+
+            if let Some(sender) = self.sender.lock().unwrap().as_ref() {
+                let _ = sender.send(au);
+            }
+
+            // Timing control - wait until next frame time
+            frame_count += 1;
+            let expected_time = start_time + frame_duration * frame_count as u32;
+            let now = clock.now();
+            if expected_time > now {
+                tokio::time::sleep(expected_time - now).await;
+            }
+        }
+    }
+
+    fn subscribers(&self) -> usize {
+        self.sender.lock().unwrap().as_ref().map_or(0, broadcast::Sender::receiver_count)
+    }
+
+    fn subscribe(self: &Arc<Self>) -> Option<FeedSubscription> {
+        let receiver = self.sender.lock().unwrap().as_ref()?.subscribe();
+        Some(FeedSubscription::new(self.clone(), receiver))
+    }
+}
+
+/// Một TCP session đang nhận từ `SourceFeed`
+pub struct FeedSubscription {
+    feed: Arc<SourceFeed>,
+    receiver: broadcast::Receiver<AccessUnit>,
+    playing: bool,
+}
+
+impl FeedSubscription {
+    fn new(feed: Arc<SourceFeed>, receiver: broadcast::Receiver<AccessUnit>) -> Self {
+        let mut subscription = Self { feed, receiver, playing: false };
+        subscription.set_playing(true);
+        subscription
+    }
+
+    /// Access unit kế tiếp; `Lagged` khi session chậm quá `FEED_CAPACITY`
+    pub async fn recv(&mut self) -> Result<AccessUnit, broadcast::error::RecvError> {
+        self.receiver.recv().await
+    }
+
+    /// SPS/PPS source đã gửi gần nhất (cái nào có)
+    pub fn parameter_sets(&self) -> CachedSets {
+        self.feed.parameter_sets.lock().unwrap().clone()
+    }
+
+    /// PAUSE / PLAY của session
+    pub fn set_playing(&mut self, playing: bool) {
+        if playing != self.playing {
+            self.playing = playing;
+            if playing {
+                self.feed.playing.fetch_add(1, Ordering::Relaxed);
+            } else {
+                self.feed.playing.fetch_sub(1, Ordering::Relaxed);
+            }
+        }
+    }
+
+    /// Feed vẫn chạy cho sessions khác trong lúc session này pause: bỏ các
+    /// AUs đã queue và nhảy tới live point. False nếu không có gì để bỏ
+    pub fn skip_to_live(&mut self) -> bool {
+        if self.receiver.len() <= 1 {
+            return false;
+        }
+        self.receiver = self.receiver.resubscribe();
+        true
+    }
+}
+
+impl Drop for FeedSubscription {
+    fn drop(&mut self) {
+        self.set_playing(false);
+    }
+}
+
+/// Shared feeds theo mount: TCP sessions cùng mount dùng chung một FFmpeg
+#[derive(Default)]
+pub struct FeedRegistry {
+    feeds: Mutex<HashMap<String, Arc<SourceFeed>>>,
+}
+
+impl FeedRegistry {
+    /// Subscribe tới feed của `mount`, start FFmpeg cho `source()` nếu mount
+    /// chưa có feed đang chạy. Ok(None) khi `source()` không có gì để phát
+    pub fn subscribe(
+        self: &Arc<Self>,
+        mount: &str,
+        config: &Arc<ServerConfig>,
+        frame_duration: Duration,
+        source: impl FnOnce() -> Option<Box<dyn Source>>,
+    ) -> std::io::Result<Option<FeedSubscription>> {
+        let mut feeds = self.feeds.lock().unwrap();
+        if let Some(subscription) = feeds.get(mount).and_then(SourceFeed::subscribe) {
+            println!("🔗 Joining shared FFmpeg of mount /{}", mount);
+            return Ok(Some(subscription));
+        }
+
+        let Some(source) = source() else {
+            return Ok(None);
+        };
+        let registry = Some((self.clone(), mount.to_string()));
+        let (feed, subscription) = SourceFeed::start(source.as_ref(), config.clone(), frame_duration, registry)?;
+        feeds.insert(mount.to_string(), feed);
+        Ok(Some(subscription))
+    }
+
+    /// Private feed (không chia sẻ), cho session seek tới vị trí riêng
+    pub fn private(
+        config: &Arc<ServerConfig>,
+        frame_duration: Duration,
+        source: &dyn Source,
+    ) -> std::io::Result<FeedSubscription> {
+        SourceFeed::start(source, config.clone(), frame_duration, None).map(|(_, subscription)| subscription)
+    }
+
+    fn remove(&self, mount: &str, feed: &Arc<SourceFeed>) {
+        let mut feeds = self.feeds.lock().unwrap();
+        if feeds.get(mount).is_some_and(|current| Arc::ptr_eq(current, feed)) {
+            feeds.remove(mount);
+        }
+    }
+}
@@ -1,22 +1,73 @@
+use super::encoder::EncoderConfig;
+use super::placeholder::Placeholder;
+use super::Source;
 use std::process::{Command, Stdio};
-use std::io::{Read, BufReader};
 
 /// Video source từ file MP4, loop vô hạn
 pub struct FileSource {
     pub file_path: String,
+    pub encoder: EncoderConfig,
+    /// Start offset (giây) trong file, cho PLAY Range
+    pub seek: Option<f64>,
+    /// Encode placeholder content instead of `file_path`
+    pub placeholder: Option<Placeholder>,
 }
 
 impl FileSource {
-    pub fn new(file_path: String) -> Self {
-        Self { file_path }
+    pub fn new(file_path: String, encoder: EncoderConfig) -> Self {
+        Self { file_path, encoder, seek: None, placeholder: None }
+    }
+
+    /// Source phát placeholder (không có file, không seek được)
+    pub fn placeholder(placeholder: Placeholder, encoder: EncoderConfig) -> Self {
+        Self {
+            file_path: placeholder.describe(),
+            encoder,
+            seek: None,
+            placeholder: Some(placeholder),
+        }
+    }
+
+    /// Start playback `seek` seconds into the file
+    pub fn with_seek(mut self, seek: Option<f64>) -> Self {
+        self.seek = seek;
+        self
+    }
+}
+
+impl Source for FileSource {
+    /// Độ dài file (giây) qua ffprobe; None nếu ffprobe không có hoặc lỗi
+    fn probe_duration(&self) -> Option<f64> {
+        if self.placeholder.is_some() {
+            return None;
+        }
+        let output = Command::new("ffprobe")
+            .args([
+                "-v", "error",
+                "-show_entries", "format=duration",
+                "-of", "default=noprint_wrappers=1:nokey=1",
+                &self.file_path,
+            ])
+            .output()
+            .ok()?;
+
+        if !output.status.success() {
+            return None;
+        }
+        String::from_utf8_lossy(&output.stdout).trim().parse().ok()
     }
 
     /// Tạo FFmpeg process để encode file MP4 thành H.264 raw stream
     /// Output: H.264 NALUs qua stdout
-    pub fn start_ffmpeg(&self) -> std::io::Result<std::process::Child> {
+    fn start_ffmpeg(&self) -> std::io::Result<std::process::Child> {
+        let args = match &self.placeholder {
+            Some(placeholder) => self.encoder.encode_args(placeholder.input_args()),
+            None => self.encoder.ffmpeg_args(&self.file_path, self.seek),
+        };
+
         // Debug: print ffmpeg command
         println!("Debug: FFmpeg command:");
-        println!("  ffmpeg -re -stream_loop -1 -i {:?} -an -c:v libx264 -preset ultrafast -tune zerolatency -f h264 pipe:1", &self.file_path);
+        println!("  ffmpeg {}", args.join(" "));
 
         // Check if ffmpeg exists
         let ffmpeg_check = Command::new("which")
@@ -36,94 +87,223 @@ impl FileSource {
         }
 
         // Check if input file exists
-        println!("Debug: Input file path: {:?}", &self.file_path);
-        println!("Debug: File exists: {}", std::path::Path::new(&self.file_path).exists());
+        if self.placeholder.is_none() {
+            println!("Debug: Input file path: {:?}", &self.file_path);
+            println!("Debug: File exists: {}", std::path::Path::new(&self.file_path).exists());
+        }
 
         let child = Command::new("ffmpeg")
-            .args(&[
+            .args(&args)
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())             // Capture stderr để xem lỗi
+            .spawn()?;
+
+        Ok(child)
+    }
+
+    fn describe(&self) -> &str {
+        &self.file_path
+    }
+
+    /// Placeholder luôn phát được; file phải tồn tại
+    fn is_available(&self) -> bool {
+        self.placeholder.is_some() || std::path::Path::new(&self.file_path).exists()
+    }
+
+    fn set_bitrate(&mut self, kbps: u32) {
+        self.encoder.bitrate = Some(kbps);
+    }
+}
+
+impl FileSource {
+    /// Tạo FFmpeg process encode audio của file thành AAC-LC
+    /// Output: ADTS frames qua stdout, luôn 48kHz stereo để SDP (config=)
+    /// không phụ thuộc file
+    pub fn start_ffmpeg_aac(&self) -> std::io::Result<std::process::Child> {
+        println!("Debug: FFmpeg AAC command:");
+        println!("  ffmpeg -re -stream_loop -1 -i {:?} -vn -c:a aac -f adts pipe:1", &self.file_path);
+
+        Command::new("ffmpeg")
+            .args([
                 "-re",                          // Real-time mode
                 "-stream_loop", "-1",           // Loop vô hạn
                 "-i", &self.file_path,          // Input file
-                "-an",                          // Không có audio
-                "-c:v", "libx264",              // H.264 codec
-                "-preset", "ultrafast",         // Encode nhanh
-                "-tune", "zerolatency",         // Low latency
-                "-profile:v", "baseline",       // Baseline profile cho compatibility
-                "-level", "3.1",                // H.264 level 3.1
-                "-pix_fmt", "yuv420p",          // Pixel format
-                "-g", "30",                     // GOP size (keyframe every 30 frames)
-                "-keyint_min", "30",            // Minimum keyframe interval
-                "-bf", "0",                     // No B-frames cho low latency
-                "-x264-params", "nal-hrd=cbr:force-cfr=1", // Constant bitrate for stable streaming
-                "-f", "h264",                   // Format H.264 raw
-                "-bsf:v", "h264_mp4toannexb",  // Ensure Annex-B format
+                "-vn",                          // Không có video
+                "-c:a", "aac",                  // AAC-LC
+                "-b:a", "128k",                 // Audio bitrate
+                "-ar", "48000",                 // Khớp rtpmap mpeg4-generic/48000
+                "-ac", "2",                     // Stereo
+                "-f", "adts",                   // ADTS framing
                 "pipe:1"                        // Output to stdout
             ])
             .stdout(Stdio::piped())
-            .stderr(Stdio::piped())             // Capture stderr để xem lỗi
-            .spawn()?;
+            .stderr(Stdio::null())              // Không ai đọc stderr của audio process
+            .spawn()
+    }
+}
 
-        Ok(child)
+#[cfg(feature = "opus")]
+impl FileSource {
+    /// Tạo FFmpeg process encode audio của file thành Opus (Ogg-encapsulated)
+    /// Output: Ogg pages qua stdout, mỗi packet là 1 Opus frame 20ms
+    pub fn start_ffmpeg_opus(&self) -> std::io::Result<std::process::Child> {
+        println!("Debug: FFmpeg Opus command:");
+        println!("  ffmpeg -re -stream_loop -1 -i {:?} -vn -c:a libopus -f ogg pipe:1", &self.file_path);
+
+        Command::new("ffmpeg")
+            .args([
+                "-re",                          // Real-time mode
+                "-stream_loop", "-1",           // Loop vô hạn
+                "-i", &self.file_path,          // Input file
+                "-vn",                          // Không có video
+                "-c:a", "libopus",              // Opus codec
+                "-b:a", "64k",                  // Audio bitrate
+                "-ar", "48000",                 // Opus chạy ở 48kHz
+                "-ac", "2",                     // Stereo
+                "-frame_duration", "20",        // 20ms frames (ptime 20)
+                "-application", "lowdelay",     // Low latency
+                "-page_duration", "20000",      // Flush Ogg page mỗi 20ms
+                "-f", "ogg",                    // Ogg container
+                "pipe:1"                        // Output to stdout
+            ])
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())              // Không ai đọc stderr của audio process
+            .spawn()
     }
 }
 
 /// Parser để tách NALUs từ H.264 stream
+///
+/// Một lần scan tuyến tính: mỗi byte được xét một lần qua mọi lần `parse`
+/// (trừ ≤ 2 bytes cuối, quét lại để bắt start code bị cắt ngang giữa hai
+/// reads). `00 00 03` (emulation prevention) không phải start code nên NALU
+/// chứa nó không bị cắt; bytes được giữ nguyên vì RTP mang NALU ở dạng này.
 pub struct NaluParser {
+    /// NALU đang dở (sau start code của nó), hoặc tail chưa sync
     buffer: Vec<u8>,
+    /// `buffer` bắt đầu bằng payload của một NALU (đã thấy start code)
+    in_nalu: bool,
+    /// Vị trí tiếp tục scan trong `buffer` ở lần `parse` sau
+    scan_from: usize,
+    /// Tolerant mode: resync on the first valid start code instead of trusting
+    /// the stream to begin with one (strict Annex-B is the default)
+    tolerant: bool,
+    synced: bool,
+    discarded: usize,
 }
 
 impl NaluParser {
     pub fn new() -> Self {
         Self {
             buffer: Vec::new(),
+            in_nalu: false,
+            scan_from: 0,
+            tolerant: false,
+            synced: true,
+            discarded: 0,
+        }
+    }
+
+    /// Parser that discards a leading non-start-code prefix (e.g. pasted AVCC
+    /// fragments) before the first NALU, logging the resync
+    pub fn tolerant() -> Self {
+        Self {
+            tolerant: true,
+            synced: false,
+            ..Self::new()
         }
     }
 
     /// Parse NALUs từ buffer
-    /// Return: Vec của các NALU (không bao gồm start code)
+    /// Return: Vec của các NALU (không bao gồm start code). NALU cuối chỉ
+    /// được trả khi start code kế tiếp tới (hoặc qua `finish`)
     pub fn parse(&mut self, data: &[u8]) -> Vec<Vec<u8>> {
         self.buffer.extend_from_slice(data);
         let mut nalus = Vec::new();
-        
-        let mut i = 0;
-        while i < self.buffer.len() {
-            // Find start code at position i
-            if let Some((sc_start, sc_len)) = self.find_start_code_at(i) {
-                // Found a start code, now find the next one
-                let nalu_start = sc_start + sc_len;
-
-                if let Some((next_sc_start, _)) = self.find_start_code_at(nalu_start) {
-                    // Found next start code, extract NALU between them
-                    let nalu = self.buffer[nalu_start..next_sc_start].to_vec();
-                    if !nalu.is_empty() && nalu.len() > 0 {
-                        nalus.push(nalu);
-                    }
-                    i = next_sc_start;
-                } else {
-                    // No more start codes, keep remaining in buffer
-                    self.buffer = self.buffer[sc_start..].to_vec();
-                    break;
+
+        if self.tolerant && !self.synced && !self.resync() {
+            return nalus;
+        }
+
+        let buf = &self.buffer;
+        // Đầu NALU hiện tại trong `buf` (None: chưa thấy start code nào)
+        let mut nalu_start = self.in_nalu.then_some(0);
+        let mut i = self.scan_from;
+        while i + 2 < buf.len() {
+            // buf[i + 2] > 1: không start code nào bắt đầu ở i, i+1, i+2
+            if buf[i + 2] > 1 {
+                i += 3;
+            } else if buf[i + 2] == 1 && buf[i] == 0 && buf[i + 1] == 0 {
+                // 4-byte start code: zero đứng trước thuộc start code, không
+                // thuộc NALU trước
+                let floor = nalu_start.unwrap_or(0);
+                let sc_start = if i > floor && buf[i - 1] == 0 { i - 1 } else { i };
+                if let Some(start) = nalu_start.filter(|start| sc_start > *start) {
+                    nalus.push(buf[start..sc_start].to_vec());
                 }
+                nalu_start = Some(i + 3);
+                i += 3;
             } else {
-                // No start code found, keep everything in buffer
-                break;
+                i += 1;
             }
         }
 
-        // If we processed all, clear buffer
-        if i > 0 && i >= self.buffer.len() {
-            self.buffer.clear();
-        }
-        
+        // Giữ NALU đang dở; chưa sync thì chỉ giữ tail có thể là start code bị cắt
+        let keep_from = nalu_start.unwrap_or_else(|| self.buffer.len().saturating_sub(3));
+        self.buffer.drain(..keep_from);
+        self.in_nalu = nalu_start.is_some();
+        self.scan_from = i.saturating_sub(keep_from);
+
         nalus
     }
 
+    /// End of stream: NALU cuối (không có start code theo sau), nếu có
+    pub fn finish(&mut self) -> Option<Vec<u8>> {
+        let nalu = std::mem::take(&mut self.buffer);
+        let in_nalu = std::mem::replace(&mut self.in_nalu, false);
+        self.scan_from = 0;
+        (in_nalu && !nalu.is_empty()).then_some(nalu)
+    }
+
+    /// Drop everything before the first start code followed by a plausible NAL
+    /// header (forbidden_zero_bit = 0, type 1..=23). Return false while no such
+    /// start code has arrived yet
+    fn resync(&mut self) -> bool {
+        let mut from = 0;
+        // Mặc định chỉ giữ lại tail có thể là start code bị cắt ngang
+        let mut keep_from = self.buffer.len().saturating_sub(3);
+
+        while let Some((sc_start, sc_len)) = self.find_start_code_at(from) {
+            match self.buffer.get(sc_start + sc_len) {
+                Some(&header) if header & 0x80 == 0 && (1..=23).contains(&(header & 0x1F)) => {
+                    self.discarded += sc_start;
+                    self.buffer.drain(..sc_start);
+                    if self.discarded > 0 {
+                        println!("🔄 NALU parser resync: discarded {} leading bytes", self.discarded);
+                    }
+                    self.synced = true;
+                    return true;
+                }
+                // Header byte chưa tới, đợi lần parse sau
+                None => {
+                    keep_from = sc_start;
+                    break;
+                }
+                _ => from = sc_start + 1,
+            }
+        }
+
+        self.discarded += keep_from;
+        self.buffer.drain(..keep_from);
+        false
+    }
+
     fn find_start_code_at(&self, start: usize) -> Option<(usize, usize)> {
         if start >= self.buffer.len() {
             return None;
         }
 
-        for i in start..self.buffer.len().saturating_sub(3) {
+        for i in start..self.buffer.len().saturating_sub(2) {
             // 4-byte start code: 0x00 0x00 0x00 0x01
             if i + 3 < self.buffer.len()
                 && self.buffer[i] == 0
@@ -143,3 +323,112 @@ impl NaluParser {
         None
     }
 }
+
+/// Gom NALUs thành access units (một picture + parameter sets/SEI đi trước)
+///
+/// Stateful qua các lần read, nên AU không bị cắt theo read buffer. Một AU
+/// chỉ hoàn tất khi NALU đầu của AU kế tiếp tới (H.264 7.4.1.2.3): AUD, SPS,
+/// PPS, SEI, hoặc VCL slice có first_mb_in_slice = 0 sau một picture.
+#[derive(Default)]
+pub struct AccessUnitSplitter {
+    pending: Vec<Vec<u8>>,
+    has_picture: bool,
+}
+
+impl AccessUnitSplitter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Feed one NALU; returns the previous access unit if this NALU starts a new one
+    pub fn push(&mut self, nalu: Vec<u8>) -> Option<Vec<Vec<u8>>> {
+        let nalu_type = nalu.first()? & 0x1F;
+        let is_vcl = (1..=5).contains(&nalu_type);
+
+        let starts_au = self.has_picture
+            && match nalu_type {
+                6..=9 | 14..=18 => true,
+                // first_mb_in_slice là ue(v) đầu tiên: bit '1' nghĩa là 0
+                1..=5 => nalu.get(1).is_some_and(|b| b & 0x80 != 0),
+                _ => false,
+            };
+
+        let finished = if starts_au {
+            self.has_picture = false;
+            Some(std::mem::take(&mut self.pending))
+        } else {
+            None
+        };
+
+        self.has_picture |= is_vcl;
+        self.pending.push(nalu);
+        finished
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// SPS, PPS, IDR (với `00 00 03` emulation prevention) và non-IDR slice
+    /// lớn, xen kẽ 3- và 4-byte start codes
+    fn annex_b() -> (Vec<u8>, Vec<Vec<u8>>) {
+        let nalus = vec![
+            vec![0x67, 0x42, 0xC0, 0x1F],
+            vec![0x68, 0xCE, 0x3C, 0x80],
+            vec![0x65, 0x88, 0x00, 0x00, 0x03, 0x00, 0x01, 0x84],
+            (0..10_000u32).map(|i| (i % 251) as u8 | 0x10).collect::<Vec<u8>>(),
+            vec![0x41, 0x9A, 0x02],
+        ];
+        let mut stream = Vec::new();
+        for (i, nalu) in nalus.iter().enumerate() {
+            stream.extend_from_slice(if i % 2 == 0 { &[0, 0, 0, 1][..] } else { &[0, 0, 1][..] });
+            stream.extend_from_slice(nalu);
+        }
+        (stream, nalus)
+    }
+
+    fn parse_chunked(mut parser: NaluParser, input: &[u8], size: usize) -> Vec<Vec<u8>> {
+        let mut output: Vec<Vec<u8>> = input.chunks(size).flat_map(|chunk| parser.parse(chunk)).collect();
+        output.extend(parser.finish());
+        output
+    }
+
+    #[test]
+    fn chunk_sizes_give_identical_nalus() {
+        let (stream, nalus) = annex_b();
+        for size in [1, 3, 8192, stream.len()] {
+            assert_eq!(parse_chunked(NaluParser::new(), &stream, size), nalus, "{}-byte reads", size);
+        }
+    }
+
+    #[test]
+    fn start_codes_split_across_reads() {
+        // Cắt ở mọi vị trí trong `00 00 00 01` giữa NALU đầu và NALU thứ hai
+        let stream = [0, 0, 0, 1, 0x67, 0x42, 0, 0, 0, 1, 0x68, 0xCE];
+        for split in 5..=10 {
+            let mut parser = NaluParser::new();
+            let mut output = parser.parse(&stream[..split]);
+            output.extend(parser.parse(&stream[split..]));
+            output.extend(parser.finish());
+            assert_eq!(output, [vec![0x67, 0x42], vec![0x68, 0xCE]], "split at {}", split);
+        }
+    }
+
+    #[test]
+    fn tolerant_parser_resyncs_after_leading_garbage() {
+        let (stream, nalus) = annex_b();
+        // AVCC length prefix + rác, gồm một start code theo sau bởi header không hợp lệ
+        let mut input = vec![0x00, 0x00, 0x10, 0xFF, 0x42, 0x00, 0x00, 0x01, 0x80, 0x13];
+        input.extend_from_slice(&stream);
+
+        for size in [1, 3, 8192] {
+            assert_eq!(parse_chunked(NaluParser::tolerant(), &input, size), nalus, "{}-byte reads", size);
+        }
+        // Strict parser coi rác trước start code đầu tiên là không thuộc NALU nào,
+        // nhưng start code với header 0x80 vẫn mở một NALU
+        let strict = parse_chunked(NaluParser::new(), &input, 8192);
+        assert_eq!(strict[0], [0x80, 0x13]);
+        assert_eq!(strict[1..], nalus[..]);
+    }
+}
@@ -1 +1,32 @@
+pub mod adts;
+pub mod bitrate;
+pub mod encoder;
+pub mod feed;
 pub mod file;
+pub mod params;
+pub mod placeholder;
+pub mod restart;
+pub mod rtsp_pull;
+pub mod watchdog;
+#[cfg(feature = "opus")]
+pub mod ogg;
+
+/// Video source của streaming tasks (UDP pipeline, TCP feeds): một FFmpeg
+/// process mà stdout là Annex-B H.264, nên tasks không cần biết nguồn là
+/// file, placeholder hay upstream RTSP
+pub trait Source: Send + Sync {
+    /// Spawn FFmpeg; stdout is piped (Annex-B H.264), stderr piped
+    fn start_ffmpeg(&self) -> std::io::Result<std::process::Child>;
+
+    /// File path / URL / placeholder description, cho logs
+    fn describe(&self) -> &str;
+
+    /// Có gì để phát không (file tồn tại); false thì fallback sang placeholder
+    fn is_available(&self) -> bool;
+
+    /// Độ dài media (giây); None cho live sources hoặc khi không probe được
+    fn probe_duration(&self) -> Option<f64>;
+
+    /// Video bitrate target (kbps) cho các lần `start_ffmpeg` sau
+    fn set_bitrate(&mut self, kbps: u32);
+}
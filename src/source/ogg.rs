@@ -0,0 +1,108 @@
+use std::io::Read;
+
+/// Tách packets từ Ogg stream (FFmpeg `-f ogg` cho Opus)
+///
+/// Một packet kết thúc ở lacing value < 255 và có thể trải qua nhiều page.
+/// Không kiểm tra CRC: input là pipe local từ FFmpeg.
+pub struct OggPacketReader<R: Read> {
+    reader: R,
+    segments: Vec<u8>,
+    segment_index: usize,
+    partial: Vec<u8>,
+}
+
+impl<R: Read> OggPacketReader<R> {
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader,
+            segments: Vec::new(),
+            segment_index: 0,
+            partial: Vec::new(),
+        }
+    }
+
+    /// Next complete packet, or None at end of stream
+    pub fn next_packet(&mut self) -> std::io::Result<Option<Vec<u8>>> {
+        loop {
+            while self.segment_index < self.segments.len() {
+                let lacing = self.segments[self.segment_index] as usize;
+                self.segment_index += 1;
+
+                let start = self.partial.len();
+                self.partial.resize(start + lacing, 0);
+                self.reader.read_exact(&mut self.partial[start..])?;
+
+                if lacing < 255 {
+                    return Ok(Some(std::mem::take(&mut self.partial)));
+                }
+            }
+
+            if !self.read_page_header()? {
+                return Ok(None);
+            }
+        }
+    }
+
+    /// Đọc page header + segment table. Return false at clean EOF
+    fn read_page_header(&mut self) -> std::io::Result<bool> {
+        // "OggS"(4) version(1) type(1) granule(8) serial(4) seq(4) crc(4) segments(1)
+        let mut header = [0u8; 27];
+        match self.reader.read_exact(&mut header) {
+            Ok(()) => {}
+            Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(false),
+            Err(e) => return Err(e),
+        }
+
+        if &header[0..4] != b"OggS" {
+            return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "Missing OggS capture pattern"));
+        }
+
+        self.segments = vec![0u8; header[26] as usize];
+        self.reader.read_exact(&mut self.segments)?;
+        self.segment_index = 0;
+        Ok(true)
+    }
+}
+
+/// OpusHead / OpusTags là header packets, không phải audio frames
+pub fn is_opus_header(packet: &[u8]) -> bool {
+    packet.starts_with(b"OpusHead") || packet.starts_with(b"OpusTags")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Một Ogg page với segment table `lacing` và `body`
+    fn page(lacing: &[u8], body: &[u8]) -> Vec<u8> {
+        let mut page = b"OggS".to_vec();
+        page.extend_from_slice(&[0; 22]); // version, type, granule, serial, seq, crc
+        page.push(lacing.len() as u8);
+        page.extend_from_slice(lacing);
+        page.extend_from_slice(body);
+        page
+    }
+
+    #[test]
+    fn reads_packets_across_pages() {
+        let head = b"OpusHead\x01\x02".to_vec();
+        let long = vec![0xAB; 300]; // 255 + 45: lacing tiếp sang page sau
+        let mut stream = page(&[head.len() as u8], &head);
+        stream.extend(page(&[3, 255], &[[1, 2, 3].as_slice(), &long[..255]].concat()));
+        stream.extend(page(&[45], &long[255..]));
+
+        let mut reader = OggPacketReader::new(stream.as_slice());
+        let first = reader.next_packet().unwrap().unwrap();
+        assert!(is_opus_header(&first));
+        assert_eq!(reader.next_packet().unwrap().unwrap(), [1, 2, 3]);
+        assert_eq!(reader.next_packet().unwrap().unwrap(), long);
+        assert!(reader.next_packet().unwrap().is_none());
+    }
+
+    #[test]
+    fn rejects_missing_capture_pattern() {
+        let mut stream = page(&[1], &[7]);
+        stream[0] = b'X';
+        assert!(OggPacketReader::new(stream.as_slice()).next_packet().is_err());
+    }
+}
@@ -0,0 +1,342 @@
+use crate::rtsp::sdp::base64_decode;
+use std::time::{Duration, Instant};
+
+/// Known-good SPS/PPS cấu hình bằng `--sprop <sps>,<pps>` (base64, cùng
+/// format với sprop-parameter-sets) cho sources có parameter sets lỗi
+///
+/// Khi set, thay thế cả sprop-parameter-sets trong SDP lẫn SPS/PPS in-band.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ParameterSets {
+    pub sps: Vec<u8>,
+    pub pps: Vec<u8>,
+}
+
+impl ParameterSets {
+    /// Parse `<base64 SPS>,<base64 PPS>` and check the NAL unit types
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let (sps, pps) = spec
+            .split_once(',')
+            .ok_or_else(|| format!("expected <sps>,<pps>, got '{}'", spec))?;
+
+        let decode = |name: &str, value: &str, nal_type: u8| {
+            let nalu = base64_decode(value.trim()).ok_or_else(|| format!("{} is not valid base64", name))?;
+            match nalu.first().map(|b| b & 0x1F) {
+                Some(t) if t == nal_type => Ok(nalu),
+                Some(t) => Err(format!("{} has NAL type {}, expected {}", name, t, nal_type)),
+                None => Err(format!("{} is empty", name)),
+            }
+        };
+
+        let sets = Self {
+            sps: decode("SPS", sps, 7)?,
+            pps: decode("PPS", pps, 8)?,
+        };
+        if sps_resolution(&sets.sps).is_none() {
+            return Err("SPS could not be parsed".to_string());
+        }
+        Ok(sets)
+    }
+
+    /// Replace the stream's SPS/PPS NALUs with the configured ones.
+    /// Warns once (per `warned` flag) if the stream's resolution differs
+    pub fn substitute(&self, nalus: &mut [Vec<u8>], warned: &mut bool) {
+        for nalu in nalus.iter_mut() {
+            match nalu.first().map(|b| b & 0x1F) {
+                Some(7) => {
+                    if !*warned && **nalu != *self.sps {
+                        let ours = sps_resolution(&self.sps);
+                        let theirs = sps_resolution(nalu);
+                        if ours != theirs {
+                            println!("⚠️  SPS override resolution {:?} differs from stream {:?}", ours, theirs);
+                        }
+                        *warned = true;
+                    }
+                    nalu.clone_from(&self.sps);
+                }
+                Some(8) => nalu.clone_from(&self.pps),
+                _ => {}
+            }
+        }
+    }
+}
+
+/// Phát hiện source không bao giờ gửi SPS/PPS (encoder cấu hình sai): client
+/// nhận video không decode được mà server không báo gì
+#[derive(Debug)]
+pub struct ParameterSetMonitor {
+    started: Instant,
+    timeout: Option<Duration>,
+    sps_seen: bool,
+    pps_seen: bool,
+    reported: bool,
+}
+
+impl ParameterSetMonitor {
+    /// `timeout` None disables the check
+    pub fn new(timeout: Option<Duration>) -> Self {
+        Self {
+            started: Instant::now(),
+            timeout,
+            sps_seen: false,
+            pps_seen: false,
+            reported: false,
+        }
+    }
+
+    pub fn observe(&mut self, nalus: &[Vec<u8>]) {
+        for nalu in nalus {
+            match nalu.first().map(|b| b & 0x1F) {
+                Some(7) => self.sps_seen = true,
+                Some(8) => self.pps_seen = true,
+                _ => {}
+            }
+        }
+    }
+
+    pub fn ready(&self) -> bool {
+        self.sps_seen && self.pps_seen
+    }
+
+    /// True exactly once, when the timeout has passed without both an SPS
+    /// and a PPS. Logs which of the two is missing
+    pub fn check(&mut self, now: Instant) -> bool {
+        let Some(timeout) = self.timeout else {
+            return false;
+        };
+        if self.reported || self.ready() || now.duration_since(self.started) < timeout {
+            return false;
+        }
+        self.reported = true;
+
+        let missing = match (self.sps_seen, self.pps_seen) {
+            (false, false) => "SPS/PPS",
+            (false, true) => "SPS",
+            _ => "PPS",
+        };
+        eprintln!("❌❌ No {} in the source stream after {:?}: clients cannot decode this video. \
+                   Check the encoder settings (e.g. repeat headers) or configure --sprop",
+                  missing, timeout);
+        true
+    }
+}
+
+/// Decoded picture size (width, height) từ một SPS NALU (H.264 7.3.2.1.1)
+pub fn sps_resolution(sps: &[u8]) -> Option<(u32, u32)> {
+    if sps.len() < 4 {
+        return None;
+    }
+    let mut r = BitReader::new(&sps[1..]);
+
+    let profile_idc = r.bits(8)?;
+    r.bits(16)?; // constraint flags + level_idc
+    r.ue()?; // seq_parameter_set_id
+
+    let mut chroma_format_idc = 1;
+    if matches!(profile_idc, 100 | 110 | 122 | 244 | 44 | 83 | 86 | 118 | 128 | 138 | 139 | 134 | 135) {
+        chroma_format_idc = r.ue()?;
+        if chroma_format_idc == 3 {
+            r.bits(1)?; // separate_colour_plane_flag
+        }
+        r.ue()?; // bit_depth_luma_minus8
+        r.ue()?; // bit_depth_chroma_minus8
+        r.bits(1)?; // qpprime_y_zero_transform_bypass_flag
+        if r.bits(1)? == 1 {
+            let lists = if chroma_format_idc == 3 { 12 } else { 8 };
+            for i in 0..lists {
+                if r.bits(1)? == 1 {
+                    r.skip_scaling_list(if i < 6 { 16 } else { 64 })?;
+                }
+            }
+        }
+    }
+
+    r.ue()?; // log2_max_frame_num_minus4
+    match r.ue()? {
+        0 => {
+            r.ue()?; // log2_max_pic_order_cnt_lsb_minus4
+        }
+        1 => {
+            r.bits(1)?; // delta_pic_order_always_zero_flag
+            r.se()?; // offset_for_non_ref_pic
+            r.se()?; // offset_for_top_to_bottom_field
+            for _ in 0..r.ue()? {
+                r.se()?;
+            }
+        }
+        _ => {}
+    }
+    r.ue()?; // max_num_ref_frames
+    r.bits(1)?; // gaps_in_frame_num_value_allowed_flag
+
+    let width_mbs = r.ue()? + 1;
+    let height_map_units = r.ue()? + 1;
+    let frame_mbs_only = r.bits(1)?;
+    if frame_mbs_only == 0 {
+        r.bits(1)?; // mb_adaptive_frame_field_flag
+    }
+    r.bits(1)?; // direct_8x8_inference_flag
+
+    let mut width = width_mbs * 16;
+    let mut height = (2 - frame_mbs_only) * height_map_units * 16;
+
+    if r.bits(1)? == 1 {
+        let (left, right, top, bottom) = (r.ue()?, r.ue()?, r.ue()?, r.ue()?);
+        let (unit_x, unit_y) = match chroma_format_idc {
+            1 => (2, 2 * (2 - frame_mbs_only)),
+            2 => (2, 2 - frame_mbs_only),
+            _ => (1, 2 - frame_mbs_only),
+        };
+        width = width.checked_sub((left + right) * unit_x)?;
+        height = height.checked_sub((top + bottom) * unit_y)?;
+    }
+
+    Some((width, height))
+}
+
+/// MSB-first bit reader over an RBSP (bỏ emulation prevention bytes 00 00 03)
+struct BitReader {
+    data: Vec<u8>,
+    pos: usize,
+}
+
+impl BitReader {
+    fn new(ebsp: &[u8]) -> Self {
+        let mut data = Vec::with_capacity(ebsp.len());
+        let mut zeros = 0;
+        for &b in ebsp {
+            if zeros >= 2 && b == 3 {
+                zeros = 0;
+                continue;
+            }
+            zeros = if b == 0 { zeros + 1 } else { 0 };
+            data.push(b);
+        }
+        Self { data, pos: 0 }
+    }
+
+    fn bits(&mut self, n: u32) -> Option<u32> {
+        let mut value = 0u32;
+        for _ in 0..n {
+            let byte = *self.data.get(self.pos / 8)?;
+            let bit = (byte >> (7 - self.pos % 8)) & 1;
+            value = (value << 1) | bit as u32;
+            self.pos += 1;
+        }
+        Some(value)
+    }
+
+    /// Unsigned Exp-Golomb
+    fn ue(&mut self) -> Option<u32> {
+        let mut zeros = 0;
+        while self.bits(1)? == 0 {
+            zeros += 1;
+            if zeros > 31 {
+                return None;
+            }
+        }
+        Some((1u32 << zeros) - 1 + self.bits(zeros)?)
+    }
+
+    /// Signed Exp-Golomb
+    fn se(&mut self) -> Option<i32> {
+        let k = self.ue()?;
+        let magnitude = k.div_ceil(2) as i32;
+        Some(if k % 2 == 1 { magnitude } else { -magnitude })
+    }
+
+    fn skip_scaling_list(&mut self, size: usize) -> Option<()> {
+        let (mut last, mut next) = (8i32, 8i32);
+        for _ in 0..size {
+            if next != 0 {
+                next = (last + self.se()? + 256) % 256;
+            }
+            if next != 0 {
+                last = next;
+            }
+        }
+        Some(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rtp::depacketize::H264Depacketizer;
+    use crate::rtp::h264::H264Packetizer;
+    use crate::rtp::packet::RtpPacket;
+
+    /// 640x480 Baseline override, 1920x1080 High từ stream
+    const OVERRIDE: &str = "Z0IAH6tAUB7I,aM48gA==";
+    const STREAM_SPS: &str = "Z2QAKKzZQHgCJ+XARAAAAwAEAAADAPA8YMZY";
+
+    #[test]
+    fn override_must_be_an_sps_and_a_pps() {
+        let sets = ParameterSets::parse(OVERRIDE).unwrap();
+        assert_eq!(sets.sps, [0x67, 0x42, 0x00, 0x1F, 0xAB, 0x40, 0x50, 0x1E, 0xC8]);
+        assert_eq!(sets.pps, [0x68, 0xCE, 0x3C, 0x80]);
+        assert_eq!(sps_resolution(&sets.sps), Some((640, 480)));
+        assert_eq!(ParameterSets::parse(" Z0IAH6tAUB7I , aM48gA== "), Ok(sets));
+
+        for (spec, error) in [
+            ("Z0IAH6tAUB7I", "expected <sps>,<pps>, got 'Z0IAH6tAUB7I'"),
+            ("Z0IAH6t!UB7I,aM48gA==", "SPS is not valid base64"),
+            ("aM48gA==,Z0IAH6tAUB7I", "SPS has NAL type 8, expected 7"),
+            ("Z0IAH6tAUB7I,Z0IAH6tAUB7I", "PPS has NAL type 7, expected 8"),
+            (",aM48gA==", "SPS is empty"),
+            ("Z0I=,aM48gA==", "SPS could not be parsed"),
+        ] {
+            assert_eq!(ParameterSets::parse(spec), Err(error.to_string()), "{}", spec);
+        }
+    }
+
+    #[test]
+    fn override_replaces_the_in_band_sets() {
+        let sets = ParameterSets::parse(OVERRIDE).unwrap();
+        let stream_sps = base64_decode(STREAM_SPS).unwrap();
+        let idr = vec![0x65, 0x88, 0x84, 0x00];
+        let mut nalus = vec![stream_sps.clone(), vec![0x68, 0xEB, 0xE3, 0xCB, 0x22, 0xC0], idr.clone()];
+        assert_eq!(sps_resolution(&stream_sps), Some((1920, 1080)));
+
+        let mut warned = false;
+        sets.substitute(&mut nalus, &mut warned);
+        assert_eq!(nalus, [sets.sps.clone(), sets.pps.clone(), idr]);
+        // Resolution khác nhau: warn một lần
+        assert!(warned);
+
+        // SPS/PPS gửi trước IDR (STAP-A) là bản override
+        let mut packetizer = H264Packetizer::with_ssrc(1);
+        let packets = packetizer.packetize_stap_a(&[&nalus[0], &nalus[1]]);
+        assert_eq!(packets.len(), 1);
+        let packet = RtpPacket::parse(&packets[0].to_bytes()).unwrap();
+        assert_eq!(packet.payload[0] & 0x1F, 24);
+        assert_eq!(H264Depacketizer::new().push(&packet).unwrap(), [sets.sps, sets.pps]);
+    }
+
+    #[test]
+    fn slice_only_stream_is_reported_once_after_the_timeout() {
+        let slices = [vec![0x65, 0x88, 0x84], vec![0x41, 0x9A, 0x02], vec![0x06, 0x05]];
+        let mut monitor = ParameterSetMonitor::new(Some(Duration::from_secs(3)));
+        let started = monitor.started;
+        monitor.observe(&slices);
+        assert!(!monitor.ready());
+        assert!(!monitor.check(started + Duration::from_secs(2)));
+        assert!(monitor.check(started + Duration::from_secs(3)));
+        // Chỉ báo (và synthesize) một lần
+        monitor.observe(&slices);
+        assert!(!monitor.check(started + Duration::from_secs(10)));
+
+        // Chỉ có SPS: vẫn thiếu PPS
+        let mut monitor = ParameterSetMonitor::new(Some(Duration::from_secs(3)));
+        monitor.observe(&[vec![0x67, 0x42, 0x00, 0x1F]]);
+        assert!(monitor.check(monitor.started + Duration::from_secs(3)));
+
+        // Đủ cả hai trước timeout, hoặc check bị tắt: không báo
+        let mut monitor = ParameterSetMonitor::new(Some(Duration::from_secs(3)));
+        monitor.observe(&[vec![0x67, 0x42, 0x00, 0x1F], vec![0x68, 0xCE, 0x3C, 0x80]]);
+        assert!(monitor.ready());
+        assert!(!monitor.check(monitor.started + Duration::from_secs(10)));
+        let mut monitor = ParameterSetMonitor::new(None);
+        monitor.observe(&slices);
+        assert!(!monitor.check(monitor.started + Duration::from_secs(3600)));
+    }
+}
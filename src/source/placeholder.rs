@@ -0,0 +1,69 @@
+use crate::rtp::h264::DEFAULT_FPS;
+use std::time::Duration;
+
+/// Khi đang phát placeholder, thử lại primary source sau mỗi khoảng này
+pub const PRIMARY_RETRY_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Kích thước placeholder, khớp với SPS mặc định trong SDP (640x480 baseline)
+const WIDTH: u32 = 640;
+const HEIGHT: u32 = 480;
+
+/// Nội dung stream thay thế khi primary source thiếu hoặc lỗi, để client
+/// luôn nhận được video decode được thay vì treo
+#[derive(Clone, Debug, PartialEq)]
+pub enum Placeholder {
+    /// Nền đen với dòng chữ ở giữa (FFmpeg `color` + `drawtext`)
+    Text(String),
+    /// Ảnh tĩnh lặp lại (kích thước nên chẵn cho yuv420p)
+    Image(String),
+}
+
+impl Placeholder {
+    /// Parse `off`, `text:<message>`, `image:<path>`; một chuỗi khác là text
+    pub fn parse(value: &str) -> Result<Option<Self>, String> {
+        if let Some(path) = value.strip_prefix("image:") {
+            if path.is_empty() {
+                return Err("image: needs a path".to_string());
+            }
+            return Ok(Some(Self::Image(path.to_string())));
+        }
+        match value {
+            "off" | "none" => Ok(None),
+            _ => Ok(Some(Self::Text(value.strip_prefix("text:").unwrap_or(value).to_string()))),
+        }
+    }
+
+    /// FFmpeg input options (tới và gồm `-i`) sinh ra placeholder real time
+    pub fn input_args(&self) -> Vec<String> {
+        let input = match self {
+            Self::Text(text) => {
+                // drawtext option value trong '...': không có ', escape : và \
+                let text = text.replace('\\', "\\\\").replace(':', "\\:").replace('\'', "");
+                vec![
+                    "-f".to_string(), "lavfi".to_string(),
+                    "-i".to_string(),
+                    format!(
+                        "color=c=black:s={}x{}:r={},drawtext=text='{}':fontcolor=white:fontsize=48:x=(w-text_w)/2:y=(h-text_h)/2",
+                        WIDTH, HEIGHT, DEFAULT_FPS, text
+                    ),
+                ]
+            }
+            Self::Image(path) => vec![
+                "-loop".to_string(), "1".to_string(),
+                "-framerate".to_string(), DEFAULT_FPS.to_string(),
+                "-i".to_string(), path.clone(),
+            ],
+        };
+        let mut args = vec!["-re".to_string()];
+        args.extend(input);
+        args
+    }
+
+    /// Mô tả ngắn cho logs
+    pub fn describe(&self) -> String {
+        match self {
+            Self::Text(text) => format!("text \"{}\"", text),
+            Self::Image(path) => format!("image {}", path),
+        }
+    }
+}
@@ -0,0 +1,52 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+/// Delay trước mỗi restart bình thường
+const BASE_DELAY: Duration = Duration::from_millis(500);
+/// Quá `STORM_RESTARTS` restarts trong `STORM_WINDOW` là restart storm
+const STORM_WINDOW: Duration = Duration::from_secs(30);
+const STORM_RESTARTS: usize = 5;
+/// Trần của exponential backoff trong storm
+const MAX_DELAY: Duration = Duration::from_secs(30);
+
+/// Backoff cho việc respawn FFmpeg khi nó tự thoát (crash, input lỗi)
+///
+/// Restart lẻ tẻ chỉ chờ `BASE_DELAY`. Source chết ngay sau mỗi lần spawn
+/// thì delay tăng gấp đôi mỗi lần tới `MAX_DELAY`, thay vì spawn FFmpeg liên
+/// tục; hết storm (không restart nào trong `STORM_WINDOW`) thì về lại mức đầu.
+#[derive(Debug, Default)]
+pub struct RestartBackoff {
+    restarts: u64,
+    recent: VecDeque<Instant>,
+}
+
+impl RestartBackoff {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record one restart at `now`; returns how long to wait before spawning
+    pub fn next_delay(&mut self, now: Instant) -> Duration {
+        self.restarts += 1;
+        while self.recent.front().is_some_and(|at| now.duration_since(*at) > STORM_WINDOW) {
+            self.recent.pop_front();
+        }
+        self.recent.push_back(now);
+
+        let excess = self.recent.len().saturating_sub(STORM_RESTARTS) as u32;
+        if excess == 0 {
+            return BASE_DELAY;
+        }
+        BASE_DELAY.saturating_mul(2u32.saturating_pow(excess)).min(MAX_DELAY)
+    }
+
+    /// Restarts so far (the count in restart logs)
+    pub fn restarts(&self) -> u64 {
+        self.restarts
+    }
+
+    /// True khi restart gần nhất nằm trong một storm
+    pub fn in_storm(&self) -> bool {
+        self.recent.len() > STORM_RESTARTS
+    }
+}
@@ -0,0 +1,50 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Stall detector cho source pipeline của một mount
+///
+/// Streaming loop gọi `feed` mỗi khi gửi packet (và khi idle, không có
+/// client), watchdog task gọi `check` định kỳ. Khi stall, watchdog kill
+/// FFmpeg; blocking read trả về EOF và loop thấy `take_fired` để respawn.
+pub struct Watchdog {
+    timeout: Duration,
+    last_packet: Mutex<Instant>,
+    fired: AtomicBool,
+}
+
+impl Watchdog {
+    pub fn new(timeout: Duration) -> Self {
+        Self {
+            timeout,
+            last_packet: Mutex::new(Instant::now()),
+            fired: AtomicBool::new(false),
+        }
+    }
+
+    /// Packets were just produced (or nobody is playing, which is not a stall)
+    pub fn feed(&self) {
+        *self.last_packet.lock().unwrap() = Instant::now();
+    }
+
+    /// True if clients are playing but nothing was sent for `timeout`.
+    /// Marks the watchdog fired and restarts the timer so it fires once per stall
+    pub fn check(&self, clients_playing: bool) -> bool {
+        let mut last_packet = self.last_packet.lock().unwrap();
+        if !clients_playing {
+            *last_packet = Instant::now();
+            return false;
+        }
+        if last_packet.elapsed() < self.timeout {
+            return false;
+        }
+        *last_packet = Instant::now();
+        self.fired.store(true, Ordering::SeqCst);
+        true
+    }
+
+    /// Whether the pipeline ended because of a stall (clears the flag)
+    pub fn take_fired(&self) -> bool {
+        self.fired.swap(false, Ordering::SeqCst)
+    }
+}
@@ -0,0 +1,419 @@
+use crate::config::ServerConfig;
+use crate::rtsp::sdp::{base64_encode, generate_sdp, mount_sdp, profile_level_id};
+use crate::rtsp::uri;
+use crate::rtsp::state::{ServerState, SharedState, TransportMode};
+use std::sync::Arc;
+use std::time::Instant;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+/// HTTP status endpoint (JSON), bật bằng `--status <addr>`
+///
+/// `GET /status` là snapshot read-only, `GET /stats` là counters của
+/// `ServerState::stats` (để scrape); `GET /sessions` liệt kê sessions và
+/// `POST /sessions/<id>/kick` (hoặc `DELETE /sessions/<id>`) ngắt một session:
+/// BYE, teardown server-side, rồi đóng RTSP connection của nó.
+/// `GET /<mount>.sdp` trả đúng SDP mà DESCRIBE trả, cho clients lấy SDP qua
+/// HTTP rồi SETUP thẳng.
+#[derive(Clone)]
+pub struct StatusServer {
+    addr: String,
+    state: SharedState,
+    config: Arc<ServerConfig>,
+}
+
+impl StatusServer {
+    pub fn new(addr: String, state: SharedState, config: Arc<ServerConfig>) -> Self {
+        Self { addr, state, config }
+    }
+
+    pub async fn run(&self) -> std::io::Result<()> {
+        let listener = TcpListener::bind(&self.addr).await?;
+        println!("📈 Status endpoint listening on http://{}/status", self.addr);
+
+        loop {
+            let (socket, _peer) = listener.accept().await?;
+            let server = self.clone();
+            tokio::spawn(async move {
+                if let Err(e) = server.respond(socket).await {
+                    eprintln!("⚠️  Status request error: {}", e);
+                }
+            });
+        }
+    }
+
+    async fn respond(&self, mut socket: TcpStream) -> std::io::Result<()> {
+        let mut buffer = [0u8; 2048];
+        let n = socket.read(&mut buffer).await?;
+        let request = String::from_utf8_lossy(&buffer[..n]);
+        let mut parts = request.split_whitespace();
+        let (method, path) = (parts.next(), parts.next());
+        let segments: Vec<&str> = path.unwrap_or("").trim_matches('/').split('/').collect();
+        // `/<mount>.sdp`, mount có thể lồng nhau (`/site/door.sdp`)
+        let sdp_path = path.map(uri::path).and_then(|path| path.strip_suffix(".sdp"));
+
+        let response = match (method, segments.as_slice()) {
+            (Some("GET"), _) if sdp_path.is_some() => self.render_sdp(sdp_path.unwrap_or_default()).await,
+            (Some("GET"), [""] | ["status"]) => json_response(&self.render().await),
+            (Some("GET"), ["stats"]) => json_response(&self.render_stats().await),
+            (Some("GET"), ["sessions"]) => json_response(&self.render_sessions().await),
+            (Some("POST"), ["sessions", id, "kick"]) | (Some("DELETE"), ["sessions", id]) => {
+                if self.state.write().await.kick(id) {
+                    json_response(&format!("{{\"kicked\":{}}}", json_string(id)))
+                } else {
+                    empty_response("404 Not Found")
+                }
+            }
+            (Some("GET" | "POST" | "DELETE"), _) => empty_response("404 Not Found"),
+            _ => "HTTP/1.1 405 Method Not Allowed\r\nAllow: GET, POST, DELETE\r\nContent-Length: 0\r\nConnection: close\r\n\r\n".to_string(),
+        };
+
+        socket.write_all(response.as_bytes()).await?;
+        socket.shutdown().await
+    }
+
+    /// SDP của mount ở `path` (phần trước `.sdp`, resolve như URL của RTSP
+    /// request: `/` là default mount), cùng `mount_sdp` (và cùng điều kiện
+    /// 503) với DESCRIBE
+    async fn render_sdp(&self, path: &str) -> String {
+        let mount = {
+            let state = self.state.read().await;
+            uri::resolve_mount(path, &self.config.default_mount, |mount| state.mounts.contains_key(mount))
+        };
+        let Some(mount) = mount else {
+            return empty_response("404 Not Found");
+        };
+        let Some(sdp) = mount_sdp(&self.config, &self.state, &mount).await else {
+            return empty_response("503 Service Unavailable");
+        };
+        format!(
+            "HTTP/1.1 200 OK\r\nContent-Type: application/sdp\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            sdp.len(),
+            sdp
+        )
+    }
+
+    /// `ServerState::stats` snapshot
+    async fn render_stats(&self) -> String {
+        let stats = self.state.read().await.stats();
+        format!(
+            "{{\"clients\":{},\"playing\":{},\"transports\":{{\"udp\":{},\"tcp\":{},\"multicast\":{}}},\"packets_sent\":{},\"bytes_sent\":{},\"restarts\":{},\"stalls\":{},\"bitrate_kbps\":{}}}",
+            stats.clients,
+            stats.playing,
+            stats.udp,
+            stats.tcp,
+            stats.multicast,
+            stats.packets_sent,
+            stats.bytes_sent,
+            stats.restarts,
+            stats.stalls,
+            stats.bitrate_kbps.map_or_else(|| "null".to_string(), |kbps| kbps.to_string())
+        )
+    }
+
+    /// Active sessions: id, peer, mount, transport, playing state, age, totals
+    async fn render_sessions(&self) -> String {
+        let state = self.state.read().await;
+        let now = Instant::now();
+        let sessions: Vec<String> = state
+            .clients
+            .values()
+            .map(|c| {
+                let transport = match c.transport {
+                    TransportMode::Udp { .. } => "udp",
+                    TransportMode::TcpInterleaved { .. } => "tcp",
+                    TransportMode::Multicast { .. } => "multicast",
+                };
+                // Loss/jitter từ RR gần nhất của client (UDP)
+                let reception = c.reception.as_ref().map_or("null".to_string(), |r| {
+                    format!(
+                        "{{\"fraction_lost\":{},\"cumulative_lost\":{},\"highest_sequence\":{},\"jitter\":{}}}",
+                        r.fraction_lost, r.cumulative_lost, r.highest_sequence, r.jitter
+                    )
+                });
+                format!(
+                    "{{\"id\":{},\"ip\":{},\"mount\":{},\"transport\":\"{}\",\"playing\":{},\"duration_secs\":{:.3},\"packets_sent\":{},\"bytes_sent\":{},\"reception\":{}}}",
+                    json_string(&c.id),
+                    json_string(&c.client_ip),
+                    json_string(&c.mount),
+                    transport,
+                    c.is_playing,
+                    now.saturating_duration_since(c.started).as_secs_f64(),
+                    c.packets_sent,
+                    c.bytes_sent,
+                    reception
+                )
+            })
+            .collect();
+        format!("{{\"sessions\":[{}]}}", sessions.join(","))
+    }
+
+    /// Snapshot the per-mount SDP and the parameter sets the stream actually carries
+    async fn render(&self) -> String {
+        let state = self.state.read().await;
+        let mut names: Vec<&String> = state.mounts.keys().collect();
+        names.sort();
+        let mounts: Vec<String> = names.into_iter().map(|mount| self.render_mount(&state, mount)).collect();
+        format!("{{\"mounts\":[{}]}}", mounts.join(","))
+    }
+
+    /// Một mount: SDP như DESCRIBE trả (duration + parameter sets của chính
+    /// mount đó, không chờ SPS/PPS). SPS/PPS thật và counters của shared UDP
+    /// pipeline chỉ có ở default mount; mounts khác chạy FFmpeg riêng mỗi
+    /// TCP session
+    fn render_mount(&self, state: &ServerState, mount: &str) -> String {
+        let shared = mount == self.config.default_mount;
+        let stream_sets = state.parameter_sets().filter(|_| shared);
+        let sdp = generate_sdp(&self.config, &state.mount_range(mount, &self.config.default_mount), stream_sets);
+
+        let sps = state.sps.as_deref().filter(|_| shared);
+        let pps = state.pps.as_deref().filter(|_| shared);
+
+        // profile_idc / constraint flags / level_idc là 3 byte sau NAL header
+        let profile = match sps {
+            Some(sps) if sps.len() >= 4 => format!(
+                ",\"profile_level_id\":{},\"profile_idc\":{},\"level_idc\":{}",
+                profile_level_id(sps).map_or("null".to_string(), |id| json_string(&id)),
+                sps[1],
+                sps[3]
+            ),
+            _ => String::new(),
+        };
+
+        // Formats học được từ ANNOUNCE (record mode)
+        let announced: Vec<String> = state
+            .announced
+            .get(mount)
+            .into_iter()
+            .flatten()
+            .map(|f| {
+                format!(
+                    "{{\"media\":{},\"payload_type\":{},\"encoding\":{},\"clock_rate\":{},\"parameter_sets\":{}}}",
+                    json_string(&f.media),
+                    f.payload_type,
+                    json_string(&f.encoding),
+                    f.clock_rate,
+                    f.parameter_sets.len()
+                )
+            })
+            .collect();
+
+        let pipeline = if shared {
+            let queues: Vec<String> = state
+                .udp_queues
+                .iter()
+                .map(|q| {
+                    format!(
+                        "{{\"addr\":{},\"depth\":{},\"dropped\":{}}}",
+                        json_string(&q.rtp_addr.to_string()),
+                        q.depth,
+                        q.dropped
+                    )
+                })
+                .collect();
+            format!(
+                ",\"stalls\":{},\"restarts\":{},\"frames_dropped\":{},\"keepalives_sent\":{},\"udp_queues\":[{}]",
+                state.stalls,
+                state.restarts,
+                state.frames_dropped,
+                state.keepalives_sent,
+                queues.join(",")
+            )
+        } else {
+            String::new()
+        };
+
+        format!(
+            "{{\"name\":{},\"default\":{},\"sdp\":{},\"sps\":{},\"pps\":{}{},\"parameter_sets_ready\":{},\"announced\":[{}]{}}}",
+            json_string(mount),
+            shared,
+            json_string(&sdp),
+            sps.map(base64_encode).as_deref().map_or("null".to_string(), json_string),
+            pps.map(base64_encode).as_deref().map_or("null".to_string(), json_string),
+            profile,
+            stream_sets.is_some(),
+            announced.join(","),
+            pipeline
+        )
+    }
+}
+
+fn json_response(body: &str) -> String {
+    format!(
+        "HTTP/1.1 200 OK\r\n\
+         Content-Type: application/json\r\n\
+         Content-Length: {}\r\n\
+         Connection: close\r\n\
+         \r\n\
+         {}",
+        body.len(),
+        body
+    )
+}
+
+fn empty_response(status: &str) -> String {
+    format!("HTTP/1.1 {}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n", status)
+}
+
+/// Quote and escape a string as a JSON string literal
+pub(crate) fn json_string(value: &str) -> String {
+    let mut out = String::with_capacity(value.len() + 2);
+    out.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rtsp::sdp::MediaFormat;
+    use crate::rtsp::session::RtspSession;
+    use crate::rtsp::state::create_shared_state;
+
+    /// Body của HTTP/RTSP response (sau headers)
+    fn body(response: &str) -> &str {
+        response.split_once("\r\n\r\n").map_or("", |(_, body)| body)
+    }
+
+    /// RTSP DESCRIBE qua một session chạy trên `tokio::io::duplex`
+    async fn describe(state: &SharedState, config: &Arc<ServerConfig>, url: &str) -> String {
+        let (mut client, server) = tokio::io::duplex(64 * 1024);
+        let mut session = RtspSession::with_stream(server, "127.0.0.1".to_string(), state.clone(), config.clone());
+        tokio::spawn(async move { session.handle().await });
+        client.write_all(format!("DESCRIBE {} RTSP/1.0\r\nCSeq: 1\r\n\r\n", url).as_bytes()).await.unwrap();
+
+        let mut response = Vec::new();
+        let mut buffer = [0u8; 4096];
+        loop {
+            let n = client.read(&mut buffer).await.unwrap();
+            assert!(n > 0, "session closed the connection");
+            response.extend_from_slice(&buffer[..n]);
+            let text = String::from_utf8_lossy(&response).to_string();
+            let length = text
+                .lines()
+                .find_map(|line| line.strip_prefix("Content-Length: "))
+                .and_then(|len| len.trim().parse::<usize>().ok());
+            if let (Some((_, body)), Some(length)) = (text.split_once("\r\n\r\n"), length) {
+                if body.len() >= length {
+                    return text;
+                }
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn http_sdp_matches_describe_byte_for_byte() {
+        let config = Arc::new(ServerConfig {
+            mounts: vec![("lobby".to_string(), "videos/lobby.mp4".to_string())],
+            ..ServerConfig::default()
+        });
+        let state = create_shared_state(config.mount_table());
+        {
+            let mut st = state.write().await;
+            st.media_duration = Some(12.5);
+            st.cache_parameter_set(&[0x67, 0x64, 0x00, 0x28, 0xAC]);
+            st.cache_parameter_set(&[0x68, 0xEE, 0x3C, 0x80]);
+        }
+        let server = StatusServer::new("127.0.0.1:0".to_string(), state.clone(), config.clone());
+
+        for mount in ["cam", "lobby"] {
+            let http = server.render_sdp(&format!("/{}", mount)).await;
+            assert!(http.starts_with("HTTP/1.1 200 OK\r\nContent-Type: application/sdp\r\n"), "{}", http);
+            let rtsp = describe(&state, &config, &format!("rtsp://127.0.0.1:8554/{}", mount)).await;
+            assert!(rtsp.starts_with("RTSP/1.0 200 OK"), "{}", rtsp);
+            assert_eq!(body(&http), body(&rtsp), "/{}.sdp", mount);
+        }
+        // Chỉ default mount có SPS/PPS và duration của shared pipeline
+        assert!(body(&server.render_sdp("/cam").await).contains("sprop-parameter-sets="));
+        assert!(!body(&server.render_sdp("/lobby").await).contains("sprop-parameter-sets="));
+    }
+
+    #[tokio::test]
+    async fn sdp_paths_follow_mount_routing() {
+        let config = Arc::new(ServerConfig {
+            mounts: vec![
+                ("lobby".to_string(), "videos/lobby.mp4".to_string()),
+                ("site/door".to_string(), "videos/door.mp4".to_string()),
+            ],
+            parameter_sets: Some(crate::source::params::ParameterSets {
+                sps: vec![0x67, 0x42, 0xC0, 0x1F],
+                pps: vec![0x68, 0xCE, 0x3C, 0x80],
+            }),
+            ..ServerConfig::default()
+        });
+        let state = create_shared_state(config.mount_table());
+        state.write().await.media_duration = Some(12.5);
+        let server = StatusServer::new("127.0.0.1:0".to_string(), state.clone(), config.clone());
+
+        let default = server.render_sdp("/").await;
+        assert!(default.contains("npt=0-12.500"), "{}", default);
+        assert_eq!(body(&server.render_sdp("/cam").await), body(&default));
+
+        let door = server.render_sdp("/site/door").await;
+        assert!(door.starts_with("HTTP/1.1 200 OK"), "{}", door);
+        assert_eq!(body(&door), body(&describe(&state, &config, "rtsp://127.0.0.1:8554/site/door").await));
+
+        assert!(server.render_sdp("/site").await.starts_with("HTTP/1.1 404 Not Found"));
+        assert!(server.render_sdp("/unknown").await.starts_with("HTTP/1.1 404 Not Found"));
+
+        let status = server.render().await;
+        assert!(status.contains("{\"name\":\"site/door\",\"default\":false"), "{}", status);
+    }
+
+    #[tokio::test]
+    async fn status_reports_each_mount_with_its_own_sdp() {
+        let config = Arc::new(ServerConfig {
+            mounts: vec![("lobby".to_string(), "videos/lobby.mp4".to_string())],
+            ..ServerConfig::default()
+        });
+        let state = create_shared_state(config.mount_table());
+        {
+            let mut st = state.write().await;
+            st.media_duration = Some(12.5);
+            st.cache_parameter_set(&[0x67, 0x64, 0x00, 0x28, 0xAC]);
+            st.cache_parameter_set(&[0x68, 0xEE, 0x3C, 0x80]);
+            st.stalls = 3;
+            let lobby = MediaFormat {
+                media: "video".to_string(),
+                payload_type: 97,
+                encoding: "H264".to_string(),
+                clock_rate: 90_000,
+                parameter_sets: Vec::new(),
+                control: None,
+            };
+            st.announced.insert("lobby".to_string(), vec![lobby]);
+        }
+        let server = StatusServer::new("127.0.0.1:0".to_string(), state.clone(), config.clone());
+
+        let status = server.render().await;
+        let cam = status.find("{\"name\":\"cam\"").expect(&status);
+        let lobby = status.find("{\"name\":\"lobby\"").expect(&status);
+        assert!(cam < lobby, "mounts are listed by name: {}", status);
+
+        let st = state.read().await;
+        let cam = server.render_mount(&st, "cam");
+        assert!(cam.contains("npt=0-12.500"), "{}", cam);
+        assert!(cam.contains("\"sps\":\"Z2QAKKw=\""), "{}", cam);
+        assert!(cam.contains("\"profile_level_id\":\"640028\""), "{}", cam);
+        assert!(cam.contains("\"parameter_sets_ready\":true,\"announced\":[]"), "{}", cam);
+        assert!(cam.contains("\"stalls\":3"), "{}", cam);
+
+        // Mount khác: không duration, không SPS/PPS của shared pipeline
+        let lobby = server.render_mount(&st, "lobby");
+        assert!(lobby.contains("npt=0-\\r\\n"), "{}", lobby);
+        assert!(lobby.contains("\"sps\":null,\"pps\":null,\"parameter_sets_ready\":false"), "{}", lobby);
+        assert!(lobby.contains("\"payload_type\":97"), "{}", lobby);
+        assert!(!lobby.contains("\"stalls\""), "{}", lobby);
+    }
+}